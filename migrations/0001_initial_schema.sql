@@ -1,3 +1,6 @@
+-- Baseline schema, formerly applied by hand from tables.sql. Every statement is
+-- IF NOT EXISTS so instances that were set up manually adopt it untouched.
+
 CREATE TABLE IF NOT EXISTS organization (
     key uuid,
     external_accounting_id text,
@@ -216,20 +219,3 @@ CREATE TABLE IF NOT EXISTS akaunting_options (
     created bigint,
     updated bigint
 );
-
-
-GRANT ALL ON ALL TABLES IN SCHEMA public TO projectmanager;
-
--- delete from akaunting_options;
--- delete from boards           ;
--- delete from contacts         ;
--- delete from entitys          ;
--- delete from files            ;
--- delete from mile_stones      ;
--- delete from notes            ;
--- delete from organization     ;
--- delete from projects         ;
--- delete from rooms            ;
--- delete from service_items    ;
--- delete from tasks            ;
--- delete from users;
\ No newline at end of file
@@ -0,0 +1,504 @@
+-- Primary keys, NOT NULL constraints, foreign keys and indexes for the baseline
+-- schema. Columns are backfilled with the same empty values the handlers write
+-- (empty strings, 0, the nil uuid) before the constraints go on, rows that
+-- share a key are cut down to the most recently updated one so the primary
+-- keys can be added, and foreign keys are added NOT VALID so rows orphaned
+-- before this migration are kept while every new write is checked.
+
+-- organization
+UPDATE organization SET key = gen_random_uuid() WHERE key IS NULL;
+UPDATE organization SET
+    owner_key = COALESCE(owner_key, '00000000-0000-0000-0000-000000000000'),
+    external_accounting_id = COALESCE(external_accounting_id, ''),
+    external_accounting_url = COALESCE(external_accounting_url, ''),
+    domain = COALESCE(domain, ''),
+    contact_email = COALESCE(contact_email, ''),
+    name = COALESCE(name, ''),
+    description = COALESCE(description, ''),
+    matrix_home_server = COALESCE(matrix_home_server, ''),
+    matrix_live_support_room_url = COALESCE(matrix_live_support_room_url, ''),
+    matrix_general_room_url = COALESCE(matrix_general_room_url, ''),
+    created = COALESCE(created, 0),
+    updated = COALESCE(updated, 0);
+DELETE FROM organization a USING (
+    SELECT ctid, row_number() OVER (PARTITION BY key ORDER BY updated DESC, created DESC) AS n FROM organization
+) d WHERE a.ctid = d.ctid AND d.n > 1;
+ALTER TABLE organization
+    ADD PRIMARY KEY (key),
+    ALTER COLUMN owner_key SET NOT NULL,
+    ALTER COLUMN external_accounting_id SET NOT NULL,
+    ALTER COLUMN external_accounting_url SET NOT NULL,
+    ALTER COLUMN domain SET NOT NULL,
+    ALTER COLUMN contact_email SET NOT NULL,
+    ALTER COLUMN name SET NOT NULL,
+    ALTER COLUMN description SET NOT NULL,
+    ALTER COLUMN matrix_home_server SET NOT NULL,
+    ALTER COLUMN matrix_live_support_room_url SET NOT NULL,
+    ALTER COLUMN matrix_general_room_url SET NOT NULL,
+    ALTER COLUMN created SET NOT NULL,
+    ALTER COLUMN updated SET NOT NULL;
+
+-- users
+UPDATE users SET key = gen_random_uuid() WHERE key IS NULL;
+UPDATE users SET
+    organization_key = COALESCE(organization_key, '00000000-0000-0000-0000-000000000000'),
+    email = COALESCE(email, ''),
+    matrix_user_id = COALESCE(matrix_user_id, ''),
+    matrix_home_server = COALESCE(matrix_home_server, ''),
+    created = COALESCE(created, 0),
+    updated = COALESCE(updated, 0);
+DELETE FROM users a USING (
+    SELECT ctid, row_number() OVER (PARTITION BY key ORDER BY updated DESC, created DESC) AS n FROM users
+) d WHERE a.ctid = d.ctid AND d.n > 1;
+ALTER TABLE users
+    ADD PRIMARY KEY (key),
+    ALTER COLUMN organization_key SET NOT NULL,
+    ALTER COLUMN email SET NOT NULL,
+    ALTER COLUMN matrix_user_id SET NOT NULL,
+    ALTER COLUMN matrix_home_server SET NOT NULL,
+    ALTER COLUMN created SET NOT NULL,
+    ALTER COLUMN updated SET NOT NULL;
+
+-- files
+UPDATE files SET key = gen_random_uuid() WHERE key IS NULL;
+UPDATE files SET
+    owner_key = COALESCE(owner_key, '00000000-0000-0000-0000-000000000000'),
+    organization_key = COALESCE(organization_key, '00000000-0000-0000-0000-000000000000'),
+    association_key = COALESCE(association_key, '00000000-0000-0000-0000-000000000000'),
+    url = COALESCE(url, ''),
+    hash = COALESCE(hash, ''),
+    name = COALESCE(name, ''),
+    description = COALESCE(description, ''),
+    tags = COALESCE(tags, ''),
+    format = COALESCE(format, ''),
+    association_type = COALESCE(association_type, 0),
+    size = COALESCE(size, 0),
+    created = COALESCE(created, 0),
+    updated = COALESCE(updated, 0);
+DELETE FROM files a USING (
+    SELECT ctid, row_number() OVER (PARTITION BY key ORDER BY updated DESC, created DESC) AS n FROM files
+) d WHERE a.ctid = d.ctid AND d.n > 1;
+ALTER TABLE files
+    ADD PRIMARY KEY (key),
+    ALTER COLUMN owner_key SET NOT NULL,
+    ALTER COLUMN organization_key SET NOT NULL,
+    ALTER COLUMN association_key SET NOT NULL,
+    ALTER COLUMN url SET NOT NULL,
+    ALTER COLUMN hash SET NOT NULL,
+    ALTER COLUMN name SET NOT NULL,
+    ALTER COLUMN description SET NOT NULL,
+    ALTER COLUMN tags SET NOT NULL,
+    ALTER COLUMN format SET NOT NULL,
+    ALTER COLUMN association_type SET NOT NULL,
+    ALTER COLUMN size SET NOT NULL,
+    ALTER COLUMN created SET NOT NULL,
+    ALTER COLUMN updated SET NOT NULL;
+
+-- notes
+UPDATE notes SET key = gen_random_uuid() WHERE key IS NULL;
+UPDATE notes SET
+    owner_key = COALESCE(owner_key, '00000000-0000-0000-0000-000000000000'),
+    organization_key = COALESCE(organization_key, '00000000-0000-0000-0000-000000000000'),
+    association_key = COALESCE(association_key, '00000000-0000-0000-0000-000000000000'),
+    url = COALESCE(url, ''),
+    hash = COALESCE(hash, ''),
+    title = COALESCE(title, ''),
+    content = COALESCE(content, ''),
+    association_type = COALESCE(association_type, 0),
+    created = COALESCE(created, 0),
+    updated = COALESCE(updated, 0);
+DELETE FROM notes a USING (
+    SELECT ctid, row_number() OVER (PARTITION BY key ORDER BY updated DESC, created DESC) AS n FROM notes
+) d WHERE a.ctid = d.ctid AND d.n > 1;
+ALTER TABLE notes
+    ADD PRIMARY KEY (key),
+    ALTER COLUMN owner_key SET NOT NULL,
+    ALTER COLUMN organization_key SET NOT NULL,
+    ALTER COLUMN association_key SET NOT NULL,
+    ALTER COLUMN url SET NOT NULL,
+    ALTER COLUMN hash SET NOT NULL,
+    ALTER COLUMN title SET NOT NULL,
+    ALTER COLUMN content SET NOT NULL,
+    ALTER COLUMN association_type SET NOT NULL,
+    ALTER COLUMN created SET NOT NULL,
+    ALTER COLUMN updated SET NOT NULL;
+
+-- service_items
+UPDATE service_items SET key = gen_random_uuid() WHERE key IS NULL;
+UPDATE service_items SET
+    owner_key = COALESCE(owner_key, '00000000-0000-0000-0000-000000000000'),
+    organization_key = COALESCE(organization_key, '00000000-0000-0000-0000-000000000000'),
+    external_accounting_id = COALESCE(external_accounting_id, ''),
+    name = COALESCE(name, ''),
+    description = COALESCE(description, ''),
+    currency = COALESCE(currency, ''),
+    value = COALESCE(value, 0),
+    service_item_type = COALESCE(service_item_type, 0),
+    service_value_type = COALESCE(service_value_type, 0),
+    created = COALESCE(created, 0),
+    updated = COALESCE(updated, 0),
+    expenses = COALESCE(expenses, '{}');
+DELETE FROM service_items a USING (
+    SELECT ctid, row_number() OVER (PARTITION BY key ORDER BY updated DESC, created DESC) AS n FROM service_items
+) d WHERE a.ctid = d.ctid AND d.n > 1;
+ALTER TABLE service_items
+    ADD PRIMARY KEY (key),
+    ALTER COLUMN owner_key SET NOT NULL,
+    ALTER COLUMN organization_key SET NOT NULL,
+    ALTER COLUMN external_accounting_id SET NOT NULL,
+    ALTER COLUMN name SET NOT NULL,
+    ALTER COLUMN description SET NOT NULL,
+    ALTER COLUMN currency SET NOT NULL,
+    ALTER COLUMN value SET NOT NULL,
+    ALTER COLUMN service_item_type SET NOT NULL,
+    ALTER COLUMN service_value_type SET NOT NULL,
+    ALTER COLUMN created SET NOT NULL,
+    ALTER COLUMN updated SET NOT NULL,
+    ALTER COLUMN expenses SET NOT NULL;
+
+-- projects
+UPDATE projects SET key = gen_random_uuid() WHERE key IS NULL;
+UPDATE projects SET
+    owner_key = COALESCE(owner_key, '00000000-0000-0000-0000-000000000000'),
+    organization_key = COALESCE(organization_key, '00000000-0000-0000-0000-000000000000'),
+    name = COALESCE(name, ''),
+    description = COALESCE(description, ''),
+    tags = COALESCE(tags, ''),
+    estimated_quarter_days = COALESCE(estimated_quarter_days, 0),
+    start = COALESCE(start, 0),
+    due = COALESCE(due, 0),
+    created = COALESCE(created, 0),
+    updated = COALESCE(updated, 0);
+DELETE FROM projects a USING (
+    SELECT ctid, row_number() OVER (PARTITION BY key ORDER BY updated DESC, created DESC) AS n FROM projects
+) d WHERE a.ctid = d.ctid AND d.n > 1;
+ALTER TABLE projects
+    ADD PRIMARY KEY (key),
+    ALTER COLUMN owner_key SET NOT NULL,
+    ALTER COLUMN organization_key SET NOT NULL,
+    ALTER COLUMN name SET NOT NULL,
+    ALTER COLUMN description SET NOT NULL,
+    ALTER COLUMN tags SET NOT NULL,
+    ALTER COLUMN estimated_quarter_days SET NOT NULL,
+    ALTER COLUMN start SET NOT NULL,
+    ALTER COLUMN due SET NOT NULL,
+    ALTER COLUMN created SET NOT NULL,
+    ALTER COLUMN updated SET NOT NULL;
+
+-- mile_stones
+UPDATE mile_stones SET key = gen_random_uuid() WHERE key IS NULL;
+UPDATE mile_stones SET
+    owner_key = COALESCE(owner_key, '00000000-0000-0000-0000-000000000000'),
+    organization_key = COALESCE(organization_key, '00000000-0000-0000-0000-000000000000'),
+    project_key = COALESCE(project_key, '00000000-0000-0000-0000-000000000000'),
+    name = COALESCE(name, ''),
+    description = COALESCE(description, ''),
+    tags = COALESCE(tags, ''),
+    estimated_quarter_days = COALESCE(estimated_quarter_days, 0),
+    start = COALESCE(start, 0),
+    due = COALESCE(due, 0),
+    created = COALESCE(created, 0),
+    updated = COALESCE(updated, 0);
+DELETE FROM mile_stones a USING (
+    SELECT ctid, row_number() OVER (PARTITION BY key ORDER BY updated DESC, created DESC) AS n FROM mile_stones
+) d WHERE a.ctid = d.ctid AND d.n > 1;
+ALTER TABLE mile_stones
+    ADD PRIMARY KEY (key),
+    ALTER COLUMN owner_key SET NOT NULL,
+    ALTER COLUMN organization_key SET NOT NULL,
+    ALTER COLUMN project_key SET NOT NULL,
+    ALTER COLUMN name SET NOT NULL,
+    ALTER COLUMN description SET NOT NULL,
+    ALTER COLUMN tags SET NOT NULL,
+    ALTER COLUMN estimated_quarter_days SET NOT NULL,
+    ALTER COLUMN start SET NOT NULL,
+    ALTER COLUMN due SET NOT NULL,
+    ALTER COLUMN created SET NOT NULL,
+    ALTER COLUMN updated SET NOT NULL;
+
+-- tasks
+UPDATE tasks SET key = gen_random_uuid() WHERE key IS NULL;
+UPDATE tasks SET
+    owner_key = COALESCE(owner_key, '00000000-0000-0000-0000-000000000000'),
+    organization_key = COALESCE(organization_key, '00000000-0000-0000-0000-000000000000'),
+    project_key = COALESCE(project_key, '00000000-0000-0000-0000-000000000000'),
+    assignee_key = COALESCE(assignee_key, '00000000-0000-0000-0000-000000000000'),
+    name = COALESCE(name, ''),
+    description = COALESCE(description, ''),
+    tags = COALESCE(tags, ''),
+    status = COALESCE(status, 0),
+    estimated_quarter_days = COALESCE(estimated_quarter_days, 0),
+    start = COALESCE(start, 0),
+    due = COALESCE(due, 0),
+    created = COALESCE(created, 0),
+    updated = COALESCE(updated, 0);
+DELETE FROM tasks a USING (
+    SELECT ctid, row_number() OVER (PARTITION BY key ORDER BY updated DESC, created DESC) AS n FROM tasks
+) d WHERE a.ctid = d.ctid AND d.n > 1;
+ALTER TABLE tasks
+    ADD PRIMARY KEY (key),
+    ALTER COLUMN owner_key SET NOT NULL,
+    ALTER COLUMN organization_key SET NOT NULL,
+    ALTER COLUMN project_key SET NOT NULL,
+    ALTER COLUMN assignee_key SET NOT NULL,
+    ALTER COLUMN name SET NOT NULL,
+    ALTER COLUMN description SET NOT NULL,
+    ALTER COLUMN tags SET NOT NULL,
+    ALTER COLUMN status SET NOT NULL,
+    ALTER COLUMN estimated_quarter_days SET NOT NULL,
+    ALTER COLUMN start SET NOT NULL,
+    ALTER COLUMN due SET NOT NULL,
+    ALTER COLUMN created SET NOT NULL,
+    ALTER COLUMN updated SET NOT NULL;
+
+-- boards
+UPDATE boards SET key = gen_random_uuid() WHERE key IS NULL;
+UPDATE boards SET
+    owner_key = COALESCE(owner_key, '00000000-0000-0000-0000-000000000000'),
+    organization_key = COALESCE(organization_key, '00000000-0000-0000-0000-000000000000'),
+    name = COALESCE(name, ''),
+    description = COALESCE(description, ''),
+    filter = COALESCE(filter, ''),
+    created = COALESCE(created, 0),
+    updated = COALESCE(updated, 0),
+    columns = COALESCE(columns, '{}'),
+    lanes = COALESCE(lanes, '{}');
+DELETE FROM boards a USING (
+    SELECT ctid, row_number() OVER (PARTITION BY key ORDER BY updated DESC, created DESC) AS n FROM boards
+) d WHERE a.ctid = d.ctid AND d.n > 1;
+ALTER TABLE boards
+    ADD PRIMARY KEY (key),
+    ALTER COLUMN owner_key SET NOT NULL,
+    ALTER COLUMN organization_key SET NOT NULL,
+    ALTER COLUMN name SET NOT NULL,
+    ALTER COLUMN description SET NOT NULL,
+    ALTER COLUMN filter SET NOT NULL,
+    ALTER COLUMN created SET NOT NULL,
+    ALTER COLUMN updated SET NOT NULL,
+    ALTER COLUMN columns SET NOT NULL,
+    ALTER COLUMN lanes SET NOT NULL;
+
+-- entitys
+UPDATE entitys SET key = gen_random_uuid() WHERE key IS NULL;
+UPDATE entitys SET
+    owner_key = COALESCE(owner_key, '00000000-0000-0000-0000-000000000000'),
+    organization_key = COALESCE(organization_key, '00000000-0000-0000-0000-000000000000'),
+    external_accounting_id = COALESCE(external_accounting_id, ''),
+    name = COALESCE(name, ''),
+    description = COALESCE(description, ''),
+    matrix_room_url = COALESCE(matrix_room_url, ''),
+    web_url = COALESCE(web_url, ''),
+    avatar_url = COALESCE(avatar_url, ''),
+    address_primary = COALESCE(address_primary, ''),
+    address_unit = COALESCE(address_unit, ''),
+    city = COALESCE(city, ''),
+    state = COALESCE(state, ''),
+    zip_code = COALESCE(zip_code, ''),
+    country = COALESCE(country, ''),
+    entity_type = COALESCE(entity_type, 0),
+    created = COALESCE(created, 0),
+    updated = COALESCE(updated, 0);
+DELETE FROM entitys a USING (
+    SELECT ctid, row_number() OVER (PARTITION BY key ORDER BY updated DESC, created DESC) AS n FROM entitys
+) d WHERE a.ctid = d.ctid AND d.n > 1;
+ALTER TABLE entitys
+    ADD PRIMARY KEY (key),
+    ALTER COLUMN owner_key SET NOT NULL,
+    ALTER COLUMN organization_key SET NOT NULL,
+    ALTER COLUMN external_accounting_id SET NOT NULL,
+    ALTER COLUMN name SET NOT NULL,
+    ALTER COLUMN description SET NOT NULL,
+    ALTER COLUMN matrix_room_url SET NOT NULL,
+    ALTER COLUMN web_url SET NOT NULL,
+    ALTER COLUMN avatar_url SET NOT NULL,
+    ALTER COLUMN address_primary SET NOT NULL,
+    ALTER COLUMN address_unit SET NOT NULL,
+    ALTER COLUMN city SET NOT NULL,
+    ALTER COLUMN state SET NOT NULL,
+    ALTER COLUMN zip_code SET NOT NULL,
+    ALTER COLUMN country SET NOT NULL,
+    ALTER COLUMN entity_type SET NOT NULL,
+    ALTER COLUMN created SET NOT NULL,
+    ALTER COLUMN updated SET NOT NULL;
+
+-- contacts, which take the organization of the entity they hang off
+UPDATE contacts SET key = gen_random_uuid() WHERE key IS NULL;
+UPDATE contacts c SET organization_key = e.organization_key
+    FROM entitys e WHERE e.key = c.entity_key AND c.organization_key IS NULL;
+UPDATE contacts SET
+    organization_key = COALESCE(organization_key, '00000000-0000-0000-0000-000000000000'),
+    entity_key = COALESCE(entity_key, '00000000-0000-0000-0000-000000000000'),
+    external_accounting_id = COALESCE(external_accounting_id, ''),
+    first_name = COALESCE(first_name, ''),
+    middle_initial = COALESCE(middle_initial, ''),
+    last_name = COALESCE(last_name, ''),
+    description = COALESCE(description, ''),
+    position = COALESCE(position, ''),
+    email = COALESCE(email, ''),
+    phone = COALESCE(phone, ''),
+    secondary_email = COALESCE(secondary_email, ''),
+    secondary_phone = COALESCE(secondary_phone, ''),
+    matrix_user_id = COALESCE(matrix_user_id, ''),
+    web_url = COALESCE(web_url, ''),
+    avatar_url = COALESCE(avatar_url, ''),
+    address_primary = COALESCE(address_primary, ''),
+    address_unit = COALESCE(address_unit, ''),
+    city = COALESCE(city, ''),
+    state = COALESCE(state, ''),
+    zip_code = COALESCE(zip_code, ''),
+    country = COALESCE(country, ''),
+    created = COALESCE(created, 0),
+    updated = COALESCE(updated, 0),
+    social_urls = COALESCE(social_urls, '{}');
+DELETE FROM contacts a USING (
+    SELECT ctid, row_number() OVER (PARTITION BY key ORDER BY updated DESC, created DESC) AS n FROM contacts
+) d WHERE a.ctid = d.ctid AND d.n > 1;
+ALTER TABLE contacts
+    ADD PRIMARY KEY (key),
+    ALTER COLUMN entity_key SET NOT NULL,
+    ALTER COLUMN organization_key SET NOT NULL,
+    ALTER COLUMN external_accounting_id SET NOT NULL,
+    ALTER COLUMN first_name SET NOT NULL,
+    ALTER COLUMN middle_initial SET NOT NULL,
+    ALTER COLUMN last_name SET NOT NULL,
+    ALTER COLUMN description SET NOT NULL,
+    ALTER COLUMN position SET NOT NULL,
+    ALTER COLUMN email SET NOT NULL,
+    ALTER COLUMN phone SET NOT NULL,
+    ALTER COLUMN secondary_email SET NOT NULL,
+    ALTER COLUMN secondary_phone SET NOT NULL,
+    ALTER COLUMN matrix_user_id SET NOT NULL,
+    ALTER COLUMN web_url SET NOT NULL,
+    ALTER COLUMN avatar_url SET NOT NULL,
+    ALTER COLUMN address_primary SET NOT NULL,
+    ALTER COLUMN address_unit SET NOT NULL,
+    ALTER COLUMN city SET NOT NULL,
+    ALTER COLUMN state SET NOT NULL,
+    ALTER COLUMN zip_code SET NOT NULL,
+    ALTER COLUMN country SET NOT NULL,
+    ALTER COLUMN created SET NOT NULL,
+    ALTER COLUMN updated SET NOT NULL,
+    ALTER COLUMN social_urls SET NOT NULL;
+
+-- rooms
+UPDATE rooms SET key = gen_random_uuid() WHERE key IS NULL;
+UPDATE rooms SET
+    owner_key = COALESCE(owner_key, '00000000-0000-0000-0000-000000000000'),
+    organization_key = COALESCE(organization_key, '00000000-0000-0000-0000-000000000000'),
+    name = COALESCE(name, ''),
+    description = COALESCE(description, ''),
+    matrix_room_url = COALESCE(matrix_room_url, ''),
+    matrix_room_id = COALESCE(matrix_room_id, ''),
+    message_types = COALESCE(message_types, 0),
+    alert_level = COALESCE(alert_level, 0),
+    created = COALESCE(created, 0),
+    updated = COALESCE(updated, 0);
+DELETE FROM rooms a USING (
+    SELECT ctid, row_number() OVER (PARTITION BY key ORDER BY updated DESC, created DESC) AS n FROM rooms
+) d WHERE a.ctid = d.ctid AND d.n > 1;
+ALTER TABLE rooms
+    ADD PRIMARY KEY (key),
+    ALTER COLUMN owner_key SET NOT NULL,
+    ALTER COLUMN organization_key SET NOT NULL,
+    ALTER COLUMN name SET NOT NULL,
+    ALTER COLUMN description SET NOT NULL,
+    ALTER COLUMN matrix_room_url SET NOT NULL,
+    ALTER COLUMN matrix_room_id SET NOT NULL,
+    ALTER COLUMN message_types SET NOT NULL,
+    ALTER COLUMN alert_level SET NOT NULL,
+    ALTER COLUMN created SET NOT NULL,
+    ALTER COLUMN updated SET NOT NULL;
+
+-- akaunting_options
+UPDATE akaunting_options SET key = gen_random_uuid() WHERE key IS NULL;
+UPDATE akaunting_options SET
+    owner_key = COALESCE(owner_key, '00000000-0000-0000-0000-000000000000'),
+    organization_key = COALESCE(organization_key, '00000000-0000-0000-0000-000000000000'),
+    user_name = COALESCE(user_name, ''),
+    user_pass = COALESCE(user_pass, ''),
+    akaunting_domain = COALESCE(akaunting_domain, ''),
+    akaunting_company_id = COALESCE(akaunting_company_id, ''),
+    last_sync = COALESCE(last_sync, 0),
+    created = COALESCE(created, 0),
+    updated = COALESCE(updated, 0),
+    organization_data = COALESCE(organization_data, false),
+    employee_data = COALESCE(employee_data, false),
+    client_data = COALESCE(client_data, false),
+    vendor_data = COALESCE(vendor_data, false),
+    item_data = COALESCE(item_data, false),
+    invoice_data = COALESCE(invoice_data, false),
+    allow_post = COALESCE(allow_post, false);
+DELETE FROM akaunting_options a USING (
+    SELECT ctid, row_number() OVER (PARTITION BY key ORDER BY updated DESC, created DESC) AS n FROM akaunting_options
+) d WHERE a.ctid = d.ctid AND d.n > 1;
+ALTER TABLE akaunting_options
+    ADD PRIMARY KEY (key),
+    ALTER COLUMN owner_key SET NOT NULL,
+    ALTER COLUMN organization_key SET NOT NULL,
+    ALTER COLUMN user_name SET NOT NULL,
+    ALTER COLUMN user_pass SET NOT NULL,
+    ALTER COLUMN akaunting_domain SET NOT NULL,
+    ALTER COLUMN akaunting_company_id SET NOT NULL,
+    ALTER COLUMN last_sync SET NOT NULL,
+    ALTER COLUMN created SET NOT NULL,
+    ALTER COLUMN updated SET NOT NULL,
+    ALTER COLUMN organization_data SET NOT NULL,
+    ALTER COLUMN employee_data SET NOT NULL,
+    ALTER COLUMN client_data SET NOT NULL,
+    ALTER COLUMN vendor_data SET NOT NULL,
+    ALTER COLUMN item_data SET NOT NULL,
+    ALTER COLUMN invoice_data SET NOT NULL,
+    ALTER COLUMN allow_post SET NOT NULL;
+
+-- foreign keys
+ALTER TABLE users ADD CONSTRAINT users_organization_key_fkey
+    FOREIGN KEY (organization_key) REFERENCES organization (key) ON DELETE CASCADE NOT VALID;
+ALTER TABLE files ADD CONSTRAINT files_organization_key_fkey
+    FOREIGN KEY (organization_key) REFERENCES organization (key) ON DELETE CASCADE NOT VALID;
+ALTER TABLE notes ADD CONSTRAINT notes_organization_key_fkey
+    FOREIGN KEY (organization_key) REFERENCES organization (key) ON DELETE CASCADE NOT VALID;
+ALTER TABLE service_items ADD CONSTRAINT service_items_organization_key_fkey
+    FOREIGN KEY (organization_key) REFERENCES organization (key) ON DELETE CASCADE NOT VALID;
+ALTER TABLE projects ADD CONSTRAINT projects_organization_key_fkey
+    FOREIGN KEY (organization_key) REFERENCES organization (key) ON DELETE CASCADE NOT VALID;
+ALTER TABLE mile_stones ADD CONSTRAINT mile_stones_organization_key_fkey
+    FOREIGN KEY (organization_key) REFERENCES organization (key) ON DELETE CASCADE NOT VALID;
+ALTER TABLE tasks ADD CONSTRAINT tasks_organization_key_fkey
+    FOREIGN KEY (organization_key) REFERENCES organization (key) ON DELETE CASCADE NOT VALID;
+ALTER TABLE boards ADD CONSTRAINT boards_organization_key_fkey
+    FOREIGN KEY (organization_key) REFERENCES organization (key) ON DELETE CASCADE NOT VALID;
+ALTER TABLE entitys ADD CONSTRAINT entitys_organization_key_fkey
+    FOREIGN KEY (organization_key) REFERENCES organization (key) ON DELETE CASCADE NOT VALID;
+ALTER TABLE contacts ADD CONSTRAINT contacts_organization_key_fkey
+    FOREIGN KEY (organization_key) REFERENCES organization (key) ON DELETE CASCADE NOT VALID;
+ALTER TABLE rooms ADD CONSTRAINT rooms_organization_key_fkey
+    FOREIGN KEY (organization_key) REFERENCES organization (key) ON DELETE CASCADE NOT VALID;
+ALTER TABLE akaunting_options ADD CONSTRAINT akaunting_options_organization_key_fkey
+    FOREIGN KEY (organization_key) REFERENCES organization (key) ON DELETE CASCADE NOT VALID;
+ALTER TABLE mile_stones ADD CONSTRAINT mile_stones_project_key_fkey
+    FOREIGN KEY (project_key) REFERENCES projects (key) ON DELETE CASCADE NOT VALID;
+ALTER TABLE tasks ADD CONSTRAINT tasks_project_key_fkey
+    FOREIGN KEY (project_key) REFERENCES projects (key) ON DELETE CASCADE NOT VALID;
+ALTER TABLE contacts ADD CONSTRAINT contacts_entity_key_fkey
+    FOREIGN KEY (entity_key) REFERENCES entitys (key) ON DELETE CASCADE NOT VALID;
+
+-- indexes
+CREATE INDEX IF NOT EXISTS users_organization_key_idx ON users (organization_key);
+CREATE INDEX IF NOT EXISTS files_organization_key_idx ON files (organization_key);
+CREATE INDEX IF NOT EXISTS notes_organization_key_idx ON notes (organization_key);
+CREATE INDEX IF NOT EXISTS service_items_organization_key_idx ON service_items (organization_key);
+CREATE INDEX IF NOT EXISTS projects_organization_key_idx ON projects (organization_key);
+CREATE INDEX IF NOT EXISTS mile_stones_organization_key_idx ON mile_stones (organization_key);
+CREATE INDEX IF NOT EXISTS tasks_organization_key_idx ON tasks (organization_key);
+CREATE INDEX IF NOT EXISTS boards_organization_key_idx ON boards (organization_key);
+CREATE INDEX IF NOT EXISTS entitys_organization_key_idx ON entitys (organization_key);
+CREATE INDEX IF NOT EXISTS contacts_organization_key_idx ON contacts (organization_key);
+CREATE INDEX IF NOT EXISTS rooms_organization_key_idx ON rooms (organization_key);
+CREATE INDEX IF NOT EXISTS akaunting_options_organization_key_idx ON akaunting_options (organization_key);
+CREATE INDEX IF NOT EXISTS mile_stones_project_key_idx ON mile_stones (project_key);
+CREATE INDEX IF NOT EXISTS tasks_project_key_idx ON tasks (project_key);
+CREATE INDEX IF NOT EXISTS tasks_assignee_key_idx ON tasks (assignee_key);
+CREATE INDEX IF NOT EXISTS contacts_entity_key_idx ON contacts (entity_key);
+CREATE INDEX IF NOT EXISTS users_matrix_user_id_idx ON users (matrix_user_id);
+CREATE INDEX IF NOT EXISTS files_association_idx ON files (association_type, association_key);
+CREATE INDEX IF NOT EXISTS notes_association_idx ON notes (association_type, association_key);
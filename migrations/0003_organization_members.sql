@@ -0,0 +1,26 @@
+-- Role of each user inside each organization they belong to. Roles are stored
+-- as smallint, see member::Role for the mapping.
+CREATE TABLE IF NOT EXISTS organization_members (
+    key uuid PRIMARY KEY,
+    organization_key uuid NOT NULL REFERENCES organization (key) ON DELETE CASCADE,
+    user_key uuid NOT NULL REFERENCES users (key) ON DELETE CASCADE,
+    role smallint NOT NULL,
+    created bigint NOT NULL,
+    updated bigint NOT NULL,
+    UNIQUE (organization_key, user_key)
+);
+
+CREATE INDEX IF NOT EXISTS organization_members_user_key_idx ON organization_members (user_key);
+
+-- existing users become owners of the organization they created, members otherwise
+INSERT INTO organization_members (key, organization_key, user_key, role, created, updated)
+SELECT gen_random_uuid(), u.organization_key, u.key,
+    CASE WHEN o.owner_key = u.key THEN 0 ELSE 2 END,
+    extract(epoch from now())::bigint, 0
+FROM users u
+INNER JOIN organization o ON o.key = u.organization_key
+ON CONFLICT (organization_key, user_key) DO NOTHING;
+
+-- users.organization_key is only the organization the user last switched into now, deleting that
+-- organization must not take the user and their other memberships with it
+ALTER TABLE users DROP CONSTRAINT IF EXISTS users_organization_key_fkey;
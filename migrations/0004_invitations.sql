@@ -0,0 +1,17 @@
+-- Pending invitations into an organization. The token is single use, accepted
+-- stays 0 until someone redeems it and expires is a unix timestamp.
+CREATE TABLE IF NOT EXISTS invitations (
+    key uuid PRIMARY KEY,
+    organization_key uuid NOT NULL REFERENCES organization (key) ON DELETE CASCADE,
+    invited_by uuid NOT NULL REFERENCES users (key) ON DELETE CASCADE,
+    email text NOT NULL,
+    matrix_user_id text NOT NULL,
+    role smallint NOT NULL,
+    token text NOT NULL UNIQUE,
+    expires bigint NOT NULL,
+    accepted bigint NOT NULL DEFAULT 0,
+    created bigint NOT NULL,
+    updated bigint NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS invitations_organization_key_idx ON invitations (organization_key);
@@ -0,0 +1,18 @@
+-- Personal API tokens. Only a bcrypt hash of the secret is kept, the plain token is
+-- shown once when it is created. scope is api_token::TokenScope, expires and revoked
+-- are unix timestamps where 0 means never.
+CREATE TABLE IF NOT EXISTS api_tokens (
+    key uuid PRIMARY KEY,
+    organization_key uuid NOT NULL REFERENCES organization (key) ON DELETE CASCADE,
+    user_key uuid NOT NULL REFERENCES users (key) ON DELETE CASCADE,
+    name text NOT NULL,
+    secret_hash text NOT NULL,
+    scope smallint NOT NULL,
+    last_used bigint NOT NULL DEFAULT 0,
+    expires bigint NOT NULL DEFAULT 0,
+    revoked bigint NOT NULL DEFAULT 0,
+    created bigint NOT NULL,
+    updated bigint NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS api_tokens_user_key_idx ON api_tokens (user_key);
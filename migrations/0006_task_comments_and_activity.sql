@@ -0,0 +1,31 @@
+-- Discussion on tasks. parent_key is the comment being replied to, nil for a top level
+-- comment. Deleted comments keep their row so replies still have somewhere to hang.
+CREATE TABLE IF NOT EXISTS task_comments (
+    key uuid PRIMARY KEY,
+    organization_key uuid NOT NULL REFERENCES organization (key) ON DELETE CASCADE,
+    task_key uuid NOT NULL REFERENCES tasks (key) ON DELETE CASCADE,
+    parent_key uuid NOT NULL,
+    author_key uuid NOT NULL REFERENCES users (key) ON DELETE CASCADE,
+    body text NOT NULL,
+    edited boolean NOT NULL DEFAULT false,
+    deleted boolean NOT NULL DEFAULT false,
+    created bigint NOT NULL,
+    updated bigint NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS task_comments_task_key_idx ON task_comments (task_key);
+
+-- What happened to a task and who did it. kind is activity::ActivityKind, from_value and
+-- to_value are the before and after as text.
+CREATE TABLE IF NOT EXISTS task_activity (
+    key uuid PRIMARY KEY,
+    organization_key uuid NOT NULL REFERENCES organization (key) ON DELETE CASCADE,
+    task_key uuid NOT NULL REFERENCES tasks (key) ON DELETE CASCADE,
+    actor_key uuid NOT NULL REFERENCES users (key) ON DELETE CASCADE,
+    kind smallint NOT NULL,
+    from_value text NOT NULL,
+    to_value text NOT NULL,
+    created bigint NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS task_activity_task_key_idx ON task_activity (task_key);
@@ -0,0 +1,15 @@
+-- Links between tasks. task_key can't start until depends_on_key is finished (FinishToStart)
+-- or has started (StartToStart), kind is dependency::DependencyKind.
+CREATE TABLE IF NOT EXISTS task_dependencies (
+    key uuid PRIMARY KEY,
+    organization_key uuid NOT NULL REFERENCES organization (key) ON DELETE CASCADE,
+    task_key uuid NOT NULL REFERENCES tasks (key) ON DELETE CASCADE,
+    depends_on_key uuid NOT NULL REFERENCES tasks (key) ON DELETE CASCADE,
+    kind smallint NOT NULL,
+    created bigint NOT NULL,
+    updated bigint NOT NULL,
+    UNIQUE (task_key, depends_on_key),
+    CHECK (task_key <> depends_on_key)
+);
+
+CREATE INDEX IF NOT EXISTS task_dependencies_depends_on_key_idx ON task_dependencies (depends_on_key);
@@ -0,0 +1,18 @@
+-- A task can sit under another task in the same project, NULL for a top level task.
+ALTER TABLE tasks ADD COLUMN IF NOT EXISTS parent_key uuid REFERENCES tasks (key) ON DELETE CASCADE;
+
+CREATE INDEX IF NOT EXISTS tasks_parent_key_idx ON tasks (parent_key);
+
+-- Small steps inside a task that don't deserve a task of their own.
+CREATE TABLE IF NOT EXISTS checklist_items (
+    key uuid PRIMARY KEY,
+    organization_key uuid NOT NULL REFERENCES organization (key) ON DELETE CASCADE,
+    task_key uuid NOT NULL REFERENCES tasks (key) ON DELETE CASCADE,
+    name text NOT NULL,
+    done boolean NOT NULL DEFAULT false,
+    position int NOT NULL DEFAULT 0,
+    created bigint NOT NULL,
+    updated bigint NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS checklist_items_task_key_idx ON checklist_items (task_key);
@@ -0,0 +1,80 @@
+-- Task statuses belong to the organization now instead of a fixed enum in the code.
+-- category: 0 not started, 1 active, 2 done
+CREATE TABLE IF NOT EXISTS workflow_statuses (
+    key uuid PRIMARY KEY,
+    organization_key uuid NOT NULL,
+    name text NOT NULL,
+    color text NOT NULL,
+    category smallint NOT NULL DEFAULT 0,
+    position int NOT NULL DEFAULT 0,
+    created bigint NOT NULL,
+    updated bigint NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS workflow_statuses_organization_key_idx ON workflow_statuses (organization_key);
+
+-- Which moves between statuses are allowed. An organization without any rows here can move
+-- a task from any status to any other.
+CREATE TABLE IF NOT EXISTS workflow_transitions (
+    key uuid PRIMARY KEY,
+    organization_key uuid NOT NULL REFERENCES organization (key) ON DELETE CASCADE,
+    from_key uuid NOT NULL REFERENCES workflow_statuses (key) ON DELETE CASCADE,
+    to_key uuid NOT NULL REFERENCES workflow_statuses (key) ON DELETE CASCADE,
+    created bigint NOT NULL,
+    UNIQUE (from_key, to_key),
+    CHECK (from_key <> to_key)
+);
+
+CREATE INDEX IF NOT EXISTS workflow_transitions_organization_key_idx ON workflow_transitions (organization_key);
+
+-- Every existing organization starts with the eight statuses the enum had, in the same order
+-- and colors, so position lines up with the old smallint value. Tasks whose organization is
+-- gone (or the nil key 0002 gave them) get a set too, so every task has a status to point at.
+INSERT INTO workflow_statuses (key, organization_key, name, color, category, position, created, updated)
+SELECT gen_random_uuid(), o.key, d.name, d.color, d.category, d.position, extract(epoch from now())::bigint, 0
+FROM (SELECT key FROM organization UNION SELECT organization_key FROM tasks) o
+CROSS JOIN (VALUES
+    ('Wishlist', '#cc66ff85', 0, 0),
+    ('Todo', '#ff6a4a85', 0, 1),
+    ('Planning And Estimating', '#ffb26085', 0, 2),
+    ('In Queue', '#f9ff5b85', 0, 3),
+    ('In Progress', '#61d1d085', 1, 4),
+    ('To Review', '#658cff85', 1, 5),
+    ('In Reviewal', '#D2CAFF85', 1, 6),
+    ('Complete', '#64ff6385', 2, 7)
+) AS d (name, color, category, position)
+WHERE NOT EXISTS (SELECT 1 FROM workflow_statuses ws WHERE ws.organization_key = o.key);
+
+-- NOT VALID like the foreign keys in 0002, the statuses of orphaned tasks are kept
+ALTER TABLE workflow_statuses ADD CONSTRAINT workflow_statuses_organization_key_fkey
+    FOREIGN KEY (organization_key) REFERENCES organization (key) ON DELETE CASCADE NOT VALID;
+
+ALTER TABLE tasks ADD COLUMN IF NOT EXISTS status_key uuid REFERENCES workflow_statuses (key);
+
+-- a value the enum never had reads as Wishlist, the way the code fell back for it
+UPDATE tasks SET status = 0 WHERE status NOT BETWEEN 0 AND 7;
+
+UPDATE tasks t SET status_key = ws.key
+FROM workflow_statuses ws
+WHERE ws.organization_key = t.organization_key AND ws.position = t.status AND t.status_key IS NULL;
+
+ALTER TABLE tasks ALTER COLUMN status_key SET NOT NULL;
+ALTER TABLE tasks DROP COLUMN IF EXISTS status;
+
+CREATE INDEX IF NOT EXISTS tasks_status_key_idx ON tasks (status_key);
+
+-- Board lanes held enum names ("InProgress"), they hold status keys now.
+UPDATE boards b SET lanes = COALESCE((
+    SELECT array_agg(ws.key::text ORDER BY l.ord)
+    FROM unnest(b.lanes) WITH ORDINALITY AS l (name, ord)
+    JOIN workflow_statuses ws ON ws.organization_key = b.organization_key AND ws.position = CASE l.name
+        WHEN 'Wishlist' THEN 0
+        WHEN 'Todo' THEN 1
+        WHEN 'PlanningAndEstimating' THEN 2
+        WHEN 'InQueue' THEN 3
+        WHEN 'InProgress' THEN 4
+        WHEN 'ToReview' THEN 5
+        WHEN 'InReviewal' THEN 6
+        WHEN 'Complete' THEN 7
+    END
+), '{}');
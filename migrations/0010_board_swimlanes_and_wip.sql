@@ -0,0 +1,5 @@
+-- swimlane: 0 none, 1 assignee, 2 project, 3 tag
+ALTER TABLE boards ADD COLUMN IF NOT EXISTS swimlane smallint NOT NULL DEFAULT 0;
+
+-- One limit per entry in lanes, in the same order. 0 (or a missing entry) means no limit.
+ALTER TABLE boards ADD COLUMN IF NOT EXISTS wip_limits int [] NOT NULL DEFAULT '{}';
@@ -0,0 +1,12 @@
+-- Where a task sits within its status column, lowest first.
+ALTER TABLE tasks ADD COLUMN IF NOT EXISTS rank int NOT NULL DEFAULT 0;
+
+-- existing cards keep the order they were already shown in, oldest at the top
+UPDATE tasks t SET rank = r.rank
+FROM (
+    SELECT key, row_number() OVER (PARTITION BY organization_key, status_key ORDER BY created) - 1 AS rank
+    FROM tasks
+) r
+WHERE r.key = t.key;
+
+CREATE INDEX IF NOT EXISTS tasks_status_rank_idx ON tasks (organization_key, status_key, rank);
@@ -0,0 +1,22 @@
+-- A repeating task keeps one rule, attached to its latest occurrence. The scheduler creates the
+-- next task when that one is finished or next_start comes around, then moves the rule onto it.
+-- frequency: 0 daily, 1 weekly, 2 monthly. until and count are 0 when the series doesn't end.
+CREATE TABLE IF NOT EXISTS task_recurrences (
+    key uuid PRIMARY KEY,
+    organization_key uuid NOT NULL REFERENCES organization (key) ON DELETE CASCADE,
+    task_key uuid NOT NULL UNIQUE REFERENCES tasks (key) ON DELETE CASCADE,
+    frequency smallint NOT NULL DEFAULT 0,
+    interval int NOT NULL DEFAULT 1 CHECK (interval > 0),
+    until bigint NOT NULL DEFAULT 0,
+    count int NOT NULL DEFAULT 0,
+    -- the first occurrence's date, later ones are counted from here so a monthly task on the
+    -- 31st comes back on the 31st after a short month
+    dtstart bigint NOT NULL,
+    occurrences int NOT NULL DEFAULT 1,
+    -- 0 once the series is over
+    next_start bigint NOT NULL DEFAULT 0,
+    created bigint NOT NULL,
+    updated bigint NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS task_recurrences_next_start_idx ON task_recurrences (next_start) WHERE next_start > 0;
@@ -0,0 +1,20 @@
+-- Hours worked on a task. stopped is 0 while the timer is still running, a user only has one of those.
+-- entity_key and service_item_key say who the time is billed to and at what rate.
+CREATE TABLE IF NOT EXISTS time_entries (
+    key uuid PRIMARY KEY,
+    organization_key uuid NOT NULL REFERENCES organization (key) ON DELETE CASCADE,
+    user_key uuid NOT NULL REFERENCES users (key) ON DELETE CASCADE,
+    task_key uuid NOT NULL REFERENCES tasks (key) ON DELETE CASCADE,
+    entity_key uuid REFERENCES entitys (key) ON DELETE SET NULL,
+    service_item_key uuid REFERENCES service_items (key) ON DELETE SET NULL,
+    started bigint NOT NULL,
+    stopped bigint NOT NULL DEFAULT 0,
+    billable boolean NOT NULL DEFAULT true,
+    note text NOT NULL DEFAULT '',
+    created bigint NOT NULL,
+    updated bigint NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS time_entries_task_key_idx ON time_entries (task_key);
+CREATE INDEX IF NOT EXISTS time_entries_entity_key_idx ON time_entries (entity_key);
+CREATE UNIQUE INDEX IF NOT EXISTS time_entries_running_idx ON time_entries (user_key) WHERE stopped = 0;
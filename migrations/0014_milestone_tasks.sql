@@ -0,0 +1,8 @@
+-- A task can count towards one milestone of its project.
+ALTER TABLE tasks ADD COLUMN IF NOT EXISTS milestone_key uuid REFERENCES mile_stones (key) ON DELETE SET NULL;
+
+CREATE INDEX IF NOT EXISTS tasks_milestone_key_idx ON tasks (milestone_key);
+
+-- The last outcome announced to the organization, so a hit or miss is only posted once.
+-- 0 open, 1 hit, 2 missed
+ALTER TABLE mile_stones ADD COLUMN IF NOT EXISTS outcome smallint NOT NULL DEFAULT 0;
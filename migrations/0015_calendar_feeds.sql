@@ -0,0 +1,11 @@
+-- Secret .ics feed links, one per person per organization. Like api tokens only a bcrypt hash of
+-- the secret is kept, the link is shown once and making a new one replaces the old.
+CREATE TABLE IF NOT EXISTS calendar_feeds (
+    key uuid PRIMARY KEY,
+    organization_key uuid NOT NULL REFERENCES organization (key) ON DELETE CASCADE,
+    user_key uuid NOT NULL REFERENCES users (key) ON DELETE CASCADE,
+    secret_hash text NOT NULL,
+    created bigint NOT NULL,
+    updated bigint NOT NULL,
+    UNIQUE (organization_key, user_key)
+);
@@ -0,0 +1,13 @@
+-- How much work each member can take on. working_days is a bitmask of weekdays, Monday is 1 up to
+-- Sunday 64, and quarter_days is how much of each of those days they work. Members without a row
+-- work Monday to Friday, full days.
+CREATE TABLE IF NOT EXISTS member_capacities (
+    key uuid PRIMARY KEY,
+    organization_key uuid NOT NULL REFERENCES organization (key) ON DELETE CASCADE,
+    user_key uuid NOT NULL REFERENCES users (key) ON DELETE CASCADE,
+    working_days smallint NOT NULL DEFAULT 31,
+    quarter_days smallint NOT NULL DEFAULT 4 CHECK (quarter_days >= 0 AND quarter_days <= 4),
+    created bigint NOT NULL,
+    updated bigint NOT NULL,
+    UNIQUE (organization_key, user_key)
+);
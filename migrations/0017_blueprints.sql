@@ -0,0 +1,14 @@
+-- Project templates. content is the JSON of a project with its milestones, tasks, task
+-- dependencies, boards and notes as they were when it was saved, see blueprint::BlueprintContent.
+CREATE TABLE IF NOT EXISTS blueprints (
+    key uuid PRIMARY KEY,
+    organization_key uuid NOT NULL REFERENCES organization (key) ON DELETE CASCADE,
+    owner_key uuid NOT NULL,
+    name text NOT NULL,
+    description text NOT NULL DEFAULT '',
+    content text NOT NULL,
+    created bigint NOT NULL,
+    updated bigint NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS blueprints_organization_key_idx ON blueprints (organization_key);
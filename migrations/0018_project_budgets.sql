@@ -0,0 +1,25 @@
+-- What a project is worth. budget_type 0 is a fixed price of amount, 1 is billed hourly at amount
+-- per hour up to hours (0 for no cap). entity_key is the client the project is invoiced to.
+CREATE TABLE IF NOT EXISTS project_budgets (
+    key uuid PRIMARY KEY,
+    organization_key uuid NOT NULL REFERENCES organization (key) ON DELETE CASCADE,
+    project_key uuid NOT NULL REFERENCES projects (key) ON DELETE CASCADE,
+    entity_key uuid REFERENCES entitys (key) ON DELETE SET NULL,
+    budget_type smallint NOT NULL DEFAULT 0,
+    amount bigint NOT NULL DEFAULT 0,
+    hours integer NOT NULL DEFAULT 0,
+    currency varchar(16) NOT NULL DEFAULT 'USD',
+    created bigint NOT NULL,
+    updated bigint NOT NULL,
+    UNIQUE (project_key)
+);
+
+CREATE INDEX IF NOT EXISTS project_budgets_organization_key_idx ON project_budgets (organization_key);
+
+-- The service items a project is sold as. Invoice lines for these items count towards the project.
+CREATE TABLE IF NOT EXISTS project_service_items (
+    project_key uuid NOT NULL REFERENCES projects (key) ON DELETE CASCADE,
+    service_item_key uuid NOT NULL REFERENCES service_items (key) ON DELETE CASCADE,
+    organization_key uuid NOT NULL REFERENCES organization (key) ON DELETE CASCADE,
+    PRIMARY KEY (project_key, service_item_key)
+);
@@ -0,0 +1,76 @@
+-- Full-text search. Each searchable table keeps a generated tsvector with its name weighted above
+-- the rest of its text, and search_documents puts them all behind one view. The view is a plain
+-- UNION ALL so the organization_key and tsvector conditions reach each table's GIN index.
+ALTER TABLE projects ADD COLUMN IF NOT EXISTS search tsvector GENERATED ALWAYS AS (
+    setweight(to_tsvector('english', coalesce(name, '')), 'A') ||
+    setweight(to_tsvector('english', coalesce(description, '') || ' ' || coalesce(tags, '')), 'B')
+) STORED;
+CREATE INDEX IF NOT EXISTS projects_search_idx ON projects USING GIN (search);
+
+ALTER TABLE tasks ADD COLUMN IF NOT EXISTS search tsvector GENERATED ALWAYS AS (
+    setweight(to_tsvector('english', coalesce(name, '')), 'A') ||
+    setweight(to_tsvector('english', coalesce(description, '') || ' ' || coalesce(tags, '')), 'B')
+) STORED;
+CREATE INDEX IF NOT EXISTS tasks_search_idx ON tasks USING GIN (search);
+
+ALTER TABLE mile_stones ADD COLUMN IF NOT EXISTS search tsvector GENERATED ALWAYS AS (
+    setweight(to_tsvector('english', coalesce(name, '')), 'A') ||
+    setweight(to_tsvector('english', coalesce(description, '') || ' ' || coalesce(tags, '')), 'B')
+) STORED;
+CREATE INDEX IF NOT EXISTS mile_stones_search_idx ON mile_stones USING GIN (search);
+
+ALTER TABLE notes ADD COLUMN IF NOT EXISTS search tsvector GENERATED ALWAYS AS (
+    setweight(to_tsvector('english', coalesce(title, '')), 'A') ||
+    setweight(to_tsvector('english', coalesce(content, '')), 'B')
+) STORED;
+CREATE INDEX IF NOT EXISTS notes_search_idx ON notes USING GIN (search);
+
+ALTER TABLE entitys ADD COLUMN IF NOT EXISTS search tsvector GENERATED ALWAYS AS (
+    setweight(to_tsvector('english', coalesce(name, '')), 'A') ||
+    setweight(to_tsvector('english', coalesce(description, '') || ' ' || coalesce(web_url, '') || ' ' || coalesce(city, '') || ' ' || coalesce(state, '') || ' ' || coalesce(country, '')), 'B')
+) STORED;
+CREATE INDEX IF NOT EXISTS entitys_search_idx ON entitys USING GIN (search);
+
+ALTER TABLE contacts ADD COLUMN IF NOT EXISTS search tsvector GENERATED ALWAYS AS (
+    setweight(to_tsvector('english', coalesce(first_name, '') || ' ' || coalesce(last_name, '')), 'A') ||
+    setweight(to_tsvector('english', coalesce(position, '') || ' ' || coalesce(email, '') || ' ' || coalesce(secondary_email, '') || ' ' || coalesce(description, '') || ' ' || coalesce(city, '')), 'B')
+) STORED;
+CREATE INDEX IF NOT EXISTS contacts_search_idx ON contacts USING GIN (search);
+
+ALTER TABLE service_items ADD COLUMN IF NOT EXISTS search tsvector GENERATED ALWAYS AS (
+    setweight(to_tsvector('english', coalesce(name, '')), 'A') ||
+    setweight(to_tsvector('english', coalesce(description, '')), 'B')
+) STORED;
+CREATE INDEX IF NOT EXISTS service_items_search_idx ON service_items USING GIN (search);
+
+ALTER TABLE files ADD COLUMN IF NOT EXISTS search tsvector GENERATED ALWAYS AS (
+    setweight(to_tsvector('english', coalesce(name, '')), 'A') ||
+    setweight(to_tsvector('english', coalesce(description, '') || ' ' || coalesce(tags, '') || ' ' || coalesce(format, '')), 'B')
+) STORED;
+CREATE INDEX IF NOT EXISTS files_search_idx ON files USING GIN (search);
+
+-- body is the text a match is highlighted in
+CREATE OR REPLACE VIEW search_documents AS
+    SELECT 'project' AS kind, key, organization_key, coalesce(name, '') AS title,
+        coalesce(description, '') || ' ' || coalesce(tags, '') AS body, search FROM projects
+    UNION ALL
+    SELECT 'task', key, organization_key, coalesce(name, ''),
+        coalesce(description, '') || ' ' || coalesce(tags, ''), search FROM tasks
+    UNION ALL
+    SELECT 'milestone', key, organization_key, coalesce(name, ''),
+        coalesce(description, '') || ' ' || coalesce(tags, ''), search FROM mile_stones
+    UNION ALL
+    SELECT 'note', key, organization_key, coalesce(title, ''), coalesce(content, ''), search FROM notes
+    UNION ALL
+    SELECT 'entity', key, organization_key, coalesce(name, ''),
+        coalesce(description, '') || ' ' || coalesce(web_url, '') || ' ' || coalesce(city, '') || ' ' || coalesce(state, '') || ' ' || coalesce(country, ''), search FROM entitys
+    UNION ALL
+    -- a contact is found in the organization of the entity it hangs off
+    SELECT 'contact', c.key, e.organization_key, trim(coalesce(c.first_name, '') || ' ' || coalesce(c.last_name, '')),
+        coalesce(c.position, '') || ' ' || coalesce(c.email, '') || ' ' || coalesce(c.secondary_email, '') || ' ' || coalesce(c.description, '') || ' ' || coalesce(c.city, ''), c.search
+    FROM contacts c JOIN entitys e ON e.key = c.entity_key
+    UNION ALL
+    SELECT 'service_item', key, organization_key, coalesce(name, ''), coalesce(description, ''), search FROM service_items
+    UNION ALL
+    SELECT 'file', key, organization_key, coalesce(name, ''),
+        coalesce(description, '') || ' ' || coalesce(tags, '') || ' ' || coalesce(format, ''), search FROM files;
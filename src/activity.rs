@@ -0,0 +1,225 @@
+use serde::{Deserialize, Serialize};
+use sqlx::pool::PoolConnection;
+use sqlx::Postgres;
+use uuid::Uuid;
+
+use tide::{http::mime, Request};
+
+use crate::error::AppError;
+use crate::task::Task;
+use crate::user::User;
+use crate::State;
+
+// SQL STUFF
+
+pub async fn get_task_activity(
+    conn: &mut PoolConnection<Postgres>,
+    task_key: uuid::Uuid,
+    organization_key: uuid::Uuid,
+) -> Result<Vec<Activity>, AppError> {
+    let records = sqlx::query!(
+        "select key, organization_key, task_key, actor_key, kind, from_value, to_value, created from task_activity where task_key = $1 AND organization_key = $2 order by created",
+        task_key,
+        organization_key
+    )
+    .fetch_all(conn)
+    .await?;
+    let mut activity = vec![];
+    for a in records {
+        activity.push(Activity {
+            key: a.key,
+            organization_key: a.organization_key,
+            task_key: a.task_key,
+            actor_key: a.actor_key,
+            kind: a.kind.into(),
+            from_value: a.from_value,
+            to_value: a.to_value,
+            created: a.created,
+        })
+    }
+    Ok(activity)
+}
+
+// The due date each task in a project had before it was first moved, only for tasks whose due date has moved
+pub async fn get_project_first_dues(
+    conn: &mut PoolConnection<Postgres>,
+    project_key: uuid::Uuid,
+    organization_key: uuid::Uuid,
+) -> Result<Vec<(Uuid, i64)>, AppError> {
+    let records = sqlx::query!(
+        "select distinct on (a.task_key) a.task_key, a.from_value from task_activity a
+        inner join tasks t on t.key = a.task_key
+        where t.project_key = $1 AND a.organization_key = $2 AND a.kind = $3
+        order by a.task_key, a.created",
+        project_key,
+        organization_key,
+        ActivityKind::DueMoved as i16
+    )
+    .fetch_all(conn)
+    .await?;
+    Ok(records
+        .into_iter()
+        .map(|a| (a.task_key, a.from_value.parse::<i64>().unwrap_or_default()))
+        .collect())
+}
+
+async fn insert_activity(conn: &mut PoolConnection<Postgres>, new_activity: &Activity) -> Result<(), AppError> {
+    sqlx::query!("INSERT INTO task_activity (key, organization_key, task_key, actor_key, kind, from_value, to_value, created) values($1, $2, $3, $4, $5, $6, $7, $8)",
+        new_activity.key,
+        new_activity.organization_key,
+        new_activity.task_key,
+        new_activity.actor_key,
+        new_activity.kind as i16,
+        &new_activity.from_value,
+        &new_activity.to_value,
+        new_activity.created,
+    )
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+// Compares a task before and after an update and writes a line to the timeline for each change we track
+pub async fn record_task_changes(
+    conn: &mut PoolConnection<Postgres>,
+    actor_key: uuid::Uuid,
+    before: &Task,
+    after: &Task,
+) -> Result<(), AppError> {
+    let mut changes = vec![];
+    if before.status_key != after.status_key {
+        // names rather than keys, so the timeline still reads right after a status is renamed or removed
+        let workflow = crate::workflow::get_workflow(conn, after.organization_key).await?;
+        changes.push((ActivityKind::StatusChanged, workflow.name(&before.status_key), workflow.name(&after.status_key)));
+    }
+    if before.assignee_key != after.assignee_key {
+        changes.push((ActivityKind::Reassigned, before.assignee_key.to_string(), after.assignee_key.to_string()));
+    }
+    if before.due != after.due {
+        changes.push((ActivityKind::DueMoved, before.due.to_string(), after.due.to_string()));
+    }
+    for (kind, from_value, to_value) in changes {
+        let a = Activity::new(after.organization_key, after.key, actor_key, kind, from_value, to_value);
+        insert_activity(conn, &a).await?;
+    }
+    Ok(())
+}
+
+// Route Stuff
+
+pub async fn get_by_task(req: Request<State>) -> tide::Result {
+    let u = match crate::user::user_or_error(&req) {
+        Ok(value) => value,
+        Err(_) => return Ok(tide::Redirect::new("/login").into()),
+    };
+    let task_key = crate::error::param_key(&req, "task_id")?;
+    let mut conn = req.state().db_pool.acquire().await?;
+    let activity = get_task_activity(&mut conn, task_key, u.organization_key).await?;
+    let j = serde_json::to_string(&activity).expect("To JSON");
+    Ok(tide::Response::builder(tide::StatusCode::Ok)
+        .content_type(mime::JSON)
+        .body(j)
+        .build())
+}
+
+// data types
+
+#[derive(PartialEq, Debug, Deserialize, Serialize, Clone, Copy, sqlx::Type)]
+pub enum ActivityKind {
+    StatusChanged,
+    Reassigned,
+    DueMoved,
+}
+
+impl Into<ActivityKind> for i16 {
+    fn into(self) -> ActivityKind {
+        match self {
+            0 => ActivityKind::StatusChanged,
+            1 => ActivityKind::Reassigned,
+            2 => ActivityKind::DueMoved,
+            _ => ActivityKind::StatusChanged,
+        }
+    }
+}
+
+impl From<ActivityKind> for i16 {
+    fn from(k: ActivityKind) -> Self {
+        match k {
+            ActivityKind::StatusChanged => 0,
+            ActivityKind::Reassigned => 1,
+            ActivityKind::DueMoved => 2,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct Activity {
+    pub key: uuid::Uuid,
+    pub organization_key: uuid::Uuid,
+    pub task_key: uuid::Uuid,
+    pub actor_key: uuid::Uuid,
+    pub kind: ActivityKind,
+    pub from_value: String,
+    pub to_value: String,
+    pub created: i64,
+}
+
+impl Activity {
+    pub fn new(
+        organization_key: uuid::Uuid,
+        task_key: uuid::Uuid,
+        actor_key: uuid::Uuid,
+        kind: ActivityKind,
+        from_value: String,
+        to_value: String,
+    ) -> Self {
+        let key = Uuid::new_v4();
+        let created = chrono::Utc::now().timestamp();
+        Self {
+            key,
+            organization_key,
+            task_key,
+            actor_key,
+            kind,
+            from_value,
+            to_value,
+            created,
+        }
+    }
+
+    // One line for the timeline on task.html, users resolve the keys we stored into names
+    pub fn describe(&self, users: &[User]) -> String {
+        let actor = user_name(users, &self.actor_key.to_string());
+        match self.kind {
+            ActivityKind::StatusChanged => format!("{} moved it from {} to {}", actor, self.from_value, self.to_value),
+            ActivityKind::Reassigned => format!(
+                "{} reassigned it from {} to {}",
+                actor,
+                user_name(users, &self.from_value),
+                user_name(users, &self.to_value)
+            ),
+            ActivityKind::DueMoved => format!("{} moved the due date from {} to {}", actor, day(&self.from_value), day(&self.to_value)),
+        }
+    }
+}
+
+fn user_name(users: &[User], key: &str) -> String {
+    if key == Uuid::nil().to_string() {
+        return "nobody".to_string();
+    }
+    match users.iter().find(|u| u.key.to_string() == key) {
+        Some(u) => u.to_string(),
+        None => "someone".to_string(),
+    }
+}
+
+fn day(timestamp: &str) -> String {
+    let timestamp = timestamp.parse::<i64>().unwrap_or_default();
+    if timestamp == 0 {
+        return "none".to_string();
+    }
+    match chrono::NaiveDateTime::from_timestamp_opt(timestamp, 0) {
+        Some(d) => d.format("%Y-%m-%d").to_string(),
+        None => "".to_string(),
+    }
+}
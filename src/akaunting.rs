@@ -1,13 +1,10 @@
 
-use std::ops::Mul;
-use std::str::FromStr;
 
 use base64::Engine;
 use base64::engine::GeneralPurposeConfig;
 use serde_derive::Deserialize;
 use serde_derive::Serialize;
 use serde_json::Value;
-use reqwest;
 use sqlx::pool::PoolConnection;
 use sqlx::Postgres;
 use serde_this_or_that::as_bool;
@@ -15,6 +12,7 @@ use tide::{http::mime, Request};
 use askama::Template;
 use tokio::join;
 use uuid::Uuid;
+use crate::error::AppError;
 use crate::common::BufferedBytesStream;
 use crate::entity::Entity;
 use crate::organization::{update_organization,get_organization};
@@ -22,10 +20,10 @@ use crate::service_item::ServiceItem;
 use crate::{State, user};
  
 
-async fn update_akaunting_options(conn: &mut PoolConnection<Postgres>, akaunting_options: &AkauntingSyncOption) {
+async fn update_akaunting_options(conn: &mut PoolConnection<Postgres>, akaunting_options: &AkauntingSyncOption) -> Result<(), AppError> {
     sqlx::query!("UPDATE akaunting_options 
     SET organization_data=$1, employee_data=$2, client_data=$3, 
-    vendor_data=$4, item_data=$5,invoice_data=$6, allow_post=$7, last_sync=$8, user_name=$9, user_pass=$10, akaunting_domain=$11, akaunting_company_id=$12 where key=$13",  
+    vendor_data=$4, item_data=$5,invoice_data=$6, allow_post=$7, last_sync=$8, user_name=$9, user_pass=$10, akaunting_domain=$11, akaunting_company_id=$12 where key=$13 AND organization_key=$14",  
     &akaunting_options.organization_data,
     &akaunting_options.employee_data,
     &akaunting_options.client_data, 
@@ -39,46 +37,46 @@ async fn update_akaunting_options(conn: &mut PoolConnection<Postgres>, akaunting
     &akaunting_options.akaunting_domain, 
     &akaunting_options.akaunting_company_id, 
     akaunting_options.key,
+    akaunting_options.organization_key,
 )
 .execute(conn)
-.await
-.expect("Insert Success");
+.await?;
+    Ok(())
 }
 
-pub async fn get_akaunting_options(conn: &mut PoolConnection<Postgres>, organization_key: uuid::Uuid) -> Option<AkauntingSyncOption> {
-    match sqlx::query!(
+pub async fn get_akaunting_options(conn: &mut PoolConnection<Postgres>, organization_key: uuid::Uuid) -> Result<Option<AkauntingSyncOption>, AppError> {
+    let akaunting_options = match sqlx::query!(
         "select key, organization_key, owner_key,  user_name, user_pass, akaunting_domain, akaunting_company_id, organization_data, employee_data, client_data, vendor_data, item_data, invoice_data, allow_post, last_sync, created, updated from akaunting_options where organization_key = $1",
         organization_key
     )
-    .fetch_one(conn)
-    .await {
-        Ok(akaunting_options) => Some(AkauntingSyncOption {
-            key: akaunting_options.key.expect("key exists"),
-            organization_key: akaunting_options.organization_key.expect("organization_key"), 
-            owner_key: akaunting_options.owner_key.expect("owner_key exists"),
-            user_name: akaunting_options.user_name.unwrap_or_default(),
-            user_pass: akaunting_options.user_pass.unwrap_or_default(),
-            akaunting_domain: akaunting_options.akaunting_domain.unwrap_or_default(),
-            akaunting_company_id: akaunting_options.akaunting_company_id.unwrap_or_default(),
-            organization_data: akaunting_options.organization_data.expect("organization_data exists"),
-            employee_data: akaunting_options.employee_data.expect("employee_data exists"),
-            client_data: akaunting_options.client_data.expect("client_data exists"), 
-            vendor_data: akaunting_options.vendor_data.expect("vendor_data exists"), 
-            item_data: akaunting_options.item_data.expect("item_data"),
-            invoice_data: akaunting_options.invoice_data.expect("invoice_data"),
-            allow_post: akaunting_options.allow_post.expect("allow_post"),
-            last_sync: akaunting_options.last_sync.expect("last_sync"),
-            created: akaunting_options.created.expect("created exists"),
-            updated: akaunting_options.updated.expect("updated exists"),
-        }),
-        Err(_) => {
-            None
-        }
-    }
+    .fetch_optional(conn)
+    .await?
+    {
+        Some(r) => r,
+        None => return Ok(None),
+    };
 
-    
+    Ok(Some(AkauntingSyncOption {
+        key: akaunting_options.key,
+        organization_key: akaunting_options.organization_key, 
+        owner_key: akaunting_options.owner_key,
+        user_name: akaunting_options.user_name,
+        user_pass: akaunting_options.user_pass,
+        akaunting_domain: akaunting_options.akaunting_domain,
+        akaunting_company_id: akaunting_options.akaunting_company_id,
+        organization_data: akaunting_options.organization_data,
+        employee_data: akaunting_options.employee_data,
+        client_data: akaunting_options.client_data, 
+        vendor_data: akaunting_options.vendor_data, 
+        item_data: akaunting_options.item_data,
+        invoice_data: akaunting_options.invoice_data,
+        allow_post: akaunting_options.allow_post,
+        last_sync: akaunting_options.last_sync,
+        created: akaunting_options.created,
+        updated: akaunting_options.updated,
+    }))
 }
-async fn insert_akaunting_options(conn: &mut PoolConnection<Postgres>, new_akaunting_options: &AkauntingSyncOption) {
+pub(crate) async fn insert_akaunting_options(conn: &mut PoolConnection<Postgres>, new_akaunting_options: &AkauntingSyncOption) -> Result<(), AppError> {
     sqlx::query!("INSERT INTO akaunting_options (key, organization_key, owner_key,  user_name, user_pass,  akaunting_domain, akaunting_company_id, organization_data, employee_data, client_data, vendor_data, item_data, invoice_data, allow_post, last_sync, created, updated) values($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17)", 
         new_akaunting_options.key,
         new_akaunting_options.organization_key, 
@@ -99,12 +97,12 @@ async fn insert_akaunting_options(conn: &mut PoolConnection<Postgres>, new_akaun
         new_akaunting_options.updated,
     )
     .execute(conn)
-    .await
-    .expect("Insert Success");
+    .await?;
+    Ok(())
 }
 
 pub async fn save_akaunting_options(mut req: Request<State>) -> tide::Result {
-    let claims: user::UserJwtState = match user::read_jwt_cookie(req.cookie("token")) {
+    let claims: user::UserJwtState = match user::request_claims(&req) {
         Some(c) => c,
         None => {
             return Ok(tide::Redirect::new("/login").into());
@@ -112,7 +110,8 @@ pub async fn save_akaunting_options(mut req: Request<State>) -> tide::Result {
     };
     let umd: Result<AkauntingSyncOption, tide::Error> = req.body_json().await;
     match umd {
-        Ok(akaunting_options) => {
+        Ok(mut akaunting_options) => {
+            akaunting_options.organization_key = crate::error::parse_key(claims.organization_key.as_str(), "organization")?;
             let mut conn = req.state().db_pool.acquire().await?;
             if akaunting_options.key == uuid::Uuid::nil() {
                 let ao = akaunting_options.clone();
@@ -120,28 +119,28 @@ pub async fn save_akaunting_options(mut req: Request<State>) -> tide::Result {
                     akaunting_options.user_pass, akaunting_options.akaunting_domain, akaunting_options.akaunting_company_id, akaunting_options.organization_data,
                      akaunting_options.employee_data, akaunting_options.client_data, akaunting_options.vendor_data, akaunting_options.item_data, akaunting_options.invoice_data, akaunting_options.allow_post,
                     akaunting_options.last_sync);
-                insert_akaunting_options(&mut conn, &s).await;
-                let organization_key = uuid::Uuid::from_str(claims.organization_key.as_str()).expect("organization key");
-                let mut org = get_organization(&mut conn, organization_key).await; 
-                let akaunting_companys = ao.list_companys().await.data;
+                insert_akaunting_options(&mut conn, &s).await?;
+                let organization_key = crate::error::parse_key(claims.organization_key.as_str(), "organization")?;
+                let mut org = get_organization(&mut conn, organization_key).await?; 
+                let akaunting_companys = ao.list_companys().await?.data;
                 for c in akaunting_companys {
                     if c.id.to_string() == ao.akaunting_company_id {
                         org.name = c.name;
                         break 
                     }
                 }
-                update_organization(&mut conn, &org).await;
+                update_organization(&mut conn, &org).await?;
                 let j = serde_json::to_string(&s).expect("To JSON");
                 Ok(tide::Response::builder(tide::StatusCode::Ok)
                     .content_type(mime::JSON)
                     .body(j)
                     .build())
             } else {
-                update_akaunting_options(&mut conn, &akaunting_options).await;
-                let organization_key = uuid::Uuid::from_str(claims.organization_key.as_str()).expect("organization key");
-                let mut org = get_organization(&mut conn, organization_key).await; 
+                update_akaunting_options(&mut conn, &akaunting_options).await?;
+                let organization_key = crate::error::parse_key(claims.organization_key.as_str(), "organization")?;
+                let mut org = get_organization(&mut conn, organization_key).await?; 
                 let ao = akaunting_options.clone();
-                let akaunting_companys = ao.list_companys().await.data;
+                let akaunting_companys = ao.list_companys().await?.data;
                 for c in akaunting_companys {
                     if c.id.to_string() == ao.akaunting_company_id {
                         org.name = c.name;
@@ -151,7 +150,7 @@ pub async fn save_akaunting_options(mut req: Request<State>) -> tide::Result {
                         break 
                     }
                 }
-                update_organization(&mut conn, &org).await;
+                update_organization(&mut conn, &org).await?;
                 let j = serde_json::to_string(&akaunting_options).expect("To JSON");
                 return Ok(tide::Response::builder(tide::StatusCode::Ok)
                     .content_type(mime::JSON)
@@ -159,51 +158,52 @@ pub async fn save_akaunting_options(mut req: Request<State>) -> tide::Result {
                     .build())
             }
         }
-        Err(e) => {
-            println!("{:?}", e);
-            Ok(tide::Response::builder(tide::StatusCode::BadRequest)
-                .content_type(mime::JSON)
-                .body("{'error': 'invalid json body'}")
-                .build())
-        }
+        Err(e) => Err(AppError::Validation(format!("invalid json body: {}", e)).into()),
     }
 }
 #[derive(Debug, Deserialize, Serialize, Clone)]
+#[allow(dead_code)]
 pub struct AkauntingImportByID {
     import_id: String,
 }
 
 pub async fn import_item(req: Request<State>) -> tide::Result {
-    let u = match crate::user::read_jwt_cookie_to_user(req.cookie("token")) {
+    let u = match crate::user::request_user(&req) {
         Some(c) => c,
         None => {
             return Ok(tide::Redirect::new("/login").into());
         }
     };
-    let mime = req.content_type().unwrap();
+    let mime = match req.content_type() {
+        Some(m) => m,
+        None => return Err(AppError::Validation("a multipart body is required".to_string()).into()),
+    };
     let mut conn = req.state().db_pool.acquire().await?; // .await? needs to be a real connection pool error handler!!!!!!!!
 
-    if mime.essence().to_string() == "multipart/form-data" {
-        let boundary = mime.param("boundary").unwrap().to_string();
+    if mime.essence() == "multipart/form-data" {
+        let boundary = match mime.param("boundary") {
+            Some(b) => b.to_string(),
+            None => return Err(AppError::Validation("multipart boundary missing".to_string()).into()),
+        };
         let mut body = BufferedBytesStream { inner: req };
         let mut multipart = multer::Multipart::new(&mut body, boundary);
         let mut import_id = "".to_string();
-        while let Some(field) = multipart.next_field().await.expect("next field") {
-            let f_name = field.name().clone().expect("get field name");
+        while let Some(field) = multipart.next_field().await.map_err(|e| AppError::Validation(e.to_string()))? {
+            let f_name = field.name().unwrap_or_default();
             if f_name == "import_id" {
-                import_id = field.text().await.expect("import_id multi field");
+                import_id = field.text().await.map_err(|e| AppError::Validation(e.to_string()))?;
             }
         } 
-        let mut options = match get_akaunting_options(&mut conn, u.organization_key).await {
+        let mut options = match get_akaunting_options(&mut conn, u.organization_key).await? {
             Some(options) => options,
             None => {
                 let s = AkauntingSyncOption::new(u.organization_key, u.key, "".to_string(),"".to_string(),"".to_string(),"".to_string(),true, true, true, true, true, true, true, 0);
-                insert_akaunting_options(&mut conn, &s).await;
+                insert_akaunting_options(&mut conn, &s).await?;
                 s
             }
         };
         let items = options.list_items();
-        if import_id.len() == 0 {
+        if import_id.is_empty() {
             return Ok(tide::Response::builder(tide::StatusCode::BadRequest)
                 .content_type(mime::JSON)
                 .body("bad import_id")
@@ -211,7 +211,7 @@ pub async fn import_item(req: Request<State>) -> tide::Result {
         }
         let item = options.get_item(import_id); 
         let (items, idata) = join!(items, item);
-        let idata = idata.data;
+        let (items, idata) = (items?, idata?.data);
         let svc_type = match idata.type_field.as_str() {
             "Service" => crate::service_item::ServiceItemType::Service,
             "Item"=> crate::service_item::ServiceItemType::Item,
@@ -227,8 +227,8 @@ pub async fn import_item(req: Request<State>) -> tide::Result {
             svc_type,
             crate::service_item::ServiceValueType::Full, 
             vec![]);
-            crate::service_item::insert_service_item(&mut conn, &svc_item).await;
-        if options.akaunting_domain.len() == 0 {
+            crate::service_item::insert_service_item(&mut conn, &svc_item).await?;
+        if options.akaunting_domain.is_empty() {
             options.akaunting_domain = "https://app.akaunting.com/api".to_string()
         }
         let org = crate::organization::get_organization(&mut conn, u.organization_key);
@@ -237,6 +237,7 @@ pub async fn import_item(req: Request<State>) -> tide::Result {
         let customers = options.list_customers();
         let users = options.list_users();
         let (org, companys, invoices, customers, users) = join!(org, companys, invoices, customers, users);
+        let (org, companys, invoices, customers, users) = (org?, companys?, invoices?, customers?, users?);
         Ok(tide::Response::builder(tide::StatusCode::Ok)
                 .content_type(mime::HTML)
                 .body(
@@ -262,35 +263,41 @@ pub async fn import_item(req: Request<State>) -> tide::Result {
 }
 
 pub async fn import_customer(req: Request<State>) -> tide::Result {
-    let u = match crate::user::read_jwt_cookie_to_user(req.cookie("token")) {
+    let u = match crate::user::request_user(&req) {
         Some(c) => c,
         None => {
             return Ok(tide::Redirect::new("/login").into());
         }
     };
-    let mime = req.content_type().unwrap();
+    let mime = match req.content_type() {
+        Some(m) => m,
+        None => return Err(AppError::Validation("a multipart body is required".to_string()).into()),
+    };
     let mut conn = req.state().db_pool.acquire().await?; // .await? needs to be a real connection pool error handler!!!!!!!!
 
-    if mime.essence().to_string() == "multipart/form-data" {
-        let boundary = mime.param("boundary").unwrap().to_string();
+    if mime.essence() == "multipart/form-data" {
+        let boundary = match mime.param("boundary") {
+            Some(b) => b.to_string(),
+            None => return Err(AppError::Validation("multipart boundary missing".to_string()).into()),
+        };
         let mut body = BufferedBytesStream { inner: req };
         let mut multipart = multer::Multipart::new(&mut body, boundary);
         let mut import_id = "".to_string();
-        while let Some(field) = multipart.next_field().await.expect("next field") {
-            let f_name = field.name().clone().expect("get field name");
+        while let Some(field) = multipart.next_field().await.map_err(|e| AppError::Validation(e.to_string()))? {
+            let f_name = field.name().unwrap_or_default();
             if f_name == "import_id" {
-                import_id = field.text().await.expect("import_id multi field");
+                import_id = field.text().await.map_err(|e| AppError::Validation(e.to_string()))?;
             }
         } 
-        let mut options = match get_akaunting_options(&mut conn, u.organization_key).await {
+        let mut options = match get_akaunting_options(&mut conn, u.organization_key).await? {
             Some(options) => options,
             None => {
                 let s = AkauntingSyncOption::new(u.organization_key, u.key, "".to_string(),"".to_string(),"".to_string(),"".to_string(),true, true, true, true, true, true, true, 0);
-                insert_akaunting_options(&mut conn, &s).await;
+                insert_akaunting_options(&mut conn, &s).await?;
                 s
             }
         };
-        if import_id.len() == 0 {
+        if import_id.is_empty() {
             return Ok(tide::Response::builder(tide::StatusCode::BadRequest)
                 .content_type(mime::JSON)
                 .body("bad import_id")
@@ -299,7 +306,7 @@ pub async fn import_customer(req: Request<State>) -> tide::Result {
         let customers = options.list_customers();
         let customer = options.get_customer(import_id); 
         let (customers, customer) = join!(customers, customer);
-        let idata = customer.data; 
+        let (customers, idata) = (customers?, customer?.data);
         let ent = Entity::new(u.organization_key, 
             idata.id.to_string(),
             u.key, 
@@ -316,8 +323,8 @@ pub async fn import_customer(req: Request<State>) -> tide::Result {
             String::default(),
             String::default(),
             );
-            crate::entity::insert_entity(&mut conn, &ent).await;
-        if options.akaunting_domain.len() == 0 {
+            crate::entity::insert_entity(&mut conn, &ent).await?;
+        if options.akaunting_domain.is_empty() {
             options.akaunting_domain = "https://app.akaunting.com/api".to_string()
         }
         let org = crate::organization::get_organization(&mut conn, u.organization_key);
@@ -326,6 +333,7 @@ pub async fn import_customer(req: Request<State>) -> tide::Result {
         let items = options.list_items();
         let users = options.list_users();
         let (org, companys, invoices, items, users) = join!(org, companys, invoices, items, users);
+        let (org, companys, invoices, items, users) = (org?, companys?, invoices?, items?, users?);
         Ok(tide::Response::builder(tide::StatusCode::Ok)
                 .content_type(mime::HTML)
                 .body(
@@ -351,7 +359,7 @@ pub async fn import_customer(req: Request<State>) -> tide::Result {
 }
 
 pub async fn get_akaunting_options_page(req: Request<State>) -> tide::Result {
-    let u = match crate::user::read_jwt_cookie_to_user(req.cookie("token")) {
+    let u = match crate::user::request_user(&req) {
         Some(c) => c,
         None => {
             return Ok(tide::Redirect::new("/login").into());
@@ -359,23 +367,23 @@ pub async fn get_akaunting_options_page(req: Request<State>) -> tide::Result {
     };
 
     let mut conn = req.state().db_pool.acquire().await?; // .await? needs to be a real connection pool error handler!!!!!!!!
-    let org: crate::organization::Organization = crate::organization::get_organization(&mut conn, u.organization_key).await;
-    let mut options = match get_akaunting_options(&mut conn, u.organization_key).await {
+    let org: crate::organization::Organization = crate::organization::get_organization(&mut conn, u.organization_key).await?;
+    let mut options = match get_akaunting_options(&mut conn, u.organization_key).await? {
         Some(options) => options,
         None => {
             let s = AkauntingSyncOption::new(u.organization_key, u.key, "".to_string(),"".to_string(),"".to_string(),"".to_string(),true, true, true, true, true, true, true, 0);
-            insert_akaunting_options(&mut conn, &s).await;
+            insert_akaunting_options(&mut conn, &s).await?;
             s
         }
     };
-    if options.akaunting_domain.len() == 0 {
+    if options.akaunting_domain.is_empty() {
         options.akaunting_domain = "https://app.akaunting.com/api".to_string()
     }
-    let companys = options.list_companys().await;
-    let items = options.list_items().await;
-    let invoices = options.list_invoices().await;
-    let customers = options.list_customers().await;
-    let users = options.list_users().await;
+    let companys = options.list_companys().await?;
+    let items = options.list_items().await?;
+    let invoices = options.list_invoices().await?;
+    let customers = options.list_customers().await?;
+    let users = options.list_users().await?;
     Ok(tide::Response::builder(tide::StatusCode::Ok)
         .content_type(mime::HTML)
         .body(
@@ -530,6 +538,10 @@ fn akaunting_unmarshal<'a, T: serde::Deserialize<'a>>(body: &'a str) -> Result<T
     }
 }
 
+fn akaunting_error(e: reqwest::Error) -> AppError {
+    AppError::Akaunting(e.to_string())
+}
+
 impl AkauntingSyncOption {
     fn get_authorization(&self) -> String { 
         let mut buf = String::new();
@@ -540,29 +552,29 @@ impl AkauntingSyncOption {
     }
     
     pub fn can_sync(&self) -> bool {
-        self.akaunting_domain.len() == 0 || self.user_name.len() == 0 || self.user_pass.len() == 0
+        self.akaunting_domain.is_empty() || self.user_name.is_empty() || self.user_pass.is_empty()
     }
 
-    pub async fn list_companys(&self) -> ListCompanys {
+    pub async fn list_companys(&self) -> Result<ListCompanys, AppError> {
         if self.can_sync() {
-            return ListCompanys{..Default::default()}
+            return Ok(ListCompanys{..Default::default()})
         }
-        let client = reqwest::Client::builder().build().expect("Built client");
+        let client = reqwest::Client::builder().build().map_err(akaunting_error)?;
     
         let request = client.request(
             reqwest::Method::GET,
             format!("{}/companies",self.akaunting_domain)
         ).header("Authorization", self.get_authorization());
-        let response = request.send().await.expect("Sent request");
-        let body = response.text().await.expect("Receive body");
-        return akaunting_unmarshal(body.as_str()).unwrap_or_default();
+        let response = request.send().await.map_err(akaunting_error)?;
+        let body = response.text().await.map_err(akaunting_error)?;
+        Ok(akaunting_unmarshal(body.as_str()).unwrap_or_default())
     }
 
-    pub async fn get_customer(&self, id: String) -> GetContactData {
+    pub async fn get_customer(&self, id: String) -> Result<GetContactData, AppError> {
         if self.can_sync() {
-            return GetContactData{..Default::default()}
+            return Ok(GetContactData{..Default::default()})
         }
-        let client = reqwest::Client::builder().build().expect("Built client");
+        let client = reqwest::Client::builder().build().map_err(akaunting_error)?;
     
         let request = client.request(
             reqwest::Method::GET,
@@ -571,80 +583,80 @@ impl AkauntingSyncOption {
         .header("Authorization", self.get_authorization())
         .header("X-Company", self.akaunting_company_id.to_string());
     
-        let response = request.send().await.expect("Sent request");
-        let body: String = response.text().await.expect("Receive body");
-        return akaunting_unmarshal(body.as_str()).unwrap_or_default();
+        let response = request.send().await.map_err(akaunting_error)?;
+        let body: String = response.text().await.map_err(akaunting_error)?;
+        Ok(akaunting_unmarshal(body.as_str()).unwrap_or_default())
     }
-    pub async fn get_item(&self, id: String) -> Item {
+    pub async fn get_item(&self, id: String) -> Result<Item, AppError> {
         if self.can_sync() {
-            return Item{..Default::default()}
+            return Ok(Item{..Default::default()})
         }
-        let client = reqwest::Client::builder().build().expect("Built client");
+        let client = reqwest::Client::builder().build().map_err(akaunting_error)?;
     
         let request = client.request(
             reqwest::Method::GET,
             format!("{}/items/{}",self.akaunting_domain, id)
         ).header("Authorization", self.get_authorization());
-        let response = request.send().await.expect("Sent request");
-        let body: String = response.text().await.expect("Receive body");
-        return akaunting_unmarshal(body.as_str()).unwrap_or_default();
+        let response = request.send().await.map_err(akaunting_error)?;
+        let body: String = response.text().await.map_err(akaunting_error)?;
+        Ok(akaunting_unmarshal(body.as_str()).unwrap_or_default())
     }
 
-    pub async fn list_items(&self) -> ListItems {
+    pub async fn list_items(&self) -> Result<ListItems, AppError> {
         if self.can_sync() {
-            return ListItems{..Default::default()}
+            return Ok(ListItems{..Default::default()})
         }
-        let client = reqwest::Client::builder().build().expect("Built client");
+        let client = reqwest::Client::builder().build().map_err(akaunting_error)?;
     
         let request = client.request(
             reqwest::Method::GET,
             format!("{}/items",self.akaunting_domain)
         ).header("Authorization", self.get_authorization());
-        let response = request.send().await.expect("Sent request");
-        let body: String = response.text().await.expect("Receive body");
-        return akaunting_unmarshal(body.as_str()).unwrap_or_default();
+        let response = request.send().await.map_err(akaunting_error)?;
+        let body: String = response.text().await.map_err(akaunting_error)?;
+        Ok(akaunting_unmarshal(body.as_str()).unwrap_or_default())
     }
-    pub async fn list_invoices(&self) -> ListInvoices {
+    pub async fn list_invoices(&self) -> Result<ListInvoices, AppError> {
         if self.can_sync() {
-            return ListInvoices{..Default::default()}
+            return Ok(ListInvoices{..Default::default()})
         }
-        let client = reqwest::Client::builder().build().expect("Built client");
+        let client = reqwest::Client::builder().build().map_err(akaunting_error)?;
     
         let request = client.request(
             reqwest::Method::GET,
             format!("{}/documents?search=type:invoice&page=1&limit=50",self.akaunting_domain)
         ).header("Authorization", self.get_authorization());
-        let response = request.send().await.expect("Sent request");
-        let body = response.text().await.expect("Receive body");
-        return akaunting_unmarshal(body.as_str()).unwrap_or_default();
+        let response = request.send().await.map_err(akaunting_error)?;
+        let body = response.text().await.map_err(akaunting_error)?;
+        Ok(akaunting_unmarshal(body.as_str()).unwrap_or_default())
     }
-    pub async fn list_customers(&self) -> ListCustomers {
+    pub async fn list_customers(&self) -> Result<ListCustomers, AppError> {
         if self.can_sync() {
-            return ListCustomers{..Default::default()}
+            return Ok(ListCustomers{..Default::default()})
         }
-        let client = reqwest::Client::builder().build().expect("Built client");
+        let client = reqwest::Client::builder().build().map_err(akaunting_error)?;
     
         let request = client.request(
             reqwest::Method::GET,
             format!("{}/contacts?search=type:customer&page=1&limit=25",self.akaunting_domain)
         ).header("Authorization", self.get_authorization());
-        let response = request.send().await.expect("Sent request");
-        let body = response.text().await.expect("Receive body");
-        return akaunting_unmarshal(body.as_str()).unwrap_or_default();
+        let response = request.send().await.map_err(akaunting_error)?;
+        let body = response.text().await.map_err(akaunting_error)?;
+        Ok(akaunting_unmarshal(body.as_str()).unwrap_or_default())
     }
-    pub async fn list_users(&self) -> ListUserData {
+    pub async fn list_users(&self) -> Result<ListUserData, AppError> {
         if self.can_sync() {
-            return ListUserData{..Default::default()}
+            return Ok(ListUserData{..Default::default()})
         }
-        let client = reqwest::Client::builder().build().expect("Built client");
+        let client = reqwest::Client::builder().build().map_err(akaunting_error)?;
     
         let request = client.request(
             reqwest::Method::GET,
             format!("{}/users",self.akaunting_domain)
         ).header("Authorization", self.get_authorization());
-        let response = request.send().await.expect("Sent request");
-        let body = response.text().await.expect("Receive body");
-        return akaunting_unmarshal(body.as_str()).unwrap_or_default();
+        let response = request.send().await.map_err(akaunting_error)?;
+        let body = response.text().await.map_err(akaunting_error)?;
+        Ok(akaunting_unmarshal(body.as_str()).unwrap_or_default())
     }
 } 
 
@@ -859,6 +871,7 @@ pub struct InvoiceData {
  
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
+#[allow(dead_code)]
 pub struct Account {
     pub id: i64,
     pub kinbrio_id: Option<uuid::Uuid>,
@@ -962,6 +975,7 @@ pub struct Link {
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
+#[allow(dead_code)]
 pub struct InsertInvoiceResult {
     pub data: InvoiceData,
 }
@@ -0,0 +1,735 @@
+
+use async_trait::async_trait;
+use schemars::JsonSchema;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use sqlx::pool::PoolConnection;
+use sqlx::Postgres;
+use uuid::Uuid;
+
+use tide::{http::mime, Request};
+
+use crate::activity;
+use crate::auth::{Authorize, Permission};
+use crate::board::{self, Board};
+use crate::entity::{self, Contact, Entity};
+use crate::dependency;
+use crate::error::{param_key, parse_key, AppError};
+use crate::file::{self, File};
+use crate::filter::Filter;
+use crate::matrix;
+use crate::member;
+use crate::milestone::{self, Milestone};
+use crate::note::{self, Note};
+use crate::organization::{self, Organization};
+use crate::project::{self, Project};
+use crate::service_item::{self, ServiceItem};
+use crate::task::{self, Task};
+use crate::workflow;
+use crate::{user, State};
+
+// The JSON api under /api/v1. Every resource implements ApiResource and gets the same five
+// routes from `resource`. Errors are AppErrors, the error::Responder renders them as an ApiError envelope.
+
+pub(crate) const DEFAULT_PER_PAGE: usize = 50;
+pub(crate) const MAX_PER_PAGE: usize = 200;
+
+pub fn resource<R: ApiResource>(app: &mut tide::Server<State>, path: &str, create: Permission, update: Permission, delete: Permission) {
+    let item_path = format!("{}/:key", path);
+    app.at(path)
+        .with(Authorize::new(Permission::View))
+        .get(list::<R>);
+    app.at(path)
+        .with(Authorize::new(create))
+        .post(create_route::<R>);
+    app.at(&item_path)
+        .with(Authorize::new(Permission::View))
+        .get(get::<R>);
+    app.at(&item_path)
+        .with(Authorize::new(update))
+        .put(update_route::<R>);
+    app.at(&item_path)
+        .with(Authorize::new(delete))
+        .delete(delete_route::<R>);
+}
+
+// Route Stuff
+
+pub async fn list<R: ApiResource>(req: Request<State>) -> tide::Result {
+    let caller = Caller::from_request(&req)?;
+    let page: PageQuery = req
+        .query()
+        .map_err(|_| AppError::Validation("page and per_page must be positive numbers".to_string()))?;
+    if page.page == 0 || page.per_page == 0 {
+        return Err(AppError::Validation("page and per_page must be positive numbers".to_string()).into());
+    }
+    let per_page = page.per_page.min(MAX_PER_PAGE);
+    let offset = offset(page.page, per_page)?;
+    let mut conn = req.state().db_pool.acquire().await?;
+    let data = R::list(&mut conn, &caller, per_page as i64, offset).await?;
+    let total = R::count(&mut conn, &caller).await? as usize;
+    Ok(json(tide::StatusCode::Ok, &Page { data, page: page.page, per_page, total }))
+}
+
+pub async fn get<R: ApiResource>(req: Request<State>) -> tide::Result {
+    let caller = Caller::from_request(&req)?;
+    let key = param_key(&req, "key")?;
+    let mut conn = req.state().db_pool.acquire().await?;
+    match R::fetch(&mut conn, &caller, key).await? {
+        Some(item) => Ok(json(tide::StatusCode::Ok, &item)),
+        None => Err(AppError::NotFound(R::NAME.to_string()).into()),
+    }
+}
+
+pub async fn create_route<R: ApiResource>(mut req: Request<State>) -> tide::Result {
+    let caller = Caller::from_request(&req)?;
+    let item: R = req
+        .body_json()
+        .await
+        .map_err(|e| AppError::Validation(format!("invalid json body: {}", e)))?;
+    let mut conn = req.state().db_pool.acquire().await?;
+    let created = R::create(&mut conn, &caller, item).await?;
+    Ok(json(tide::StatusCode::Created, &created))
+}
+
+pub async fn update_route<R: ApiResource>(mut req: Request<State>) -> tide::Result {
+    let caller = Caller::from_request(&req)?;
+    let key = param_key(&req, "key")?;
+    let mut item: R = req
+        .body_json()
+        .await
+        .map_err(|e| AppError::Validation(format!("invalid json body: {}", e)))?;
+    item.set_key(key);
+    let mut conn = req.state().db_pool.acquire().await?;
+    let before = match R::fetch(&mut conn, &caller, key).await? {
+        Some(b) => b,
+        None => return Err(AppError::NotFound(R::NAME.to_string()).into()),
+    };
+    let warnings = R::warnings(&mut conn, &caller, &before, &item).await?;
+    let updated = R::update(&mut conn, &caller, item).await?;
+    if warnings.is_empty() {
+        return Ok(json(tide::StatusCode::Ok, &updated));
+    }
+    let mut body = serde_json::to_value(&updated)?;
+    body["warnings"] = serde_json::json!(warnings);
+    Ok(json(tide::StatusCode::Ok, &body))
+}
+
+pub async fn delete_route<R: ApiResource>(req: Request<State>) -> tide::Result {
+    let caller = Caller::from_request(&req)?;
+    let key = param_key(&req, "key")?;
+    let mut conn = req.state().db_pool.acquire().await?;
+    if R::remove(&mut conn, &caller, key).await? {
+        return Ok(tide::Response::builder(tide::StatusCode::NoContent).build());
+    }
+    Err(AppError::NotFound(R::NAME.to_string()).into())
+}
+
+// rows to skip to get to `page`, a page that far out is a bad request rather than an overflow
+pub(crate) fn offset(page: usize, per_page: usize) -> Result<i64, AppError> {
+    (page - 1)
+        .checked_mul(per_page)
+        .and_then(|o| i64::try_from(o).ok())
+        .ok_or_else(|| AppError::Validation(format!("page {} is past the end of any list", page)))
+}
+
+pub fn json<T: Serialize>(status: tide::StatusCode, body: &T) -> tide::Response {
+    tide::Response::builder(status)
+        .content_type(mime::JSON)
+        .body(serde_json::to_string(body).expect("To JSON"))
+        .build()
+}
+
+// Matrix posts and milestone announcements come after the write they are about, a failure there is
+// logged instead of failing a request whose change has already been saved
+pub(crate) fn report_matrix(result: Result<(), anyhow::Error>) {
+    if let Err(e) = result {
+        println!("Posting to matrix {:?}", e);
+    }
+}
+
+pub(crate) fn report_announcement(result: Result<(), AppError>) {
+    if let Err(e) = result {
+        println!("Announcing milestone {:?}", e);
+    }
+}
+
+// data types
+
+pub struct Caller {
+    pub user_key: Uuid,
+    pub organization_key: Uuid,
+    pub claims: user::UserJwtState,
+}
+
+impl Caller {
+    // Authorize has already validated the token and stashed the claims on the request
+    pub fn from_request(req: &Request<State>) -> Result<Self, AppError> {
+        let claims = match req.ext::<user::UserJwtState>() {
+            Some(c) => c.clone(),
+            None => return Err(AppError::Unauthorized),
+        };
+        let user_key = parse_key(claims.key.as_str(), "user")?;
+        let organization_key = parse_key(claims.organization_key.as_str(), "organization")?;
+        Ok(Self {
+            user_key,
+            organization_key,
+            claims,
+        })
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PageQuery {
+    #[serde(default = "first_page")]
+    pub page: usize,
+    #[serde(default = "default_per_page")]
+    pub per_page: usize,
+}
+
+fn first_page() -> usize {
+    1
+}
+
+fn default_per_page() -> usize {
+    DEFAULT_PER_PAGE
+}
+
+#[derive(Debug, Serialize)]
+pub struct Page<T> {
+    pub data: Vec<T>,
+    pub page: usize,
+    pub per_page: usize,
+    pub total: usize,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct ApiError {
+    pub error: ApiErrorBody,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct ApiErrorBody {
+    pub status: u16,
+    pub code: String,
+    pub message: String,
+}
+
+impl ApiError {
+    pub fn new(status: tide::StatusCode, code: &str, message: &str) -> Self {
+        Self {
+            error: ApiErrorBody {
+                status: status as u16,
+                code: code.to_string(),
+                message: message.to_string(),
+            },
+        }
+    }
+}
+
+#[async_trait]
+pub trait ApiResource: Serialize + DeserializeOwned + JsonSchema + Send + Sync + 'static {
+    const NAME: &'static str;
+
+    fn set_key(&mut self, key: Uuid);
+    async fn list(conn: &mut PoolConnection<Postgres>, caller: &Caller, limit: i64, offset: i64) -> Result<Vec<Self>, AppError>;
+    async fn count(conn: &mut PoolConnection<Postgres>, caller: &Caller) -> Result<i64, AppError>;
+    async fn fetch(conn: &mut PoolConnection<Postgres>, caller: &Caller, key: Uuid) -> Result<Option<Self>, AppError>;
+    async fn create(conn: &mut PoolConnection<Postgres>, caller: &Caller, item: Self) -> Result<Self, AppError>;
+    // only called once fetch has found the record in the caller's organization
+    async fn update(conn: &mut PoolConnection<Postgres>, caller: &Caller, item: Self) -> Result<Self, AppError>;
+    async fn remove(conn: &mut PoolConnection<Postgres>, caller: &Caller, key: Uuid) -> Result<bool, AppError>;
+    // what the caller should hear about an update that goes through anyway, sent back as `warnings`
+    async fn warnings(_conn: &mut PoolConnection<Postgres>, _caller: &Caller, _before: &Self, _item: &Self) -> Result<Vec<String>, AppError> {
+        Ok(vec![])
+    }
+}
+
+fn removed(result: Result<sqlx::postgres::PgQueryResult, sqlx::Error>) -> Result<bool, AppError> {
+    Ok(result?.rows_affected() > 0)
+}
+
+async fn require_project(conn: &mut PoolConnection<Postgres>, caller: &Caller, project_key: Uuid) -> Result<(), AppError> {
+    match project::get_project(conn, project_key, caller.organization_key).await? {
+        Some(_) => Ok(()),
+        None => Err(AppError::NotFound("project".to_string())),
+    }
+}
+
+#[async_trait]
+impl ApiResource for Organization {
+    const NAME: &'static str = "organization";
+
+    fn set_key(&mut self, key: Uuid) {
+        self.key = key;
+    }
+
+    async fn list(conn: &mut PoolConnection<Postgres>, caller: &Caller, limit: i64, offset: i64) -> Result<Vec<Self>, AppError> {
+        organization::get_user_organizations_page(conn, caller.user_key, limit, offset).await
+    }
+
+    async fn count(conn: &mut PoolConnection<Postgres>, caller: &Caller) -> Result<i64, AppError> {
+        organization::count_user_organizations(conn, caller.user_key).await
+    }
+
+    async fn fetch(conn: &mut PoolConnection<Postgres>, caller: &Caller, key: Uuid) -> Result<Option<Self>, AppError> {
+        if member::get_role(conn, key, caller.user_key).await.is_none() {
+            return Ok(None);
+        }
+        Ok(Some(organization::get_organization(conn, key).await?))
+    }
+
+    async fn create(conn: &mut PoolConnection<Postgres>, caller: &Caller, item: Self) -> Result<Self, AppError> {
+        let s = Organization::new(
+            Uuid::new_v4(),
+            item.external_accounting_id,
+            item.external_accounting_url,
+            caller.user_key,
+            item.name,
+            item.description,
+            item.matrix_home_server,
+            item.matrix_live_support_room_url,
+            item.matrix_general_room_url,
+            item.domain,
+            item.contact_email,
+        );
+        organization::create_organization(conn, &s, caller.user_key).await?;
+        Ok(s)
+    }
+
+    // the role in the token is for the active organization, so that is the only one it can change
+    async fn update(conn: &mut PoolConnection<Postgres>, caller: &Caller, item: Self) -> Result<Self, AppError> {
+        if item.key != caller.organization_key {
+            return Err(AppError::Forbidden("switch to the organization before changing it".to_string()));
+        }
+        organization::update_organization(conn, &item).await?;
+        organization::get_organization(conn, item.key).await
+    }
+
+    async fn remove(conn: &mut PoolConnection<Postgres>, caller: &Caller, key: Uuid) -> Result<bool, AppError> {
+        if key != caller.organization_key {
+            return Err(AppError::Forbidden("switch to the organization before deleting it".to_string()));
+        }
+        removed(organization::delete_organization(conn, key).await)
+    }
+}
+
+#[async_trait]
+impl ApiResource for Project {
+    const NAME: &'static str = "project";
+
+    fn set_key(&mut self, key: Uuid) {
+        self.key = key;
+    }
+
+    async fn list(conn: &mut PoolConnection<Postgres>, caller: &Caller, limit: i64, offset: i64) -> Result<Vec<Self>, AppError> {
+        project::get_projects_by_organization_page(conn, caller.organization_key, limit, offset).await
+    }
+
+    async fn count(conn: &mut PoolConnection<Postgres>, caller: &Caller) -> Result<i64, AppError> {
+        project::count_projects_by_organization(conn, caller.organization_key).await
+    }
+
+    async fn fetch(conn: &mut PoolConnection<Postgres>, caller: &Caller, key: Uuid) -> Result<Option<Self>, AppError> {
+        project::get_project(conn, key, caller.organization_key).await
+    }
+
+    async fn create(conn: &mut PoolConnection<Postgres>, caller: &Caller, item: Self) -> Result<Self, AppError> {
+        let s = Project::new(caller.organization_key, caller.user_key, item.name, item.description, item.tags, item.estimated_quarter_days, item.start, item.due);
+        project::insert_project(conn, &s).await?;
+        let c = caller.claims.clone();
+        report_matrix(matrix::post_project_create(conn, c.matrix_home_server, c.matrix_user_id, caller.organization_key, c.matrix_access_token, &s).await);
+        Ok(s)
+    }
+
+    async fn update(conn: &mut PoolConnection<Postgres>, caller: &Caller, mut item: Self) -> Result<Self, AppError> {
+        item.organization_key = caller.organization_key;
+        project::update_project(conn, &item).await?;
+        project::get_project(conn, item.key, caller.organization_key).await?.ok_or(AppError::NotFound(Self::NAME.to_string()))
+    }
+
+    async fn remove(conn: &mut PoolConnection<Postgres>, caller: &Caller, key: Uuid) -> Result<bool, AppError> {
+        removed(project::delete_project(conn, key, caller.organization_key).await)
+    }
+}
+
+#[async_trait]
+impl ApiResource for Milestone {
+    const NAME: &'static str = "milestone";
+
+    fn set_key(&mut self, key: Uuid) {
+        self.key = key;
+    }
+
+    async fn list(conn: &mut PoolConnection<Postgres>, caller: &Caller, limit: i64, offset: i64) -> Result<Vec<Self>, AppError> {
+        milestone::get_milestones_by_organization_page(conn, caller.organization_key, limit, offset).await
+    }
+
+    async fn count(conn: &mut PoolConnection<Postgres>, caller: &Caller) -> Result<i64, AppError> {
+        milestone::count_milestones_by_organization(conn, caller.organization_key).await
+    }
+
+    async fn fetch(conn: &mut PoolConnection<Postgres>, caller: &Caller, key: Uuid) -> Result<Option<Self>, AppError> {
+        milestone::get_milestone(conn, key, caller.organization_key).await
+    }
+
+    async fn create(conn: &mut PoolConnection<Postgres>, caller: &Caller, item: Self) -> Result<Self, AppError> {
+        require_project(conn, caller, item.project_key).await?;
+        let s = Milestone::new(caller.organization_key, caller.user_key, item.project_key, item.name, item.description, item.tags, item.estimated_quarter_days, item.start, item.due);
+        milestone::insert_milestone(conn, &s).await?;
+        let c = caller.claims.clone();
+        report_matrix(matrix::post_milestone_create(conn, c.matrix_home_server, c.matrix_user_id, caller.organization_key, c.matrix_access_token, &s).await);
+        Ok(s)
+    }
+
+    async fn update(conn: &mut PoolConnection<Postgres>, caller: &Caller, mut item: Self) -> Result<Self, AppError> {
+        require_project(conn, caller, item.project_key).await?;
+        item.organization_key = caller.organization_key;
+        milestone::update_milestone(conn, &item).await?;
+        report_announcement(milestone::announce_outcome(conn, &caller.claims, caller.organization_key, Some(item.key)).await);
+        milestone::get_milestone(conn, item.key, caller.organization_key).await?.ok_or(AppError::NotFound(Self::NAME.to_string()))
+    }
+
+    async fn remove(conn: &mut PoolConnection<Postgres>, caller: &Caller, key: Uuid) -> Result<bool, AppError> {
+        removed(milestone::delete_milestone(conn, key, caller.organization_key).await)
+    }
+}
+
+#[async_trait]
+impl ApiResource for Task {
+    const NAME: &'static str = "task";
+
+    fn set_key(&mut self, key: Uuid) {
+        self.key = key;
+    }
+
+    async fn list(conn: &mut PoolConnection<Postgres>, caller: &Caller, limit: i64, offset: i64) -> Result<Vec<Self>, AppError> {
+        task::get_tasks_by_organization_page(conn, caller.organization_key, limit, offset).await
+    }
+
+    async fn count(conn: &mut PoolConnection<Postgres>, caller: &Caller) -> Result<i64, AppError> {
+        task::count_tasks_by_organization(conn, caller.organization_key).await
+    }
+
+    async fn fetch(conn: &mut PoolConnection<Postgres>, caller: &Caller, key: Uuid) -> Result<Option<Self>, AppError> {
+        task::get_task(conn, key, caller.organization_key).await
+    }
+
+    async fn create(conn: &mut PoolConnection<Postgres>, caller: &Caller, item: Self) -> Result<Self, AppError> {
+        require_project(conn, caller, item.project_key).await?;
+        let workflow = workflow::get_workflow(conn, caller.organization_key).await?;
+        // leaving the status out starts the task wherever the workflow starts new work
+        let status_key = match item.status_key.is_nil() {
+            true => workflow.initial().ok_or(AppError::Validation("the workflow has no statuses".to_string()))?,
+            false => item.status_key,
+        };
+        workflow.check_move(None, status_key)?;
+        let s = Task::new(caller.organization_key, item.project_key, item.parent_key, item.milestone_key, caller.user_key, item.assignee_key, item.name, item.description, item.tags, status_key, item.estimated_quarter_days, item.start, item.due);
+        task::check_parent(conn, &s).await?;
+        task::check_milestone(conn, &s).await?;
+        task::insert_task(conn, &s).await?;
+        report_announcement(milestone::announce_outcome(conn, &caller.claims, caller.organization_key, s.milestone_key).await);
+        let c = caller.claims.clone();
+        report_matrix(matrix::post_task_create(conn, c.matrix_home_server, c.matrix_user_id, caller.organization_key, c.matrix_access_token, &s).await);
+        Ok(s)
+    }
+
+    async fn update(conn: &mut PoolConnection<Postgres>, caller: &Caller, mut item: Self) -> Result<Self, AppError> {
+        require_project(conn, caller, item.project_key).await?;
+        item.organization_key = caller.organization_key;
+        let before = task::get_task(conn, item.key, caller.organization_key).await?.ok_or(AppError::NotFound(Self::NAME.to_string()))?;
+        task::check_parent(conn, &item).await?;
+        task::check_milestone(conn, &item).await?;
+        workflow::get_workflow(conn, caller.organization_key).await?.check_move(Some(before.status_key), item.status_key)?;
+        task::update_task(conn, &item).await?;
+        activity::record_task_changes(conn, caller.user_key, &before, &item).await?;
+        report_announcement(milestone::announce_outcome(conn, &caller.claims, caller.organization_key, item.milestone_key).await);
+        if before.milestone_key != item.milestone_key {
+            report_announcement(milestone::announce_outcome(conn, &caller.claims, caller.organization_key, before.milestone_key).await);
+        }
+        task::get_task(conn, item.key, caller.organization_key).await?.ok_or(AppError::NotFound(Self::NAME.to_string()))
+    }
+
+    async fn warnings(conn: &mut PoolConnection<Postgres>, caller: &Caller, before: &Self, item: &Self) -> Result<Vec<String>, AppError> {
+        let workflow = workflow::get_workflow(conn, caller.organization_key).await?;
+        dependency::blocker_warnings(conn, &workflow, before.key, caller.organization_key, before.status_key, item.status_key).await
+    }
+
+    async fn remove(conn: &mut PoolConnection<Postgres>, caller: &Caller, key: Uuid) -> Result<bool, AppError> {
+        removed(task::delete_task(conn, key, caller.organization_key).await)
+    }
+}
+
+#[async_trait]
+impl ApiResource for Board {
+    const NAME: &'static str = "board";
+
+    fn set_key(&mut self, key: Uuid) {
+        self.key = key;
+    }
+
+    async fn list(conn: &mut PoolConnection<Postgres>, caller: &Caller, limit: i64, offset: i64) -> Result<Vec<Self>, AppError> {
+        board::get_organization_boards_page(conn, caller.organization_key, limit, offset).await
+    }
+
+    async fn count(conn: &mut PoolConnection<Postgres>, caller: &Caller) -> Result<i64, AppError> {
+        board::count_organization_boards(conn, caller.organization_key).await
+    }
+
+    async fn fetch(conn: &mut PoolConnection<Postgres>, caller: &Caller, key: Uuid) -> Result<Option<Self>, AppError> {
+        board::get_board(conn, key, caller.organization_key).await
+    }
+
+    async fn create(conn: &mut PoolConnection<Postgres>, caller: &Caller, item: Self) -> Result<Self, AppError> {
+        Filter::parse(&item.filter)?;
+        let s = Board::new(caller.organization_key, caller.user_key, item.name, item.description, item.columns, item.lanes, item.filter, item.swimlane, item.wip_limits);
+        board::insert_board(conn, &s).await?;
+        let c = caller.claims.clone();
+        report_matrix(matrix::post_board_create(conn, c.matrix_home_server, c.matrix_user_id, caller.organization_key, c.matrix_access_token, &s).await);
+        Ok(s)
+    }
+
+    async fn update(conn: &mut PoolConnection<Postgres>, caller: &Caller, mut item: Self) -> Result<Self, AppError> {
+        item.organization_key = caller.organization_key;
+        Filter::parse(&item.filter)?;
+        board::update_board(conn, &item).await?;
+        board::get_board(conn, item.key, caller.organization_key).await?.ok_or(AppError::NotFound(Self::NAME.to_string()))
+    }
+
+    async fn remove(conn: &mut PoolConnection<Postgres>, caller: &Caller, key: Uuid) -> Result<bool, AppError> {
+        removed(board::delete_board(conn, key, caller.organization_key).await)
+    }
+}
+
+#[async_trait]
+impl ApiResource for Entity {
+    const NAME: &'static str = "entity";
+
+    fn set_key(&mut self, key: Uuid) {
+        self.key = key;
+    }
+
+    async fn list(conn: &mut PoolConnection<Postgres>, caller: &Caller, limit: i64, offset: i64) -> Result<Vec<Self>, AppError> {
+        entity::get_organization_entitys_page(conn, caller.organization_key, limit, offset).await
+    }
+
+    async fn count(conn: &mut PoolConnection<Postgres>, caller: &Caller) -> Result<i64, AppError> {
+        entity::count_organization_entitys(conn, caller.organization_key).await
+    }
+
+    async fn fetch(conn: &mut PoolConnection<Postgres>, caller: &Caller, key: Uuid) -> Result<Option<Self>, AppError> {
+        entity::get_entity(conn, key, caller.organization_key).await
+    }
+
+    async fn create(conn: &mut PoolConnection<Postgres>, caller: &Caller, item: Self) -> Result<Self, AppError> {
+        let s = Entity::new(
+            caller.organization_key,
+            item.external_accounting_id,
+            caller.user_key,
+            item.name,
+            item.description,
+            item.matrix_room_url,
+            item.web_url,
+            item.avatar_url,
+            item.entity_type,
+            item.address_primary,
+            item.address_unit,
+            item.city,
+            item.state,
+            item.zip_code,
+            item.country,
+        );
+        entity::insert_entity(conn, &s).await?;
+        let c = caller.claims.clone();
+        report_matrix(matrix::post_entity_create(conn, c.matrix_home_server, c.matrix_user_id, caller.organization_key, c.matrix_access_token, &s).await);
+        Ok(s)
+    }
+
+    async fn update(conn: &mut PoolConnection<Postgres>, caller: &Caller, mut item: Self) -> Result<Self, AppError> {
+        item.organization_key = caller.organization_key;
+        entity::update_entity(conn, &item).await?;
+        entity::get_entity(conn, item.key, caller.organization_key).await?.ok_or(AppError::NotFound(Self::NAME.to_string()))
+    }
+
+    async fn remove(conn: &mut PoolConnection<Postgres>, caller: &Caller, key: Uuid) -> Result<bool, AppError> {
+        removed(entity::delete_entity(conn, key, caller.organization_key).await)
+    }
+}
+
+#[async_trait]
+impl ApiResource for Contact {
+    const NAME: &'static str = "contact";
+
+    fn set_key(&mut self, key: Uuid) {
+        self.key = key;
+    }
+
+    async fn list(conn: &mut PoolConnection<Postgres>, caller: &Caller, limit: i64, offset: i64) -> Result<Vec<Self>, AppError> {
+        entity::get_contacts_page(conn, caller.organization_key, limit, offset).await
+    }
+
+    async fn count(conn: &mut PoolConnection<Postgres>, caller: &Caller) -> Result<i64, AppError> {
+        entity::count_contacts(conn, caller.organization_key).await
+    }
+
+    async fn fetch(conn: &mut PoolConnection<Postgres>, caller: &Caller, key: Uuid) -> Result<Option<Self>, AppError> {
+        entity::get_contact(conn, key, caller.organization_key).await
+    }
+
+    async fn create(conn: &mut PoolConnection<Postgres>, caller: &Caller, item: Self) -> Result<Self, AppError> {
+        if entity::get_entity(conn, item.entity_key, caller.organization_key).await?.is_none() {
+            return Err(AppError::NotFound("entity".to_string()));
+        }
+        let s = Contact::new(
+            item.entity_key,
+            item.external_accounting_id,
+            item.first_name,
+            item.middle_initial,
+            item.last_name,
+            item.description,
+            item.position,
+            item.email,
+            item.phone,
+            item.secondary_email,
+            item.secondary_phone,
+            item.matrix_user_id,
+            item.web_url,
+            item.avatar_url,
+            item.social_urls,
+            item.address_primary,
+            item.address_unit,
+            item.city,
+            item.state,
+            item.zip_code,
+            item.country,
+        );
+        entity::insert_contact(conn, &s, caller.organization_key).await?;
+        let c = caller.claims.clone();
+        report_matrix(matrix::post_contact_create(conn, c.matrix_home_server, c.matrix_user_id, caller.organization_key, c.matrix_access_token, &s).await);
+        Ok(s)
+    }
+
+    async fn update(conn: &mut PoolConnection<Postgres>, caller: &Caller, item: Self) -> Result<Self, AppError> {
+        entity::update_contact(conn, &item, caller.organization_key).await?;
+        entity::get_contact(conn, item.key, caller.organization_key).await?.ok_or(AppError::NotFound(Self::NAME.to_string()))
+    }
+
+    async fn remove(conn: &mut PoolConnection<Postgres>, caller: &Caller, key: Uuid) -> Result<bool, AppError> {
+        removed(entity::delete_contact(conn, key, caller.organization_key).await)
+    }
+}
+
+#[async_trait]
+impl ApiResource for ServiceItem {
+    const NAME: &'static str = "service item";
+
+    fn set_key(&mut self, key: Uuid) {
+        self.key = key;
+    }
+
+    async fn list(conn: &mut PoolConnection<Postgres>, caller: &Caller, limit: i64, offset: i64) -> Result<Vec<Self>, AppError> {
+        service_item::get_organization_service_items_page(conn, caller.organization_key, limit, offset).await
+    }
+
+    async fn count(conn: &mut PoolConnection<Postgres>, caller: &Caller) -> Result<i64, AppError> {
+        service_item::count_organization_service_items(conn, caller.organization_key).await
+    }
+
+    async fn fetch(conn: &mut PoolConnection<Postgres>, caller: &Caller, key: Uuid) -> Result<Option<Self>, AppError> {
+        service_item::get_service_item(conn, key, caller.organization_key).await
+    }
+
+    async fn create(conn: &mut PoolConnection<Postgres>, caller: &Caller, item: Self) -> Result<Self, AppError> {
+        let s = ServiceItem::new(caller.organization_key, item.external_accounting_id, caller.user_key, item.name, item.description, item.value, item.currency, item.service_item_type, item.service_value_type, item.expenses);
+        service_item::insert_service_item(conn, &s).await?;
+        Ok(s)
+    }
+
+    async fn update(conn: &mut PoolConnection<Postgres>, caller: &Caller, mut item: Self) -> Result<Self, AppError> {
+        item.organization_key = caller.organization_key;
+        service_item::update_service_item(conn, &item).await?;
+        service_item::get_service_item(conn, item.key, caller.organization_key).await?.ok_or(AppError::NotFound(Self::NAME.to_string()))
+    }
+
+    async fn remove(conn: &mut PoolConnection<Postgres>, caller: &Caller, key: Uuid) -> Result<bool, AppError> {
+        removed(service_item::delete_service_item(conn, key, caller.organization_key).await)
+    }
+}
+
+#[async_trait]
+impl ApiResource for Note {
+    const NAME: &'static str = "note";
+
+    fn set_key(&mut self, key: Uuid) {
+        self.key = key;
+    }
+
+    async fn list(conn: &mut PoolConnection<Postgres>, caller: &Caller, limit: i64, offset: i64) -> Result<Vec<Self>, AppError> {
+        note::get_organization_notes_page(conn, caller.organization_key, limit, offset).await
+    }
+
+    async fn count(conn: &mut PoolConnection<Postgres>, caller: &Caller) -> Result<i64, AppError> {
+        note::count_organization_notes(conn, caller.organization_key).await
+    }
+
+    async fn fetch(conn: &mut PoolConnection<Postgres>, caller: &Caller, key: Uuid) -> Result<Option<Self>, AppError> {
+        note::get_note(conn, key, caller.organization_key).await
+    }
+
+    async fn create(conn: &mut PoolConnection<Postgres>, caller: &Caller, item: Self) -> Result<Self, AppError> {
+        let s = Note::new(caller.organization_key, caller.user_key, item.association_type, item.association_key, item.url, item.title, item.content);
+        note::insert_note(conn, &s).await?;
+        let c = caller.claims.clone();
+        report_matrix(matrix::post_note_create(conn, c.matrix_home_server, c.matrix_user_id, caller.organization_key, c.matrix_access_token, &s).await);
+        Ok(s)
+    }
+
+    async fn update(conn: &mut PoolConnection<Postgres>, caller: &Caller, mut item: Self) -> Result<Self, AppError> {
+        item.organization_key = caller.organization_key;
+        note::update_note(conn, &item).await?;
+        note::get_note(conn, item.key, caller.organization_key).await?.ok_or(AppError::NotFound(Self::NAME.to_string()))
+    }
+
+    async fn remove(conn: &mut PoolConnection<Postgres>, caller: &Caller, key: Uuid) -> Result<bool, AppError> {
+        removed(note::delete_note(conn, key, caller.organization_key).await)
+    }
+}
+
+// Files are metadata only here, the bytes still go through the multipart upload on /file
+#[async_trait]
+impl ApiResource for File {
+    const NAME: &'static str = "file";
+
+    fn set_key(&mut self, key: Uuid) {
+        self.key = key;
+    }
+
+    async fn list(conn: &mut PoolConnection<Postgres>, caller: &Caller, limit: i64, offset: i64) -> Result<Vec<Self>, AppError> {
+        file::get_organization_files_page(conn, caller.organization_key, limit, offset).await
+    }
+
+    async fn count(conn: &mut PoolConnection<Postgres>, caller: &Caller) -> Result<i64, AppError> {
+        file::count_organization_files(conn, caller.organization_key).await
+    }
+
+    async fn fetch(conn: &mut PoolConnection<Postgres>, caller: &Caller, key: Uuid) -> Result<Option<Self>, AppError> {
+        file::get_file(conn, key, caller.organization_key).await
+    }
+
+    async fn create(conn: &mut PoolConnection<Postgres>, caller: &Caller, item: Self) -> Result<Self, AppError> {
+        let s = File::new(caller.user_key, caller.organization_key, item.association_type, item.association_key, item.url, item.hash, item.name, item.description, item.tags, item.format, item.size);
+        file::insert_file(conn, &s).await?;
+        let c = caller.claims.clone();
+        report_matrix(matrix::post_file_create(conn, c.matrix_home_server, c.matrix_user_id, caller.organization_key, c.matrix_access_token, &s).await);
+        Ok(s)
+    }
+
+    async fn update(conn: &mut PoolConnection<Postgres>, caller: &Caller, item: Self) -> Result<Self, AppError> {
+        file::update_file(conn, &item, caller.organization_key).await?;
+        file::get_file(conn, item.key, caller.organization_key).await?.ok_or(AppError::NotFound(Self::NAME.to_string()))
+    }
+
+    async fn remove(conn: &mut PoolConnection<Postgres>, caller: &Caller, key: Uuid) -> Result<bool, AppError> {
+        removed(file::delete_file(conn, key, caller.organization_key).await)
+    }
+}
@@ -0,0 +1,409 @@
+use std::str::FromStr;
+
+use askama::Template;
+use serde::{Deserialize, Serialize};
+use sqlx::pool::PoolConnection;
+use sqlx::postgres::PgQueryResult;
+use sqlx::Postgres;
+use strum::IntoEnumIterator;
+use strum_macros::EnumIter;
+use uuid::Uuid;
+
+use tide::{http::mime, Request};
+
+use crate::error::AppError;
+use crate::home::NotFoundTemplate;
+use crate::member::{self, Role};
+use crate::{user, State};
+
+const TOKEN_PREFIX: &str = "kb_";
+
+// SQL STUFF
+
+pub async fn get_api_token(conn: &mut PoolConnection<Postgres>, key: uuid::Uuid) -> Result<Option<ApiToken>, AppError> {
+    let token = match sqlx::query!(
+        "select key, organization_key, user_key, name, secret_hash, scope, last_used, expires, revoked, created, updated from api_tokens where key = $1",
+        key
+    )
+    .fetch_optional(conn)
+    .await?
+    {
+        Some(r) => r,
+        None => return Ok(None),
+    };
+
+    Ok(Some(ApiToken {
+        key: token.key,
+        organization_key: token.organization_key,
+        user_key: token.user_key,
+        name: token.name,
+        secret_hash: token.secret_hash,
+        scope: token.scope.into(),
+        last_used: token.last_used,
+        expires: token.expires,
+        revoked: token.revoked,
+        created: token.created,
+        updated: token.updated,
+    }))
+}
+
+pub async fn get_user_api_tokens(
+    conn: &mut PoolConnection<Postgres>,
+    user_key: uuid::Uuid,
+    organization_key: uuid::Uuid,
+) -> Result<Vec<ApiToken>, AppError> {
+    let records = sqlx::query!(
+        "select key, organization_key, user_key, name, secret_hash, scope, last_used, expires, revoked, created, updated from api_tokens where user_key = $1 AND organization_key = $2 AND revoked = 0 order by created desc",
+        user_key,
+        organization_key
+    )
+    .fetch_all(conn)
+    .await?;
+    let mut tokens = vec![];
+    for token in records {
+        tokens.push(ApiToken {
+            key: token.key,
+            organization_key: token.organization_key,
+            user_key: token.user_key,
+            name: token.name,
+            secret_hash: token.secret_hash,
+            scope: token.scope.into(),
+            last_used: token.last_used,
+            expires: token.expires,
+            revoked: token.revoked,
+            created: token.created,
+            updated: token.updated,
+        })
+    }
+    Ok(tokens)
+}
+
+async fn insert_api_token(conn: &mut PoolConnection<Postgres>, new_token: &ApiToken) -> Result<(), AppError> {
+    sqlx::query!("INSERT INTO api_tokens (key, organization_key, user_key, name, secret_hash, scope, last_used, expires, revoked, created, updated) values($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)",
+        new_token.key,
+        new_token.organization_key,
+        new_token.user_key,
+        &new_token.name,
+        &new_token.secret_hash,
+        new_token.scope as i16,
+        new_token.last_used,
+        new_token.expires,
+        new_token.revoked,
+        new_token.created,
+        new_token.updated,
+    )
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+async fn revoke_api_token(
+    conn: &mut PoolConnection<Postgres>,
+    key: uuid::Uuid,
+    user_key: uuid::Uuid,
+) -> Result<PgQueryResult, sqlx::Error> {
+    return sqlx::query!(
+        "UPDATE api_tokens SET revoked=extract(epoch from now()), updated=extract(epoch from now()) where key=$1 AND user_key=$2 AND revoked = 0",
+        key,
+        user_key
+    )
+    .execute(conn)
+    .await;
+}
+
+async fn touch_api_token(conn: &mut PoolConnection<Postgres>, key: uuid::Uuid) -> Result<(), AppError> {
+    sqlx::query!("UPDATE api_tokens SET last_used=extract(epoch from now()) where key=$1", key)
+        .execute(conn)
+        .await?;
+    Ok(())
+}
+
+// Resolves the value of an `Authorization: Bearer kb_...` header into the same claims a session
+// cookie carries, with the role capped by the token's scope. None for anything unknown, revoked,
+// expired or belonging to someone who has since left the organization.
+pub async fn authenticate(conn: &mut PoolConnection<Postgres>, header: &str) -> Result<Option<user::UserJwtState>, AppError> {
+    let (key, secret) = match header
+        .strip_prefix("Bearer ")
+        .and_then(|b| b.trim().strip_prefix(TOKEN_PREFIX))
+        .and_then(|b| b.split_once('_'))
+    {
+        Some(parts) => parts,
+        None => return Ok(None),
+    };
+    let key = match Uuid::from_str(key) {
+        Ok(k) => k,
+        Err(_) => return Ok(None),
+    };
+    let token = match get_api_token(conn, key).await? {
+        Some(t) if t.is_active() && pwhash::bcrypt::verify(secret, t.secret_hash.as_str()) => t,
+        _ => return Ok(None),
+    };
+    let role = match member::get_role(conn, token.organization_key, token.user_key).await {
+        Some(r) => r,
+        None => return Ok(None),
+    };
+    let mut u = match user::get_user(conn, token.user_key).await {
+        Some(u) => u,
+        None => return Ok(None),
+    };
+    touch_api_token(conn, token.key).await?;
+    u.organization_key = token.organization_key;
+    Ok(Some(user::api_claims(&u, token.scope.cap(role))))
+}
+
+// Route Stuff
+
+pub async fn get(req: Request<State>) -> tide::Result {
+    let u = match user::user_or_error(&req) {
+        Ok(value) => value,
+        Err(_) => return Ok(tide::Redirect::new("/login").into()),
+    };
+    let mut conn = req.state().db_pool.acquire().await?;
+    let tokens = get_user_api_tokens(&mut conn, u.key, u.organization_key).await?;
+    Ok(tide::Response::builder(tide::StatusCode::Ok)
+        .content_type(mime::HTML)
+        .body(ApiTokenTemplate::new(u, tokens).render_string())
+        .build())
+}
+
+// Needs a real session, otherwise a read only token could mint itself a stronger one
+pub async fn insert(mut req: Request<State>) -> tide::Result {
+    let claims: user::UserJwtState = match user::read_jwt_cookie(req.cookie("token")) {
+        Some(c) => c,
+        None => {
+            return Ok(tide::Redirect::new("/login").into());
+        }
+    };
+    let umd: Result<ApiTokenRequest, tide::Error> = req.body_json().await;
+    match umd {
+        Ok(request) => {
+            let name = request.name.trim().to_string();
+            if name.is_empty() {
+                return Err(AppError::Validation("a name is required".into()).into());
+            }
+            let expires = match request.expires_in_days {
+                0 => 0,
+                days => chrono::Utc::now().timestamp() + days * 60 * 60 * 24,
+            };
+            let secret = format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple());
+            let secret_hash = match pwhash::bcrypt::hash(secret.as_str()) {
+                Ok(h) => h,
+                Err(e) => {
+                    return Ok(tide::Response::builder(tide::StatusCode::InternalServerError)
+                        .content_type(mime::PLAIN)
+                        .body(e.to_string())
+                        .build())
+                }
+            };
+            let mut conn = req.state().db_pool.acquire().await?;
+            let s = ApiToken::new(
+                crate::error::parse_key(claims.organization_key.as_str(), "organization")?,
+                crate::error::parse_key(claims.key.as_str(), "user")?,
+                name,
+                secret_hash,
+                request.scope,
+                expires,
+            );
+            insert_api_token(&mut conn, &s).await?;
+            let created = CreatedApiToken {
+                token: format!("{}{}_{}", TOKEN_PREFIX, s.key.simple(), secret),
+                api_token: s,
+            };
+            let j = serde_json::to_string(&created).expect("To JSON");
+            Ok(tide::Response::builder(tide::StatusCode::Created)
+                .content_type(mime::JSON)
+                .body(j)
+                .build())
+        }
+        Err(e) => Err(AppError::Validation(format!("invalid json body: {}", e)).into()),
+    }
+}
+
+pub async fn delete(req: Request<State>) -> tide::Result {
+    let u = match user::user_or_error(&req) {
+        Ok(value) => value,
+        Err(e) => return e,
+    };
+    let key = match req.param("token_id").map(uuid::Uuid::from_str) {
+        Ok(Ok(k)) => k,
+        _ => {
+            return Ok(tide::Response::builder(tide::StatusCode::NotFound)
+                .content_type(mime::HTML)
+                .body(NotFoundTemplate::new().render_string())
+                .build())
+        }
+    };
+    let mut conn = req.state().db_pool.acquire().await?;
+    match revoke_api_token(&mut conn, key, u.key).await {
+        Ok(r) if r.rows_affected() > 0 => Ok(tide::Response::builder(tide::StatusCode::Ok)
+            .content_type(mime::HTML)
+            .build()),
+        Ok(_) => Ok(tide::Response::builder(tide::StatusCode::NotFound)
+            .content_type(mime::HTML)
+            .body(NotFoundTemplate::new().render_string())
+            .build()),
+        Err(_) => Ok(
+            tide::Response::builder(tide::StatusCode::InternalServerError)
+                .content_type(mime::HTML)
+                .body(NotFoundTemplate::new().render_string())
+                .build(),
+        ),
+    }
+}
+
+// data types
+
+#[derive(PartialEq, Debug, Deserialize, Serialize, Clone, Copy, sqlx::Type, EnumIter)]
+pub enum TokenScope {
+    Read,
+    Write,
+    Admin,
+}
+
+impl TokenScope {
+    // the most a token with this scope can do, whatever the owner's role
+    fn ceiling(&self) -> Role {
+        match self {
+            TokenScope::Read => Role::Guest,
+            TokenScope::Write => Role::Member,
+            TokenScope::Admin => Role::Admin,
+        }
+    }
+
+    // roles go from Owner (0) down, so the weaker of the two is the larger number
+    pub fn cap(&self, role: Role) -> Role {
+        i16::from(role).max(i16::from(self.ceiling())).into()
+    }
+}
+
+impl Into<TokenScope> for i16 {
+    fn into(self) -> TokenScope {
+        match self {
+            0 => TokenScope::Read,
+            1 => TokenScope::Write,
+            2 => TokenScope::Admin,
+            _ => TokenScope::Read,
+        }
+    }
+}
+
+impl From<TokenScope> for i16 {
+    fn from(s: TokenScope) -> Self {
+        match s {
+            TokenScope::Read => 0,
+            TokenScope::Write => 1,
+            TokenScope::Admin => 2,
+        }
+    }
+}
+
+impl FromStr for TokenScope {
+    type Err = ();
+    fn from_str(input: &str) -> Result<TokenScope, Self::Err> {
+        match input {
+            "Read" => Ok(TokenScope::Read),
+            "Write" => Ok(TokenScope::Write),
+            "Admin" => Ok(TokenScope::Admin),
+            _ => Err(()),
+        }
+    }
+}
+
+impl ToString for TokenScope {
+    fn to_string(&self) -> String {
+        match self {
+            TokenScope::Read => "Read".to_owned(),
+            TokenScope::Write => "Write".to_owned(),
+            TokenScope::Admin => "Admin".to_owned(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ApiTokenRequest {
+    pub name: String,
+    pub scope: TokenScope,
+    #[serde(default)]
+    pub expires_in_days: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CreatedApiToken {
+    pub token: String,
+    pub api_token: ApiToken,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ApiToken {
+    pub key: uuid::Uuid,
+    pub organization_key: uuid::Uuid,
+    pub user_key: uuid::Uuid,
+    pub name: String,
+    #[serde(skip_serializing)]
+    pub secret_hash: String,
+    pub scope: TokenScope,
+    pub last_used: i64,
+    pub expires: i64,
+    pub revoked: i64,
+    pub created: i64,
+    pub updated: i64,
+}
+
+impl ApiToken {
+    pub fn new(
+        organization_key: uuid::Uuid,
+        user_key: uuid::Uuid,
+        name: String,
+        secret_hash: String,
+        scope: TokenScope,
+        expires: i64,
+    ) -> Self {
+        let key = Uuid::new_v4();
+        let created = chrono::Utc::now().timestamp();
+        let updated = 0;
+        Self {
+            key,
+            organization_key,
+            user_key,
+            name,
+            secret_hash,
+            scope,
+            last_used: 0,
+            expires,
+            revoked: 0,
+            created,
+            updated,
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.revoked == 0 && (self.expires == 0 || self.expires > chrono::Utc::now().timestamp())
+    }
+}
+
+#[derive(Template)]
+#[template(path = "api_tokens.html")]
+pub struct ApiTokenTemplate {
+    user: crate::user::User,
+    tokens: Vec<ApiToken>,
+}
+
+impl<'a> ApiTokenTemplate {
+    pub fn new(user: crate::user::User, tokens: Vec<ApiToken>) -> Self {
+        return Self { user, tokens };
+    }
+
+    pub fn date<'aa>(&'aa self, timestamp: &i64) -> String {
+        if *timestamp == 0 {
+            return "Never".to_string();
+        }
+        match chrono::NaiveDateTime::from_timestamp_opt(*timestamp, 0) {
+            Some(d) => d.format("%Y-%m-%d %H:%M").to_string(),
+            None => "".to_string(),
+        }
+    }
+
+    pub fn render_string(&self) -> String {
+        return self.render().unwrap();
+    }
+}
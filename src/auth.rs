@@ -0,0 +1,108 @@
+use async_trait::async_trait;
+use tide::{http::mime, Middleware, Next, Request};
+
+use std::str::FromStr;
+
+use sqlx::pool::PoolConnection;
+use sqlx::Postgres;
+
+use crate::error::AppError;
+use crate::member::{self, Role};
+use crate::{api_token, user, State};
+
+// Route level authorization. Every route in main.rs that needs a logged in user is wrapped
+// with an Authorize for the permission it needs. The user comes from the JWT or, for
+// scripts, from an `Authorization: Bearer` API token, and either way the role is the one
+// organization_members holds now, not the one they logged in with.
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum Permission {
+    View,
+    Edit,
+    Delete,
+    Administer,
+    Own,
+}
+
+impl Permission {
+    pub fn granted_to(&self, role: Role) -> bool {
+        match role {
+            Role::Owner => true,
+            Role::Admin => *self != Permission::Own,
+            Role::Member => matches!(self, Permission::View | Permission::Edit | Permission::Delete),
+            Role::Guest | Role::Client => *self == Permission::View,
+        }
+    }
+}
+
+pub struct Authorize {
+    permission: Permission,
+}
+
+impl Authorize {
+    pub fn new(permission: Permission) -> Self {
+        Self { permission }
+    }
+}
+
+#[async_trait]
+impl Middleware<State> for Authorize {
+    async fn handle(&self, mut req: Request<State>, next: Next<'_, State>) -> tide::Result {
+        let api = req.url().path().starts_with("/api/");
+        let bearer = req.header("Authorization").map(|h| h.as_str().to_string());
+        let claims = match bearer.as_deref() {
+            Some(header) => {
+                let mut conn = req.state().db_pool.acquire().await?;
+                api_token::authenticate(&mut conn, header).await?
+            }
+            None => match user::read_jwt_cookie(req.cookie("token")) {
+                Some(c) => {
+                    let mut conn = req.state().db_pool.acquire().await?;
+                    with_current_role(&mut conn, c).await
+                }
+                None => None,
+            },
+        };
+        let claims = match claims {
+            Some(c) => c,
+            None => {
+                if api {
+                    return Err(AppError::Unauthorized.into());
+                }
+                if req.method() == tide::http::Method::Get && bearer.is_none() {
+                    return Ok(tide::Redirect::new("/login").into());
+                }
+                return Ok(tide::Response::builder(tide::StatusCode::Unauthorized)
+                    .content_type(mime::PLAIN)
+                    .body("UNAUTHORIZED")
+                    .build());
+            }
+        };
+        if let Some(value) = user::user_jwt_state_invalid(claims.clone()) {
+            if api {
+                return Err(AppError::Unauthorized.into());
+            }
+            return value;
+        }
+        if !self.permission.granted_to(claims.role) {
+            if api {
+                return Err(AppError::Forbidden("your role does not allow this".to_string()).into());
+            }
+            return Ok(tide::Response::builder(tide::StatusCode::Forbidden)
+                .content_type(mime::PLAIN)
+                .body("FORBIDDEN")
+                .build());
+        }
+        req.set_ext(claims);
+        Ok(next.run(req).await)
+    }
+}
+
+// The session's claims with the role changed to the user's current one, None once they are no
+// longer a member of the organization the session is for
+async fn with_current_role(conn: &mut PoolConnection<Postgres>, mut claims: user::UserJwtState) -> Option<user::UserJwtState> {
+    let organization_key = uuid::Uuid::from_str(&claims.organization_key).ok()?;
+    let user_key = uuid::Uuid::from_str(&claims.key).ok()?;
+    claims.role = member::get_role(conn, organization_key, user_key).await?;
+    Some(claims)
+}
@@ -0,0 +1,480 @@
+use std::collections::HashMap;
+
+use askama::Template;
+use serde::{Deserialize, Serialize};
+use sqlx::pool::PoolConnection;
+use sqlx::postgres::PgQueryResult;
+use sqlx::{Connection, Postgres};
+use uuid::Uuid;
+
+use tide::{http::mime, Request};
+
+use crate::board::Board;
+use crate::dependency::Dependency;
+use crate::error::AppError;
+use crate::file::AssociationType;
+use crate::filter::{Filter, Term};
+use crate::matrix::post_project_create;
+use crate::milestone::Milestone;
+use crate::note::Note;
+use crate::project::Project;
+use crate::task::Task;
+use crate::{user, State};
+
+// SQL STUFF
+
+pub async fn get_organization_blueprints(conn: &mut PoolConnection<Postgres>, organization_key: uuid::Uuid) -> Result<Vec<Blueprint>, AppError> {
+    let records = sqlx::query!(
+        "select key, organization_key, owner_key, name, description, content, created, updated from blueprints where organization_key = $1 order by name",
+        organization_key
+    )
+    .fetch_all(conn)
+    .await?;
+    let mut blueprints = vec![];
+    for b in records {
+        blueprints.push(Blueprint {
+            key: b.key,
+            organization_key: b.organization_key,
+            owner_key: b.owner_key,
+            name: b.name,
+            description: b.description,
+            content: b.content,
+            created: b.created,
+            updated: b.updated,
+        })
+    }
+    Ok(blueprints)
+}
+
+pub async fn get_blueprint(conn: &mut PoolConnection<Postgres>, key: uuid::Uuid, organization_key: uuid::Uuid) -> Result<Option<Blueprint>, AppError> {
+    let b = match sqlx::query!(
+        "select key, organization_key, owner_key, name, description, content, created, updated from blueprints where key = $1 AND organization_key = $2",
+        key,
+        organization_key
+    )
+    .fetch_optional(conn)
+    .await?
+    {
+        Some(r) => r,
+        None => return Ok(None),
+    };
+
+    Ok(Some(Blueprint {
+        key: b.key,
+        organization_key: b.organization_key,
+        owner_key: b.owner_key,
+        name: b.name,
+        description: b.description,
+        content: b.content,
+        created: b.created,
+        updated: b.updated,
+    }))
+}
+
+async fn insert_blueprint(conn: &mut PoolConnection<Postgres>, new_blueprint: &Blueprint) -> Result<(), AppError> {
+    sqlx::query!("INSERT INTO blueprints (key, organization_key, owner_key, name, description, content, created, updated) values($1, $2, $3, $4, $5, $6, $7, $8)",
+        new_blueprint.key,
+        new_blueprint.organization_key,
+        new_blueprint.owner_key,
+        &new_blueprint.name,
+        &new_blueprint.description,
+        &new_blueprint.content,
+        new_blueprint.created,
+        new_blueprint.updated,
+    )
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+async fn delete_blueprint(conn: &mut PoolConnection<Postgres>, key: uuid::Uuid, organization_key: uuid::Uuid) -> Result<PgQueryResult, sqlx::Error> {
+    return sqlx::query!("DELETE FROM blueprints where key=$1 AND organization_key=$2", key, organization_key)
+        .execute(conn)
+        .await;
+}
+
+// Route Stuff
+
+pub async fn get(req: Request<State>) -> tide::Result {
+    let u = match user::user_or_error(&req) {
+        Ok(value) => value,
+        Err(_) => return Ok(tide::Redirect::new("/login").into()),
+    };
+    let mut conn = req.state().db_pool.acquire().await?;
+    let blueprints = get_organization_blueprints(&mut conn, u.organization_key).await?;
+    let users = user::get_users_by_organization(&mut conn, u.organization_key).await?;
+    Ok(tide::Response::builder(tide::StatusCode::Ok)
+        .content_type(mime::HTML)
+        .body(BlueprintTemplate::new(u, blueprints, users).render_string())
+        .build())
+}
+
+// Saves a copy of the project as it is now, changing the project later doesn't change the template
+pub async fn insert(mut req: Request<State>) -> tide::Result {
+    let u = match user::user_or_error(&req) {
+        Ok(value) => value,
+        Err(e) => return e,
+    };
+    let project_key = crate::error::param_key(&req, "project_id")?;
+    let request: BlueprintRequest = req.body_json().await.map_err(|e| AppError::Validation(e.to_string()))?;
+    let mut conn = req.state().db_pool.acquire().await?;
+    let project = match crate::project::get_project(&mut conn, project_key, u.organization_key).await? {
+        Some(p) => p,
+        None => return Err(AppError::NotFound("project".to_string()).into()),
+    };
+    let milestones = crate::milestone::get_milestones_by_project(&mut conn, project_key, u.organization_key).await?;
+    let tasks = crate::task::get_tasks_by_project(&mut conn, project_key, u.organization_key).await?;
+    let mut dependencies = crate::dependency::get_organization_dependencies(&mut conn, u.organization_key).await?;
+    dependencies.retain(|d| tasks.iter().any(|t| t.key == d.task_key) && tasks.iter().any(|t| t.key == d.depends_on_key));
+    let mut boards = crate::board::get_organization_boards(&mut conn, u.organization_key).await?;
+    boards.retain(|b| shows_project(b, &project));
+    let notes = crate::note::get_associated_notes(&mut conn, u.organization_key, AssociationType::Project, project_key).await?;
+    let name = match request.name.trim() {
+        "" => project.name.clone(),
+        n => n.to_string(),
+    };
+    let content = BlueprintContent {
+        project,
+        milestones,
+        tasks,
+        dependencies,
+        boards,
+        notes,
+    };
+    let b = Blueprint::new(u.organization_key, u.key, name, request.description, serde_json::to_string(&content).expect("To JSON"));
+    insert_blueprint(&mut conn, &b).await?;
+    let j = serde_json::to_string(&b).expect("To JSON");
+    Ok(tide::Response::builder(tide::StatusCode::Created)
+        .content_type(mime::JSON)
+        .body(j)
+        .build())
+}
+
+// A new project from a template, all of it or none of it
+pub async fn instantiate(mut req: Request<State>) -> tide::Result {
+    let claims: user::UserJwtState = match user::request_claims(&req) {
+        Some(c) => c,
+        None => {
+            return Ok(tide::Redirect::new("/login").into());
+        }
+    };
+    let organization_key = crate::error::parse_key(claims.organization_key.as_str(), "organization")?;
+    let owner_key = crate::error::parse_key(claims.key.as_str(), "user")?;
+    let blueprint_key = crate::error::param_key(&req, "blueprint_id")?;
+    let request: InstantiateRequest = req.body_json().await.map_err(|e| AppError::Validation(e.to_string()))?;
+    if request.name.trim().is_empty() {
+        return Err(AppError::Validation("the new project needs a name".to_string()).into());
+    }
+    let mut conn = req.state().db_pool.acquire().await?;
+    let blueprint = match get_blueprint(&mut conn, blueprint_key, organization_key).await? {
+        Some(b) => b,
+        None => return Err(AppError::NotFound("template".to_string()).into()),
+    };
+    let users = user::get_users_by_organization(&mut conn, organization_key).await?;
+    if let Some(k) = request.assignees.values().find(|k| !k.is_nil() && users.iter().all(|u| u.key != **k)) {
+        return Err(AppError::Validation(format!("{} isn't a member of the organization", k)).into());
+    }
+    let workflow = crate::workflow::get_workflow(&mut conn, organization_key).await?;
+    let status_key = workflow.initial().ok_or(AppError::Validation("the workflow has no statuses".to_string()))?;
+    let copy = blueprint.content()?.instantiate(
+        organization_key,
+        owner_key,
+        request.name.trim().to_string(),
+        request.start,
+        &request.assignees,
+        status_key,
+    );
+
+    let mut tx = conn.begin().await?;
+    crate::project::insert_project(&mut tx, &copy.project).await?;
+    for m in copy.milestones.iter() {
+        crate::milestone::insert_milestone(&mut tx, m).await?;
+    }
+    for t in copy.tasks.iter() {
+        crate::task::insert_task(&mut tx, t).await?;
+    }
+    for d in copy.dependencies.iter() {
+        crate::dependency::insert_dependency(&mut tx, d).await?;
+    }
+    for b in copy.boards.iter() {
+        crate::board::insert_board(&mut tx, b).await?;
+    }
+    for n in copy.notes.iter() {
+        crate::note::insert_note(&mut tx, n).await?;
+    }
+    tx.commit().await?;
+
+    // the project is there now, a room that couldn't be told about it doesn't undo that
+    crate::api::report_matrix(
+        post_project_create(&mut conn, claims.matrix_home_server, claims.matrix_user_id, organization_key, claims.matrix_access_token, &copy.project).await,
+    );
+    let j = serde_json::to_string(&copy.project).expect("To JSON");
+    Ok(tide::Response::builder(tide::StatusCode::Created)
+        .content_type(mime::JSON)
+        .body(j)
+        .build())
+}
+
+pub async fn delete(req: Request<State>) -> tide::Result {
+    let u = match user::user_or_error(&req) {
+        Ok(value) => value,
+        Err(e) => return e,
+    };
+    let key = crate::error::param_key(&req, "blueprint_id")?;
+    let mut conn = req.state().db_pool.acquire().await?;
+    match delete_blueprint(&mut conn, key, u.organization_key).await.map_err(AppError::from)? {
+        r if r.rows_affected() > 0 => Ok(tide::Response::builder(tide::StatusCode::Ok)
+            .content_type(mime::JSON)
+            .body("{}")
+            .build()),
+        _ => Err(AppError::NotFound("template".to_string()).into()),
+    }
+}
+
+// Boards aren't tied to a project, a board is the project's when its filter picks the project out by name or key
+fn shows_project(board: &Board, project: &Project) -> bool {
+    match Filter::parse(board.filter.as_str()) {
+        Ok(f) => f.terms.iter().any(|t| is_project(t, project)),
+        Err(_) => false,
+    }
+}
+
+fn is_project(term: &Term, project: &Project) -> bool {
+    match term {
+        Term::Project(value) => *value == project.key.to_string() || value.to_lowercase() == project.name.to_lowercase(),
+        _ => false,
+    }
+}
+
+// data types
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct BlueprintRequest {
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct InstantiateRequest {
+    pub name: String,
+    // the day the new project starts, everything moves by as much as the project's start does
+    pub start: i64,
+    // who gets the tasks of whom, anyone left out keeps their tasks
+    #[serde(default)]
+    pub assignees: HashMap<Uuid, Uuid>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Blueprint {
+    pub key: uuid::Uuid,
+    pub organization_key: uuid::Uuid,
+    pub owner_key: uuid::Uuid,
+    pub name: String,
+    pub description: String,
+    #[serde(skip_serializing)]
+    pub content: String,
+    pub created: i64,
+    pub updated: i64,
+}
+
+impl Blueprint {
+    pub fn new(organization_key: uuid::Uuid, owner_key: uuid::Uuid, name: String, description: String, content: String) -> Self {
+        let key = Uuid::new_v4();
+        let created = chrono::Utc::now().timestamp();
+        let updated = 0;
+        Self {
+            key,
+            organization_key,
+            owner_key,
+            name,
+            description,
+            content,
+            created,
+            updated,
+        }
+    }
+
+    pub fn content(&self) -> Result<BlueprintContent, AppError> {
+        serde_json::from_str(self.content.as_str()).map_err(|e| AppError::Validation(format!("template {} can't be read: {}", self.name, e)))
+    }
+}
+
+// Everything a template copies, kept as JSON in the blueprints table
+#[derive(Debug, Deserialize, Serialize)]
+pub struct BlueprintContent {
+    pub project: Project,
+    pub milestones: Vec<Milestone>,
+    pub tasks: Vec<Task>,
+    pub dependencies: Vec<Dependency>,
+    pub boards: Vec<Board>,
+    pub notes: Vec<Note>,
+}
+
+impl BlueprintContent {
+    // the date everything else is measured from, the project's start or else the earliest date in it
+    fn anchor(&self) -> i64 {
+        if self.project.start != 0 {
+            return self.project.start;
+        }
+        [self.project.due]
+            .into_iter()
+            .chain(self.milestones.iter().flat_map(|m| [m.start, m.due]))
+            .chain(self.tasks.iter().flat_map(|t| [t.start, t.due]))
+            .filter(|d| *d != 0)
+            .min()
+            .unwrap_or(0)
+    }
+
+    pub fn assignees(&self) -> Vec<Uuid> {
+        let mut keys: Vec<Uuid> = vec![];
+        for t in self.tasks.iter() {
+            if !t.assignee_key.is_nil() && !keys.contains(&t.assignee_key) {
+                keys.push(t.assignee_key);
+            }
+        }
+        keys
+    }
+
+    // Fresh copies of everything with new keys, dates moved so the project starts on `start` and
+    // tasks handed over per `assignees`. Tasks start over in the workflow's first status, parents
+    // come before their subtasks so they can be inserted in order.
+    pub fn instantiate(
+        &self,
+        organization_key: uuid::Uuid,
+        owner_key: uuid::Uuid,
+        name: String,
+        start: i64,
+        assignees: &HashMap<Uuid, Uuid>,
+        status_key: uuid::Uuid,
+    ) -> BlueprintContent {
+        let anchor = self.anchor();
+        let offset = if start == 0 || anchor == 0 { 0 } else { start - anchor };
+        let shift = |d: i64| if d == 0 { 0 } else { d + offset };
+        let p = &self.project;
+        let project = Project::new(organization_key, owner_key, name, p.description.clone(), p.tags.clone(), p.estimated_quarter_days, shift(p.start), shift(p.due));
+
+        let mut keys: HashMap<Uuid, Uuid> = HashMap::new();
+        let mut milestones = vec![];
+        for m in self.milestones.iter() {
+            let copy = Milestone::new(organization_key, owner_key, project.key, m.name.clone(), m.description.clone(), m.tags.clone(), m.estimated_quarter_days, shift(m.start), shift(m.due));
+            keys.insert(m.key, copy.key);
+            milestones.push(copy);
+        }
+
+        let mut waiting: Vec<&Task> = self.tasks.iter().collect();
+        waiting.sort_by_key(|t| t.rank);
+        let mut tasks = vec![];
+        while !waiting.is_empty() {
+            let (ready, rest): (Vec<&Task>, Vec<&Task>) = waiting
+                .into_iter()
+                .partition(|t| t.parent_key.is_none_or(|k| keys.contains_key(&k) || self.tasks.iter().all(|o| o.key != k)));
+            // a parent that never turns up, don't loop on it
+            let ready = if ready.is_empty() { rest.clone() } else { ready };
+            waiting = rest.into_iter().filter(|t| !ready.iter().any(|r| r.key == t.key)).collect();
+            for t in ready {
+                let copy = Task::new(
+                    organization_key,
+                    project.key,
+                    t.parent_key.and_then(|k| keys.get(&k).copied()),
+                    t.milestone_key.and_then(|k| keys.get(&k).copied()),
+                    owner_key,
+                    assignees.get(&t.assignee_key).copied().unwrap_or(t.assignee_key),
+                    t.name.clone(),
+                    t.description.clone(),
+                    t.tags.clone(),
+                    status_key,
+                    t.estimated_quarter_days,
+                    shift(t.start),
+                    shift(t.due),
+                );
+                keys.insert(t.key, copy.key);
+                tasks.push(copy);
+            }
+        }
+
+        let dependencies = self
+            .dependencies
+            .iter()
+            .filter_map(|d| match (keys.get(&d.task_key), keys.get(&d.depends_on_key)) {
+                (Some(task_key), Some(depends_on_key)) => Some(Dependency::new(organization_key, *task_key, *depends_on_key, d.kind)),
+                _ => None,
+            })
+            .collect();
+
+        let boards = self
+            .boards
+            .iter()
+            .map(|b| {
+                let filter = match Filter::parse(b.filter.as_str()) {
+                    Ok(f) => Filter {
+                        terms: f
+                            .terms
+                            .into_iter()
+                            .map(|t| if is_project(&t, p) { Term::Project(project.key.to_string()) } else { t })
+                            .collect(),
+                    }
+                    .to_string(),
+                    Err(_) => b.filter.clone(),
+                };
+                let name = match p.name.as_str() {
+                    "" => b.name.clone(),
+                    old => b.name.replace(old, project.name.as_str()),
+                };
+                Board::new(organization_key, owner_key, name, b.description.clone(), b.columns.clone(), b.lanes.clone(), filter, b.swimlane, b.wip_limits.clone())
+            })
+            .collect();
+
+        let notes = self
+            .notes
+            .iter()
+            .map(|n| Note::new(organization_key, owner_key, AssociationType::Project, project.key, n.url.clone(), n.title.clone(), n.content.clone()))
+            .collect();
+
+        BlueprintContent {
+            project,
+            milestones,
+            tasks,
+            dependencies,
+            boards,
+            notes,
+        }
+    }
+}
+
+#[derive(Template)]
+#[template(path = "blueprint.html")]
+pub struct BlueprintTemplate {
+    user: crate::user::User,
+    blueprints: Vec<Blueprint>,
+    users: Vec<crate::user::User>,
+}
+
+impl<'a> BlueprintTemplate {
+    pub fn new(user: crate::user::User, blueprints: Vec<Blueprint>, users: Vec<crate::user::User>) -> Self {
+        return Self { user, blueprints, users };
+    }
+
+    pub fn assignees<'aa>(&'aa self, blueprint: &Blueprint) -> Vec<Uuid> {
+        blueprint.content().map(|c| c.assignees()).unwrap_or_default()
+    }
+
+    pub fn summary<'aa>(&'aa self, blueprint: &Blueprint) -> String {
+        match blueprint.content() {
+            Ok(c) => format!("{} milestones, {} tasks, {} boards and {} notes", c.milestones.len(), c.tasks.len(), c.boards.len(), c.notes.len()),
+            Err(e) => e.to_string(),
+        }
+    }
+
+    pub fn author<'aa>(&'aa self, key: &Uuid) -> String {
+        match self.users.iter().find(|u| u.key == *key) {
+            Some(u) => u.to_string(),
+            None => "someone who left".to_string(),
+        }
+    }
+
+    pub fn render_string(&self) -> String {
+        return self.render().unwrap();
+    }
+}
@@ -1,120 +1,171 @@
-use std::str::FromStr;
-
 use askama::Template;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use sqlx::pool::PoolConnection;
-use sqlx::Postgres;
+use sqlx::{PgConnection, Postgres};
 use sqlx::postgres::PgQueryResult;
-use uuid::Uuid;
 use strum::IntoEnumIterator;
+use strum_macros::EnumIter;
+use uuid::Uuid;
 
 use tide::{http::mime, Request}; 
+use crate::error::AppError;
+use crate::filter::{get_filtered_tasks, Filter};
 use crate::home::NotFoundTemplate;
 use crate::matrix::post_board_create;
-use crate::task::{TaskStatus, Task, get_tasks_by_organization};
+use crate::checklist::ChecklistItem;
+use crate::task::{Task, Rollup, get_descendant_tasks, get_tasks_by_organization};
+use crate::milestone::Milestone;
+use crate::project::Project;
+use crate::workflow::Workflow;
 use crate::{State, user};
  
 // SQL STUFF
 
-pub async fn get_organization_boards(conn: &mut PoolConnection<Postgres>, organization_key: uuid::Uuid) -> Vec<Board> {
+pub async fn get_organization_boards(conn: &mut PoolConnection<Postgres>, organization_key: uuid::Uuid) -> Result<Vec<Board>, AppError> {
     let records = sqlx::query!(
-        "select key, owner_key, organization_key, name, description, columns, lanes, filter, created, updated from boards where organization_key = $1",
+        "select key, owner_key, organization_key, name, description, columns, lanes, filter, swimlane, wip_limits, created, updated from boards where organization_key = $1",
         organization_key
     )
     .fetch_all(conn)
-    .await
-    .expect("Select project by key");
+    .await?;
     let mut boards = Vec::<Board>::new();
     for board in records {
         let brd = Board {
-            key: board.key.expect("key exists"),
-            organization_key: board.organization_key.expect("organization_key"), 
-            owner_key: board.owner_key.expect("owner_key exists"),
-            name: board.name.expect("name exists"),
-            description: board.description.expect("description exists"), 
-            columns: board.columns.expect("columns"),
-            lanes: board.lanes.expect("columns exists"), 
-            filter: board.filter.expect("filter"),
-            created: board.created.expect("created exists"),
-            updated: board.updated.expect("updated exists"),
+            key: board.key,
+            organization_key: board.organization_key, 
+            owner_key: board.owner_key,
+            name: board.name,
+            description: board.description, 
+            columns: board.columns,
+            lanes: board.lanes, 
+            filter: board.filter,
+            swimlane: board.swimlane.into(),
+            wip_limits: board.wip_limits,
+            created: board.created,
+            updated: board.updated,
         };
         boards.push(brd);
     }
-    return boards
+    Ok(boards)
 }
 
-pub async fn get_user_boards(conn: &mut PoolConnection<Postgres>, user_key: uuid::Uuid) -> Vec<Board> {
+pub async fn get_organization_boards_page(conn: &mut PoolConnection<Postgres>, organization_key: uuid::Uuid, limit: i64, offset: i64) -> Result<Vec<Board>, AppError> {
     let records = sqlx::query!(
-        "select  key, owner_key, organization_key, name, description, columns, lanes, filter, created, updated from boards where owner_key = $1",
-        user_key
+        "select key, owner_key, organization_key, name, description, columns, lanes, filter, swimlane, wip_limits, created, updated from boards where organization_key = $1 order by created, key limit $2 offset $3",
+        organization_key, limit, offset
     )
     .fetch_all(conn)
-    .await
-    .expect("Select project by key");
+    .await?;
     let mut boards = Vec::<Board>::new();
     for board in records {
-        let brd: Board = Board {
-            key: board.key.expect("key exists"),
-            organization_key: board.organization_key.expect("organization_key"), 
-            owner_key: board.owner_key.expect("owner_key exists"),
-            name: board.name.expect("name exists"),
-            description: board.description.expect("description exists"), 
-            columns: board.columns.expect("columns"),
-            lanes: board.lanes.expect("columns exists"), 
-            filter: board.filter.expect("filter"),
-            created: board.created.expect("created exists"),
-            updated: board.updated.expect("updated exists"),
+        let brd = Board {
+            key: board.key,
+            organization_key: board.organization_key, 
+            owner_key: board.owner_key,
+            name: board.name,
+            description: board.description, 
+            columns: board.columns,
+            lanes: board.lanes, 
+            filter: board.filter,
+            swimlane: board.swimlane.into(),
+            wip_limits: board.wip_limits,
+            created: board.created,
+            updated: board.updated,
         };
         boards.push(brd);
     }
-    return boards
+    Ok(boards)
 }
 
-pub async fn get_board(conn: &mut PoolConnection<Postgres>, key: uuid::Uuid) -> Board {
-    let board = sqlx::query!(
-        "select key, owner_key, organization_key, name, description, columns, lanes, filter, created, updated from boards where key = $1",
-        key
+pub async fn count_organization_boards(conn: &mut PoolConnection<Postgres>, organization_key: uuid::Uuid) -> Result<i64, AppError> {
+    let record = sqlx::query!(r#"select count(*) as "count!" from boards where organization_key = $1"#, organization_key)
+        .fetch_one(conn)
+        .await?;
+    Ok(record.count)
+}
+
+pub async fn get_user_boards(conn: &mut PoolConnection<Postgres>, user_key: uuid::Uuid, organization_key: uuid::Uuid) -> Result<Vec<Board>, AppError> {
+    let records = sqlx::query!(
+        "select  key, owner_key, organization_key, name, description, columns, lanes, filter, swimlane, wip_limits, created, updated from boards where owner_key = $1 AND organization_key = $2",
+        user_key, organization_key
     )
-    .fetch_one(conn)
-    .await
-    .expect("Select board by key");
-
-    Board {
-        key: board.key.expect("key exists"),
-        organization_key: board.organization_key.expect("organization_key"), 
-        owner_key: board.owner_key.expect("owner_key exists"),
-        name: board.name.expect("name exists"),
-        description: board.description.expect("description exists"),
-        columns: board.columns.expect("columns exists"), 
-        lanes: board.lanes.expect("columns exists"), 
-        filter: board.filter.expect("filter"),
-        created: board.created.expect("created exists"),
-        updated: board.updated.expect("updated exists"),
+    .fetch_all(conn)
+    .await?;
+    let mut boards = Vec::<Board>::new();
+    for board in records {
+        let brd: Board = Board {
+            key: board.key,
+            organization_key: board.organization_key, 
+            owner_key: board.owner_key,
+            name: board.name,
+            description: board.description, 
+            columns: board.columns,
+            lanes: board.lanes, 
+            filter: board.filter,
+            swimlane: board.swimlane.into(),
+            wip_limits: board.wip_limits,
+            created: board.created,
+            updated: board.updated,
+        };
+        boards.push(brd);
     }
+    Ok(boards)
+}
+
+pub async fn get_board(conn: &mut PoolConnection<Postgres>, key: uuid::Uuid, organization_key: uuid::Uuid) -> Result<Option<Board>, AppError> {
+    let board = match sqlx::query!(
+        "select key, owner_key, organization_key, name, description, columns, lanes, filter, swimlane, wip_limits, created, updated from boards where key = $1 AND organization_key = $2",
+        key, organization_key
+    )
+    .fetch_optional(conn)
+    .await?
+    {
+        Some(r) => r,
+        None => return Ok(None),
+    };
+
+    Ok(Some(Board {
+        key: board.key,
+        organization_key: board.organization_key, 
+        owner_key: board.owner_key,
+        name: board.name,
+        description: board.description,
+        columns: board.columns, 
+        lanes: board.lanes, 
+        filter: board.filter,
+        swimlane: board.swimlane.into(),
+        wip_limits: board.wip_limits,
+        created: board.created,
+        updated: board.updated,
+    }))
 }
 
-async fn delete_board(conn: &mut PoolConnection<Postgres>, key: uuid::Uuid, owner_key: uuid::Uuid) -> Result<PgQueryResult, sqlx::Error> {
-    return sqlx::query!("DELETE FROM boards where owner_key=$1 AND key=$2", owner_key, key)
+pub(crate) async fn delete_board(conn: &mut PoolConnection<Postgres>, key: uuid::Uuid, organization_key: uuid::Uuid) -> Result<PgQueryResult, sqlx::Error> {
+    return sqlx::query!("DELETE FROM boards where organization_key=$1 AND key=$2", organization_key, key)
     .execute(conn)
     .await;
 }
-async fn update_board(conn: &mut PoolConnection<Postgres>, board: &Board) {
+pub(crate) async fn update_board(conn: &mut PoolConnection<Postgres>, board: &Board) -> Result<(), AppError> {
     sqlx::query!("UPDATE boards SET name=$1, description=$2, columns=$3, 
-    lanes=$4, filter=$5 where key=$6",  
+    lanes=$4, filter=$5, swimlane=$8, wip_limits=$9 where key=$6 AND organization_key=$7",  
     &board.name,
     &board.description,
     &board.columns, 
     &board.lanes, 
     &board.filter, 
     board.key,
+    board.organization_key,
+    board.swimlane as i16,
+    &board.wip_limits,
 )
 .execute(conn)
-.await
-.expect("Insert Success");
+.await?;
+    Ok(())
 }
 
-async fn insert_board(conn: &mut PoolConnection<Postgres>, new_board: &Board) {
-    sqlx::query!("INSERT INTO boards (key, organization_key, owner_key, name, description, columns, lanes, filter, created, updated) values($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)", 
+pub(crate) async fn insert_board(conn: &mut PgConnection, new_board: &Board) -> Result<(), AppError> {
+    sqlx::query!("INSERT INTO boards (key, organization_key, owner_key, name, description, columns, lanes, filter, swimlane, wip_limits, created, updated) values($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12)", 
         new_board.key,
         new_board.organization_key, 
         new_board.owner_key,
@@ -123,12 +174,14 @@ async fn insert_board(conn: &mut PoolConnection<Postgres>, new_board: &Board) {
         &new_board.columns, 
         &new_board.lanes, 
         &new_board.filter, 
+        new_board.swimlane as i16,
+        &new_board.wip_limits,
         new_board.created,
         new_board.updated,
     )
     .execute(conn)
-    .await
-    .expect("Insert Success");
+    .await?;
+    Ok(())
 }
 
 
@@ -137,7 +190,9 @@ pub async fn add(req: Request<State>) -> tide::Result {
         Ok(value) => value,
         Err(_) => return Ok(tide::Redirect::new("/login").into()),
     };
-    let mut board= Board::new( u.organization_key, u.key,  "".to_owned(), "".to_owned(), vec![], vec![], "".to_owned());
+    let mut conn = req.state().db_pool.acquire().await?;
+    let workflow = crate::workflow::get_workflow(&mut conn, u.organization_key).await?;
+    let mut board= Board::new( u.organization_key, u.key,  "".to_owned(), "".to_owned(), vec![], vec![], "".to_owned(), Swimlane::None, vec![]);
     board.key = uuid::Uuid::nil();
     Ok(tide::Response::builder(tide::StatusCode::Ok)
     .content_type(mime::HTML)
@@ -147,6 +202,12 @@ pub async fn add(req: Request<State>) -> tide::Result {
             u,
             vec![],
             vec![],
+            "".to_string(),
+            vec![],
+            vec![],
+            vec![],
+            vec![],
+            workflow,
         )
         .render_string(),
     )
@@ -162,8 +223,8 @@ pub async fn delete(req: Request<State>) -> tide::Result {
     match req.param("board_id") {
         Ok(key) => {
             let mut conn = req.state().db_pool.acquire().await?;
-            let s_uuid = uuid::Uuid::from_str(key).expect("Board uuid parse");
-            match delete_board(&mut conn, s_uuid, u.key).await {
+            let s_uuid = crate::error::parse_key(key, "board")?;
+            match delete_board(&mut conn, s_uuid, u.organization_key).await {
                 Ok(_) =>    Ok(tide::Response::builder(tide::StatusCode::Ok)
                 .content_type(mime::HTML)
                 .build()),
@@ -184,7 +245,7 @@ pub async fn delete(req: Request<State>) -> tide::Result {
 }
 
 pub async fn get(req: Request<State>) -> tide::Result {
-    let u = match crate::user::read_jwt_cookie_to_user(req.cookie("token")) {
+    let u = match crate::user::request_user(&req) {
         Some(c) => c,
         None => {
             return Ok(tide::Redirect::new("/login").into());
@@ -194,10 +255,28 @@ pub async fn get(req: Request<State>) -> tide::Result {
     match req.param("board_id") {
         Ok(key) => {
             let mut conn = req.state().db_pool.acquire().await?; // .await? needs to be a real connection pool error handler!!!!!!!!
-            let s_uuid = uuid::Uuid::from_str(key).expect("Board uuid parse");
-            let board = get_board(&mut conn, s_uuid).await;
-            let tasks = get_tasks_by_organization(&mut conn, u.organization_key).await;
-            let users = crate::user::get_users_by_organization(&mut conn, u.organization_key).await;
+            let s_uuid = crate::error::parse_key(key, "board")?;
+            let board = match get_board(&mut conn, s_uuid, u.organization_key).await? {
+                Some(b) => b,
+                None => {
+                    return Ok(tide::Response::builder(tide::StatusCode::NotFound)
+                        .content_type(mime::HTML)
+                        .body(NotFoundTemplate::new().render_string())
+                        .build())
+                }
+            };
+            // a board saved before filters were checked may not parse, it shows everything and says why
+            let (shown, filter_error) = match Filter::parse(&board.filter) {
+                Ok(filter) => (get_filtered_tasks(&mut conn, u.organization_key, u.key, &filter).await?, "".to_string()),
+                Err(e) => (get_tasks_by_organization(&mut conn, u.organization_key).await?, e.public_message()),
+            };
+            let shown_keys: Vec<uuid::Uuid> = shown.iter().map(|t| t.key).collect();
+            let descendants = get_descendant_tasks(&mut conn, u.organization_key, &shown_keys).await?;
+            let users = crate::user::get_users_by_organization(&mut conn, u.organization_key).await?;
+            let checklist = crate::checklist::get_organization_checklists(&mut conn, u.organization_key).await?;
+            let workflow = crate::workflow::get_workflow(&mut conn, u.organization_key).await?;
+            let projects = crate::project::get_projects_by_organization(&mut conn, u.organization_key).await?;
+            let milestones = crate::milestone::get_milestones_by_organization(&mut conn, u.organization_key).await?;
 
             Ok(tide::Response::builder(tide::StatusCode::Ok)
                 .content_type(mime::HTML)
@@ -205,8 +284,14 @@ pub async fn get(req: Request<State>) -> tide::Result {
                     BoardTemplate::new(
                         board,
                         u,
-                        tasks,
+                        descendants,
+                        shown,
+                        filter_error,
                         users,
+                        projects,
+                        milestones,
+                        checklist,
+                        workflow,
                     )
                     .render_string(),
                 )
@@ -223,7 +308,7 @@ pub async fn get(req: Request<State>) -> tide::Result {
 }
 
 pub async fn insert(mut req: Request<State>) -> tide::Result {
-    let claims: user::UserJwtState = match user::read_jwt_cookie(req.cookie("token")) {
+    let claims: user::UserJwtState = match user::request_claims(&req) {
         Some(c) => c,
         None => {
             return Ok(tide::Redirect::new("/login").into());
@@ -231,7 +316,10 @@ pub async fn insert(mut req: Request<State>) -> tide::Result {
     };
     let umd: Result<Board, tide::Error> = req.body_json().await;
     match umd {
-        Ok(board) => {
+        Ok(mut board) => {
+            Filter::parse(&board.filter)?;
+            let organization_key = crate::error::parse_key(claims.organization_key.as_str(), "organization")?;
+            board.organization_key = organization_key;
             let mut conn = match req.state().db_pool.acquire().await {
                 Ok(c) => c,
                 Err(e) => {
@@ -244,17 +332,16 @@ pub async fn insert(mut req: Request<State>) -> tide::Result {
                 }
             };
             if board.key == uuid::Uuid::nil() {
-                let s = Board::new(board.organization_key, board.owner_key, board.name, board.description, board.columns, board.lanes, board.filter);
-                insert_board(&mut conn, &s).await;
-                let organization_key = uuid::Uuid::from_str(claims.organization_key.as_str()).expect("organization key");
-                post_board_create(&mut conn, claims.matrix_home_server, claims.matrix_user_id, organization_key, claims.matrix_access_token, &s).await.expect("Posting to matrix");
+                let s = Board::new(board.organization_key, board.owner_key, board.name, board.description, board.columns, board.lanes, board.filter, board.swimlane, board.wip_limits);
+                insert_board(&mut conn, &s).await?;
+                crate::api::report_matrix(post_board_create(&mut conn, claims.matrix_home_server, claims.matrix_user_id, organization_key, claims.matrix_access_token, &s).await);
                 let j = serde_json::to_string(&s).expect("To JSON");
                 Ok(tide::Response::builder(tide::StatusCode::Ok)
                     .content_type(mime::JSON)
                     .body(j)
                     .build())
             } else {
-                update_board(&mut conn, &board).await;
+                update_board(&mut conn, &board).await?;
                 let j = serde_json::to_string(&board).expect("To JSON");
                 return Ok(tide::Response::builder(tide::StatusCode::Ok)
                     .content_type(mime::JSON)
@@ -262,18 +349,59 @@ pub async fn insert(mut req: Request<State>) -> tide::Result {
                     .build())
             }
         }
-        Err(e) => {
-            println!("{:?}", e);
-            Ok(tide::Response::builder(tide::StatusCode::BadRequest)
-                .content_type(mime::JSON)
-                .body("{'error': 'invalid json body'}")
-                .build())
-        }
+        Err(e) => Err(AppError::Validation(format!("invalid json body: {}", e)).into()),
     }
 }
 
 // data types 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(PartialEq, Debug, Default, Deserialize, Serialize, JsonSchema, Clone, Copy, sqlx::Type, EnumIter)]
+pub enum Swimlane {
+    #[default]
+    None,
+    Assignee,
+    Project,
+    Tag,
+    Milestone,
+}
+
+impl Into<Swimlane> for i16 {
+    fn into(self) -> Swimlane {
+        match self {
+            0 => Swimlane::None,
+            1 => Swimlane::Assignee,
+            2 => Swimlane::Project,
+            3 => Swimlane::Tag,
+            4 => Swimlane::Milestone,
+            _ => Swimlane::None,
+        }
+    }
+}
+
+impl From<Swimlane> for i16 {
+    fn from(s: Swimlane) -> Self {
+        match s {
+            Swimlane::None => 0,
+            Swimlane::Assignee => 1,
+            Swimlane::Project => 2,
+            Swimlane::Tag => 3,
+            Swimlane::Milestone => 4,
+        }
+    }
+}
+
+impl ToString for Swimlane {
+    fn to_string(&self) -> String {
+        match self {
+            Swimlane::None => "None".to_owned(),
+            Swimlane::Assignee => "Assignee".to_owned(),
+            Swimlane::Project => "Project".to_owned(),
+            Swimlane::Tag => "Tag".to_owned(),
+            Swimlane::Milestone => "Milestone".to_owned(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
 pub struct Board {
     pub key: uuid::Uuid,
     pub organization_key: uuid::Uuid,
@@ -283,6 +411,10 @@ pub struct Board {
     pub columns: Vec::<String>,
     pub lanes: Vec::<String>,
     pub filter: String,
+    #[serde(default)]
+    pub swimlane: Swimlane,
+    #[serde(default)]
+    pub wip_limits: Vec::<i32>,
     pub created: i64,
     pub updated: i64,
 }
@@ -296,6 +428,8 @@ impl Board {
          columns: Vec::<String>,
          lanes: Vec::<String>,
         filter: String,
+        swimlane: Swimlane,
+        wip_limits: Vec::<i32>,
     ) -> Self {
         let key = Uuid::new_v4();
         let created = chrono::Utc::now().timestamp();
@@ -309,6 +443,8 @@ impl Board {
             columns,
             lanes,
             filter,
+            swimlane,
+            wip_limits,
             created, 
             updated,
         }
@@ -320,21 +456,40 @@ impl Board {
 pub struct BoardTemplate {
     board: Board,
     user: crate::user::User,
-    tasks: Vec<Task>,
+    // everything below the shown cards, what their children and rollups are made of
+    descendants: Vec<Task>,
+    shown: Vec<Task>,
+    filter_error: String,
     users: Vec<crate::user::User>,
+    projects: Vec<Project>,
+    milestones: Vec<Milestone>,
+    checklist: Vec<ChecklistItem>,
+    workflow: Workflow,
 }
 impl<'a> BoardTemplate {
     pub fn new(
         board: Board,
         user: crate::user::User,
-        tasks: Vec<Task>,
+        descendants: Vec<Task>,
+        shown: Vec<Task>,
+        filter_error: String,
         users: Vec<crate::user::User>,
+        projects: Vec<Project>,
+        milestones: Vec<Milestone>,
+        checklist: Vec<ChecklistItem>,
+        workflow: Workflow,
     ) -> Self {
         return Self {
             board,
             user,
-            tasks,
+            descendants,
+            shown,
+            filter_error,
             users,
+            projects,
+            milestones,
+            checklist,
+            workflow,
         };
     }
 
@@ -342,19 +497,107 @@ impl<'a> BoardTemplate {
         return self.render().unwrap();
     } 
     
-    pub fn lane_name<'aa>(&'aa self, lane: &String) -> String{
-        let st = TaskStatus::from_str(lane).expect("Get status");
-        st.to_string()
+    // lanes are workflow status keys, one that has since been removed just shows up empty
+    pub fn lane_name<'aa>(&'aa self, lane: &str) -> String{
+        match uuid::Uuid::parse_str(lane) {
+            Ok(key) => self.workflow.name(&key),
+            Err(_) => lane.to_string(),
+        }
     }
 
-    pub fn get_tasks<'aa>(&'aa self, lane: String, tasks: &'aa Vec<Task>) -> Vec<&Task> {
-        let st = TaskStatus::from_str(&lane).expect("Get status");
-        let lane_tasks = tasks.iter().filter(|t| t.status == st).collect();
+    // the cards in one cell of the board, a status column crossed with a swimlane
+    pub fn get_tasks<'aa>(&'aa self, lane: &String, swimlane: &String) -> Vec<&'aa Task> {
+        let lane_tasks = self.shown.iter().filter(|t| t.status_key.to_string() == *lane && Self::swimlane_key(self, t) == *swimlane).collect();
         lane_tasks
     }
 
-    pub fn lane_contained(&self, lane: &TaskStatus, lanes: &Vec<String>) -> bool {
-        let s_lane = lane.to_string().replace(" ", "");
-        lanes.contains(&s_lane)
+    pub fn board_tasks<'aa>(&'aa self) -> Vec<&'aa Task> {
+        self.shown.iter().filter(|t| self.board.lanes.contains(&t.status_key.to_string())).collect()
+    }
+
+    // every swimlane with a card in it as (key, heading), a single unnamed one when the board has none
+    pub fn swimlanes<'aa>(&'aa self) -> Vec<(String, String)> {
+        let mut swimlanes: Vec<(String, String)> = vec![];
+        for task in Self::board_tasks(self) {
+            let key = Self::swimlane_key(self, task);
+            if !swimlanes.iter().any(|(k, _)| *k == key) {
+                let name = Self::swimlane_name(self, &key);
+                swimlanes.push((key, name));
+            }
+        }
+        swimlanes.sort_by_key(|(_, name)| name.to_lowercase());
+        if swimlanes.is_empty() {
+            swimlanes.push(("".to_string(), "".to_string()));
+        }
+        swimlanes
+    }
+
+    // a task with several tags goes in the lane of its first one, so each card shows up once
+    fn swimlane_key(&self, task: &Task) -> String {
+        match self.board.swimlane {
+            Swimlane::None => "".to_string(),
+            Swimlane::Assignee => task.assignee_key.to_string(),
+            Swimlane::Project => task.project_key.to_string(),
+            Swimlane::Tag => task.tags.split(',').map(|t| t.trim()).find(|t| !t.is_empty()).unwrap_or("").to_lowercase(),
+            Swimlane::Milestone => task.milestone_key.map(|k| k.to_string()).unwrap_or_default(),
+        }
+    }
+
+    fn swimlane_name(&self, key: &String) -> String {
+        match self.board.swimlane {
+            Swimlane::None => "".to_string(),
+            Swimlane::Assignee => match self.users.iter().find(|u| u.key.to_string() == *key) {
+                Some(u) => u.to_string(),
+                None if *key == uuid::Uuid::nil().to_string() => "Unassigned".to_string(),
+                None => "Someone else".to_string(),
+            },
+            Swimlane::Project => match self.projects.iter().find(|p| p.key.to_string() == *key) {
+                Some(p) => p.name.clone(),
+                None => "Another project".to_string(),
+            },
+            Swimlane::Tag if key.is_empty() => "No tag".to_string(),
+            Swimlane::Tag => key.clone(),
+            Swimlane::Milestone => match self.milestones.iter().find(|m| m.key.to_string() == *key) {
+                Some(m) => m.name.clone(),
+                None => "No milestone".to_string(),
+            },
+        }
+    }
+
+    pub fn wip_limit<'aa>(&'aa self, lane: &String) -> i32 {
+        match self.board.lanes.iter().position(|l| l == lane) {
+            Some(i) => self.board.wip_limits.get(i).copied().unwrap_or(0),
+            None => 0,
+        }
+    }
+
+    pub fn lane_count<'aa>(&'aa self, lane: &String) -> usize {
+        self.shown.iter().filter(|t| t.status_key.to_string() == *lane).count()
+    }
+
+    pub fn over_limit<'aa>(&'aa self, lane: &String) -> bool {
+        let limit = Self::wip_limit(self, lane);
+        limit > 0 && Self::lane_count(self, lane) > limit as usize
+    }
+
+    // what the WIP input for a status starts at on the details tab
+    pub fn status_limit<'aa>(&'aa self, status_key: &uuid::Uuid) -> i32 {
+        Self::wip_limit(self, &status_key.to_string())
+    }
+
+    pub fn status_name<'aa>(&'aa self, key: &uuid::Uuid) -> String {
+        self.workflow.name(key)
+    }
+
+    pub fn children<'aa>(&'aa self, key: &uuid::Uuid) -> Vec<&'aa Task> {
+        self.descendants.iter().filter(|t| t.parent_key == Some(*key)).collect()
+    }
+
+    pub fn rollup<'aa>(&'aa self, key: &uuid::Uuid) -> Rollup {
+        crate::task::rollup(*key, &self.descendants, &self.checklist, &self.workflow)
+    }
+
+    pub fn lane_contained(&self, status_key: &uuid::Uuid, lanes: &[String]) -> bool {
+        lanes.contains(&status_key.to_string())
     }
 }
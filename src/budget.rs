@@ -0,0 +1,494 @@
+use askama::Template;
+use serde::{Deserialize, Serialize};
+use sqlx::pool::PoolConnection;
+use sqlx::{Connection, PgConnection, Postgres};
+use strum::IntoEnumIterator;
+use strum_macros::EnumIter;
+use uuid::Uuid;
+
+use tide::{http::mime, Request};
+
+use crate::akaunting::InvoiceData;
+use crate::entity::Entity;
+use crate::error::AppError;
+use crate::home::NotFoundTemplate;
+use crate::project::Project;
+use crate::service_item::ServiceItem;
+use crate::time_entry::{ReportLine, TimeEntry};
+use crate::{user, State};
+
+// SQL STUFF
+
+pub async fn get_project_budget(conn: &mut PoolConnection<Postgres>, project_key: uuid::Uuid, organization_key: uuid::Uuid) -> Result<Option<Budget>, AppError> {
+    let budget = match sqlx::query!(
+        "select key, organization_key, project_key, entity_key, budget_type, amount, hours, currency, created, updated from project_budgets where project_key = $1 AND organization_key = $2",
+        project_key,
+        organization_key
+    )
+    .fetch_optional(conn)
+    .await?
+    {
+        Some(r) => r,
+        None => return Ok(None),
+    };
+    Ok(Some(Budget {
+        key: budget.key,
+        organization_key: budget.organization_key,
+        project_key: budget.project_key,
+        entity_key: budget.entity_key,
+        budget_type: budget.budget_type.into(),
+        amount: budget.amount,
+        hours: budget.hours,
+        currency: budget.currency,
+        created: budget.created,
+        updated: budget.updated,
+    }))
+}
+
+pub async fn get_project_service_items(conn: &mut PoolConnection<Postgres>, project_key: uuid::Uuid, organization_key: uuid::Uuid) -> Result<Vec<uuid::Uuid>, AppError> {
+    let records = sqlx::query!(
+        "select service_item_key from project_service_items where project_key = $1 AND organization_key = $2",
+        project_key,
+        organization_key
+    )
+    .fetch_all(conn)
+    .await?;
+    Ok(records.into_iter().map(|r| r.service_item_key).collect())
+}
+
+async fn upsert_budget(conn: &mut PgConnection, budget: &Budget) -> Result<(), AppError> {
+    sqlx::query!("INSERT INTO project_budgets (key, organization_key, project_key, entity_key, budget_type, amount, hours, currency, created, updated) values($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
+        ON CONFLICT (project_key) DO UPDATE SET entity_key=$4, budget_type=$5, amount=$6, hours=$7, currency=$8, updated=extract(epoch from now())",
+        budget.key,
+        budget.organization_key,
+        budget.project_key,
+        budget.entity_key,
+        i16::from(budget.budget_type),
+        budget.amount,
+        budget.hours,
+        &budget.currency,
+        budget.created,
+        budget.updated,
+    )
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+async fn set_project_service_items(conn: &mut PgConnection, project_key: uuid::Uuid, organization_key: uuid::Uuid, service_item_keys: &[uuid::Uuid]) -> Result<(), AppError> {
+    sqlx::query!("DELETE FROM project_service_items where project_key = $1 AND organization_key = $2", project_key, organization_key)
+        .execute(&mut *conn)
+        .await?;
+    for service_item_key in service_item_keys {
+        sqlx::query!("INSERT INTO project_service_items (project_key, service_item_key, organization_key) values($1, $2, $3) ON CONFLICT DO NOTHING",
+            project_key,
+            service_item_key,
+            organization_key,
+        )
+        .execute(&mut *conn)
+        .await?;
+    }
+    Ok(())
+}
+
+// Route Stuff
+
+pub async fn get(req: Request<State>) -> tide::Result {
+    let u = match user::user_or_error(&req) {
+        Ok(value) => value,
+        Err(_) => return Ok(tide::Redirect::new("/login").into()),
+    };
+    let project_key = crate::error::param_key(&req, "project_id")?;
+    let query: BudgetQuery = req.query()?;
+    let mut conn = req.state().db_pool.acquire().await?;
+    let project = match crate::project::get_project(&mut conn, project_key, u.organization_key).await? {
+        Some(p) => p,
+        None => {
+            return Ok(tide::Response::builder(tide::StatusCode::NotFound)
+                .content_type(mime::HTML)
+                .body(NotFoundTemplate::new().render_string())
+                .build())
+        }
+    };
+    let budget = get_project_budget(&mut conn, project.key, u.organization_key)
+        .await?
+        .unwrap_or_else(|| Budget::new(u.organization_key, project.key, None, BudgetType::Fixed, 0, 0, "USD".to_string()));
+    let linked = get_project_service_items(&mut conn, project.key, u.organization_key).await?;
+    let entitys = crate::entity::get_organization_entitys(&mut conn, u.organization_key).await?;
+    let service_items = crate::service_item::get_organization_service_items(&mut conn, u.organization_key).await?;
+    let entries = crate::time_entry::get_project_time_entries(&mut conn, project.key, u.organization_key).await?;
+
+    // Akaunting is only asked when the page asks for the invoices, and the report still shows
+    // budget against effort when it can't be reached, saying so rather than passing for a client
+    // with nothing in Akaunting
+    let mut invoices = None;
+    let mut invoices_error = "".to_string();
+    let client = budget.entity_key.and_then(|k| entitys.iter().find(|e| e.key == k));
+    if let Some(client) = client.filter(|_| query.invoices) {
+        if let Some(options) = crate::akaunting::get_akaunting_options(&mut conn, u.organization_key).await? {
+            match options.list_invoices().await {
+                Ok(list) => invoices = project_invoices(&list.data, client, &service_items, &linked, &budget.currency),
+                Err(e) => {
+                    println!("{:?}", e);
+                    invoices_error = "the invoices couldn't be loaded from Akaunting".to_string();
+                }
+            }
+        }
+    }
+    let lines = crate::time_entry::report_lines(&entries, &service_items);
+    let profitability = Profitability::new(&budget, &entries, &lines, invoices.as_deref());
+
+    Ok(tide::Response::builder(tide::StatusCode::Ok)
+        .content_type(mime::HTML)
+        .body(
+            BudgetTemplate::new(u, project, budget, linked, entitys, service_items, lines, invoices, query.invoices, invoices_error, profitability)
+                .render_string(),
+        )
+        .build())
+}
+
+pub async fn update(mut req: Request<State>) -> tide::Result {
+    let claims: user::UserJwtState = match user::request_claims(&req) {
+        Some(c) => c,
+        None => {
+            return Ok(tide::Redirect::new("/login").into());
+        }
+    };
+    let organization_key = crate::error::parse_key(claims.organization_key.as_str(), "organization")?;
+    let project_key = crate::error::param_key(&req, "project_id")?;
+    let request: BudgetRequest = req.body_json().await.map_err(|e| AppError::Validation(e.to_string()))?;
+    if request.amount < 0 || request.hours < 0 {
+        return Err(AppError::Validation("a budget can't be negative".to_string()).into());
+    }
+    let currency = match request.currency.trim() {
+        "" => "USD".to_string(),
+        c => c.to_uppercase(),
+    };
+    let mut conn = req.state().db_pool.acquire().await?;
+    if crate::project::get_project(&mut conn, project_key, organization_key).await?.is_none() {
+        return Err(AppError::NotFound("project".to_string()).into());
+    }
+    if let Some(entity_key) = request.entity_key {
+        if crate::entity::get_entity(&mut conn, entity_key, organization_key).await?.is_none() {
+            return Err(AppError::NotFound("entity".to_string()).into());
+        }
+    }
+    for service_item_key in request.service_item_keys.iter() {
+        if crate::service_item::get_service_item(&mut conn, *service_item_key, organization_key).await?.is_none() {
+            return Err(AppError::NotFound("service item".to_string()).into());
+        }
+    }
+    let budget = Budget::new(organization_key, project_key, request.entity_key, request.budget_type, request.amount, request.hours, currency);
+
+    let mut tx = conn.begin().await?;
+    upsert_budget(&mut tx, &budget).await?;
+    set_project_service_items(&mut tx, project_key, organization_key, &request.service_item_keys).await?;
+    tx.commit().await?;
+
+    let j = serde_json::to_string(&budget).expect("To JSON");
+    Ok(tide::Response::builder(tide::StatusCode::Ok)
+        .content_type(mime::JSON)
+        .body(j)
+        .build())
+}
+
+// The invoices to the project's client in its currency, leaving out drafts and cancelled ones.
+// Only the invoice lines for the service items the project is linked to count, a client can have
+// more than one project so without any links nothing is. None when the client isn't in Akaunting.
+pub fn project_invoices(
+    invoices: &[InvoiceData],
+    client: &Entity,
+    service_items: &[ServiceItem],
+    linked: &[uuid::Uuid],
+    currency: &str,
+) -> Option<Vec<ProjectInvoice>> {
+    let contact_id: i64 = client.external_accounting_id.parse().ok()?;
+    let item_ids: Vec<i64> = service_items
+        .iter()
+        .filter(|s| linked.contains(&s.key))
+        .filter_map(|s| s.external_accounting_id.parse().ok())
+        .collect();
+    let mut found = vec![];
+    for invoice in invoices.iter().filter(|i| i.contact_id == Some(contact_id)) {
+        if matches!(invoice.status.as_deref(), Some("draft") | Some("cancelled")) {
+            continue;
+        }
+        if invoice.currency_code.as_deref().is_some_and(|c| !c.eq_ignore_ascii_case(currency)) {
+            continue;
+        }
+        let amount: f64 = invoice
+            .items
+            .data
+            .iter()
+            .filter(|line| line.item_id.is_some_and(|id| item_ids.contains(&id)))
+            .map(|line| line.total.unwrap_or_default())
+            .sum();
+        if amount == 0.0 {
+            continue;
+        }
+        found.push(ProjectInvoice {
+            number: invoice.document_number.clone().unwrap_or_default(),
+            issued: invoice.issued_at.clone().unwrap_or_default(),
+            status: invoice.status.clone().unwrap_or_default(),
+            amount,
+        });
+    }
+    Some(found)
+}
+
+pub fn money(amount: f64) -> String {
+    format!("{:.2}", amount)
+}
+
+// data types
+
+#[derive(PartialEq, Debug, Deserialize, Serialize, Clone, Copy, EnumIter)]
+pub enum BudgetType {
+    Fixed,
+    Hourly,
+}
+
+impl Into<BudgetType> for i16 {
+    fn into(self) -> BudgetType {
+        match self {
+            0 => BudgetType::Fixed,
+            1 => BudgetType::Hourly,
+            _ => BudgetType::Fixed,
+        }
+    }
+}
+
+impl From<BudgetType> for i16 {
+    fn from(t: BudgetType) -> Self {
+        match t {
+            BudgetType::Fixed => 0,
+            BudgetType::Hourly => 1,
+        }
+    }
+}
+
+impl ToString for BudgetType {
+    fn to_string(&self) -> String {
+        match self {
+            BudgetType::Fixed => "Fixed Price".to_owned(),
+            BudgetType::Hourly => "Hourly Rate".to_owned(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BudgetQuery {
+    #[serde(default)]
+    pub invoices: bool,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct BudgetRequest {
+    #[serde(default = "fixed")]
+    pub budget_type: BudgetType,
+    #[serde(default)]
+    pub amount: i64,
+    #[serde(default)]
+    pub hours: i32,
+    #[serde(default)]
+    pub currency: String,
+    #[serde(default)]
+    pub entity_key: Option<uuid::Uuid>,
+    #[serde(default)]
+    pub service_item_keys: Vec<uuid::Uuid>,
+}
+
+fn fixed() -> BudgetType {
+    BudgetType::Fixed
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct Budget {
+    pub key: uuid::Uuid,
+    pub organization_key: uuid::Uuid,
+    pub project_key: uuid::Uuid,
+    pub entity_key: Option<uuid::Uuid>,
+    pub budget_type: BudgetType,
+    // the whole price of a fixed budget, the rate per hour of an hourly one
+    pub amount: i64,
+    // an hourly budget's cap, 0 for none
+    pub hours: i32,
+    pub currency: String,
+    pub created: i64,
+    pub updated: i64,
+}
+
+impl Budget {
+    pub fn new(
+        organization_key: uuid::Uuid,
+        project_key: uuid::Uuid,
+        entity_key: Option<uuid::Uuid>,
+        budget_type: BudgetType,
+        amount: i64,
+        hours: i32,
+        currency: String,
+    ) -> Self {
+        let key = Uuid::new_v4();
+        let created = chrono::Utc::now().timestamp();
+        let updated = 0;
+        Self {
+            key,
+            organization_key,
+            project_key,
+            entity_key,
+            budget_type,
+            amount,
+            hours,
+            currency,
+            created,
+            updated,
+        }
+    }
+
+    // what the whole project may bring in, None for an hourly budget without a cap
+    pub fn total(&self) -> Option<f64> {
+        match self.budget_type {
+            BudgetType::Fixed => Some(self.amount as f64),
+            BudgetType::Hourly if self.hours > 0 => Some(self.amount as f64 * self.hours as f64),
+            BudgetType::Hourly => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ProjectInvoice {
+    pub number: String,
+    pub issued: String,
+    pub status: String,
+    pub amount: f64,
+}
+
+// Budget, effort and invoices side by side. Effort is the billable time priced at the budget's
+// rate for hourly projects, and at the hourly service items it was logged under for fixed ones.
+// Earned is what the client owes for the project so far: the whole price of a fixed budget, or
+// the effort of an hourly one up to its cap.
+#[derive(Debug, Clone)]
+pub struct Profitability {
+    pub budget: Option<f64>,
+    pub seconds: i64,
+    pub billable_seconds: i64,
+    pub effort: f64,
+    pub earned: f64,
+    pub invoiced: Option<f64>,
+}
+
+impl Profitability {
+    pub fn new(budget: &Budget, entries: &[TimeEntry], lines: &[ReportLine], invoices: Option<&[ProjectInvoice]>) -> Self {
+        let seconds = entries.iter().map(|e| e.seconds()).sum();
+        let billable_seconds: i64 = entries.iter().filter(|e| e.billable).map(|e| e.seconds()).sum();
+        let effort = match budget.budget_type {
+            BudgetType::Hourly => budget.amount as f64 * billable_seconds as f64 / 3600.0,
+            BudgetType::Fixed => lines
+                .iter()
+                .filter_map(|l| l.rate.map(|rate| rate as f64 * l.billable_seconds as f64 / 3600.0))
+                .sum(),
+        };
+        let earned = match budget.budget_type {
+            BudgetType::Fixed => budget.amount as f64,
+            BudgetType::Hourly => budget.total().map_or(effort, |total| effort.min(total)),
+        };
+        Self {
+            budget: budget.total(),
+            seconds,
+            billable_seconds,
+            effort,
+            earned,
+            invoiced: invoices.map(|i| i.iter().map(|i| i.amount).sum()),
+        }
+    }
+
+    pub fn remaining(&self) -> Option<f64> {
+        self.budget.map(|b| b - self.effort)
+    }
+
+    pub fn over(&self) -> bool {
+        self.remaining().is_some_and(|r| r < 0.0)
+    }
+
+    pub fn used_percent(&self) -> Option<i64> {
+        self.budget.filter(|b| *b > 0.0).map(|b| (self.effort / b * 100.0).round() as i64)
+    }
+
+    pub fn uninvoiced(&self) -> Option<f64> {
+        self.invoiced.map(|i| self.earned - i)
+    }
+
+    // what every hour logged, billable or not, has been worth
+    pub fn effective_rate(&self) -> Option<f64> {
+        if self.seconds == 0 {
+            return None;
+        }
+        Some(self.earned / (self.seconds as f64 / 3600.0))
+    }
+}
+
+#[derive(Template)]
+#[template(path = "budget.html")]
+pub struct BudgetTemplate {
+    user: crate::user::User,
+    project: Project,
+    budget: Budget,
+    linked: Vec<uuid::Uuid>,
+    entitys: Vec<Entity>,
+    service_items: Vec<ServiceItem>,
+    lines: Vec<ReportLine>,
+    invoices: Option<Vec<ProjectInvoice>>,
+    invoices_loaded: bool,
+    invoices_error: String,
+    profitability: Profitability,
+}
+
+impl<'a> BudgetTemplate {
+    pub fn new(
+        user: crate::user::User,
+        project: Project,
+        budget: Budget,
+        linked: Vec<uuid::Uuid>,
+        entitys: Vec<Entity>,
+        service_items: Vec<ServiceItem>,
+        lines: Vec<ReportLine>,
+        invoices: Option<Vec<ProjectInvoice>>,
+        invoices_loaded: bool,
+        invoices_error: String,
+        profitability: Profitability,
+    ) -> Self {
+        return Self {
+            user,
+            project,
+            budget,
+            linked,
+            entitys,
+            service_items,
+            lines,
+            invoices,
+            invoices_loaded,
+            invoices_error,
+            profitability,
+        };
+    }
+
+    pub fn money<'aa>(&'aa self, amount: &f64) -> String {
+        money(*amount)
+    }
+
+    pub fn hours<'aa>(&'aa self, seconds: &i64) -> String {
+        crate::time_entry::hours(*seconds)
+    }
+
+    pub fn is_client<'aa>(&'aa self, key: &uuid::Uuid) -> bool {
+        self.budget.entity_key == Some(*key)
+    }
+
+    pub fn is_linked<'aa>(&'aa self, key: &uuid::Uuid) -> bool {
+        self.linked.contains(key)
+    }
+
+    pub fn render_string(&self) -> String {
+        return self.render().unwrap();
+    }
+}
@@ -0,0 +1,463 @@
+use std::str::FromStr;
+
+use askama::Template;
+use serde::{Deserialize, Serialize};
+use sqlx::pool::PoolConnection;
+use sqlx::postgres::PgQueryResult;
+use sqlx::Postgres;
+use uuid::Uuid;
+
+use tide::{http::mime, Request};
+
+use crate::common::DAY_SECONDS;
+use crate::error::AppError;
+use crate::milestone::Milestone;
+use crate::project::Project;
+use crate::task::Task;
+use crate::workflow::Workflow;
+use crate::{member, user, State};
+
+// SQL STUFF
+
+pub async fn get_calendar_feed(conn: &mut PoolConnection<Postgres>, key: uuid::Uuid) -> Result<Option<CalendarFeed>, AppError> {
+    let feed = match sqlx::query!(
+        "select key, organization_key, user_key, secret_hash, created, updated from calendar_feeds where key = $1",
+        key
+    )
+    .fetch_optional(conn)
+    .await?
+    {
+        Some(r) => r,
+        None => return Ok(None),
+    };
+
+    Ok(Some(CalendarFeed {
+        key: feed.key,
+        organization_key: feed.organization_key,
+        user_key: feed.user_key,
+        secret_hash: feed.secret_hash,
+        created: feed.created,
+        updated: feed.updated,
+    }))
+}
+
+pub async fn get_user_calendar_feed(
+    conn: &mut PoolConnection<Postgres>,
+    user_key: uuid::Uuid,
+    organization_key: uuid::Uuid,
+) -> Result<Option<CalendarFeed>, AppError> {
+    let feed = match sqlx::query!(
+        "select key, organization_key, user_key, secret_hash, created, updated from calendar_feeds where user_key = $1 AND organization_key = $2",
+        user_key,
+        organization_key
+    )
+    .fetch_optional(conn)
+    .await?
+    {
+        Some(r) => r,
+        None => return Ok(None),
+    };
+
+    Ok(Some(CalendarFeed {
+        key: feed.key,
+        organization_key: feed.organization_key,
+        user_key: feed.user_key,
+        secret_hash: feed.secret_hash,
+        created: feed.created,
+        updated: feed.updated,
+    }))
+}
+
+// a new key as well as a new secret, so the old link stops working even before bcrypt is asked
+async fn upsert_calendar_feed(conn: &mut PoolConnection<Postgres>, feed: &CalendarFeed) -> Result<(), AppError> {
+    sqlx::query!("INSERT INTO calendar_feeds (key, organization_key, user_key, secret_hash, created, updated) values($1, $2, $3, $4, $5, $6)
+        ON CONFLICT (organization_key, user_key) DO UPDATE SET key=$1, secret_hash=$4, created=$5, updated=extract(epoch from now())",
+        feed.key,
+        feed.organization_key,
+        feed.user_key,
+        &feed.secret_hash,
+        feed.created,
+        feed.updated,
+    )
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+async fn delete_calendar_feed(
+    conn: &mut PoolConnection<Postgres>,
+    user_key: uuid::Uuid,
+    organization_key: uuid::Uuid,
+) -> Result<PgQueryResult, sqlx::Error> {
+    return sqlx::query!(
+        "DELETE FROM calendar_feeds where user_key=$1 AND organization_key=$2",
+        user_key,
+        organization_key
+    )
+    .execute(conn)
+    .await;
+}
+
+// Route Stuff
+
+pub async fn get(req: Request<State>) -> tide::Result {
+    let u = match user::user_or_error(&req) {
+        Ok(value) => value,
+        Err(_) => return Ok(tide::Redirect::new("/login").into()),
+    };
+    let query: CalendarQuery = req.query()?;
+    // "Everyone" submits an empty user_key
+    let user_key = match query.user_key.as_deref() {
+        None | Some("") => None,
+        Some(k) => Some(crate::error::parse_key(k, "user")?),
+    };
+    let mut conn = req.state().db_pool.acquire().await?;
+    let mut projects = crate::project::get_projects_by_organization(&mut conn, u.organization_key).await?;
+    let mut milestones = crate::milestone::get_milestones_by_organization(&mut conn, u.organization_key).await?;
+    let mut tasks = crate::task::get_tasks_by_organization(&mut conn, u.organization_key).await?;
+    if let Some(user_key) = user_key {
+        for_user(user_key, &mut projects, &mut milestones, &mut tasks);
+    }
+    let workflow = crate::workflow::get_workflow(&mut conn, u.organization_key).await?;
+    let users = user::get_users_by_organization(&mut conn, u.organization_key).await?;
+    let feed = get_user_calendar_feed(&mut conn, u.key, u.organization_key).await?;
+    Ok(tide::Response::builder(tide::StatusCode::Ok)
+        .content_type(mime::HTML)
+        .body(CalendarTemplate::new(u, users, user_key, projects, milestones, tasks, workflow, feed).render_string())
+        .build())
+}
+
+pub async fn insert_feed(req: Request<State>) -> tide::Result {
+    let u = match user::user_or_error(&req) {
+        Ok(value) => value,
+        Err(e) => return e,
+    };
+    let secret = format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple());
+    let secret_hash = match pwhash::bcrypt::hash(secret.as_str()) {
+        Ok(h) => h,
+        Err(e) => {
+            return Ok(tide::Response::builder(tide::StatusCode::InternalServerError)
+                .content_type(mime::PLAIN)
+                .body(e.to_string())
+                .build())
+        }
+    };
+    let feed = CalendarFeed::new(u.organization_key, u.key, secret_hash);
+    let mut conn = req.state().db_pool.acquire().await?;
+    upsert_calendar_feed(&mut conn, &feed).await?;
+    let created = CreatedCalendarFeed {
+        url: format!(
+            "{}/calendar/feed/{}_{}.ics",
+            req.url().origin().ascii_serialization(),
+            feed.key.simple(),
+            secret
+        ),
+        calendar_feed: feed,
+    };
+    let j = serde_json::to_string(&created).expect("To JSON");
+    Ok(tide::Response::builder(tide::StatusCode::Created)
+        .content_type(mime::JSON)
+        .body(j)
+        .build())
+}
+
+pub async fn delete_feed(req: Request<State>) -> tide::Result {
+    let u = match user::user_or_error(&req) {
+        Ok(value) => value,
+        Err(e) => return e,
+    };
+    let mut conn = req.state().db_pool.acquire().await?;
+    match delete_calendar_feed(&mut conn, u.key, u.organization_key).await.map_err(AppError::from)? {
+        r if r.rows_affected() > 0 => Ok(tide::Response::builder(tide::StatusCode::Ok)
+            .content_type(mime::JSON)
+            .body("{}")
+            .build()),
+        _ => Err(AppError::NotFound("calendar feed".to_string()).into()),
+    }
+}
+
+// Calendar apps can't log in, the secret in the link is all they have. It stops working once
+// the link is replaced or turned off, or the person leaves the organization.
+pub async fn feed(req: Request<State>) -> tide::Result {
+    let (key, secret) = match req
+        .param("feed_id")
+        .ok()
+        .map(|f| f.trim_end_matches(".ics"))
+        .and_then(|f| f.split_once('_'))
+    {
+        Some(parts) => parts,
+        None => return Err(AppError::NotFound("calendar feed".to_string()).into()),
+    };
+    let key = Uuid::from_str(key).map_err(|_| AppError::NotFound("calendar feed".to_string()))?;
+    let mut conn = req.state().db_pool.acquire().await?;
+    let feed = match get_calendar_feed(&mut conn, key).await? {
+        Some(f) if pwhash::bcrypt::verify(secret, f.secret_hash.as_str()) => f,
+        _ => return Err(AppError::NotFound("calendar feed".to_string()).into()),
+    };
+    if member::get_role(&mut conn, feed.organization_key, feed.user_key).await.is_none() {
+        return Err(AppError::NotFound("calendar feed".to_string()).into());
+    }
+    let mut projects = crate::project::get_projects_by_organization(&mut conn, feed.organization_key).await?;
+    let mut milestones = crate::milestone::get_milestones_by_organization(&mut conn, feed.organization_key).await?;
+    let mut tasks = crate::task::get_tasks_by_organization(&mut conn, feed.organization_key).await?;
+    for_user(feed.user_key, &mut projects, &mut milestones, &mut tasks);
+    let workflow = crate::workflow::get_workflow(&mut conn, feed.organization_key).await?;
+    let organization = crate::organization::get_organization(&mut conn, feed.organization_key).await?;
+    Ok(tide::Response::builder(tide::StatusCode::Ok)
+        .content_type("text/calendar; charset=utf-8")
+        .header("Cache-Control", "private, max-age=900")
+        .body(ics(organization.name.as_str(), &milestones, &tasks, &workflow))
+        .build())
+}
+
+// Someone's calendar is the tasks assigned to them, and the milestones and projects those tasks count towards
+fn for_user(user_key: uuid::Uuid, projects: &mut Vec<Project>, milestones: &mut Vec<Milestone>, tasks: &mut Vec<Task>) {
+    tasks.retain(|t| t.assignee_key == user_key);
+    milestones.retain(|m| tasks.iter().any(|t| t.project_key == m.project_key));
+    projects.retain(|p| tasks.iter().any(|t| t.project_key == p.key));
+}
+
+// Whole days covering start to due, the end is the day after the last one. None when neither date is set.
+fn days(start: i64, due: i64) -> Option<(i64, i64)> {
+    let (first, last) = match (start, due) {
+        (0, 0) => return None,
+        (0, d) | (d, 0) => (d, d),
+        (s, d) => (s.min(d), s.max(d)),
+    };
+    Some((first - first.rem_euclid(DAY_SECONDS), last - last.rem_euclid(DAY_SECONDS) + DAY_SECONDS))
+}
+
+pub fn ics(name: &str, milestones: &[Milestone], tasks: &[Task], workflow: &Workflow) -> String {
+    let mut lines = vec![
+        "BEGIN:VCALENDAR".to_string(),
+        "VERSION:2.0".to_string(),
+        "PRODID:-//Kinbrio//Kinbrio Calendar//EN".to_string(),
+        "CALSCALE:GREGORIAN".to_string(),
+        format!("X-WR-CALNAME:{}", escape(format!("Kinbrio {}", name).as_str())),
+    ];
+    for task in tasks {
+        if let Some((start, end)) = days(task.start, task.due) {
+            lines.extend(vevent(
+                task.key,
+                task.created.max(task.updated),
+                start,
+                end,
+                if workflow.is_done(&task.status_key) { format!("✔ {}", task.name) } else { task.name.clone() }.as_str(),
+                format!("{}\n\n{}", workflow.name(&task.status_key), task.description).as_str(),
+            ));
+        }
+    }
+    for milestone in milestones {
+        if let Some((start, end)) = days(milestone.due, 0) {
+            lines.extend(vevent(
+                milestone.key,
+                milestone.created.max(milestone.updated),
+                start,
+                end,
+                format!("🏁 {}", milestone.name).as_str(),
+                milestone.description.as_str(),
+            ));
+        }
+    }
+    lines.push("END:VCALENDAR".to_string());
+    lines.iter().map(|l| fold(l)).collect::<Vec<String>>().join("")
+}
+
+fn vevent(key: uuid::Uuid, stamp: i64, start: i64, end: i64, summary: &str, description: &str) -> Vec<String> {
+    vec![
+        "BEGIN:VEVENT".to_string(),
+        format!("UID:{}@kinbrio", key),
+        format!("DTSTAMP:{}", timestamp(stamp).format("%Y%m%dT%H%M%SZ")),
+        format!("DTSTART;VALUE=DATE:{}", timestamp(start).format("%Y%m%d")),
+        format!("DTEND;VALUE=DATE:{}", timestamp(end).format("%Y%m%d")),
+        format!("SUMMARY:{}", escape(summary)),
+        format!("DESCRIPTION:{}", escape(description)),
+        "TRANSP:TRANSPARENT".to_string(),
+        "END:VEVENT".to_string(),
+    ]
+}
+
+fn timestamp(seconds: i64) -> chrono::NaiveDateTime {
+    chrono::NaiveDateTime::from_timestamp_opt(seconds, 0).unwrap_or_default()
+}
+
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace("\r\n", "\\n")
+        .replace('\n', "\\n")
+}
+
+// Lines longer than 75 octets carry on after a CRLF and a space, without splitting a character
+fn fold(line: &str) -> String {
+    let mut folded = String::new();
+    let mut width = 0;
+    for c in line.chars() {
+        if width + c.len_utf8() > 75 {
+            folded.push_str("\r\n ");
+            width = 1;
+        }
+        folded.push(c);
+        width += c.len_utf8();
+    }
+    folded.push_str("\r\n");
+    folded
+}
+
+// data types
+
+#[derive(Debug, Deserialize)]
+pub struct CalendarQuery {
+    #[serde(default)]
+    pub user_key: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CreatedCalendarFeed {
+    pub url: String,
+    pub calendar_feed: CalendarFeed,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct CalendarFeed {
+    pub key: uuid::Uuid,
+    pub organization_key: uuid::Uuid,
+    pub user_key: uuid::Uuid,
+    #[serde(skip_serializing)]
+    pub secret_hash: String,
+    pub created: i64,
+    pub updated: i64,
+}
+
+impl CalendarFeed {
+    pub fn new(organization_key: uuid::Uuid, user_key: uuid::Uuid, secret_hash: String) -> Self {
+        let key = Uuid::new_v4();
+        let created = chrono::Utc::now().timestamp();
+        let updated = 0;
+        Self {
+            key,
+            organization_key,
+            user_key,
+            secret_hash,
+            created,
+            updated,
+        }
+    }
+}
+
+// One entry on the calendar page, handed to calendar.js as JSON
+#[derive(Debug, Serialize)]
+pub struct CalendarEvent {
+    pub id: String,
+    pub title: String,
+    pub description: String,
+    pub from: i64,
+    pub to: i64,
+    pub color: String,
+    pub group: String,
+    pub url: String,
+}
+
+#[derive(Template)]
+#[template(path = "calendar.html")]
+pub struct CalendarTemplate {
+    user: crate::user::User,
+    users: Vec<crate::user::User>,
+    user_key: Option<uuid::Uuid>,
+    projects: Vec<Project>,
+    milestones: Vec<Milestone>,
+    tasks: Vec<Task>,
+    workflow: Workflow,
+    feed: Option<CalendarFeed>,
+}
+
+impl<'a> CalendarTemplate {
+    pub fn new(
+        user: crate::user::User,
+        users: Vec<crate::user::User>,
+        user_key: Option<uuid::Uuid>,
+        projects: Vec<Project>,
+        milestones: Vec<Milestone>,
+        tasks: Vec<Task>,
+        workflow: Workflow,
+        feed: Option<CalendarFeed>,
+    ) -> Self {
+        return Self {
+            user,
+            users,
+            user_key,
+            projects,
+            milestones,
+            tasks,
+            workflow,
+            feed,
+        };
+    }
+
+    pub fn events<'aa>(&'aa self) -> Vec<CalendarEvent> {
+        let mut events = vec![];
+        for project in &self.projects {
+            if let Some((from, to)) = days(project.start, project.due) {
+                events.push(CalendarEvent {
+                    id: project.key.to_string(),
+                    title: project.name.clone(),
+                    description: project.description.clone(),
+                    from,
+                    to: to - 1,
+                    color: "#3a506b".to_string(),
+                    group: "Projects".to_string(),
+                    url: format!("/project/{}", project.key),
+                });
+            }
+        }
+        for milestone in &self.milestones {
+            if let Some((from, to)) = days(milestone.due, 0) {
+                events.push(CalendarEvent {
+                    id: milestone.key.to_string(),
+                    title: format!("🏁 {}", milestone.name),
+                    description: milestone.description.clone(),
+                    from,
+                    to: to - 1,
+                    color: "#e07a5f".to_string(),
+                    group: "Milestones".to_string(),
+                    url: format!("/milestone/{}", milestone.key),
+                });
+            }
+        }
+        for task in &self.tasks {
+            if let Some((from, to)) = days(task.start, task.due) {
+                events.push(CalendarEvent {
+                    id: task.key.to_string(),
+                    title: task.name.clone(),
+                    description: task.description.clone(),
+                    from,
+                    to: to - 1,
+                    color: self.workflow.color(&task.status_key),
+                    group: self.workflow.name(&task.status_key),
+                    url: format!("/task/{}", task.key),
+                });
+            }
+        }
+        events
+    }
+
+    pub fn events_json<'aa>(&'aa self) -> String {
+        crate::common::script_json(&self.events())
+    }
+
+    pub fn is_selected<'aa>(&'aa self, key: &uuid::Uuid) -> bool {
+        self.user_key == Some(*key)
+    }
+
+    pub fn when<'aa>(&'aa self, timestamp: &i64) -> String {
+        match chrono::NaiveDateTime::from_timestamp_opt(*timestamp, 0) {
+            Some(t) => t.format("%Y-%m-%d").to_string(),
+            None => "".to_string(),
+        }
+    }
+
+    pub fn render_string(&self) -> String {
+        return self.render().unwrap();
+    }
+}
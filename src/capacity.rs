@@ -0,0 +1,342 @@
+use std::str::FromStr;
+
+use askama::Template;
+use serde::{Deserialize, Serialize};
+use sqlx::pool::PoolConnection;
+use sqlx::Postgres;
+use uuid::Uuid;
+
+use tide::{http::mime, Request};
+
+use crate::auth::Permission;
+use crate::common::{day, monday, weekday, DAY_SECONDS};
+use crate::error::AppError;
+use crate::task::Task;
+use crate::user::User;
+use crate::workflow::Workflow;
+use crate::{member, user, State};
+
+const WEEKDAYS: [chrono::Weekday; 7] = [
+    chrono::Weekday::Mon,
+    chrono::Weekday::Tue,
+    chrono::Weekday::Wed,
+    chrono::Weekday::Thu,
+    chrono::Weekday::Fri,
+    chrono::Weekday::Sat,
+    chrono::Weekday::Sun,
+];
+
+// SQL STUFF
+
+pub async fn get_organization_capacities(conn: &mut PoolConnection<Postgres>, organization_key: uuid::Uuid) -> Result<Vec<Capacity>, AppError> {
+    let records = sqlx::query!(
+        "select key, organization_key, user_key, working_days, quarter_days, created, updated from member_capacities where organization_key = $1",
+        organization_key
+    )
+    .fetch_all(conn)
+    .await?;
+    let mut capacities = vec![];
+    for c in records {
+        capacities.push(Capacity {
+            key: c.key,
+            organization_key: c.organization_key,
+            user_key: c.user_key,
+            working_days: c.working_days,
+            quarter_days: c.quarter_days,
+            created: c.created,
+            updated: c.updated,
+        })
+    }
+    Ok(capacities)
+}
+
+async fn upsert_capacity(conn: &mut PoolConnection<Postgres>, capacity: &Capacity) -> Result<(), AppError> {
+    sqlx::query!("INSERT INTO member_capacities (key, organization_key, user_key, working_days, quarter_days, created, updated) values($1, $2, $3, $4, $5, $6, $7)
+        ON CONFLICT (organization_key, user_key) DO UPDATE SET working_days=$4, quarter_days=$5, updated=extract(epoch from now())",
+        capacity.key,
+        capacity.organization_key,
+        capacity.user_key,
+        capacity.working_days,
+        capacity.quarter_days,
+        capacity.created,
+        capacity.updated,
+    )
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+// Route Stuff
+
+pub async fn get(req: Request<State>) -> tide::Result {
+    let u = match user::user_or_error(&req) {
+        Ok(value) => value,
+        Err(_) => return Ok(tide::Redirect::new("/login").into()),
+    };
+    let query: CapacityQuery = req.query()?;
+    let now = chrono::Utc::now().timestamp();
+    let first = monday(match query.from.as_deref() {
+        None | Some("") => now,
+        Some(from) => day(from)?,
+    });
+    let weeks = query.weeks.unwrap_or(8).clamp(1, 26);
+    let mut conn = req.state().db_pool.acquire().await?;
+    let users = user::get_users_by_organization(&mut conn, u.organization_key).await?;
+    let capacities = get_organization_capacities(&mut conn, u.organization_key).await?;
+    let tasks = crate::task::get_tasks_by_organization(&mut conn, u.organization_key).await?;
+    let workflow = crate::workflow::get_workflow(&mut conn, u.organization_key).await?;
+    let workloads = users
+        .iter()
+        .map(|p| {
+            let capacity = capacities
+                .iter()
+                .find(|c| c.user_key == p.key)
+                .cloned()
+                .unwrap_or_else(|| Capacity::new(u.organization_key, p.key, Capacity::WEEKDAYS, 4));
+            workload(p, capacity, &tasks, &workflow, first, weeks)
+        })
+        .collect();
+    let claims = user::request_claims(&req).ok_or(AppError::Unauthorized)?;
+    let manage = Permission::Administer.granted_to(claims.role);
+    Ok(tide::Response::builder(tide::StatusCode::Ok)
+        .content_type(mime::HTML)
+        .body(CapacityTemplate::new(u, workloads, first, weeks, manage).render_string())
+        .build())
+}
+
+// Anyone can set their own working days, admins can set everyone's
+pub async fn update(mut req: Request<State>) -> tide::Result {
+    let claims: user::UserJwtState = match user::request_claims(&req) {
+        Some(c) => c,
+        None => {
+            return Ok(tide::Redirect::new("/login").into());
+        }
+    };
+    let organization_key = crate::error::parse_key(claims.organization_key.as_str(), "organization")?;
+    let user_key = crate::error::param_key(&req, "user_id")?;
+    if user_key.to_string() != claims.key && !Permission::Administer.granted_to(claims.role) {
+        return Err(AppError::Forbidden("only admins can change someone else's capacity".to_string()).into());
+    }
+    let request: CapacityRequest = req.body_json().await.map_err(|e| AppError::Validation(e.to_string()))?;
+    if !(0..=4).contains(&request.quarter_days) {
+        return Err(AppError::Validation("quarter_days is how much of a day they work, from 0 to 4".to_string()).into());
+    }
+    let mut working_days = 0;
+    for name in request.working_days.iter() {
+        match chrono::Weekday::from_str(name) {
+            Ok(d) => working_days |= 1 << d.num_days_from_monday(),
+            Err(_) => return Err(AppError::Validation(format!("`{}` isn't a day of the week", name)).into()),
+        }
+    }
+    let mut conn = req.state().db_pool.acquire().await?;
+    if member::get_role(&mut conn, organization_key, user_key).await.is_none() {
+        return Err(AppError::NotFound("member".to_string()).into());
+    }
+    let capacity = Capacity::new(organization_key, user_key, working_days, request.quarter_days);
+    upsert_capacity(&mut conn, &capacity).await?;
+    let j = serde_json::to_string(&capacity).expect("To JSON");
+    Ok(tide::Response::builder(tide::StatusCode::Ok)
+        .content_type(mime::JSON)
+        .body(j)
+        .build())
+}
+
+// A task's estimate is spread evenly over the assignee's working days from its start to its due
+// date. Work due on a day off lands on the due date, and open tasks without dates are unscheduled.
+pub fn workload(person: &User, capacity: Capacity, tasks: &[Task], workflow: &Workflow, first: i64, weeks: i64) -> Workload {
+    let mut load = Workload {
+        user_key: person.key,
+        name: person.to_string(),
+        weeks: (0..weeks)
+            .map(|w| WeekLoad {
+                start: first + w * 7 * DAY_SECONDS,
+                capacity: capacity.working_days() as i64 * capacity.quarter_days as i64,
+                scheduled: 0.0,
+                tasks: vec![],
+            })
+            .collect(),
+        unscheduled: 0,
+        capacity,
+    };
+    for task in tasks
+        .iter()
+        .filter(|t| t.assignee_key == person.key && t.estimated_quarter_days > 0 && !workflow.is_done(&t.status_key))
+    {
+        let (start, due) = match (task.start, task.due) {
+            (0, 0) => {
+                load.unscheduled += task.estimated_quarter_days as i64;
+                continue;
+            }
+            (0, d) | (d, 0) => (d, d),
+            (s, d) => (s.min(d), s.max(d)),
+        };
+        let (start, due) = (start - start.rem_euclid(DAY_SECONDS), due - due.rem_euclid(DAY_SECONDS));
+        let mut days: Vec<i64> = (0..=(due - start) / DAY_SECONDS)
+            .map(|d| start + d * DAY_SECONDS)
+            .filter(|d| load.capacity.works_on(weekday(*d)))
+            .collect();
+        if days.is_empty() {
+            days.push(due);
+        }
+        let per_day = task.estimated_quarter_days as f64 / days.len() as f64;
+        for d in days {
+            let w = (d - first).div_euclid(7 * DAY_SECONDS);
+            if w < 0 || w >= weeks {
+                continue;
+            }
+            let week = &mut load.weeks[w as usize];
+            week.scheduled += per_day;
+            if !week.tasks.contains(&task.name) {
+                week.tasks.push(task.name.clone());
+            }
+        }
+    }
+    load
+}
+
+// data types
+
+#[derive(Debug, Deserialize)]
+pub struct CapacityQuery {
+    #[serde(default)]
+    pub from: Option<String>,
+    #[serde(default)]
+    pub weeks: Option<i64>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct CapacityRequest {
+    pub working_days: Vec<String>,
+    pub quarter_days: i16,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct Capacity {
+    pub key: uuid::Uuid,
+    pub organization_key: uuid::Uuid,
+    pub user_key: uuid::Uuid,
+    pub working_days: i16,
+    pub quarter_days: i16,
+    pub created: i64,
+    pub updated: i64,
+}
+
+impl Capacity {
+    // Monday to Friday
+    pub const WEEKDAYS: i16 = 0b0011111;
+
+    pub fn new(organization_key: uuid::Uuid, user_key: uuid::Uuid, working_days: i16, quarter_days: i16) -> Self {
+        let key = Uuid::new_v4();
+        let created = chrono::Utc::now().timestamp();
+        let updated = 0;
+        Self {
+            key,
+            organization_key,
+            user_key,
+            working_days,
+            quarter_days,
+            created,
+            updated,
+        }
+    }
+
+    pub fn works_on(&self, day: chrono::Weekday) -> bool {
+        self.working_days & (1 << day.num_days_from_monday()) != 0
+    }
+
+    pub fn working_days(&self) -> usize {
+        WEEKDAYS.iter().filter(|d| self.works_on(**d)).count()
+    }
+}
+
+pub struct WeekLoad {
+    pub start: i64,
+    pub capacity: i64,
+    pub scheduled: f64,
+    pub tasks: Vec<String>,
+}
+
+impl WeekLoad {
+    pub fn over(&self) -> bool {
+        self.scheduled > self.capacity as f64 + 0.001
+    }
+
+    // in days rather than quarter days, that's how people plan
+    pub fn scheduled_days(&self) -> String {
+        format!("{:.1}", self.scheduled / 4.0)
+    }
+
+    pub fn capacity_days(&self) -> String {
+        format!("{:.1}", self.capacity as f64 / 4.0)
+    }
+}
+
+pub struct Workload {
+    pub user_key: uuid::Uuid,
+    pub name: String,
+    pub capacity: Capacity,
+    pub weeks: Vec<WeekLoad>,
+    pub unscheduled: i64,
+}
+
+impl Workload {
+    pub fn over(&self) -> bool {
+        self.weeks.iter().any(|w| w.over())
+    }
+
+    pub fn unscheduled_days(&self) -> String {
+        format!("{:.1}", self.unscheduled as f64 / 4.0)
+    }
+}
+
+#[derive(Template)]
+#[template(path = "capacity.html")]
+pub struct CapacityTemplate {
+    user: crate::user::User,
+    workloads: Vec<Workload>,
+    first: i64,
+    weeks: i64,
+    manage: bool,
+}
+
+impl<'a> CapacityTemplate {
+    pub fn new(user: crate::user::User, workloads: Vec<Workload>, first: i64, weeks: i64, manage: bool) -> Self {
+        return Self {
+            user,
+            workloads,
+            first,
+            weeks,
+            manage,
+        };
+    }
+
+    pub fn weekdays<'aa>(&'aa self) -> Vec<String> {
+        WEEKDAYS.iter().map(|d| d.to_string()).collect()
+    }
+
+    pub fn works_on<'aa>(&'aa self, capacity: &Capacity, name: &str) -> bool {
+        chrono::Weekday::from_str(name).map(|d| capacity.works_on(d)).unwrap_or(false)
+    }
+
+    pub fn can_edit<'aa>(&'aa self, user_key: &uuid::Uuid) -> bool {
+        self.manage || *user_key == self.user.key
+    }
+
+    pub fn when<'aa>(&'aa self, timestamp: &i64) -> String {
+        match chrono::NaiveDateTime::from_timestamp_opt(*timestamp, 0) {
+            Some(t) => t.format("%b %-d").to_string(),
+            None => "".to_string(),
+        }
+    }
+
+    pub fn from<'aa>(&'aa self) -> String {
+        match chrono::NaiveDateTime::from_timestamp_opt(self.first, 0) {
+            Some(t) => t.format("%Y-%m-%d").to_string(),
+            None => "".to_string(),
+        }
+    }
+
+    pub fn render_string(&self) -> String {
+        return self.render().unwrap();
+    }
+}
@@ -0,0 +1,226 @@
+use serde::{Deserialize, Serialize};
+use sqlx::pool::PoolConnection;
+use sqlx::postgres::PgQueryResult;
+use sqlx::Postgres;
+use uuid::Uuid;
+
+use tide::{http::mime, Request};
+
+use crate::error::AppError;
+use crate::{user, State};
+
+// SQL STUFF
+
+pub async fn get_task_checklist(
+    conn: &mut PoolConnection<Postgres>,
+    task_key: uuid::Uuid,
+    organization_key: uuid::Uuid,
+) -> Result<Vec<ChecklistItem>, AppError> {
+    let records = sqlx::query!(
+        "select key, organization_key, task_key, name, done, position, created, updated from checklist_items where task_key = $1 AND organization_key = $2 order by position, created",
+        task_key,
+        organization_key
+    )
+    .fetch_all(conn)
+    .await?;
+    let mut items = vec![];
+    for item in records {
+        items.push(ChecklistItem {
+            key: item.key,
+            organization_key: item.organization_key,
+            task_key: item.task_key,
+            name: item.name,
+            done: item.done,
+            position: item.position,
+            created: item.created,
+            updated: item.updated,
+        })
+    }
+    Ok(items)
+}
+
+pub async fn get_organization_checklists(
+    conn: &mut PoolConnection<Postgres>,
+    organization_key: uuid::Uuid,
+) -> Result<Vec<ChecklistItem>, AppError> {
+    let records = sqlx::query!(
+        "select key, organization_key, task_key, name, done, position, created, updated from checklist_items where organization_key = $1 order by position, created",
+        organization_key
+    )
+    .fetch_all(conn)
+    .await?;
+    let mut items = vec![];
+    for item in records {
+        items.push(ChecklistItem {
+            key: item.key,
+            organization_key: item.organization_key,
+            task_key: item.task_key,
+            name: item.name,
+            done: item.done,
+            position: item.position,
+            created: item.created,
+            updated: item.updated,
+        })
+    }
+    Ok(items)
+}
+
+async fn insert_checklist_item(conn: &mut PoolConnection<Postgres>, new_item: &ChecklistItem) -> Result<(), AppError> {
+    sqlx::query!("INSERT INTO checklist_items (key, organization_key, task_key, name, done, position, created, updated) values($1, $2, $3, $4, $5, $6, $7, $8)",
+        new_item.key,
+        new_item.organization_key,
+        new_item.task_key,
+        &new_item.name,
+        new_item.done,
+        new_item.position,
+        new_item.created,
+        new_item.updated,
+    )
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+async fn update_checklist_item(conn: &mut PoolConnection<Postgres>, item: &ChecklistItem) -> Result<PgQueryResult, AppError> {
+    Ok(sqlx::query!("UPDATE checklist_items SET name=$4, done=$5, position=$6, updated=extract(epoch from now()) where key = $1 AND organization_key = $2 AND task_key = $3",
+        item.key,
+        item.organization_key,
+        item.task_key,
+        &item.name,
+        item.done,
+        item.position,
+    )
+    .execute(conn)
+    .await?)
+}
+
+async fn delete_checklist_item(
+    conn: &mut PoolConnection<Postgres>,
+    key: uuid::Uuid,
+    organization_key: uuid::Uuid,
+) -> Result<PgQueryResult, sqlx::Error> {
+    return sqlx::query!(
+        "DELETE FROM checklist_items where organization_key=$1 AND key=$2",
+        organization_key,
+        key
+    )
+    .execute(conn)
+    .await;
+}
+
+// Route Stuff
+
+pub async fn get_by_task(req: Request<State>) -> tide::Result {
+    let u = match crate::user::user_or_error(&req) {
+        Ok(value) => value,
+        Err(_) => return Ok(tide::Redirect::new("/login").into()),
+    };
+    let task_key = crate::error::param_key(&req, "task_id")?;
+    let mut conn = req.state().db_pool.acquire().await?;
+    let items = get_task_checklist(&mut conn, task_key, u.organization_key).await?;
+    let j = serde_json::to_string(&items).expect("To JSON");
+    Ok(tide::Response::builder(tide::StatusCode::Ok)
+        .content_type(mime::JSON)
+        .body(j)
+        .build())
+}
+
+// A nil key adds an item to the end of the list, any other key updates that item
+pub async fn insert(mut req: Request<State>) -> tide::Result {
+    let umd: Result<ChecklistItem, tide::Error> = req.body_json().await;
+    let claims: user::UserJwtState = match user::request_claims(&req) {
+        Some(c) => c,
+        None => {
+            return Ok(tide::Redirect::new("/login").into());
+        }
+    };
+    let mut item = match umd {
+        Ok(i) => i,
+        Err(e) => return Err(AppError::Validation(format!("invalid json body: {}", e)).into()),
+    };
+    if item.name.trim().is_empty() {
+        return Err(AppError::Validation("a checklist item needs a name".to_string()).into());
+    }
+    let task_key = crate::error::param_key(&req, "task_id")?;
+    let organization_key = crate::error::parse_key(claims.organization_key.as_str(), "organization")?;
+    let mut conn = req.state().db_pool.acquire().await?;
+    if crate::task::get_task(&mut conn, task_key, organization_key).await?.is_none() {
+        return Err(AppError::NotFound("task".to_string()).into());
+    }
+    item.organization_key = organization_key;
+    item.task_key = task_key;
+
+    if item.key == uuid::Uuid::nil() {
+        let position = get_task_checklist(&mut conn, task_key, organization_key).await?.len() as i32;
+        let s = ChecklistItem::new(organization_key, task_key, item.name, position);
+        insert_checklist_item(&mut conn, &s).await?;
+        let j = serde_json::to_string(&s).expect("To JSON");
+        return Ok(tide::Response::builder(tide::StatusCode::Ok)
+            .content_type(mime::JSON)
+            .body(j)
+            .build());
+    }
+
+    if update_checklist_item(&mut conn, &item).await?.rows_affected() == 0 {
+        return Err(AppError::NotFound("checklist item".to_string()).into());
+    }
+    let j = serde_json::to_string(&item).expect("To JSON");
+    Ok(tide::Response::builder(tide::StatusCode::Ok)
+        .content_type(mime::JSON)
+        .body(j)
+        .build())
+}
+
+pub async fn delete(req: Request<State>) -> tide::Result {
+    let u = match crate::user::user_or_error(&req) {
+        Ok(value) => value,
+        Err(_) => return Ok(tide::Redirect::new("/login").into()),
+    };
+    let key = crate::error::param_key(&req, "item_id")?;
+    let mut conn = req.state().db_pool.acquire().await?;
+    if delete_checklist_item(&mut conn, key, u.organization_key).await?.rows_affected() == 0 {
+        return Err(AppError::NotFound("checklist item".to_string()).into());
+    }
+    Ok(tide::Response::builder(tide::StatusCode::Ok)
+        .content_type(mime::HTML)
+        .build())
+}
+
+// data types
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct ChecklistItem {
+    #[serde(default)]
+    pub key: uuid::Uuid,
+    #[serde(default)]
+    pub organization_key: uuid::Uuid,
+    #[serde(default)]
+    pub task_key: uuid::Uuid,
+    pub name: String,
+    #[serde(default)]
+    pub done: bool,
+    #[serde(default)]
+    pub position: i32,
+    #[serde(default)]
+    pub created: i64,
+    #[serde(default)]
+    pub updated: i64,
+}
+
+impl ChecklistItem {
+    pub fn new(organization_key: uuid::Uuid, task_key: uuid::Uuid, name: String, position: i32) -> Self {
+        let key = Uuid::new_v4();
+        let created = chrono::Utc::now().timestamp();
+        let updated = 0;
+        Self {
+            key,
+            organization_key,
+            task_key,
+            name,
+            done: false,
+            position,
+            created,
+            updated,
+        }
+    }
+}
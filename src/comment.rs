@@ -0,0 +1,310 @@
+use serde::{Deserialize, Serialize};
+use sqlx::pool::PoolConnection;
+use sqlx::Postgres;
+use uuid::Uuid;
+
+use tide::{http::mime, Request};
+
+use crate::auth::Permission;
+use crate::error::AppError;
+use crate::{user, State};
+
+// SQL STUFF
+
+pub async fn get_task_comments(
+    conn: &mut PoolConnection<Postgres>,
+    task_key: uuid::Uuid,
+    organization_key: uuid::Uuid,
+) -> Result<Vec<Comment>, AppError> {
+    let records = sqlx::query!(
+        "select key, organization_key, task_key, parent_key, author_key, body, edited, deleted, created, updated from task_comments where task_key = $1 AND organization_key = $2 order by created",
+        task_key,
+        organization_key
+    )
+    .fetch_all(conn)
+    .await?;
+    let mut comments = vec![];
+    for comment in records {
+        comments.push(Comment {
+            key: comment.key,
+            organization_key: comment.organization_key,
+            task_key: comment.task_key,
+            parent_key: comment.parent_key,
+            author_key: comment.author_key,
+            body: comment.body,
+            edited: comment.edited,
+            deleted: comment.deleted,
+            created: comment.created,
+            updated: comment.updated,
+        })
+    }
+    Ok(comments)
+}
+
+pub async fn get_comment(
+    conn: &mut PoolConnection<Postgres>,
+    key: uuid::Uuid,
+    organization_key: uuid::Uuid,
+) -> Result<Option<Comment>, AppError> {
+    let comment = match sqlx::query!(
+        "select key, organization_key, task_key, parent_key, author_key, body, edited, deleted, created, updated from task_comments where key = $1 AND organization_key = $2",
+        key,
+        organization_key
+    )
+    .fetch_optional(conn)
+    .await?
+    {
+        Some(r) => r,
+        None => return Ok(None),
+    };
+
+    Ok(Some(Comment {
+        key: comment.key,
+        organization_key: comment.organization_key,
+        task_key: comment.task_key,
+        parent_key: comment.parent_key,
+        author_key: comment.author_key,
+        body: comment.body,
+        edited: comment.edited,
+        deleted: comment.deleted,
+        created: comment.created,
+        updated: comment.updated,
+    }))
+}
+
+async fn insert_comment(conn: &mut PoolConnection<Postgres>, new_comment: &Comment) -> Result<(), AppError> {
+    sqlx::query!("INSERT INTO task_comments (key, organization_key, task_key, parent_key, author_key, body, edited, deleted, created, updated) values($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)",
+        new_comment.key,
+        new_comment.organization_key,
+        new_comment.task_key,
+        new_comment.parent_key,
+        new_comment.author_key,
+        &new_comment.body,
+        new_comment.edited,
+        new_comment.deleted,
+        new_comment.created,
+        new_comment.updated,
+    )
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+async fn update_comment_body(conn: &mut PoolConnection<Postgres>, comment: &Comment) -> Result<(), AppError> {
+    sqlx::query!("UPDATE task_comments SET body=$3, edited=true, updated=extract(epoch from now()) where key = $1 AND organization_key = $2",
+        comment.key,
+        comment.organization_key,
+        &comment.body,
+    )
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+// The row stays so its replies keep their place in the thread, only the text goes
+async fn mark_deleted(conn: &mut PoolConnection<Postgres>, key: uuid::Uuid, organization_key: uuid::Uuid) -> Result<(), AppError> {
+    sqlx::query!("UPDATE task_comments SET body='', deleted=true, updated=extract(epoch from now()) where key = $1 AND organization_key = $2",
+        key,
+        organization_key,
+    )
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+// Orders comments so every reply follows its parent, paired with how deep it sits in the thread
+pub fn thread(comments: Vec<Comment>) -> Vec<(usize, Comment)> {
+    fn walk(parent: Uuid, depth: usize, comments: &[Comment], out: &mut Vec<(usize, Comment)>) {
+        for c in comments.iter().filter(|c| c.parent_key == parent) {
+            out.push((depth, c.clone()));
+            walk(c.key, depth + 1, comments, out);
+        }
+    }
+    let mut out = vec![];
+    walk(Uuid::nil(), 0, &comments, &mut out);
+    // replies to a parent we can't see still get shown, at the top level
+    for c in comments.iter() {
+        if !out.iter().any(|(_, o)| o.key == c.key) {
+            out.push((0, c.clone()));
+        }
+    }
+    out
+}
+
+// Route Stuff
+
+pub async fn get_by_task(req: Request<State>) -> tide::Result {
+    let u = match crate::user::user_or_error(&req) {
+        Ok(value) => value,
+        Err(_) => return Ok(tide::Redirect::new("/login").into()),
+    };
+    let task_key = crate::error::param_key(&req, "task_id")?;
+    let mut conn = req.state().db_pool.acquire().await?;
+    let comments = get_task_comments(&mut conn, task_key, u.organization_key).await?;
+    let j = serde_json::to_string(&comments).expect("To JSON");
+    Ok(tide::Response::builder(tide::StatusCode::Ok)
+        .content_type(mime::JSON)
+        .body(j)
+        .build())
+}
+
+// Posting with a nil key adds a comment, posting an existing key edits it
+pub async fn insert(mut req: Request<State>) -> tide::Result {
+    let umd: Result<Comment, tide::Error> = req.body_json().await;
+    let claims: user::UserJwtState = match user::request_claims(&req) {
+        Some(c) => c,
+        None => {
+            return Ok(tide::Redirect::new("/login").into());
+        }
+    };
+    let comment = match umd {
+        Ok(c) => c,
+        Err(e) => return Err(AppError::Validation(format!("invalid json body: {}", e)).into()),
+    };
+    if comment.body.trim().is_empty() {
+        return Err(AppError::Validation("a comment needs some text".to_string()).into());
+    }
+    let task_key = crate::error::param_key(&req, "task_id")?;
+    let organization_key = crate::error::parse_key(claims.organization_key.as_str(), "organization")?;
+    let author_key = crate::error::parse_key(claims.key.as_str(), "user")?;
+    let mut conn = req.state().db_pool.acquire().await?;
+    if crate::task::get_task(&mut conn, task_key, organization_key).await?.is_none() {
+        return Err(AppError::NotFound("task".to_string()).into());
+    }
+
+    if comment.key == uuid::Uuid::nil() {
+        if !comment.parent_key.is_nil() {
+            match get_comment(&mut conn, comment.parent_key, organization_key).await? {
+                Some(parent) if parent.task_key == task_key => (),
+                _ => return Err(AppError::NotFound("comment".to_string()).into()),
+            }
+        }
+        let s = Comment::new(organization_key, task_key, comment.parent_key, author_key, comment.body);
+        insert_comment(&mut conn, &s).await?;
+        let j = serde_json::to_string(&s).expect("To JSON");
+        return Ok(tide::Response::builder(tide::StatusCode::Ok)
+            .content_type(mime::JSON)
+            .body(j)
+            .build());
+    }
+
+    let mut existing = match get_comment(&mut conn, comment.key, organization_key).await? {
+        Some(c) if c.task_key == task_key && !c.deleted => c,
+        _ => return Err(AppError::NotFound("comment".to_string()).into()),
+    };
+    if existing.author_key != author_key {
+        return Err(AppError::Forbidden("only the author can edit a comment".to_string()).into());
+    }
+    existing.body = comment.body;
+    existing.edited = true;
+    update_comment_body(&mut conn, &existing).await?;
+    let j = serde_json::to_string(&existing).expect("To JSON");
+    Ok(tide::Response::builder(tide::StatusCode::Ok)
+        .content_type(mime::JSON)
+        .body(j)
+        .build())
+}
+
+pub async fn delete(req: Request<State>) -> tide::Result {
+    let claims: user::UserJwtState = match user::request_claims(&req) {
+        Some(c) => c,
+        None => {
+            return Ok(tide::Redirect::new("/login").into());
+        }
+    };
+    let key = crate::error::param_key(&req, "comment_id")?;
+    let organization_key = crate::error::parse_key(claims.organization_key.as_str(), "organization")?;
+    let author_key = crate::error::parse_key(claims.key.as_str(), "user")?;
+    let mut conn = req.state().db_pool.acquire().await?;
+    let comment = match get_comment(&mut conn, key, organization_key).await? {
+        Some(c) => c,
+        None => return Err(AppError::NotFound("comment".to_string()).into()),
+    };
+    // admins can clear out anyone's comment, everybody else only their own
+    if comment.author_key != author_key && !Permission::Administer.granted_to(claims.role) {
+        return Err(AppError::Forbidden("only the author can delete a comment".to_string()).into());
+    }
+    mark_deleted(&mut conn, key, organization_key).await?;
+    Ok(tide::Response::builder(tide::StatusCode::Ok)
+        .content_type(mime::HTML)
+        .build())
+}
+
+// data types
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct Comment {
+    #[serde(default)]
+    pub key: uuid::Uuid,
+    #[serde(default)]
+    pub organization_key: uuid::Uuid,
+    #[serde(default)]
+    pub task_key: uuid::Uuid,
+    #[serde(default)]
+    pub parent_key: uuid::Uuid,
+    #[serde(default)]
+    pub author_key: uuid::Uuid,
+    pub body: String,
+    #[serde(default)]
+    pub edited: bool,
+    #[serde(default)]
+    pub deleted: bool,
+    #[serde(default)]
+    pub created: i64,
+    #[serde(default)]
+    pub updated: i64,
+}
+
+impl Comment {
+    pub fn new(
+        organization_key: uuid::Uuid,
+        task_key: uuid::Uuid,
+        parent_key: uuid::Uuid,
+        author_key: uuid::Uuid,
+        body: String,
+    ) -> Self {
+        let key = Uuid::new_v4();
+        let created = chrono::Utc::now().timestamp();
+        let updated = 0;
+        Self {
+            key,
+            organization_key,
+            task_key,
+            parent_key,
+            author_key,
+            body,
+            edited: false,
+            deleted: false,
+            created,
+            updated,
+        }
+    }
+
+    // markdown::to_html passes raw html and javascript: links through, ammonia strips them
+    pub fn html(&self) -> String {
+        ammonia::clean(&markdown::to_html(self.body.as_str()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Comment;
+
+    fn html(body: &str) -> String {
+        let nil = uuid::Uuid::nil();
+        Comment::new(nil, nil, nil, nil, body.to_string()).html()
+    }
+
+    #[test]
+    fn markdown_still_renders() {
+        assert_eq!(html("**done**"), "<p><strong>done</strong></p>\n");
+    }
+
+    #[test]
+    fn scripts_and_handlers_are_stripped() {
+        let rendered = html("<script>alert(1)</script><img src=x onerror=\"alert(1)\"> [x](javascript:alert(1))");
+        assert!(!rendered.contains("<script"), "{}", rendered);
+        assert!(!rendered.contains("<img"), "{}", rendered);
+        assert!(!rendered.contains("javascript:"), "{}", rendered);
+    }
+}
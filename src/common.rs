@@ -1,6 +1,11 @@
 use async_std::{io::{Read, self}, pin::Pin, task::{Context, Poll, ready}};
 
 use async_std::stream::Stream;
+use chrono::Datelike;
+
+use crate::error::AppError;
+
+pub const DAY_SECONDS: i64 = 60 * 60 * 24;
 
 #[derive(Debug)]
 pub struct BufferedBytesStream<T> {
@@ -22,4 +27,27 @@ impl<T: Read + Unpin> Stream for BufferedBytesStream<T> {
             Err(e) => Poll::Ready(Some(Err(e))),
         }
     }
-}
\ No newline at end of file
+}
+
+// JSON that can go straight into a <script> block, a `</` in some name can't close the tag early
+pub fn script_json<T: serde::Serialize>(value: &T) -> String {
+    serde_json::to_string(value).expect("To JSON").replace("</", "<\\/")
+}
+
+// midnight UTC of a YYYY-MM-DD date, the way dates come in on query strings and filters
+pub fn day(value: &str) -> Result<i64, AppError> {
+    match chrono::NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+        Ok(d) => Ok(d.and_hms_opt(0, 0, 0).unwrap_or_default().and_utc().timestamp()),
+        Err(_) => Err(AppError::Validation(format!("`{}` isn't a date, use YYYY-MM-DD", value))),
+    }
+}
+
+// midnight UTC of the Monday of the week `timestamp` falls in
+pub fn monday(timestamp: i64) -> i64 {
+    let day = timestamp - timestamp.rem_euclid(DAY_SECONDS);
+    day - weekday(day).num_days_from_monday() as i64 * DAY_SECONDS
+}
+
+pub fn weekday(timestamp: i64) -> chrono::Weekday {
+    chrono::NaiveDateTime::from_timestamp_opt(timestamp, 0).unwrap_or_default().weekday()
+}
@@ -0,0 +1,470 @@
+use std::collections::{HashMap, HashSet};
+
+use serde::{Deserialize, Serialize};
+use sqlx::pool::PoolConnection;
+use sqlx::postgres::PgQueryResult;
+use sqlx::{Connection, PgConnection, Postgres};
+use strum_macros::EnumIter;
+use uuid::Uuid;
+
+use tide::{http::mime, Request};
+
+use crate::error::AppError;
+use crate::task::Task;
+use crate::workflow::{StatusCategory, Workflow};
+use crate::{user, State};
+
+// SQL STUFF
+
+pub async fn get_task_dependencies(
+    conn: &mut PoolConnection<Postgres>,
+    task_key: uuid::Uuid,
+    organization_key: uuid::Uuid,
+) -> Result<Vec<Dependency>, AppError> {
+    let records = sqlx::query!(
+        "select key, organization_key, task_key, depends_on_key, kind, created, updated from task_dependencies where task_key = $1 AND organization_key = $2",
+        task_key,
+        organization_key
+    )
+    .fetch_all(conn)
+    .await?;
+    let mut dependencies = vec![];
+    for d in records {
+        dependencies.push(Dependency {
+            key: d.key,
+            organization_key: d.organization_key,
+            task_key: d.task_key,
+            depends_on_key: d.depends_on_key,
+            kind: d.kind.into(),
+            created: d.created,
+            updated: d.updated,
+        })
+    }
+    Ok(dependencies)
+}
+
+pub async fn get_organization_dependencies(
+    conn: &mut PgConnection,
+    organization_key: uuid::Uuid,
+) -> Result<Vec<Dependency>, AppError> {
+    let records = sqlx::query!(
+        "select key, organization_key, task_key, depends_on_key, kind, created, updated from task_dependencies where organization_key = $1",
+        organization_key
+    )
+    .fetch_all(conn)
+    .await?;
+    let mut dependencies = vec![];
+    for d in records {
+        dependencies.push(Dependency {
+            key: d.key,
+            organization_key: d.organization_key,
+            task_key: d.task_key,
+            depends_on_key: d.depends_on_key,
+            kind: d.kind.into(),
+            created: d.created,
+            updated: d.updated,
+        })
+    }
+    Ok(dependencies)
+}
+
+pub(crate) async fn insert_dependency(conn: &mut PgConnection, new_dependency: &Dependency) -> Result<(), AppError> {
+    sqlx::query!("INSERT INTO task_dependencies (key, organization_key, task_key, depends_on_key, kind, created, updated) values($1, $2, $3, $4, $5, $6, $7)",
+        new_dependency.key,
+        new_dependency.organization_key,
+        new_dependency.task_key,
+        new_dependency.depends_on_key,
+        new_dependency.kind as i16,
+        new_dependency.created,
+        new_dependency.updated,
+    )
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+async fn delete_dependency(
+    conn: &mut PoolConnection<Postgres>,
+    key: uuid::Uuid,
+    organization_key: uuid::Uuid,
+) -> Result<PgQueryResult, sqlx::Error> {
+    return sqlx::query!(
+        "DELETE FROM task_dependencies where organization_key=$1 AND key=$2",
+        organization_key,
+        key
+    )
+    .execute(conn)
+    .await;
+}
+
+// The tasks still holding `task_key` back, empty when it is free to start
+pub async fn get_blockers(
+    conn: &mut PoolConnection<Postgres>,
+    task_key: uuid::Uuid,
+    organization_key: uuid::Uuid,
+) -> Result<Vec<Task>, AppError> {
+    let dependencies = get_task_dependencies(conn, task_key, organization_key).await?;
+    let workflow = crate::workflow::get_workflow(conn, organization_key).await?;
+    let mut blockers = vec![];
+    for d in dependencies {
+        if let Some(t) = crate::task::get_task(conn, d.depends_on_key, organization_key).await? {
+            if d.kind.blocks(workflow.category(&t.status_key)) {
+                blockers.push(t);
+            }
+        }
+    }
+    Ok(blockers)
+}
+
+// Starting blocked work is allowed, but whoever moves a task from not started to active should
+// hear what is still holding it back
+pub(crate) async fn blocker_warnings(
+    conn: &mut PoolConnection<Postgres>,
+    workflow: &Workflow,
+    task_key: uuid::Uuid,
+    organization_key: uuid::Uuid,
+    from_key: uuid::Uuid,
+    to_key: uuid::Uuid,
+) -> Result<Vec<String>, AppError> {
+    let mut warnings = vec![];
+    if workflow.category(&to_key) == StatusCategory::Active && workflow.category(&from_key) == StatusCategory::NotStarted {
+        for b in get_blockers(conn, task_key, organization_key).await? {
+            warnings.push(format!("{} is still {}", b.name, workflow.name(&b.status_key)));
+        }
+    }
+    Ok(warnings)
+}
+
+// Would making `task_key` depend on `depends_on_key` close a loop? It does if `depends_on_key`
+// already waits on `task_key`, directly or further up the chain.
+pub fn creates_cycle(dependencies: &[Dependency], task_key: Uuid, depends_on_key: Uuid) -> bool {
+    if task_key == depends_on_key {
+        return true;
+    }
+    let mut edges: HashMap<Uuid, Vec<Uuid>> = HashMap::new();
+    for d in dependencies {
+        edges.entry(d.task_key).or_default().push(d.depends_on_key);
+    }
+    let mut seen = HashSet::new();
+    let mut stack = vec![depends_on_key];
+    while let Some(k) = stack.pop() {
+        if k == task_key {
+            return true;
+        }
+        if !seen.insert(k) {
+            continue;
+        }
+        if let Some(next) = edges.get(&k) {
+            stack.extend(next.iter().copied());
+        }
+    }
+    false
+}
+
+// A task's length for scheduling, the estimate when there is one otherwise its start to due span
+fn duration(task: &Task) -> i64 {
+    if task.estimated_quarter_days > 0 {
+        return task.estimated_quarter_days as i64;
+    }
+    let days = (task.due - task.start) / (60 * 60 * 24);
+    (days * 4).max(1)
+}
+
+// The longest chain of linked work through a project's tasks, in the order it has to happen.
+// Links to tasks outside the project are ignored. Slipping any task on it moves the end date.
+pub fn critical_path(tasks: &[Task], dependencies: &[Dependency]) -> Vec<Uuid> {
+    let in_project: HashSet<Uuid> = tasks.iter().map(|t| t.key).collect();
+    let durations: HashMap<Uuid, i64> = tasks.iter().map(|t| (t.key, duration(t))).collect();
+    let links: Vec<&Dependency> = dependencies
+        .iter()
+        .filter(|d| in_project.contains(&d.task_key) && in_project.contains(&d.depends_on_key))
+        .collect();
+    // without links every task is its own chain, nothing worth highlighting
+    if links.is_empty() {
+        return vec![];
+    }
+
+    // Kahn's algorithm, a cycle can't be inserted but a bad row shouldn't spin us forever either
+    let mut waiting: HashMap<Uuid, usize> = tasks.iter().map(|t| (t.key, 0)).collect();
+    for d in links.iter() {
+        *waiting.entry(d.task_key).or_default() += 1;
+    }
+    let mut ready: Vec<Uuid> = tasks.iter().filter(|t| waiting[&t.key] == 0).map(|t| t.key).collect();
+    let mut order = vec![];
+    while let Some(k) = ready.pop() {
+        order.push(k);
+        for d in links.iter().filter(|d| d.depends_on_key == k) {
+            let w = waiting.entry(d.task_key).or_default();
+            *w -= 1;
+            if *w == 0 {
+                ready.push(d.task_key);
+            }
+        }
+    }
+
+    let mut earliest_start: HashMap<Uuid, i64> = HashMap::new();
+    let mut driven_by: HashMap<Uuid, Uuid> = HashMap::new();
+    for k in order.iter() {
+        let mut start = 0;
+        for d in links.iter().filter(|d| d.task_key == *k) {
+            let p_start = *earliest_start.get(&d.depends_on_key).unwrap_or(&0);
+            let ready_at = match d.kind {
+                DependencyKind::FinishToStart => p_start + durations[&d.depends_on_key],
+                DependencyKind::StartToStart => p_start,
+            };
+            // the predecessor that lets this task start last is the one driving it
+            if !driven_by.contains_key(k) || ready_at > start {
+                start = ready_at;
+                driven_by.insert(*k, d.depends_on_key);
+            }
+        }
+        earliest_start.insert(*k, start);
+    }
+
+    let last = order
+        .iter()
+        .max_by_key(|k| earliest_start[*k] + durations[*k])
+        .copied();
+    let mut path = vec![];
+    let mut current = last;
+    while let Some(k) = current {
+        path.push(k);
+        current = driven_by.get(&k).copied();
+    }
+    path.reverse();
+    path
+}
+
+// Route Stuff
+
+pub async fn get_by_task(req: Request<State>) -> tide::Result {
+    let u = match crate::user::user_or_error(&req) {
+        Ok(value) => value,
+        Err(_) => return Ok(tide::Redirect::new("/login").into()),
+    };
+    let task_key = crate::error::param_key(&req, "task_id")?;
+    let mut conn = req.state().db_pool.acquire().await?;
+    let dependencies = get_task_dependencies(&mut conn, task_key, u.organization_key).await?;
+    let j = serde_json::to_string(&dependencies).expect("To JSON");
+    Ok(tide::Response::builder(tide::StatusCode::Ok)
+        .content_type(mime::JSON)
+        .body(j)
+        .build())
+}
+
+pub async fn insert(mut req: Request<State>) -> tide::Result {
+    let umd: Result<DependencyRequest, tide::Error> = req.body_json().await;
+    let claims: user::UserJwtState = match user::request_claims(&req) {
+        Some(c) => c,
+        None => {
+            return Ok(tide::Redirect::new("/login").into());
+        }
+    };
+    let request = match umd {
+        Ok(r) => r,
+        Err(e) => return Err(AppError::Validation(format!("invalid json body: {}", e)).into()),
+    };
+    let task_key = crate::error::param_key(&req, "task_id")?;
+    let organization_key = crate::error::parse_key(claims.organization_key.as_str(), "organization")?;
+    let mut conn = req.state().db_pool.acquire().await?;
+    for key in [task_key, request.depends_on_key] {
+        if crate::task::get_task(&mut conn, key, organization_key).await?.is_none() {
+            return Err(AppError::NotFound("task".to_string()).into());
+        }
+    }
+    // two requests that each add half of a loop would both pass the check on their own
+    let mut tx = conn.begin().await?;
+    crate::task::lock_organization(&mut tx, organization_key).await?;
+    let existing = get_organization_dependencies(&mut tx, organization_key).await?;
+    if creates_cycle(&existing, task_key, request.depends_on_key) {
+        return Err(AppError::Validation("that dependency would make the tasks wait on each other".to_string()).into());
+    }
+    if existing.iter().any(|d| d.task_key == task_key && d.depends_on_key == request.depends_on_key) {
+        return Err(AppError::Validation("those tasks are already linked".to_string()).into());
+    }
+    let s = Dependency::new(organization_key, task_key, request.depends_on_key, request.kind);
+    insert_dependency(&mut tx, &s).await?;
+    tx.commit().await?;
+    let j = serde_json::to_string(&s).expect("To JSON");
+    Ok(tide::Response::builder(tide::StatusCode::Ok)
+        .content_type(mime::JSON)
+        .body(j)
+        .build())
+}
+
+pub async fn delete(req: Request<State>) -> tide::Result {
+    let u = match crate::user::user_or_error(&req) {
+        Ok(value) => value,
+        Err(_) => return Ok(tide::Redirect::new("/login").into()),
+    };
+    let key = crate::error::param_key(&req, "dependency_id")?;
+    let mut conn = req.state().db_pool.acquire().await?;
+    if delete_dependency(&mut conn, key, u.organization_key).await?.rows_affected() == 0 {
+        return Err(AppError::NotFound("dependency".to_string()).into());
+    }
+    Ok(tide::Response::builder(tide::StatusCode::Ok)
+        .content_type(mime::HTML)
+        .build())
+}
+
+pub async fn get_critical_path(req: Request<State>) -> tide::Result {
+    let u = match crate::user::user_or_error(&req) {
+        Ok(value) => value,
+        Err(_) => return Ok(tide::Redirect::new("/login").into()),
+    };
+    let project_key = crate::error::param_key(&req, "project_id")?;
+    let mut conn = req.state().db_pool.acquire().await?;
+    if crate::project::get_project(&mut conn, project_key, u.organization_key).await?.is_none() {
+        return Err(AppError::NotFound("project".to_string()).into());
+    }
+    let tasks = crate::task::get_tasks_by_project(&mut conn, project_key, u.organization_key).await?;
+    let dependencies = get_organization_dependencies(&mut conn, u.organization_key).await?;
+    let j = serde_json::to_string(&critical_path(&tasks, &dependencies)).expect("To JSON");
+    Ok(tide::Response::builder(tide::StatusCode::Ok)
+        .content_type(mime::JSON)
+        .body(j)
+        .build())
+}
+
+// data types
+
+#[derive(PartialEq, Debug, Deserialize, Serialize, Clone, Copy, sqlx::Type, EnumIter)]
+pub enum DependencyKind {
+    FinishToStart,
+    StartToStart,
+}
+
+impl DependencyKind {
+    // whether a predecessor whose status is in `category` still holds the dependent task back
+    pub fn blocks(&self, category: StatusCategory) -> bool {
+        match self {
+            DependencyKind::FinishToStart => category != StatusCategory::Done,
+            DependencyKind::StartToStart => category == StatusCategory::NotStarted,
+        }
+    }
+}
+
+impl Into<DependencyKind> for i16 {
+    fn into(self) -> DependencyKind {
+        match self {
+            0 => DependencyKind::FinishToStart,
+            1 => DependencyKind::StartToStart,
+            _ => DependencyKind::FinishToStart,
+        }
+    }
+}
+
+impl From<DependencyKind> for i16 {
+    fn from(k: DependencyKind) -> Self {
+        match k {
+            DependencyKind::FinishToStart => 0,
+            DependencyKind::StartToStart => 1,
+        }
+    }
+}
+
+impl ToString for DependencyKind {
+    fn to_string(&self) -> String {
+        match self {
+            DependencyKind::FinishToStart => "Finish To Start".to_owned(),
+            DependencyKind::StartToStart => "Start To Start".to_owned(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct DependencyRequest {
+    pub depends_on_key: uuid::Uuid,
+    pub kind: DependencyKind,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct Dependency {
+    pub key: uuid::Uuid,
+    pub organization_key: uuid::Uuid,
+    pub task_key: uuid::Uuid,
+    pub depends_on_key: uuid::Uuid,
+    pub kind: DependencyKind,
+    pub created: i64,
+    pub updated: i64,
+}
+
+impl Dependency {
+    pub fn new(
+        organization_key: uuid::Uuid,
+        task_key: uuid::Uuid,
+        depends_on_key: uuid::Uuid,
+        kind: DependencyKind,
+    ) -> Self {
+        let key = Uuid::new_v4();
+        let created = chrono::Utc::now().timestamp();
+        let updated = 0;
+        Self {
+            key,
+            organization_key,
+            task_key,
+            depends_on_key,
+            kind,
+            created,
+            updated,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn task(quarter_days: i32) -> Task {
+        let nil = Uuid::nil();
+        Task::new(nil, nil, None, None, nil, nil, String::new(), String::new(), String::new(), nil, quarter_days, 0, 0)
+    }
+
+    // `task` waits for `on` to finish
+    fn after(task: &Task, on: &Task) -> Dependency {
+        Dependency::new(Uuid::nil(), task.key, on.key, DependencyKind::FinishToStart)
+    }
+
+    #[test]
+    fn a_direct_cycle_is_caught() {
+        let (a, b) = (task(1), task(1));
+        let dependencies = vec![after(&b, &a)];
+        assert!(creates_cycle(&dependencies, a.key, b.key));
+        assert!(creates_cycle(&dependencies, a.key, a.key));
+        assert!(!creates_cycle(&dependencies, b.key, a.key));
+    }
+
+    #[test]
+    fn an_indirect_cycle_is_caught() {
+        let (a, b, c) = (task(1), task(1), task(1));
+        let dependencies = vec![after(&b, &a), after(&c, &b)];
+        assert!(creates_cycle(&dependencies, a.key, c.key));
+        assert!(!creates_cycle(&dependencies, c.key, a.key));
+    }
+
+    #[test]
+    fn a_diamond_is_not_a_cycle_and_its_longer_side_is_critical() {
+        let (top, left, right, bottom) = (task(4), task(8), task(2), task(4));
+        let dependencies = vec![after(&left, &top), after(&right, &top), after(&bottom, &left), after(&bottom, &right)];
+        assert!(!creates_cycle(&dependencies, right.key, left.key));
+        assert!(!creates_cycle(&dependencies, bottom.key, top.key));
+        assert!(creates_cycle(&dependencies, top.key, bottom.key));
+
+        let tasks = vec![top, left, right, bottom];
+        assert_eq!(critical_path(&tasks, &dependencies), vec![tasks[0].key, tasks[1].key, tasks[3].key]);
+    }
+
+    #[test]
+    fn the_longest_path_goes_by_estimate_not_by_task_count() {
+        let (long, short_1, short_2, short_3, end) = (task(20), task(2), task(2), task(2), task(1));
+        let dependencies = vec![after(&end, &long), after(&short_2, &short_1), after(&short_3, &short_2), after(&end, &short_3)];
+        let tasks = vec![long, short_1, short_2, short_3, end];
+        assert_eq!(critical_path(&tasks, &dependencies), vec![tasks[0].key, tasks[4].key]);
+    }
+
+    #[test]
+    fn unlinked_tasks_have_no_critical_path() {
+        let tasks = vec![task(4), task(8)];
+        assert!(critical_path(&tasks, &[]).is_empty());
+    }
+}
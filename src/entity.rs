@@ -1,6 +1,6 @@
-use std::str::FromStr;
 
 use askama::Template;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use sqlx::pool::PoolConnection;
 use sqlx::Postgres;
@@ -9,12 +9,13 @@ use uuid::Uuid;
 
 use tide::{http::mime, Request};
 
+use crate::error::AppError;
 use crate::akaunting::InvoiceData;
 use crate::home::NotFoundTemplate;
 use crate::matrix::{post_entity_create, post_contact_create};
 use crate::{State, file, note, user};
 
-pub async fn get_organization_entitys(conn: &mut PoolConnection<Postgres>, organization_key: uuid::Uuid) -> Vec<Entity> {
+pub async fn get_organization_entitys(conn: &mut PoolConnection<Postgres>, organization_key: uuid::Uuid) -> Result<Vec<Entity>, AppError> {
     let records = sqlx::query!(
         "select 
         key,
@@ -38,37 +39,97 @@ pub async fn get_organization_entitys(conn: &mut PoolConnection<Postgres>, organ
         organization_key
     )
     .fetch_all(conn)
-    .await
-    .expect("Select entitys by organization key");
+    .await?;
     let mut entitys = Vec::<Entity>::new();
     for entity in records {
-        let e_type: EntityType = entity.entity_type.expect("entity_type exists").into();
+        let e_type: EntityType = entity.entity_type.into();
         let brd = Entity {
-            key: entity.key.expect("key exists"),
-            organization_key: entity.organization_key.expect("organization_key"), 
-            external_accounting_id: entity.external_accounting_id.expect("external_accounting_id"), 
-            owner_key: entity.owner_key.expect("owner_key exists"),
-            name: entity.name.expect("name exists"),
-            description: entity.description.expect("description exists"),
-            matrix_room_url: entity.matrix_room_url.expect("matrix_room_url exists"),
-            web_url: entity.web_url.expect("web_url exists"),
-            avatar_url: entity.avatar_url.expect("avatar_url exists"), 
+            key: entity.key,
+            organization_key: entity.organization_key, 
+            external_accounting_id: entity.external_accounting_id, 
+            owner_key: entity.owner_key,
+            name: entity.name,
+            description: entity.description,
+            matrix_room_url: entity.matrix_room_url,
+            web_url: entity.web_url,
+            avatar_url: entity.avatar_url, 
             entity_type: e_type, 
-            address_primary: entity.address_primary.expect("address_primary exists"), 
-            address_unit: entity.address_unit.expect("address_unit exists"), 
-            city: entity.city.expect("city exists"), 
-            state: entity.state.expect("state exists"), 
-            zip_code: entity.zip_code.expect("zip_code exists"), 
-            country: entity.country.expect("country exists"),
-            created: entity.created.expect("created exists"),
-            updated: entity.updated.expect("updated exists"),
+            address_primary: entity.address_primary, 
+            address_unit: entity.address_unit, 
+            city: entity.city, 
+            state: entity.state, 
+            zip_code: entity.zip_code, 
+            country: entity.country,
+            created: entity.created,
+            updated: entity.updated,
         };
         entitys.push(brd);
     }
-    return entitys
+    Ok(entitys)
 }
 
-pub async fn get_user_entitys(conn: &mut PoolConnection<Postgres>, user_key: uuid::Uuid) -> Vec<Entity> {
+pub async fn get_organization_entitys_page(conn: &mut PoolConnection<Postgres>, organization_key: uuid::Uuid, limit: i64, offset: i64) -> Result<Vec<Entity>, AppError> {
+    let records = sqlx::query!(
+        "select 
+        key,
+        organization_key,
+        external_accounting_id,
+        owner_key,
+        name,
+        description, 
+        matrix_room_url, 
+        web_url, 
+        avatar_url, 
+        entity_type, 
+        address_primary, 
+        address_unit, 
+        city, 
+        state, 
+        zip_code, 
+        country,
+        created, 
+        updated from entitys where organization_key = $1 order by created, key limit $2 offset $3",
+        organization_key, limit, offset
+    )
+    .fetch_all(conn)
+    .await?;
+    let mut entitys = Vec::<Entity>::new();
+    for entity in records {
+        let e_type: EntityType = entity.entity_type.into();
+        let brd = Entity {
+            key: entity.key,
+            organization_key: entity.organization_key, 
+            external_accounting_id: entity.external_accounting_id, 
+            owner_key: entity.owner_key,
+            name: entity.name,
+            description: entity.description,
+            matrix_room_url: entity.matrix_room_url,
+            web_url: entity.web_url,
+            avatar_url: entity.avatar_url, 
+            entity_type: e_type, 
+            address_primary: entity.address_primary, 
+            address_unit: entity.address_unit, 
+            city: entity.city, 
+            state: entity.state, 
+            zip_code: entity.zip_code, 
+            country: entity.country,
+            created: entity.created,
+            updated: entity.updated,
+        };
+        entitys.push(brd);
+    }
+    Ok(entitys)
+}
+
+pub async fn count_organization_entitys(conn: &mut PoolConnection<Postgres>, organization_key: uuid::Uuid) -> Result<i64, AppError> {
+    let record = sqlx::query!(r#"select count(*) as "count!" from entitys where organization_key = $1"#, organization_key)
+        .fetch_one(conn)
+        .await?;
+    Ok(record.count)
+}
+
+#[allow(dead_code)]
+pub async fn get_user_entitys(conn: &mut PoolConnection<Postgres>, user_key: uuid::Uuid) -> Result<Vec<Entity>, AppError> {
     let records = sqlx::query!(
         "select 
         key,
@@ -92,38 +153,37 @@ pub async fn get_user_entitys(conn: &mut PoolConnection<Postgres>, user_key: uui
         user_key
     )
     .fetch_all(conn)
-    .await
-    .expect("Select project by key");
+    .await?;
     let mut entitys = Vec::<Entity>::new();
     for entity in records {
-        let e_type: EntityType = entity.entity_type.expect("entity_type exists").into();
+        let e_type: EntityType = entity.entity_type.into();
         let brd: Entity = Entity {
-            key: entity.key.expect("key exists"),
-            external_accounting_id: entity.external_accounting_id.expect("external_accounting_id"), 
-            organization_key: entity.organization_key.expect("organization_key"), 
-            owner_key: entity.owner_key.expect("owner_key exists"),
-            name: entity.name.expect("name exists"),
-            description: entity.description.expect("description exists"),
-            matrix_room_url: entity.matrix_room_url.expect("matrix_room_url exists"),
-            web_url: entity.web_url.expect("web_url exists"),
-            avatar_url: entity.avatar_url.expect("avatar_url exists"), 
+            key: entity.key,
+            external_accounting_id: entity.external_accounting_id, 
+            organization_key: entity.organization_key, 
+            owner_key: entity.owner_key,
+            name: entity.name,
+            description: entity.description,
+            matrix_room_url: entity.matrix_room_url,
+            web_url: entity.web_url,
+            avatar_url: entity.avatar_url, 
             entity_type: e_type, 
-            address_primary: entity.address_primary.expect("address_primary exists"), 
-            address_unit: entity.address_unit.expect("address_unit exists"), 
-            city: entity.city.expect("city exists"), 
-            state: entity.state.expect("state exists"), 
-            zip_code: entity.zip_code.expect("zip_code exists"), 
-            country: entity.country.expect("country exists"),
-            created: entity.created.expect("created exists"),
-            updated: entity.updated.expect("updated exists"),
+            address_primary: entity.address_primary, 
+            address_unit: entity.address_unit, 
+            city: entity.city, 
+            state: entity.state, 
+            zip_code: entity.zip_code, 
+            country: entity.country,
+            created: entity.created,
+            updated: entity.updated,
         };
         entitys.push(brd);
     }
-    return entitys
+    Ok(entitys)
 }
 
-pub async fn get_entity(conn: &mut PoolConnection<Postgres>, key: uuid::Uuid) -> Entity {
-    let entity = sqlx::query!(
+pub async fn get_entity(conn: &mut PoolConnection<Postgres>, key: uuid::Uuid, organization_key: uuid::Uuid) -> Result<Option<Entity>, AppError> {
+    let entity = match sqlx::query!(
         "select 
         key,
         organization_key,
@@ -142,46 +202,49 @@ pub async fn get_entity(conn: &mut PoolConnection<Postgres>, key: uuid::Uuid) ->
         zip_code, 
         country,
         created, 
-        updated from entitys where key = $1",
-        key
+        updated from entitys where key = $1 AND organization_key = $2",
+        key, organization_key
     )
-    .fetch_one(conn)
-    .await
-    .expect("Select entity by key");
+    .fetch_optional(conn)
+    .await?
+    {
+        Some(r) => r,
+        None => return Ok(None),
+    };
 
-    let e_type: EntityType = entity.entity_type.expect("entity_type exists").into();
-    Entity {
-        key: entity.key.expect("key exists"),
-        organization_key: entity.organization_key.expect("organization_key"), 
-        external_accounting_id: entity.external_accounting_id.expect("external_accounting_id"), 
-        owner_key: entity.owner_key.expect("owner_key exists"),
-        name: entity.name.expect("name exists"),
-        description: entity.description.expect("description exists"),
-        matrix_room_url: entity.matrix_room_url.expect("matrix_room_url exists"),
-        web_url: entity.web_url.expect("web_url exists"),
-        avatar_url: entity.avatar_url.expect("avatar_url exists"), 
+    let e_type: EntityType = entity.entity_type.into();
+    Ok(Some(Entity {
+        key: entity.key,
+        organization_key: entity.organization_key, 
+        external_accounting_id: entity.external_accounting_id, 
+        owner_key: entity.owner_key,
+        name: entity.name,
+        description: entity.description,
+        matrix_room_url: entity.matrix_room_url,
+        web_url: entity.web_url,
+        avatar_url: entity.avatar_url, 
         entity_type: e_type, 
-        address_primary: entity.address_primary.expect("address_primary exists"), 
-        address_unit: entity.address_unit.expect("address_unit exists"), 
-        city: entity.city.expect("city exists"), 
-        state: entity.state.expect("state exists"), 
-        zip_code: entity.zip_code.expect("zip_code exists"), 
-        country: entity.country.expect("country exists"),
-        created: entity.created.expect("created exists"),
-        updated: entity.updated.expect("updated exists"),
-    }
+        address_primary: entity.address_primary, 
+        address_unit: entity.address_unit, 
+        city: entity.city, 
+        state: entity.state, 
+        zip_code: entity.zip_code, 
+        country: entity.country,
+        created: entity.created,
+        updated: entity.updated,
+    }))
 }
 
-async fn delete_entity(conn: &mut PoolConnection<Postgres>, key: uuid::Uuid, owner_key: uuid::Uuid) -> Result<PgQueryResult, sqlx::Error> {
-    return sqlx::query!("DELETE FROM entitys where owner_key=$1 AND key=$2", owner_key, key)
+pub(crate) async fn delete_entity(conn: &mut PoolConnection<Postgres>, key: uuid::Uuid, organization_key: uuid::Uuid) -> Result<PgQueryResult, sqlx::Error> {
+    return sqlx::query!("DELETE FROM entitys where organization_key=$1 AND key=$2", organization_key, key)
     .execute(conn)
     .await;
 }
 
-async fn update_entity(conn: &mut PoolConnection<Postgres>, entity: &Entity) {
+pub(crate) async fn update_entity(conn: &mut PoolConnection<Postgres>, entity: &Entity) -> Result<(), AppError> {
     sqlx::query!("UPDATE entitys SET name=$1, description=$2, matrix_room_url=$3, 
     web_url=$4, avatar_url=$5, entity_type=$6, address_primary=$7, address_unit=$8, 
-    city=$9, state=$10, zip_code=$11, country=$12, external_accounting_id=$13 where key=$14",
+    city=$9, state=$10, zip_code=$11, country=$12, external_accounting_id=$13 where key=$14 AND organization_key=$15",
     &entity.name,
     &entity.description,
     &entity.matrix_room_url,
@@ -196,13 +259,14 @@ async fn update_entity(conn: &mut PoolConnection<Postgres>, entity: &Entity) {
     &entity.country,
     &entity.external_accounting_id,
     entity.key,
+    entity.organization_key,
 )
 .execute(conn)
-.await
-.expect("Insert Success");
+.await?;
+    Ok(())
 }
 
-pub async fn insert_entity(conn: &mut PoolConnection<Postgres>, new_entity: &Entity) {
+pub async fn insert_entity(conn: &mut PoolConnection<Postgres>, new_entity: &Entity) -> Result<(), AppError> {
     sqlx::query!("INSERT INTO entitys (
         key,
         organization_key,
@@ -243,8 +307,8 @@ pub async fn insert_entity(conn: &mut PoolConnection<Postgres>, new_entity: &Ent
         new_entity.updated,
     )
     .execute(conn)
-    .await
-    .expect("Insert Success");
+    .await?;
+    Ok(())
 }
 
 pub async fn add(req: Request<State>) -> tide::Result {
@@ -278,8 +342,8 @@ pub async fn delete(req: Request<State>) -> tide::Result {
     match req.param("entity_id") {
         Ok(key) => {
             let mut conn = req.state().db_pool.acquire().await?;
-            let s_uuid = uuid::Uuid::from_str(key).expect("Entity uuid parse");
-            match delete_entity(&mut conn, s_uuid, u.key).await {
+            let s_uuid = crate::error::parse_key(key, "entity")?;
+            match delete_entity(&mut conn, s_uuid, u.organization_key).await {
                 Ok(_) =>    Ok(tide::Response::builder(tide::StatusCode::Ok)
                 .content_type(mime::HTML)
                 .build()),
@@ -301,7 +365,7 @@ pub async fn delete(req: Request<State>) -> tide::Result {
 
 
 pub async fn get_invoices(req: Request<State>) -> tide::Result {
-    let u = match crate::user::read_jwt_cookie_to_user(req.cookie("token")) {
+    let u = match crate::user::request_user(&req) {
         Some(c) => c,
         None => {
             return Ok(tide::Redirect::new("/login").into());
@@ -312,11 +376,22 @@ pub async fn get_invoices(req: Request<State>) -> tide::Result {
         Ok(key) => {
 
             let mut conn = req.state().db_pool.acquire().await?; // .await? needs to be a real connection pool error handler!!!!!!!!
-            let s_uuid = uuid::Uuid::from_str(key).expect("Entity uuid parse");
-            let entity = get_entity(&mut conn, s_uuid).await;
+            let s_uuid = crate::error::parse_key(key, "entity")?;
+            let entity = match get_entity(&mut conn, s_uuid, u.organization_key).await? {
+                Some(e) => e,
+                None => {
+                    return Ok(tide::Response::builder(tide::StatusCode::NotFound)
+                        .content_type(mime::HTML)
+                        .body(NotFoundTemplate::new().render_string())
+                        .build())
+                }
+            };
             
-            let options =  crate::akaunting::get_akaunting_options(&mut conn, u.organization_key).await.expect("get options");
-            let invoices = options.list_invoices().await;
+            let options = match crate::akaunting::get_akaunting_options(&mut conn, u.organization_key).await? {
+                Some(o) => o,
+                None => return Err(AppError::NotFound("akaunting options".to_string()).into()),
+            };
+            let invoices = options.list_invoices().await?;
             let accounting_id = match req.param("external_id") {
                 Ok(id)=> id,
                 Err(e) => {
@@ -327,10 +402,12 @@ pub async fn get_invoices(req: Request<State>) -> tide::Result {
                         .build())
                 }
             };
+            let act_id: i64 = accounting_id
+                .parse()
+                .map_err(|_| AppError::Validation("external id must be a number".to_string()))?;
             let mut invoice_list = vec![];
             for i in invoices.data.clone() { 
-                let act_id: i64 = accounting_id.parse().expect("String is i64");
-                if i.contact_id.expect("contact id exists") == act_id {
+                if i.contact_id == Some(act_id) {
                     invoice_list.push(i);
                 }
             }
@@ -357,7 +434,7 @@ pub async fn get_invoices(req: Request<State>) -> tide::Result {
 }
 
 pub async fn get(req: Request<State>) -> tide::Result {
-    let u = match crate::user::read_jwt_cookie_to_user(req.cookie("token")) {
+    let u = match crate::user::request_user(&req) {
         Some(c) => c,
         None => {
             return Ok(tide::Redirect::new("/login").into());
@@ -367,12 +444,20 @@ pub async fn get(req: Request<State>) -> tide::Result {
     match req.param("entity_id") {
         Ok(key) => {
             let mut conn = req.state().db_pool.acquire().await?; // .await? needs to be a real connection pool error handler!!!!!!!!
-            let s_uuid = uuid::Uuid::from_str(key).expect("Entity uuid parse");
-            let entity = get_entity(&mut conn, s_uuid).await;
-            let contacts = get_contacts_by_entity(&mut conn, s_uuid).await;
+            let s_uuid = crate::error::parse_key(key, "entity")?;
+            let entity = match get_entity(&mut conn, s_uuid, u.organization_key).await? {
+                Some(e) => e,
+                None => {
+                    return Ok(tide::Response::builder(tide::StatusCode::NotFound)
+                        .content_type(mime::HTML)
+                        .body(NotFoundTemplate::new().render_string())
+                        .build())
+                }
+            };
+            let contacts = get_contacts_by_entity(&mut conn, s_uuid, u.organization_key).await?;
 
-            let notes = note::get_associated_notes(&mut conn, crate::file::AssociationType::Entity, s_uuid).await;
-            let files = file::get_associated_files(&mut conn, crate::file::AssociationType::Entity, s_uuid).await;
+            let notes = note::get_associated_notes(&mut conn, u.organization_key, crate::file::AssociationType::Entity, s_uuid).await?;
+            let files = file::get_associated_files(&mut conn, u.organization_key, crate::file::AssociationType::Entity, s_uuid).await?;
 
             Ok(tide::Response::builder(tide::StatusCode::Ok)
                 .content_type(mime::HTML)
@@ -400,14 +485,16 @@ pub async fn get(req: Request<State>) -> tide::Result {
 
 pub async fn insert(mut req: Request<State>) -> tide::Result {
     let umd: Result<Entity, tide::Error> = req.body_json().await;
-    let claims: user::UserJwtState = match user::read_jwt_cookie(req.cookie("token")) {
+    let claims: user::UserJwtState = match user::request_claims(&req) {
         Some(c) => c,
         None => {
             return Ok(tide::Redirect::new("/login").into());
         },
     };
     match umd {
-        Ok(entity) => {
+        Ok(mut entity) => {
+            let organization_key = crate::error::parse_key(claims.organization_key.as_str(), "organization")?;
+            entity.organization_key = organization_key;
             let mut conn = match req.state().db_pool.acquire().await {
                 Ok(c) => c,
                 Err(e) => {
@@ -422,16 +509,15 @@ pub async fn insert(mut req: Request<State>) -> tide::Result {
 
             if entity.key == uuid::Uuid::nil() {
                 let s = Entity::new(entity.organization_key, "".to_owned(), entity.owner_key, entity.name, entity.description, entity.matrix_room_url, entity.web_url, entity.avatar_url, entity.entity_type, entity.address_primary, entity.address_unit, entity.city, entity.state, entity.zip_code, entity.country);
-                insert_entity(&mut conn, &s).await;
-                let organization_key = uuid::Uuid::from_str(claims.organization_key.as_str()).expect("organization key");
-                post_entity_create(&mut conn, claims.matrix_home_server, claims.matrix_user_id,  organization_key, claims.matrix_access_token, &s).await.expect("Posting to matrix");
+                insert_entity(&mut conn, &s).await?;
+                crate::api::report_matrix(post_entity_create(&mut conn, claims.matrix_home_server, claims.matrix_user_id,  organization_key, claims.matrix_access_token, &s).await);
                 let j = serde_json::to_string(&s).expect("To JSON");
                 Ok(tide::Response::builder(tide::StatusCode::Ok)
                     .content_type(mime::JSON)
                     .body(j)
                     .build())
             } else {
-                update_entity(&mut conn, &entity).await;
+                update_entity(&mut conn, &entity).await?;
                 let j = serde_json::to_string(&entity).expect("To JSON");
                 Ok(tide::Response::builder(tide::StatusCode::Ok)
                     .content_type(mime::JSON)
@@ -439,13 +525,7 @@ pub async fn insert(mut req: Request<State>) -> tide::Result {
                     .build())
             }
         }
-        Err(e) => {
-            println!("{:?}", e);
-            Ok(tide::Response::builder(tide::StatusCode::BadRequest)
-                .content_type(mime::JSON)
-                .body("{'error': 'invalid json body'}")
-                .build())
-        }
+        Err(e) => Err(AppError::Validation(format!("invalid json body: {}", e)).into()),
     }
 }
 
@@ -457,7 +537,7 @@ pub async fn add_contact(req: Request<State>) -> tide::Result {
     };
     match req.param("entity_id") {
         Ok(k) => {
-            let entity_uuid = uuid::Uuid::from_str(k).expect("entity key supplied");
+            let entity_uuid = crate::error::parse_key(k, "entity")?;
             let mut contact=       Contact::new(
                 entity_uuid,
                 "".to_owned(),
@@ -498,13 +578,13 @@ pub async fn add_contact(req: Request<State>) -> tide::Result {
     } 
 }
 
-pub async fn delete_contact(conn: &mut PoolConnection<Postgres>, key: uuid::Uuid) -> Result<PgQueryResult, sqlx::Error> {
-    return sqlx::query!("DELETE FROM contacts where key=$1",  key)
+pub async fn delete_contact(conn: &mut PoolConnection<Postgres>, key: uuid::Uuid, organization_key: uuid::Uuid) -> Result<PgQueryResult, sqlx::Error> {
+    return sqlx::query!("DELETE FROM contacts c USING entitys e where c.key=$1 AND e.key = c.entity_key AND e.organization_key=$2", key, organization_key)
     .execute(conn)
     .await;
 } 
 
-pub async fn get_contacts_by_entity(conn: &mut PoolConnection<Postgres>, entity_key: uuid::Uuid) -> Vec::<Contact> {
+pub async fn get_contacts_by_entity(conn: &mut PoolConnection<Postgres>, entity_key: uuid::Uuid, organization_key: uuid::Uuid) -> Result<Vec::<Contact>, AppError> {
     let contact_records = sqlx::query!(
         "select 
         c.key,
@@ -531,46 +611,46 @@ pub async fn get_contacts_by_entity(conn: &mut PoolConnection<Postgres>, entity_
         c.country,
         c.created, 
         c.updated from contacts c
-        where c.entity_key = $1",
-        entity_key
+        inner join entitys e on e.key = c.entity_key 
+        where c.entity_key = $1 AND e.organization_key = $2",
+        entity_key, organization_key
     )
     .fetch_all(conn)
-    .await
-    .expect("Select contacts by organization_key");
+    .await?;
     let mut contacts = vec![];
     for contact in contact_records {
         contacts.push(Contact {
-            key: contact.key.expect("key exists"),
-            external_accounting_id: contact.external_accounting_id.expect("external_accounting_id"), 
-            entity_key: contact.entity_key.expect("entity_key"), 
-            first_name: contact.first_name.expect("first_name exists"),
-            middle_initial: contact.middle_initial.expect("middle_initial exists"),
-            last_name: contact.last_name.expect("last_name exists"),
-            position: contact.position.expect("position exists"),
-            description: contact.description.expect("description exists"),
-            email: contact.email.expect("email exists"),
-            phone: contact.phone.expect("phone exists"),
-            secondary_email: contact.secondary_email.expect("secondary_email exists"),
-            secondary_phone: contact.secondary_phone.expect("secondary_phone exists"),
-            web_url: contact.web_url.expect("web_url exists"),
-            avatar_url: contact.avatar_url.expect("avatar_url exists"), 
-            social_urls: contact.social_urls.expect("social_urls exists"), 
-            matrix_user_id: contact.matrix_user_id.expect("matrix_user_id exists"), 
+            key: contact.key,
+            external_accounting_id: contact.external_accounting_id, 
+            entity_key: contact.entity_key, 
+            first_name: contact.first_name,
+            middle_initial: contact.middle_initial,
+            last_name: contact.last_name,
+            position: contact.position,
+            description: contact.description,
+            email: contact.email,
+            phone: contact.phone,
+            secondary_email: contact.secondary_email,
+            secondary_phone: contact.secondary_phone,
+            web_url: contact.web_url,
+            avatar_url: contact.avatar_url, 
+            social_urls: contact.social_urls, 
+            matrix_user_id: contact.matrix_user_id, 
             
-            address_primary: contact.address_primary.expect("address_primary exists"), 
-            address_unit: contact.address_unit.expect("address_unit exists"), 
-            city: contact.city.expect("city exists"), 
-            state: contact.state.expect("state exists"), 
-            zip_code: contact.zip_code.expect("zip_code exists"), 
-            country: contact.country.expect("country exists"),
-            created: contact.created.expect("created exists"),
-            updated: contact.updated.expect("updated exists"),
+            address_primary: contact.address_primary, 
+            address_unit: contact.address_unit, 
+            city: contact.city, 
+            state: contact.state, 
+            zip_code: contact.zip_code, 
+            country: contact.country,
+            created: contact.created,
+            updated: contact.updated,
         });
     }
-    contacts
+    Ok(contacts)
 }
 
-pub async fn get_contacts(conn: &mut PoolConnection<Postgres>, organization_key: uuid::Uuid) -> Vec::<Contact> {
+pub async fn get_contacts(conn: &mut PoolConnection<Postgres>, organization_key: uuid::Uuid) -> Result<Vec::<Contact>, AppError> {
     let contact_records = sqlx::query!(
         "select 
         c.key,
@@ -602,110 +682,188 @@ pub async fn get_contacts(conn: &mut PoolConnection<Postgres>, organization_key:
         organization_key
     )
     .fetch_all(conn)
-    .await
-    .expect("Select contacts by organization_key");
+    .await?;
     let mut contacts = vec![];
     for contact in contact_records {
         contacts.push(Contact {
-            key: contact.key.expect("key exists"),
-            external_accounting_id: contact.external_accounting_id.expect("external_accounting_id"), 
-            entity_key: contact.entity_key.expect("entity_key"), 
-            first_name: contact.first_name.expect("first_name exists"),
-            middle_initial: contact.middle_initial.expect("middle_initial exists"),
-            last_name: contact.last_name.expect("last_name exists"),
-            position: contact.position.expect("position exists"),
-            description: contact.description.expect("description exists"),
-            email: contact.email.expect("email exists"),
-            phone: contact.phone.expect("phone exists"),
-            secondary_email: contact.secondary_email.expect("secondary_email exists"),
-            secondary_phone: contact.secondary_phone.expect("secondary_phone exists"),
-            web_url: contact.web_url.expect("web_url exists"),
-            avatar_url: contact.avatar_url.expect("avatar_url exists"), 
-            social_urls: contact.social_urls.expect("social_urls exists"), 
-            matrix_user_id: contact.matrix_user_id.expect("matrix_user_id exists"), 
+            key: contact.key,
+            external_accounting_id: contact.external_accounting_id, 
+            entity_key: contact.entity_key, 
+            first_name: contact.first_name,
+            middle_initial: contact.middle_initial,
+            last_name: contact.last_name,
+            position: contact.position,
+            description: contact.description,
+            email: contact.email,
+            phone: contact.phone,
+            secondary_email: contact.secondary_email,
+            secondary_phone: contact.secondary_phone,
+            web_url: contact.web_url,
+            avatar_url: contact.avatar_url, 
+            social_urls: contact.social_urls, 
+            matrix_user_id: contact.matrix_user_id, 
             
-            address_primary: contact.address_primary.expect("address_primary exists"), 
-            address_unit: contact.address_unit.expect("address_unit exists"), 
-            city: contact.city.expect("city exists"), 
-            state: contact.state.expect("state exists"), 
-            zip_code: contact.zip_code.expect("zip_code exists"), 
-            country: contact.country.expect("country exists"),
-            created: contact.created.expect("created exists"),
-            updated: contact.updated.expect("updated exists"),
+            address_primary: contact.address_primary, 
+            address_unit: contact.address_unit, 
+            city: contact.city, 
+            state: contact.state, 
+            zip_code: contact.zip_code, 
+            country: contact.country,
+            created: contact.created,
+            updated: contact.updated,
         });
     }
-    contacts
+    Ok(contacts)
 }
 
-pub async fn get_contact(conn: &mut PoolConnection<Postgres>, key: uuid::Uuid) -> Contact {
-    let contact = sqlx::query!(
+pub async fn get_contacts_page(conn: &mut PoolConnection<Postgres>, organization_key: uuid::Uuid, limit: i64, offset: i64) -> Result<Vec::<Contact>, AppError> {
+    let contact_records = sqlx::query!(
         "select 
-        key,
-        external_accounting_id,
-        entity_key,
-        first_name,
-        middle_initial,
-        last_name,
-        description, 
-        position, 
-        email, 
-        phone, 
-        secondary_email, 
-        secondary_phone, 
-        matrix_user_id,
-        web_url, 
-        avatar_url, 
-        social_urls, 
-        address_primary, 
-        address_unit, 
-        city, 
-        state, 
-        zip_code, 
-        country,
-        created, 
-        updated from contacts where key = $1",
-        key
+        c.key,
+        c.external_accounting_id,
+        c.entity_key,
+        c.first_name,
+        c.middle_initial,
+        c.last_name,
+        c.description, 
+        c.position, 
+        c.email, 
+        c.phone, 
+        c.secondary_email, 
+        c.secondary_phone, 
+        c.matrix_user_id,
+        c.web_url, 
+        c.avatar_url, 
+        c.social_urls, 
+        c.address_primary, 
+        c.address_unit, 
+        c.city, 
+        c.state, 
+        c.zip_code, 
+        c.country,
+        c.created, 
+        c.updated from contacts c
+        inner join entitys e on e.key = c.entity_key 
+        where e.organization_key = $1 order by c.created, c.key limit $2 offset $3",
+        organization_key, limit, offset
+    )
+    .fetch_all(conn)
+    .await?;
+    let mut contacts = vec![];
+    for contact in contact_records {
+        contacts.push(Contact {
+            key: contact.key,
+            external_accounting_id: contact.external_accounting_id, 
+            entity_key: contact.entity_key, 
+            first_name: contact.first_name,
+            middle_initial: contact.middle_initial,
+            last_name: contact.last_name,
+            position: contact.position,
+            description: contact.description,
+            email: contact.email,
+            phone: contact.phone,
+            secondary_email: contact.secondary_email,
+            secondary_phone: contact.secondary_phone,
+            web_url: contact.web_url,
+            avatar_url: contact.avatar_url, 
+            social_urls: contact.social_urls, 
+            matrix_user_id: contact.matrix_user_id, 
+            
+            address_primary: contact.address_primary, 
+            address_unit: contact.address_unit, 
+            city: contact.city, 
+            state: contact.state, 
+            zip_code: contact.zip_code, 
+            country: contact.country,
+            created: contact.created,
+            updated: contact.updated,
+        });
+    }
+    Ok(contacts)
+}
+
+pub async fn count_contacts(conn: &mut PoolConnection<Postgres>, organization_key: uuid::Uuid) -> Result<i64, AppError> {
+    let record = sqlx::query!(r#"select count(*) as "count!" from contacts c inner join entitys e on e.key = c.entity_key where e.organization_key = $1"#, organization_key)
+        .fetch_one(conn)
+        .await?;
+    Ok(record.count)
+}
+
+pub async fn get_contact(conn: &mut PoolConnection<Postgres>, key: uuid::Uuid, organization_key: uuid::Uuid) -> Result<Option<Contact>, AppError> {
+    let contact = match sqlx::query!(
+        "select 
+        c.key,
+        c.external_accounting_id,
+        c.entity_key,
+        c.first_name,
+        c.middle_initial,
+        c.last_name,
+        c.description, 
+        c.position, 
+        c.email, 
+        c.phone, 
+        c.secondary_email, 
+        c.secondary_phone, 
+        c.matrix_user_id,
+        c.web_url, 
+        c.avatar_url, 
+        c.social_urls, 
+        c.address_primary, 
+        c.address_unit, 
+        c.city, 
+        c.state, 
+        c.zip_code, 
+        c.country,
+        c.created, 
+        c.updated from contacts c
+        inner join entitys e on e.key = c.entity_key 
+        where c.key = $1 AND e.organization_key = $2",
+        key, organization_key
     )
-    .fetch_one(conn)
-    .await
-    .expect("Select contact by key");
+    .fetch_optional(conn)
+    .await?
+    {
+        Some(r) => r,
+        None => return Ok(None),
+    };
 
-    Contact {
-        key: contact.key.expect("key exists"),
-        external_accounting_id: contact.external_accounting_id.expect("external_accounting_id"), 
-        entity_key: contact.entity_key.expect("entity_key"), 
-        first_name: contact.first_name.expect("first_name exists"),
-        middle_initial: contact.middle_initial.expect("middle_initial exists"),
-        last_name: contact.last_name.expect("last_name exists"),
-        position: contact.position.expect("position exists"),
-        description: contact.description.expect("description exists"),
-        email: contact.email.expect("email exists"),
-        phone: contact.phone.expect("phone exists"),
-        secondary_email: contact.secondary_email.expect("secondary_email exists"),
-        secondary_phone: contact.secondary_phone.expect("secondary_phone exists"),
-        web_url: contact.web_url.expect("web_url exists"),
-        avatar_url: contact.avatar_url.expect("avatar_url exists"), 
-        social_urls: contact.social_urls.expect("social_urls exists"), 
-        matrix_user_id: contact.matrix_user_id.expect("matrix_user_id exists"), 
+    Ok(Some(Contact {
+        key: contact.key,
+        external_accounting_id: contact.external_accounting_id, 
+        entity_key: contact.entity_key, 
+        first_name: contact.first_name,
+        middle_initial: contact.middle_initial,
+        last_name: contact.last_name,
+        position: contact.position,
+        description: contact.description,
+        email: contact.email,
+        phone: contact.phone,
+        secondary_email: contact.secondary_email,
+        secondary_phone: contact.secondary_phone,
+        web_url: contact.web_url,
+        avatar_url: contact.avatar_url, 
+        social_urls: contact.social_urls, 
+        matrix_user_id: contact.matrix_user_id, 
         
-        address_primary: contact.address_primary.expect("address_primary exists"), 
-        address_unit: contact.address_unit.expect("address_unit exists"), 
-        city: contact.city.expect("city exists"), 
-        state: contact.state.expect("state exists"), 
-        zip_code: contact.zip_code.expect("zip_code exists"), 
-        country: contact.country.expect("country exists"),
-        created: contact.created.expect("created exists"),
-        updated: contact.updated.expect("updated exists"),
-    }
+        address_primary: contact.address_primary, 
+        address_unit: contact.address_unit, 
+        city: contact.city, 
+        state: contact.state, 
+        zip_code: contact.zip_code, 
+        country: contact.country,
+        created: contact.created,
+        updated: contact.updated,
+    }))
 }
 
-async fn update_contact(conn: &mut PoolConnection<Postgres>, contact: &Contact) {
+pub(crate) async fn update_contact(conn: &mut PoolConnection<Postgres>, contact: &Contact, organization_key: uuid::Uuid) -> Result<(), AppError> {
     let social_urls = contact.social_urls.as_slice();
     sqlx::query!("UPDATE contacts SET first_name=$1, middle_initial=$2, last_name=$3, 
     description=$4, position=$5, email=$6, phone=$7, secondary_email=$8, 
     secondary_phone=$9, matrix_user_id=$10, web_url=$11, avatar_url=$12, social_urls=$13,
     address_primary=$14, address_unit=$15, city=$16, state=$17, zip_code=$18, country=$19, 
-    external_accounting_id=$20 where key=$21",
+    external_accounting_id=$20 FROM entitys e where contacts.key=$21 
+    AND e.key = contacts.entity_key AND e.organization_key=$22",
     contact.first_name,
     &contact.middle_initial,
     &contact.last_name, 
@@ -727,13 +885,14 @@ async fn update_contact(conn: &mut PoolConnection<Postgres>, contact: &Contact)
     &contact.country, 
     &contact.external_accounting_id,
     contact.key,
+    organization_key,
 )
 .execute(conn)
-.await
-.expect("Insert Success");
+.await?;
+    Ok(())
 }
 
-pub async fn insert_contact(conn: &mut PoolConnection<Postgres>, new_contact: &Contact) {
+pub async fn insert_contact(conn: &mut PoolConnection<Postgres>, new_contact: &Contact, organization_key: uuid::Uuid) -> Result<(), AppError> {
     let social_urls = new_contact.social_urls.as_slice();
     sqlx::query!("INSERT INTO contacts (
         key,
@@ -759,7 +918,8 @@ pub async fn insert_contact(conn: &mut PoolConnection<Postgres>, new_contact: &C
         zip_code, 
         country,
         created, 
-        updated) values($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17, $18, $19, $20, $21, $22, $23, $24)", 
+        updated,
+        organization_key) values($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17, $18, $19, $20, $21, $22, $23, $24, $25)", 
         new_contact.key,
         new_contact.external_accounting_id,
         new_contact.entity_key, 
@@ -784,23 +944,24 @@ pub async fn insert_contact(conn: &mut PoolConnection<Postgres>, new_contact: &C
         &new_contact.country, 
         new_contact.created,
         new_contact.updated,
+        organization_key,
     )
     .execute(conn)
-    .await
-    .expect("Insert Success");
+    .await?;
+    Ok(())
 }
 
 
 pub async fn delete_contact_route(req: Request<State>) -> tide::Result {
-    let _u = match crate::user::user_or_error(&req) {
+    let u = match crate::user::user_or_error(&req) {
         Ok(value) => value,
         Err(_) => return Ok(tide::Redirect::new("/login").into()),
     };
     match req.param("contact_id") {
         Ok(key) => {
             let mut conn = req.state().db_pool.acquire().await?;
-            let s_uuid = uuid::Uuid::from_str(key).expect("contact uuid parse");
-            match delete_contact(&mut conn, s_uuid).await {
+            let s_uuid = crate::error::parse_key(key, "contact")?;
+            match delete_contact(&mut conn, s_uuid, u.organization_key).await {
                 Ok(_) =>    Ok(tide::Response::builder(tide::StatusCode::Ok)
                 .content_type(mime::HTML)
                 .build()),
@@ -821,7 +982,7 @@ pub async fn delete_contact_route(req: Request<State>) -> tide::Result {
 }
 
 pub async fn get_contact_route(req: Request<State>) -> tide::Result {
-    let u = match crate::user::read_jwt_cookie_to_user(req.cookie("token")) {
+    let u = match crate::user::request_user(&req) {
         Some(c) => c,
         None => {
             return Ok(tide::Redirect::new("/login").into());
@@ -831,11 +992,19 @@ pub async fn get_contact_route(req: Request<State>) -> tide::Result {
     match req.param("contact_id") {
         Ok(key) => {
             let mut conn = req.state().db_pool.acquire().await?; // .await? needs to be a real connection pool error handler!!!!!!!!
-            let s_uuid = uuid::Uuid::from_str(key).expect("Entity uuid parse");
-            let contact = get_contact(&mut conn, s_uuid).await;
+            let s_uuid = crate::error::parse_key(key, "entity")?;
+            let contact = match get_contact(&mut conn, s_uuid, u.organization_key).await? {
+                Some(c) => c,
+                None => {
+                    return Ok(tide::Response::builder(tide::StatusCode::NotFound)
+                        .content_type(mime::HTML)
+                        .body(NotFoundTemplate::new().render_string())
+                        .build())
+                }
+            };
 
-            let notes = note::get_associated_notes(&mut conn, crate::file::AssociationType::Contact, s_uuid).await;
-            let files = file::get_associated_files(&mut conn, crate::file::AssociationType::Contact, s_uuid).await;
+            let notes = note::get_associated_notes(&mut conn, u.organization_key, crate::file::AssociationType::Contact, s_uuid).await?;
+            let files = file::get_associated_files(&mut conn, u.organization_key, crate::file::AssociationType::Contact, s_uuid).await?;
             Ok(tide::Response::builder(tide::StatusCode::Ok)
                 .content_type(mime::HTML)
                 .body(
@@ -861,7 +1030,7 @@ pub async fn get_contact_route(req: Request<State>) -> tide::Result {
 
 pub async fn insert_contact_route(mut req: Request<State>) -> tide::Result {
     let umd: Result<Contact, tide::Error> = req.body_json().await;
-    let claims: user::UserJwtState = match user::read_jwt_cookie(req.cookie("token")) {
+    let claims: user::UserJwtState = match user::request_claims(&req) {
         Some(c) => c,
         None => {
             return Ok(tide::Redirect::new("/login").into());
@@ -870,6 +1039,7 @@ pub async fn insert_contact_route(mut req: Request<State>) -> tide::Result {
     
     match umd {
         Ok(contact) => {
+            let organization_key = crate::error::parse_key(claims.organization_key.as_str(), "organization")?;
             let mut conn = match req.state().db_pool.acquire().await {
                 Ok(c) => c,
                 Err(e) => {
@@ -881,6 +1051,13 @@ pub async fn insert_contact_route(mut req: Request<State>) -> tide::Result {
                     )
                 }
             };
+            // contacts hang off an entity, so the entity has to be one of ours
+            if get_entity(&mut conn, contact.entity_key, organization_key).await?.is_none() {
+                return Ok(tide::Response::builder(tide::StatusCode::NotFound)
+                    .content_type(mime::HTML)
+                    .body(NotFoundTemplate::new().render_string())
+                    .build());
+            }
             
             if contact.key == uuid::Uuid::nil() {
                 let s = Contact::new(
@@ -906,32 +1083,26 @@ pub async fn insert_contact_route(mut req: Request<State>) -> tide::Result {
                     contact.zip_code, 
                     contact.country,
                     );
-                insert_contact(&mut conn, &s).await;
-                post_contact_create(&mut conn, claims.matrix_home_server, claims.matrix_user_id, uuid::Uuid::from_str(claims.organization_key.as_str()).expect("org key"), claims.matrix_access_token,  &s).await.expect("Posting to matrix");
+                insert_contact(&mut conn, &s, organization_key).await?;
+                crate::api::report_matrix(post_contact_create(&mut conn, claims.matrix_home_server, claims.matrix_user_id, organization_key, claims.matrix_access_token,  &s).await);
                 let j = serde_json::to_string(&s).expect("To JSON");
                 return Ok(tide::Response::builder(tide::StatusCode::Ok)
                     .content_type(mime::JSON)
                     .body(j)
                     .build())   
             }
-            update_contact(&mut conn, &contact).await;
+            update_contact(&mut conn, &contact, organization_key).await?;
             let j = serde_json::to_string(&contact).expect("To JSON");
             return Ok(tide::Response::builder(tide::StatusCode::Ok)
                 .content_type(mime::JSON)
                 .body(j)
                 .build())
         }
-        Err(e) => {
-            println!("{:?}", e);
-            Ok(tide::Response::builder(tide::StatusCode::BadRequest)
-                .content_type(mime::JSON)
-                .body("{'error': 'invalid json body'}")
-                .build())
-        }
+        Err(e) => Err(AppError::Validation(format!("invalid json body: {}", e)).into()),
     }
 }
 
-#[derive(PartialEq, Debug, Deserialize, Serialize, Clone, Copy, sqlx::Type)]
+#[derive(PartialEq, Debug, Deserialize, Serialize, JsonSchema, Clone, Copy, sqlx::Type)]
 pub enum EntityType {
     Client,
     Supplier,
@@ -956,7 +1127,7 @@ impl From<EntityType> for i16 {
     }
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
 pub struct Entity {
     pub key: uuid::Uuid,
     pub organization_key: uuid::Uuid,
@@ -980,7 +1151,7 @@ pub struct Entity {
     pub updated: i64,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
 pub struct Contact {
     pub key: uuid::Uuid,
     pub external_accounting_id: String,
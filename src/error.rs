@@ -0,0 +1,144 @@
+use std::fmt;
+use std::str::FromStr;
+
+use async_trait::async_trait;
+use tide::{http::mime, Middleware, Next, Request};
+
+use crate::api::ApiError;
+use crate::home::NotFoundTemplate;
+use crate::State;
+
+// One error type for the whole crate. Query functions and handlers return it (through `?` into
+// tide::Error) and the Responder middleware turns it into the right status and body, JSON for
+// api clients and HTML for the browser.
+
+#[derive(Debug)]
+pub enum AppError {
+    Validation(String),
+    NotFound(String),
+    Unauthorized,
+    Forbidden(String),
+    Database(sqlx::Error),
+    Storage(String),
+    Matrix(String),
+    Akaunting(String),
+}
+
+impl AppError {
+    pub fn status(&self) -> tide::StatusCode {
+        match self {
+            AppError::Validation(_) => tide::StatusCode::BadRequest,
+            AppError::NotFound(_) => tide::StatusCode::NotFound,
+            AppError::Unauthorized => tide::StatusCode::Unauthorized,
+            AppError::Forbidden(_) => tide::StatusCode::Forbidden,
+            AppError::Database(_) => tide::StatusCode::InternalServerError,
+            AppError::Storage(_) | AppError::Matrix(_) | AppError::Akaunting(_) => tide::StatusCode::BadGateway,
+        }
+    }
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            AppError::Validation(_) => "bad_request",
+            AppError::NotFound(_) => "not_found",
+            AppError::Unauthorized => "unauthorized",
+            AppError::Forbidden(_) => "forbidden",
+            AppError::Database(_) => "database",
+            AppError::Storage(_) => "storage",
+            AppError::Matrix(_) => "matrix",
+            AppError::Akaunting(_) => "akaunting",
+        }
+    }
+
+    // what the caller gets to see, internals stay in the log
+    pub fn public_message(&self) -> String {
+        match self {
+            AppError::Validation(m) => m.clone(),
+            AppError::NotFound(what) => format!("{} not found", what),
+            AppError::Unauthorized => "a session or API token is required".to_string(),
+            AppError::Forbidden(m) => m.clone(),
+            AppError::Database(_) => "the request could not be completed".to_string(),
+            AppError::Storage(_) => "file storage is unavailable".to_string(),
+            AppError::Matrix(_) => "matrix is unavailable".to_string(),
+            AppError::Akaunting(_) => "akaunting is unavailable".to_string(),
+        }
+    }
+
+    pub fn envelope(&self) -> ApiError {
+        ApiError::new(self.status(), self.code(), self.public_message().as_str())
+    }
+}
+
+impl fmt::Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AppError::Validation(m) => write!(f, "validation: {}", m),
+            AppError::NotFound(what) => write!(f, "{} not found", what),
+            AppError::Unauthorized => write!(f, "unauthorized"),
+            AppError::Forbidden(m) => write!(f, "forbidden: {}", m),
+            AppError::Database(e) => write!(f, "database: {}", e),
+            AppError::Storage(m) => write!(f, "storage: {}", m),
+            AppError::Matrix(m) => write!(f, "matrix: {}", m),
+            AppError::Akaunting(m) => write!(f, "akaunting: {}", m),
+        }
+    }
+}
+
+impl std::error::Error for AppError {}
+
+impl From<sqlx::Error> for AppError {
+    fn from(e: sqlx::Error) -> Self {
+        match e {
+            sqlx::Error::RowNotFound => AppError::NotFound("record".to_string()),
+            e => AppError::Database(e),
+        }
+    }
+}
+
+impl From<minio_rsc::error::Error> for AppError {
+    fn from(e: minio_rsc::error::Error) -> Self {
+        AppError::Storage(e.to_string())
+    }
+}
+
+// A malformed key is the caller's mistake not ours, so it is a 400 rather than a panic
+pub fn parse_key(value: &str, name: &str) -> Result<uuid::Uuid, AppError> {
+    uuid::Uuid::from_str(value).map_err(|_| AppError::Validation(format!("{} key must be a uuid", name)))
+}
+
+pub fn param_key(req: &Request<State>, name: &str) -> Result<uuid::Uuid, AppError> {
+    let value = req
+        .param(name)
+        .map_err(|_| AppError::Validation(format!("missing {}", name)))?;
+    parse_key(value, name)
+}
+
+pub struct Responder;
+
+#[async_trait]
+impl Middleware<State> for Responder {
+    async fn handle(&self, req: Request<State>, next: Next<'_, State>) -> tide::Result {
+        let wants_json = req.url().path().starts_with("/api/")
+            || req.content_type().map(|m| m.essence() == mime::JSON.essence()).unwrap_or(false)
+            || req.header("Accept").map(|h| h.as_str().contains("application/json")).unwrap_or(false);
+        let res = next.run(req).await;
+        let err = match res.downcast_error::<AppError>() {
+            Some(e) => e,
+            None => return Ok(res),
+        };
+        println!("{}", err);
+        if wants_json {
+            return Ok(tide::Response::builder(err.status())
+                .content_type(mime::JSON)
+                .body(serde_json::to_string(&err.envelope()).expect("To JSON"))
+                .build());
+        }
+        let body = match err {
+            AppError::NotFound(_) | AppError::Validation(_) => NotFoundTemplate::new().render_string(),
+            _ => err.public_message(),
+        };
+        Ok(tide::Response::builder(err.status())
+            .content_type(mime::HTML)
+            .body(body)
+            .build())
+    }
+}
@@ -1,10 +1,10 @@
-use std::io::Cursor;
 use std::str::FromStr;
 
 use crate::common::BufferedBytesStream;
 use askama::Template;
 use minio_rsc::Minio;
 use minio_rsc::provider::StaticProvider;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use sqlx::pool::PoolConnection;
 use sqlx::postgres::PgQueryResult;
@@ -14,6 +14,7 @@ use uuid::Uuid;
 
 use tide::{http::mime, Request};
 
+use crate::error::AppError;
 use crate::home::NotFoundTemplate;
 use crate::matrix::post_file_create;
 use crate::{user, State};
@@ -21,128 +22,171 @@ use crate::{user, State};
 
 pub async fn get_associated_files(
     conn: &mut PoolConnection<Postgres>,
+    organization_key: uuid::Uuid,
     association_type: AssociationType,
     association_key: uuid::Uuid,
-) -> Vec<File> {
+) -> Result<Vec<File>, AppError> {
     let file_records = sqlx::query!(
         "select key, owner_key, organization_key, association_type, association_key, url, hash, name, description, tags, format, size, created, updated 
         from files 
-        WHERE association_type = $1 AND association_key = $2",
-        association_type as i16, association_key
+        WHERE organization_key = $1 AND association_type = $2 AND association_key = $3",
+        organization_key, association_type as i16, association_key
     )
     .fetch_all(conn)
-    .await
-    .expect("Select file by association key");
+    .await?;
     let mut files = vec![];
     for file in file_records {
         let association_type: AssociationType = file
             .association_type
-            .expect("association_type exists")
             .into();
         files.push(File {
-            key: file.key.expect("key exists"),
-            owner_key: file.owner_key.expect("owner_key exists"),
-            organization_key: file.organization_key.expect("organization_key exists"),
-            hash: file.hash.expect("hash exists"),
-            name: file.name.expect("name exists"),
-            description: file.description.expect("description exists"),
-            url: file.url.expect("url exists"),
-            tags: file.tags.expect("tags exists"),
-            format: file.format.expect("format exists"),
-            size: file.size.expect("size exists"),
-            created: file.created.expect("created exists"),
-            updated: file.updated.expect("updated exists"),
+            key: file.key,
+            owner_key: file.owner_key,
+            organization_key: file.organization_key,
+            hash: file.hash,
+            name: file.name,
+            description: file.description,
+            url: file.url,
+            tags: file.tags,
+            format: file.format,
+            size: file.size,
+            created: file.created,
+            updated: file.updated,
             association_type,
-            association_key: file.association_key.expect("association_key exists"),
+            association_key: file.association_key,
         });
     }
-    return files;
+    Ok(files)
 }
  
 
 pub async fn get_organization_files(
     conn: &mut PoolConnection<Postgres>,
     org_key: uuid::Uuid,
-) -> Vec<File> {
+) -> Result<Vec<File>, AppError> {
     let file_records = sqlx::query!(
         "select key, owner_key, organization_key, association_type, association_key, url, hash, name, description, tags, format, size, created, updated from files where organization_key = $1",
         org_key
     )
     .fetch_all(conn)
-    .await
-    .expect("Select file by key");
+    .await?;
     let mut files = vec![];
     for file in file_records {
         let association_type: AssociationType = file
             .association_type
-            .expect("association_type exists")
             .into();
         files.push(File {
-            key: file.key.expect("key exists"),
-            owner_key: file.owner_key.expect("owner_key exists"),
-            organization_key: file.organization_key.expect("organization_key exists"),
-            hash: file.hash.expect("hash exists"),
-            name: file.name.expect("name exists"),
-            description: file.description.expect("description exists"),
-            url: file.url.expect("url exists"),
-            tags: file.tags.expect("tags exists"),
-            format: file.format.expect("format exists"),
-            size: file.size.expect("size exists"),
-            created: file.created.expect("created exists"),
-            updated: file.updated.expect("updated exists"),
+            key: file.key,
+            owner_key: file.owner_key,
+            organization_key: file.organization_key,
+            hash: file.hash,
+            name: file.name,
+            description: file.description,
+            url: file.url,
+            tags: file.tags,
+            format: file.format,
+            size: file.size,
+            created: file.created,
+            updated: file.updated,
             association_type,
-            association_key: file.association_key.expect("association_key exists"),
+            association_key: file.association_key,
         });
     }
-    return files;
+    Ok(files)
 }
 
-pub async fn get_file(conn: &mut PoolConnection<Postgres>, key: uuid::Uuid) -> File {
-    let file = sqlx::query!(
-        "select key, owner_key, organization_key, association_type, association_key, url, hash, name, description, tags, format, size, created, updated from files where key = $1",
-        key
+pub async fn get_organization_files_page(
+    conn: &mut PoolConnection<Postgres>,
+    org_key: uuid::Uuid,
+    limit: i64,
+    offset: i64,
+) -> Result<Vec<File>, AppError> {
+    let file_records = sqlx::query!(
+        "select key, owner_key, organization_key, association_type, association_key, url, hash, name, description, tags, format, size, created, updated from files where organization_key = $1 order by created, key limit $2 offset $3",
+        org_key, limit, offset
     )
-    .fetch_one(conn)
-    .await
-    .expect("Select file by key");
+    .fetch_all(conn)
+    .await?;
+    let mut files = vec![];
+    for file in file_records {
+        let association_type: AssociationType = file
+            .association_type
+            .into();
+        files.push(File {
+            key: file.key,
+            owner_key: file.owner_key,
+            organization_key: file.organization_key,
+            hash: file.hash,
+            name: file.name,
+            description: file.description,
+            url: file.url,
+            tags: file.tags,
+            format: file.format,
+            size: file.size,
+            created: file.created,
+            updated: file.updated,
+            association_type,
+            association_key: file.association_key,
+        });
+    }
+    Ok(files)
+}
+
+pub async fn count_organization_files(conn: &mut PoolConnection<Postgres>, organization_key: uuid::Uuid) -> Result<i64, AppError> {
+    let record = sqlx::query!(r#"select count(*) as "count!" from files where organization_key = $1"#, organization_key)
+        .fetch_one(conn)
+        .await?;
+    Ok(record.count)
+}
+
+pub async fn get_file(conn: &mut PoolConnection<Postgres>, key: uuid::Uuid, organization_key: uuid::Uuid) -> Result<Option<File>, AppError> {
+    let file = match sqlx::query!(
+        "select key, owner_key, organization_key, association_type, association_key, url, hash, name, description, tags, format, size, created, updated from files where key = $1 AND organization_key = $2",
+        key, organization_key
+    )
+    .fetch_optional(conn)
+    .await?
+    {
+        Some(r) => r,
+        None => return Ok(None),
+    };
 
     let association_type: AssociationType = file
         .association_type
-        .expect("association_type exists")
         .into();
-    File {
-        key: file.key.expect("key exists"),
-        owner_key: file.owner_key.expect("owner_key exists"),
-        organization_key: file.organization_key.expect("organization_key exists"),
-        hash: file.hash.expect("hash exists"),
-        name: file.name.expect("name exists"),
-        description: file.description.expect("description exists"),
-        url: file.url.expect("url exists"),
-        tags: file.tags.expect("tags exists"),
-        format: file.format.expect("format exists"),
-        size: file.size.expect("size exists"),
-        created: file.created.expect("created exists"),
-        updated: file.updated.expect("updated exists"),
+    Ok(Some(File {
+        key: file.key,
+        owner_key: file.owner_key,
+        organization_key: file.organization_key,
+        hash: file.hash,
+        name: file.name,
+        description: file.description,
+        url: file.url,
+        tags: file.tags,
+        format: file.format,
+        size: file.size,
+        created: file.created,
+        updated: file.updated,
         association_type,
-        association_key: file.association_key.expect("association_key exists"),
-    }
+        association_key: file.association_key,
+    }))
 }
 
-async fn delete_file(
+pub(crate) async fn delete_file(
     conn: &mut PoolConnection<Postgres>,
     key: uuid::Uuid,
-    owner_key: uuid::Uuid,
+    organization_key: uuid::Uuid,
 ) -> Result<PgQueryResult, sqlx::Error> {
     return sqlx::query!(
-        "DELETE FROM files where owner_key=$1 AND key=$2",
-        owner_key,
+        "DELETE FROM files where organization_key=$1 AND key=$2",
+        organization_key,
         key
     )
     .execute(conn)
     .await;
 }
 
-async fn insert_file(conn: &mut PoolConnection<Postgres>, new_file: &File) {
+pub(crate) async fn insert_file(conn: &mut PoolConnection<Postgres>, new_file: &File) -> Result<(), AppError> {
     sqlx::query!("INSERT INTO files (key, owner_key, organization_key, association_type, association_key, url, hash, name, description, tags, format, size, created, updated) values($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14)", 
         new_file.key,
         new_file.owner_key,
@@ -160,8 +204,21 @@ async fn insert_file(conn: &mut PoolConnection<Postgres>, new_file: &File) {
         new_file.updated,
     )
     .execute(conn)
-    .await
-    .expect("Insert Success");
+    .await?;
+    Ok(())
+}
+
+pub(crate) async fn update_file(conn: &mut PoolConnection<Postgres>, file: &File, organization_key: uuid::Uuid) -> Result<(), AppError> {
+    sqlx::query!("UPDATE files SET name=$1, description=$2, tags=$3, updated=extract(epoch from now()) where key=$4 AND organization_key=$5",
+        &file.name,
+        &file.description,
+        &file.tags,
+        file.key,
+        organization_key,
+    )
+    .execute(conn)
+    .await?;
+    Ok(())
 }
 
 // Route Stuff
@@ -174,8 +231,8 @@ pub async fn delete(req: Request<State>) -> tide::Result {
     match req.param("file_id") {
         Ok(key) => {
             let mut conn = req.state().db_pool.acquire().await?;
-            let s_uuid = uuid::Uuid::from_str(key).expect("File uuid parse");
-            match delete_file(&mut conn, s_uuid, u.key).await {
+            let s_uuid = crate::error::parse_key(key, "file")?;
+            match delete_file(&mut conn, s_uuid, u.organization_key).await {
                 Ok(_) => Ok(tide::Response::builder(tide::StatusCode::Ok)
                     .content_type(mime::HTML)
                     .build()),
@@ -206,11 +263,11 @@ pub async fn add(req: Request<State>) -> tide::Result {
     match req.param("association_type") {
         Ok(association_type) => match req.param("association_id") {
             Ok(association_id) => {
-                let association_id = uuid::Uuid::from_str(association_id).expect("file uuid parse");
+                let association_id = crate::error::parse_key(association_id, "association")?;
                 let mut file = File::new(
                     u.key,
                     u.organization_key,
-                    AssociationType::from_str(association_type).expect("Valid association type"),
+                    AssociationType::from_str(association_type).map_err(|_| AppError::Validation("unknown association type".to_string()))?,
                     association_id,
                     "".to_owned(),
                     "".to_owned(),
@@ -245,7 +302,7 @@ pub async fn add(req: Request<State>) -> tide::Result {
 }
 
 pub async fn get(req: Request<State>) -> tide::Result {
-    let u = match crate::user::read_jwt_cookie_to_user(req.cookie("token")) {
+    let u = match crate::user::request_user(&req) {
         Some(c) => c,
         None => {
             return Ok(tide::Redirect::new("/login").into());
@@ -254,8 +311,16 @@ pub async fn get(req: Request<State>) -> tide::Result {
     match req.param("file_id") {
         Ok(key) => {
             let mut conn = req.state().db_pool.acquire().await?; // .await? needs to be a real connection pool error handler!!!!!!!!
-            let s_uuid = uuid::Uuid::from_str(key).expect("File uuid parse");
-            let file = get_file(&mut conn, s_uuid).await;
+            let s_uuid = crate::error::parse_key(key, "file")?;
+            let file = match get_file(&mut conn, s_uuid, u.organization_key).await? {
+                Some(f) => f,
+                None => {
+                    return Ok(tide::Response::builder(tide::StatusCode::NotFound)
+                        .content_type(mime::HTML)
+                        .body(NotFoundTemplate::new().render_string())
+                        .build())
+                }
+            };
 
             Ok(tide::Response::builder(tide::StatusCode::Ok)
                 .content_type(mime::HTML)
@@ -292,13 +357,16 @@ pub async fn get_file_fs(association_type: String, association_key: String, name
 }
 
 pub async fn insert(req: Request<State>) -> tide::Result {
-    let claims: user::UserJwtState = match user::read_jwt_cookie(req.cookie("token")) {
+    let claims: user::UserJwtState = match user::request_claims(&req) {
         Some(c) => c,
         None => {
             return Ok(tide::Redirect::new("/login").into());
         }
     };
-    let mime = req.content_type().unwrap();
+    let mime = match req.content_type() {
+        Some(m) => m,
+        None => return Err(AppError::Validation("a multipart body is required".to_string()).into()),
+    };
     let mut conn = match req.state().db_pool.acquire().await {
         Ok(c) => c,
         Err(e) => {
@@ -312,8 +380,12 @@ pub async fn insert(req: Request<State>) -> tide::Result {
         }
     };
     
-    if mime.essence().to_string() == "multipart/form-data" {
-        let boundary = mime.param("boundary").unwrap().to_string();
+    if mime.essence() == "multipart/form-data" {
+        let boundary = match mime.param("boundary") {
+            Some(b) => b.to_string(),
+            None => return Err(AppError::Validation("multipart boundary missing".to_string()).into()),
+        };
+        let bad_field = |e: multer::Error| AppError::Validation(e.to_string());
         let mut body = BufferedBytesStream { inner: req };
         let mut multipart = multer::Multipart::new(&mut body, boundary);
         let mut key = "".to_string();
@@ -322,45 +394,42 @@ pub async fn insert(req: Request<State>) -> tide::Result {
         let mut format = "".to_string();
         let mut tags = "".to_string();
         let mut url = "".to_string();
-        let mut organization_key = "".to_string();
         let mut association_type = "".to_string();
         let mut association_key = "".to_string();
         let mut hash = "".to_string();
         let mut size = 0;
         
         let mut buffer = vec![];
-        while let Some(mut field) = multipart.next_field().await.expect("next field") {
-            let f_name = field.name().clone().expect("get field name");
+        while let Some(mut field) = multipart.next_field().await.map_err(bad_field)? {
+            let f_name = field.name().unwrap_or_default().to_string();
             println!("{f_name}");
             if f_name == "name" {
-                name = field.text().await.expect("name multi field");
+                name = field.text().await.map_err(bad_field)?;
             } else if f_name == "key" {
-                key = field.text().await.expect("key multi field");
+                key = field.text().await.map_err(bad_field)?;
             } else if f_name == "description" {
-                description = field.text().await.expect("description multi field");
+                description = field.text().await.map_err(bad_field)?;
             } else if f_name == "tags" {
-                tags = field.text().await.expect("tags field");
-            } else if f_name == "organization_key" {
-                organization_key = field.text().await.expect("organization_key multi field");
+                tags = field.text().await.map_err(bad_field)?;
             } else if f_name == "association_type" {
-                association_type = field.text().await.expect("association_type multi field");
+                association_type = field.text().await.map_err(bad_field)?;
             } else if f_name == "association_key" {
-                association_key = field.text().await.expect("association_key multi field");
+                association_key = field.text().await.map_err(bad_field)?;
             } else if f_name == "hash" {
-                hash = field.text().await.expect("hash multi field");
+                hash = field.text().await.map_err(bad_field)?;
             } else if f_name == "size" {
-                size = field.text().await.expect("size multi field").parse::<i64>().expect("Valid int64");
+                size = field.text().await.map_err(bad_field)?.parse::<i64>().map_err(|_| AppError::Validation("size must be a number".to_string()))?;
             } else if f_name == "url" {
-                url = field.text().await.expect("url multi field");
+                url = field.text().await.map_err(bad_field)?;
             } else if f_name == "format" {
-                format = field.text().await.expect("format field");
+                format = field.text().await.map_err(bad_field)?;
             } else if f_name == "file" {
                 while let Some(chunk) = field
                     .chunk()
                     .await
-                    .expect("read in chunk from multipart stream")
+                    .map_err(bad_field)?
                 {
-                    buffer.write_all(&chunk).await.expect("Write to s3buffer");
+                    buffer.write_all(&chunk).await?;
                 }
             }
         }
@@ -377,15 +446,18 @@ pub async fn insert(req: Request<State>) -> tide::Result {
         .secure(false)
         .build()
         .unwrap();
+        // the organization always comes from the caller, never from the form
+        let organization_key =
+            crate::error::parse_key(claims.organization_key.as_str(), "organization")?;
       
         let bucket_name = get_bucket_name(association_type.clone(), association_key.clone());
         minio_client.make_bucket(bucket_name.clone(), true).await.unwrap_or_else(|_e| "Already Exists".to_string());
-        minio_client.put_object(bucket_name, name.clone(), buffer.into()).await.expect("Put buffer");
+        minio_client.put_object(bucket_name, name.clone(), buffer.into()).await.map_err(AppError::from)?;
         let mut s = File::new(
-            uuid::Uuid::from_str(claims.key.as_str()).expect("user key exists"),
-            uuid::Uuid::from_str(organization_key.as_str()).expect("organization_key exists"),
-            AssociationType::from_str(association_type.as_str()).expect("Valid association type"),
-            uuid::Uuid::from_str(association_key.as_str()).expect("association_key exists"),
+            crate::error::parse_key(claims.key.as_str(), "user")?,
+            organization_key,
+            AssociationType::from_str(association_type.as_str()).map_err(|_| AppError::Validation("unknown association type".to_string()))?,
+            crate::error::parse_key(association_key.as_str(), "association")?,
             url,
             hash,
             name.clone(),
@@ -394,24 +466,23 @@ pub async fn insert(req: Request<State>) -> tide::Result {
             format,
             size,
         );
-        let key = uuid::Uuid::from_str(key.as_str()).expect("user key exists");
+        let key = crate::error::parse_key(key.as_str(), "file")?;
         if !key.is_nil() {
             s.key = key;
             // updo0t
         } else {
-            insert_file(&mut conn, &s).await;
-            let organization_key =
-                uuid::Uuid::from_str(claims.organization_key.as_str()).expect("organization key");
-            post_file_create(
-                &mut conn,
-                claims.matrix_home_server,
-                claims.matrix_user_id,
-                organization_key, 
-                claims.matrix_access_token,
-                &s,
-            )
-            .await
-            .expect("Posting to matrix");
+            insert_file(&mut conn, &s).await?;
+            crate::api::report_matrix(
+                post_file_create(
+                    &mut conn,
+                    claims.matrix_home_server,
+                    claims.matrix_user_id,
+                    organization_key,
+                    claims.matrix_access_token,
+                    &s,
+                )
+                .await,
+            );
         }
         return Ok(tide::Redirect::new("/").into());
     }
@@ -426,7 +497,7 @@ fn get_bucket_name(association_type: String, association_key:String) -> String {
 }
 // data types
 
-#[derive(PartialEq, Debug, Deserialize, Serialize, Clone, Copy, sqlx::Type)]
+#[derive(PartialEq, Debug, Deserialize, Serialize, JsonSchema, Clone, Copy, sqlx::Type)]
 pub enum AssociationType {
     Organization,
     Project,
@@ -496,7 +567,7 @@ impl From<AssociationType> for i16 {
     }
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
 pub struct File {
     pub key: uuid::Uuid,
     pub owner_key: uuid::Uuid,
@@ -568,11 +639,13 @@ impl<'a> FileTemplate {
 }
 #[derive(Template)]
 #[template(path = "xls_editor.html")]
+#[allow(dead_code)]
 pub struct XLSEditorTemplate {
     file: File,
     user: crate::user::User,
 }
 
+#[allow(dead_code)]
 impl<'a> XLSEditorTemplate {
     pub fn new(file: File, user: crate::user::User) -> Self {
         return Self { file, user };
@@ -0,0 +1,338 @@
+use sqlx::pool::PoolConnection;
+use sqlx::postgres::PgRow;
+use sqlx::{Postgres, QueryBuilder, Row};
+use uuid::Uuid;
+
+use crate::common::day;
+use crate::error::AppError;
+use crate::task::Task;
+
+// A board filter is a list of terms separated by spaces, every one of them has to match:
+//
+//   assignee:me  assignee:none  assignee:jake@kinbrio.com  project:"Website Redesign"
+//   tag:frontend  status:"In Progress"  due_before:2024-06-01  due_after:2024-05-01  login
+//
+// Values with spaces go in double quotes and a term without a `key:` matches the task's name or
+// description. Names are matched case insensitively, keys work anywhere a name does.
+
+// SQL STUFF
+
+pub async fn get_filtered_tasks(
+    conn: &mut PoolConnection<Postgres>,
+    organization_key: uuid::Uuid,
+    viewer_key: uuid::Uuid,
+    filter: &Filter,
+) -> Result<Vec<Task>, AppError> {
+    let mut query = QueryBuilder::<Postgres>::new(
+        "select key, owner_key, organization_key, project_key, parent_key, milestone_key, assignee_key, name, description, tags, status_key, rank, estimated_quarter_days, start, due, created, updated from tasks where organization_key = ",
+    );
+    query.push_bind(organization_key);
+    filter.push_sql(&mut query, organization_key, viewer_key);
+    query.push(" order by rank, created");
+    let rows = query.build().fetch_all(conn).await?;
+    let mut tasks = vec![];
+    for row in rows {
+        tasks.push(task_from_row(&row)?);
+    }
+    Ok(tasks)
+}
+
+fn task_from_row(row: &PgRow) -> Result<Task, sqlx::Error> {
+    Ok(Task {
+        key: row.try_get("key")?,
+        organization_key: row.try_get("organization_key")?,
+        project_key: row.try_get("project_key")?,
+        parent_key: row.try_get("parent_key")?,
+        milestone_key: row.try_get("milestone_key")?,
+        owner_key: row.try_get("owner_key")?,
+        assignee_key: row.try_get("assignee_key")?,
+        name: row.try_get("name")?,
+        description: row.try_get("description")?,
+        tags: row.try_get("tags")?,
+        status_key: row.try_get("status_key")?,
+        rank: row.try_get("rank")?,
+        estimated_quarter_days: row.try_get("estimated_quarter_days")?,
+        start: row.try_get("start")?,
+        due: row.try_get("due")?,
+        created: row.try_get("created")?,
+        updated: row.try_get("updated")?,
+    })
+}
+
+// data types
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Term {
+    Assignee(String),
+    Project(String),
+    Tag(String),
+    Status(String),
+    DueBefore(i64),
+    DueAfter(i64),
+    Text(String),
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Filter {
+    pub terms: Vec<Term>,
+}
+
+impl Filter {
+    // Boards are checked with this when they're saved, so a stored filter always parses
+    pub fn parse(input: &str) -> Result<Filter, AppError> {
+        let mut terms = vec![];
+        for (key, value) in tokens(input)? {
+            if value.is_empty() && key.is_none() {
+                continue;
+            }
+            if value.is_empty() {
+                return Err(AppError::Validation(format!(
+                    "filter term `{}:` needs a value",
+                    key.unwrap_or_default()
+                )));
+            }
+            let term = match key.as_deref() {
+                None => Term::Text(value),
+                Some("assignee") => Term::Assignee(value),
+                Some("project") => Term::Project(value),
+                Some("tag") => Term::Tag(value.replace(' ', "").to_lowercase()),
+                Some("status") => Term::Status(value),
+                Some("due_before") => Term::DueBefore(day(&value)?),
+                // after the whole day, not from its first second
+                Some("due_after") => Term::DueAfter(day(&value)? + 60 * 60 * 24),
+                Some(other) => {
+                    return Err(AppError::Validation(format!(
+                        "unknown filter `{}`, use assignee, project, tag, status, due_before or due_after",
+                        other
+                    )))
+                }
+            };
+            terms.push(term);
+        }
+        Ok(Filter { terms })
+    }
+
+    fn push_sql(&self, query: &mut QueryBuilder<Postgres>, organization_key: Uuid, viewer_key: Uuid) {
+        for term in &self.terms {
+            match term {
+                Term::Assignee(value) if value == "me" => {
+                    query.push(" AND assignee_key = ").push_bind(viewer_key);
+                }
+                Term::Assignee(value) if value == "none" => {
+                    query.push(" AND assignee_key = ").push_bind(Uuid::nil());
+                }
+                Term::Assignee(value) => {
+                    query
+                        .push(" AND assignee_key IN (select key from users where key::text = ")
+                        .push_bind(value.clone())
+                        .push(" OR lower(email) = lower(")
+                        .push_bind(value.clone())
+                        .push(") OR split_part(lower(email), '@', 1) = lower(")
+                        .push_bind(value.clone())
+                        .push(") OR lower(matrix_user_id) = lower(")
+                        .push_bind(value.clone())
+                        .push("))");
+                }
+                Term::Project(value) => {
+                    query
+                        .push(" AND project_key IN (select key from projects where organization_key = ")
+                        .push_bind(organization_key)
+                        .push(" AND (key::text = ")
+                        .push_bind(value.clone())
+                        .push(" OR lower(name) = lower(")
+                        .push_bind(value.clone())
+                        .push(")))");
+                }
+                Term::Tag(value) => {
+                    query
+                        .push(" AND ")
+                        .push_bind(value.clone())
+                        .push(" = ANY(string_to_array(lower(replace(tags, ' ', '')), ','))");
+                }
+                Term::Status(value) => {
+                    query
+                        .push(" AND status_key IN (select key from workflow_statuses where organization_key = ")
+                        .push_bind(organization_key)
+                        .push(" AND (key::text = ")
+                        .push_bind(value.clone())
+                        .push(" OR lower(name) = lower(")
+                        .push_bind(value.clone())
+                        .push(")))");
+                }
+                // a due of 0 means there isn't one, it is never before anything
+                Term::DueBefore(timestamp) => {
+                    query.push(" AND due > 0 AND due < ").push_bind(*timestamp);
+                }
+                Term::DueAfter(timestamp) => {
+                    query.push(" AND due >= ").push_bind(*timestamp);
+                }
+                Term::Text(value) => {
+                    query
+                        .push(" AND (strpos(lower(name), lower(")
+                        .push_bind(value.clone())
+                        .push(")) > 0 OR strpos(lower(description), lower(")
+                        .push_bind(value.clone())
+                        .push(")) > 0)");
+                }
+            }
+        }
+    }
+}
+
+// Back to the text a board stores, parsing it again gives the same terms
+impl ToString for Filter {
+    fn to_string(&self) -> String {
+        self.terms
+            .iter()
+            .map(|term| match term {
+                Term::Assignee(value) => format!("assignee:{}", quote(value)),
+                Term::Project(value) => format!("project:{}", quote(value)),
+                Term::Tag(value) => format!("tag:{}", quote(value)),
+                Term::Status(value) => format!("status:{}", quote(value)),
+                Term::DueBefore(timestamp) => format!("due_before:{}", date(*timestamp)),
+                Term::DueAfter(timestamp) => format!("due_after:{}", date(*timestamp - 60 * 60 * 24)),
+                Term::Text(value) => quote(value),
+            })
+            .collect::<Vec<String>>()
+            .join(" ")
+    }
+}
+
+fn quote(value: &str) -> String {
+    if value.contains(|c: char| c.is_whitespace() || c == ':') {
+        format!("\"{}\"", value)
+    } else {
+        value.to_string()
+    }
+}
+
+fn date(timestamp: i64) -> String {
+    chrono::NaiveDateTime::from_timestamp_opt(timestamp, 0)
+        .map(|d| d.format("%Y-%m-%d").to_string())
+        .unwrap_or_default()
+}
+
+// Splits on spaces outside of double quotes. Each token comes back with the key in front of its
+// first unquoted colon, if it has one, and the quotes stripped from the value.
+fn tokens(input: &str) -> Result<Vec<(Option<String>, String)>, AppError> {
+    let mut out = vec![];
+    let mut key: Option<String> = None;
+    let mut current = String::new();
+    let mut quoted = false;
+    let mut started = false;
+    for c in input.chars() {
+        match c {
+            '"' => {
+                quoted = !quoted;
+                started = true;
+            }
+            ':' if !quoted && key.is_none() => {
+                key = Some(current.trim().to_lowercase());
+                current.clear();
+                started = true;
+            }
+            c if c.is_whitespace() && !quoted => {
+                if started {
+                    out.push((key.take(), current.clone()));
+                    current.clear();
+                    started = false;
+                }
+            }
+            c => {
+                current.push(c);
+                started = true;
+            }
+        }
+    }
+    if quoted {
+        return Err(AppError::Validation("filter has a quote that isn't closed".to_string()));
+    }
+    if started {
+        out.push((key.take(), current));
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn timestamp(date: &str) -> i64 {
+        crate::common::day(date).unwrap()
+    }
+
+    #[test]
+    fn quoted_values_keep_their_spaces() {
+        let filter = Filter::parse(r#"project:"Website Redesign" status:"In Progress" "login page" tag:"front end""#).unwrap();
+        assert_eq!(
+            filter.terms,
+            vec![
+                Term::Project("Website Redesign".to_string()),
+                Term::Status("In Progress".to_string()),
+                Term::Text("login page".to_string()),
+                Term::Tag("frontend".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn colons_in_quotes_are_part_of_the_value() {
+        let filter = Filter::parse(r#"assignee:"@jake:kinbrio.com" "a:b""#).unwrap();
+        assert_eq!(filter.terms, vec![Term::Assignee("@jake:kinbrio.com".to_string()), Term::Text("a:b".to_string())]);
+    }
+
+    #[test]
+    fn a_quote_that_is_not_closed_is_an_error() {
+        assert!(matches!(Filter::parse(r#"project:"Website"#), Err(AppError::Validation(_))));
+    }
+
+    #[test]
+    fn unknown_fields_are_errors() {
+        match Filter::parse("assignee:me priority:high") {
+            Err(AppError::Validation(message)) => assert!(message.contains("`priority`"), "{}", message),
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn keys_need_a_value() {
+        assert!(matches!(Filter::parse("tag:"), Err(AppError::Validation(_))));
+    }
+
+    #[test]
+    fn dates_have_to_be_days() {
+        for bad in ["due_before:tomorrow", "due_after:2024-13-01", "due_before:01/06/2024", "due_after:2024-02-30"] {
+            assert!(matches!(Filter::parse(bad), Err(AppError::Validation(_))), "{}", bad);
+        }
+        let filter = Filter::parse("due_before:2024-06-01 due_after:2024-05-01").unwrap();
+        assert_eq!(
+            filter.terms,
+            vec![Term::DueBefore(timestamp("2024-06-01")), Term::DueAfter(timestamp("2024-05-02"))]
+        );
+    }
+
+    #[test]
+    fn to_string_parses_back_to_the_same_terms() {
+        let filter = Filter::parse(r#"assignee:me project:"Website Redesign" due_after:2024-05-01 login"#).unwrap();
+        assert_eq!(filter.to_string(), r#"assignee:me project:"Website Redesign" due_after:2024-05-01 login"#);
+        assert_eq!(Filter::parse(&filter.to_string()).unwrap(), filter);
+    }
+
+    // the ORs inside a term are wrapped in parentheses, so they can't loosen the organization
+    // check or the terms around them
+    #[test]
+    fn every_term_is_anded_on_its_own() {
+        let filter = Filter::parse("assignee:jake tag:api login").unwrap();
+        let mut query = QueryBuilder::<Postgres>::new("select key from tasks where organization_key = ");
+        query.push_bind(Uuid::nil());
+        filter.push_sql(&mut query, Uuid::nil(), Uuid::nil());
+        assert_eq!(
+            query.sql(),
+            "select key from tasks where organization_key = $1 \
+             AND assignee_key IN (select key from users where key::text = $2 OR lower(email) = lower($3) \
+             OR split_part(lower(email), '@', 1) = lower($4) OR lower(matrix_user_id) = lower($5)) \
+             AND $6 = ANY(string_to_array(lower(replace(tags, ' ', '')), ',')) \
+             AND (strpos(lower(name), lower($7)) > 0 OR strpos(lower(description), lower($8)) > 0)"
+        );
+    }
+}
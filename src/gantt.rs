@@ -0,0 +1,261 @@
+use askama::Template;
+
+use tide::{http::mime, Request};
+
+use crate::common::{monday, DAY_SECONDS};
+use crate::dependency::Dependency;
+use crate::home::NotFoundTemplate;
+use crate::milestone::Milestone;
+use crate::project::Project;
+use crate::task::Task;
+use crate::workflow::Workflow;
+use crate::{user, State};
+
+// two years of days is already a very wide page
+const MAX_DAYS: i64 = 7 * 104;
+
+// Route Stuff
+
+pub async fn get(req: Request<State>) -> tide::Result {
+    let u = match user::user_or_error(&req) {
+        Ok(value) => value,
+        Err(_) => return Ok(tide::Redirect::new("/login").into()),
+    };
+    let project_key = crate::error::param_key(&req, "project_id")?;
+    let mut conn = req.state().db_pool.acquire().await?;
+    let project = match crate::project::get_project(&mut conn, project_key, u.organization_key).await? {
+        Some(p) => p,
+        None => {
+            return Ok(tide::Response::builder(tide::StatusCode::NotFound)
+                .content_type(mime::HTML)
+                .body(NotFoundTemplate::new().render_string())
+                .build())
+        }
+    };
+    let tasks = crate::task::get_tasks_by_project(&mut conn, project_key, u.organization_key).await?;
+    let milestones = crate::milestone::get_milestones_by_project(&mut conn, project_key, u.organization_key).await?;
+    let mut dependencies = crate::dependency::get_organization_dependencies(&mut conn, u.organization_key).await?;
+    dependencies.retain(|d| tasks.iter().any(|t| t.key == d.task_key) && tasks.iter().any(|t| t.key == d.depends_on_key));
+    let workflow = crate::workflow::get_workflow(&mut conn, u.organization_key).await?;
+    let first_dues = crate::activity::get_project_first_dues(&mut conn, project_key, u.organization_key).await?;
+    let critical = crate::dependency::critical_path(&tasks, &dependencies);
+    let timeline = Timeline::new(&project, &milestones, &tasks, chrono::Utc::now().timestamp());
+    let rows = timeline.rows(&milestones, &tasks, &workflow, &critical, &first_dues);
+    Ok(tide::Response::builder(tide::StatusCode::Ok)
+        .content_type(mime::HTML)
+        .body(GanttTemplate::new(u, project, timeline, rows, tasks, dependencies, workflow).render_string())
+        .build())
+}
+
+// data types
+
+// The days the chart covers, whole weeks from the Monday before the earliest date to the Sunday after the last
+pub struct Timeline {
+    pub first: i64,
+    pub days: i64,
+    pub now: i64,
+}
+
+impl Timeline {
+    pub fn new(project: &Project, milestones: &[Milestone], tasks: &[Task], now: i64) -> Self {
+        let dates: Vec<i64> = [project.start, project.due]
+            .into_iter()
+            .chain(milestones.iter().flat_map(|m| [m.start, m.due]))
+            .chain(tasks.iter().flat_map(|t| [t.start, t.due]))
+            .filter(|d| *d != 0)
+            .collect();
+        let earliest = dates.iter().min().copied().unwrap_or(now);
+        let latest = dates.iter().max().copied().unwrap_or(now);
+        let first = monday(earliest);
+        let days = ((monday(latest) - first) / DAY_SECONDS + 7).min(MAX_DAYS);
+        Self { first, days, now }
+    }
+
+    // 1 based day of the chart a timestamp falls on, clamped to the chart
+    pub fn day(&self, timestamp: i64) -> i64 {
+        ((timestamp - self.first).div_euclid(DAY_SECONDS) + 1).clamp(1, self.days)
+    }
+
+    pub fn today(&self) -> Option<i64> {
+        match (self.now - self.first).div_euclid(DAY_SECONDS) + 1 {
+            d if d >= 1 && d <= self.days => Some(d),
+            _ => None,
+        }
+    }
+
+    pub fn weeks(&self) -> Vec<Week> {
+        (0..self.days / 7)
+            .map(|w| Week {
+                day: w * 7 + 1,
+                label: chrono::NaiveDateTime::from_timestamp_opt(self.first + w * 7 * DAY_SECONDS, 0)
+                    .map(|d| d.format("%b %-d").to_string())
+                    .unwrap_or_default(),
+            })
+            .collect()
+    }
+
+    // a bar from start to due, a single day when only one of them is set
+    fn bar(&self, start: i64, due: i64) -> (i64, i64) {
+        let (start, due) = match (start, due) {
+            (0, 0) => return (0, 0),
+            (0, d) | (d, 0) => (d, d),
+            (s, d) => (s.min(d), s.max(d)),
+        };
+        let day = self.day(start);
+        (day, self.day(due) - day + 1)
+    }
+
+    // Milestones in the order they're due, each followed by its tasks, then the tasks without one
+    pub fn rows(
+        &self,
+        milestones: &[Milestone],
+        tasks: &[Task],
+        workflow: &Workflow,
+        critical: &[uuid::Uuid],
+        first_dues: &[(uuid::Uuid, i64)],
+    ) -> Vec<GanttRow> {
+        let mut ordered: Vec<&Milestone> = milestones.iter().collect();
+        ordered.sort_by_key(|m| if m.due == 0 { i64::MAX } else { m.due });
+        let mut groups: Vec<(Option<&Milestone>, Vec<&Task>)> = ordered
+            .into_iter()
+            .map(|m| (Some(m), tasks.iter().filter(|t| t.milestone_key == Some(m.key)).collect()))
+            .collect();
+        let loose: Vec<&Task> = tasks
+            .iter()
+            .filter(|t| t.milestone_key.is_none_or(|k| milestones.iter().all(|m| m.key != k)))
+            .collect();
+        if !loose.is_empty() {
+            groups.push((None, loose));
+        }
+
+        let mut rows = vec![];
+        for (milestone, mut group) in groups {
+            rows.push(match milestone {
+                Some(m) => {
+                    let (day, span) = self.bar(m.start, m.due);
+                    let done = !group.is_empty() && group.iter().all(|t| workflow.is_done(&t.status_key));
+                    GanttRow {
+                        key: m.key,
+                        name: format!("🏁 {}", m.name),
+                        url: format!("/milestone/{}", m.key),
+                        milestone: true,
+                        day,
+                        span,
+                        color: "#e07a5f".to_string(),
+                        critical: false,
+                        late: m.due != 0 && m.due < self.now && !done,
+                        slipped_days: 0,
+                        planned_day: 0,
+                    }
+                }
+                None => GanttRow {
+                    key: uuid::Uuid::nil(),
+                    name: "No milestone".to_string(),
+                    url: "".to_string(),
+                    milestone: true,
+                    day: 0,
+                    span: 0,
+                    color: "".to_string(),
+                    critical: false,
+                    late: false,
+                    slipped_days: 0,
+                    planned_day: 0,
+                },
+            });
+            group.sort_by_key(|t| (if t.start == 0 { t.due } else { t.start }, t.rank));
+            for task in group {
+                let (day, span) = self.bar(task.start, task.due);
+                let first_due = first_dues.iter().find(|(k, _)| *k == task.key).map(|(_, d)| *d).unwrap_or(0);
+                let slipped = first_due != 0 && task.due > first_due;
+                rows.push(GanttRow {
+                    key: task.key,
+                    name: task.name.clone(),
+                    url: format!("/task/{}", task.key),
+                    milestone: false,
+                    day,
+                    span,
+                    color: workflow.color(&task.status_key),
+                    critical: critical.contains(&task.key),
+                    late: task.due != 0 && task.due < self.now && !workflow.is_done(&task.status_key),
+                    slipped_days: if slipped { (task.due - first_due) / DAY_SECONDS } else { 0 },
+                    planned_day: if slipped { self.day(first_due) } else { 0 },
+                });
+            }
+        }
+        rows
+    }
+}
+
+pub struct Week {
+    pub day: i64,
+    pub label: String,
+}
+
+// One line of the chart. day is the 1 based day the bar starts on and span how many days it covers,
+// 0 for both when there are no dates to draw. planned_day is where the due date was first set when
+// it has since slipped later.
+#[derive(Debug)]
+pub struct GanttRow {
+    pub key: uuid::Uuid,
+    pub name: String,
+    pub url: String,
+    pub milestone: bool,
+    pub day: i64,
+    pub span: i64,
+    pub color: String,
+    pub critical: bool,
+    pub late: bool,
+    pub slipped_days: i64,
+    pub planned_day: i64,
+}
+
+#[derive(Template)]
+#[template(path = "gantt.html")]
+pub struct GanttTemplate {
+    user: crate::user::User,
+    project: Project,
+    timeline: Timeline,
+    rows: Vec<GanttRow>,
+    tasks: Vec<Task>,
+    dependencies: Vec<Dependency>,
+    workflow: Workflow,
+}
+
+impl<'a> GanttTemplate {
+    pub fn new(
+        user: crate::user::User,
+        project: Project,
+        timeline: Timeline,
+        rows: Vec<GanttRow>,
+        tasks: Vec<Task>,
+        dependencies: Vec<Dependency>,
+        workflow: Workflow,
+    ) -> Self {
+        return Self {
+            user,
+            project,
+            timeline,
+            rows,
+            tasks,
+            dependencies,
+            workflow,
+        };
+    }
+
+    // the label takes the first grid column, so day 1 is column 2
+    pub fn column<'aa>(&'aa self, day: &i64) -> i64 {
+        day + 1
+    }
+
+    pub fn tasks_json<'aa>(&'aa self) -> String {
+        crate::common::script_json(&self.tasks)
+    }
+
+    pub fn dependencies_json<'aa>(&'aa self) -> String {
+        crate::common::script_json(&self.dependencies)
+    }
+
+    pub fn render_string(&self) -> String {
+        return self.render().unwrap();
+    }
+}
@@ -1,5 +1,3 @@
-use std::str::FromStr;
-
 use askama::Template;
 
 use crate::{
@@ -10,7 +8,7 @@ use crate::{
 use tide::{http::mime, Request};
 
 pub async fn home(req: Request<State>) -> tide::Result {
-    return match user::read_jwt_cookie(req.cookie("token")) {
+    return match user::request_claims(&req) {
         Some(_c) => dashboard(req).await,
         None => {
             let home = HomeTemplate::new(user::User::new(
@@ -28,7 +26,7 @@ pub async fn home(req: Request<State>) -> tide::Result {
     };
 }
 pub async fn account(req: Request<State>) -> tide::Result {
-    let claims: user::UserJwtState = match user::read_jwt_cookie(req.cookie("token")) {
+    let claims: user::UserJwtState = match user::request_claims(&req) {
         Some(c) => c,
         None => {
             return Ok(tide::Redirect::new("/login").into());
@@ -46,8 +44,11 @@ pub async fn account(req: Request<State>) -> tide::Result {
             )
         }
     };
-    let key = uuid::Uuid::from_str(claims.key.as_str()).expect("uuid parse");
-    let user = user::get_user(&mut conn, key).await.expect("user exists");
+    let key = crate::error::parse_key(claims.key.as_str(), "user")?;
+    let user = match user::get_user(&mut conn, key).await {
+        Some(u) => u,
+        None => return Err(crate::error::AppError::NotFound("user".to_string()).into()),
+    };
 
     let home = user::UserTemplate::new(&user, user.email.as_str());
     Ok(tide::Response::builder(tide::StatusCode::Ok)
@@ -56,12 +57,13 @@ pub async fn account(req: Request<State>) -> tide::Result {
         .build())
 }
 
+#[allow(unreachable_code, unused_variables)]
 pub async fn documentation(req: Request<State>) -> tide::Result {
     return Ok(tide::Redirect::new("http://localhost:3005").into());
-    let user = match user::read_jwt_cookie(req.cookie("token")) {
+    let user = match user::request_claims(&req) {
         Some(c) => user::User::new(
-            uuid::Uuid::from_str(c.key.as_str()).expect("parsed cookie"),
-            uuid::Uuid::from_str(c.organization_key.as_str()).expect("parsed cookie"),
+            crate::error::parse_key(c.key.as_str(), "user")?,
+            crate::error::parse_key(c.organization_key.as_str(), "organization")?,
             c.email,
             c.matrix_user_id,
             c.matrix_home_server,
@@ -85,7 +87,7 @@ pub async fn documentation(req: Request<State>) -> tide::Result {
 }
 
 pub async fn dashboard(req: Request<State>) -> tide::Result {
-    let claims: user::UserJwtState = match user::read_jwt_cookie(req.cookie("token")) {
+    let claims: user::UserJwtState = match user::request_claims(&req) {
         Some(c) => c,
         None => {
             return Ok(tide::Redirect::new("/login").into());
@@ -103,8 +105,8 @@ pub async fn dashboard(req: Request<State>) -> tide::Result {
             )
         }
     };
-    let key = uuid::Uuid::from_str(claims.key.as_str()).expect("uuid parse");
-    let user = match user::get_user(&mut conn, key).await {
+    let key = crate::error::parse_key(claims.key.as_str(), "user")?;
+    let mut user = match user::get_user(&mut conn, key).await {
         Some(u) => u,
         None => {
             return Ok(tide::Redirect::new("/login").into());
@@ -121,17 +123,20 @@ pub async fn dashboard(req: Request<State>) -> tide::Result {
             )
         }
     };
-    let org_key = uuid::Uuid::from_str(claims.organization_key.as_str()).expect("Valid UUID");
+    // everything on the dashboard belongs to the organization active in the JWT
+    let org_key = crate::error::parse_key(claims.organization_key.as_str(), "organization")?;
+    user.organization_key = org_key;
     
-    let projects = project::get_projects_by_organization(&mut conn, org_key).await;
-    let organization = organization::get_organization(&mut conn, org_key).await;
-    let organization_boards = board::get_organization_boards(&mut conn, org_key).await;
-    let user_boards = board::get_user_boards(&mut conn, key).await;
-    let entitys = entity::get_organization_entitys(&mut conn, org_key).await;
-    let contacts = entity::get_contacts(&mut conn, org_key).await;
-    let notes = note::get_organization_notes(&mut conn, org_key).await;
-    let files = file::get_organization_files(&mut conn, org_key).await;
-    let service_items = service_item::get_organization_service_items(&mut conn, org_key).await;
+    let projects = project::get_projects_by_organization(&mut conn, org_key).await?;
+    let organization = organization::get_organization(&mut conn, org_key).await?;
+    let organization_boards = board::get_organization_boards(&mut conn, org_key).await?;
+    let user_boards = board::get_user_boards(&mut conn, key, org_key).await?;
+    let organizations = organization::get_user_organizations(&mut conn, key).await?;
+    let entitys = entity::get_organization_entitys(&mut conn, org_key).await?;
+    let contacts = entity::get_contacts(&mut conn, org_key).await?;
+    let notes = note::get_organization_notes(&mut conn, org_key).await?;
+    let files = file::get_organization_files(&mut conn, org_key).await?;
+    let service_items = service_item::get_organization_service_items(&mut conn, org_key).await?;
     let home = DashboardTemplate::new(
         user,
         organization,
@@ -143,6 +148,7 @@ pub async fn dashboard(req: Request<State>) -> tide::Result {
         contacts,
         notes,
         files,
+        organizations,
     );
     Ok(tide::Response::builder(tide::StatusCode::Ok)
         .content_type(mime::HTML)
@@ -172,6 +178,7 @@ pub struct DashboardTemplate {
     user: User,
     organization: organization::Organization,
     projects: Vec<project::Project>,
+    #[allow(dead_code)]
     organization_boards: Vec<board::Board>,
     user_boards: Vec<board::Board>,
     service_items: Vec<service_item::ServiceItem>,
@@ -179,6 +186,7 @@ pub struct DashboardTemplate {
     contacts: Vec<entity::Contact>,
     notes: Vec<note::Note>,
     files: Vec<file::File>,
+    organizations: Vec<organization::Organization>,
 }
 
 impl<'a> DashboardTemplate {
@@ -193,6 +201,7 @@ impl<'a> DashboardTemplate {
         contacts: Vec<entity::Contact>,
         notes: Vec<note::Note>,
         files: Vec<file::File>,
+        organizations: Vec<organization::Organization>,
     ) -> Self {
         return Self {
             user,
@@ -205,6 +214,7 @@ impl<'a> DashboardTemplate {
             contacts,
             notes,
             files,
+            organizations,
         };
     }
 
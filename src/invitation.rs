@@ -0,0 +1,381 @@
+use askama::Template;
+use serde::{Deserialize, Serialize};
+use sqlx::pool::PoolConnection;
+use sqlx::postgres::PgQueryResult;
+use sqlx::Postgres;
+use strum::IntoEnumIterator;
+use uuid::Uuid;
+
+use tide::http::cookies::Cookie;
+use tide::{http::mime, Request};
+
+use crate::error::AppError;
+use crate::home::NotFoundTemplate;
+use crate::member::{self, Member, Role};
+use crate::{user, State};
+
+// an invitation link is good for a week
+const INVITATION_TTL_SECONDS: i64 = 60 * 60 * 24 * 7;
+
+// SQL STUFF
+
+pub async fn get_invitation_by_token(
+    conn: &mut PoolConnection<Postgres>,
+    token: &str,
+) -> Result<Option<Invitation>, AppError> {
+    let invitation = match sqlx::query!(
+        "select key, organization_key, invited_by, email, matrix_user_id, role, token, expires, accepted, created, updated from invitations where token = $1",
+        token
+    )
+    .fetch_optional(conn)
+    .await?
+    {
+        Some(r) => r,
+        None => return Ok(None),
+    };
+
+    Ok(Some(Invitation {
+        key: invitation.key,
+        organization_key: invitation.organization_key,
+        invited_by: invitation.invited_by,
+        email: invitation.email,
+        matrix_user_id: invitation.matrix_user_id,
+        role: invitation.role.into(),
+        token: invitation.token,
+        expires: invitation.expires,
+        accepted: invitation.accepted,
+        created: invitation.created,
+        updated: invitation.updated,
+    }))
+}
+
+pub async fn get_organization_invitations(
+    conn: &mut PoolConnection<Postgres>,
+    organization_key: uuid::Uuid,
+) -> Result<Vec<Invitation>, AppError> {
+    let records = sqlx::query!(
+        "select key, organization_key, invited_by, email, matrix_user_id, role, token, expires, accepted, created, updated from invitations where organization_key = $1 AND accepted = 0 order by created desc",
+        organization_key
+    )
+    .fetch_all(conn)
+    .await?;
+    let mut invitations = vec![];
+    for invitation in records {
+        invitations.push(Invitation {
+            key: invitation.key,
+            organization_key: invitation.organization_key,
+            invited_by: invitation.invited_by,
+            email: invitation.email,
+            matrix_user_id: invitation.matrix_user_id,
+            role: invitation.role.into(),
+            token: invitation.token,
+            expires: invitation.expires,
+            accepted: invitation.accepted,
+            created: invitation.created,
+            updated: invitation.updated,
+        })
+    }
+    Ok(invitations)
+}
+
+async fn insert_invitation(conn: &mut PoolConnection<Postgres>, new_invitation: &Invitation) -> Result<(), AppError> {
+    sqlx::query!("INSERT INTO invitations (key, organization_key, invited_by, email, matrix_user_id, role, token, expires, accepted, created, updated) values($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)",
+        new_invitation.key,
+        new_invitation.organization_key,
+        new_invitation.invited_by,
+        &new_invitation.email,
+        &new_invitation.matrix_user_id,
+        new_invitation.role as i16,
+        &new_invitation.token,
+        new_invitation.expires,
+        new_invitation.accepted,
+        new_invitation.created,
+        new_invitation.updated,
+    )
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+async fn delete_invitation(
+    conn: &mut PoolConnection<Postgres>,
+    key: uuid::Uuid,
+    organization_key: uuid::Uuid,
+) -> Result<PgQueryResult, sqlx::Error> {
+    return sqlx::query!(
+        "DELETE FROM invitations where organization_key=$1 AND key=$2",
+        organization_key,
+        key
+    )
+    .execute(conn)
+    .await;
+}
+
+async fn mark_accepted(
+    conn: &mut PoolConnection<Postgres>,
+    key: uuid::Uuid,
+) -> Result<PgQueryResult, sqlx::Error> {
+    return sqlx::query!(
+        "UPDATE invitations SET accepted=extract(epoch from now()), updated=extract(epoch from now()) where key=$1 AND accepted = 0",
+        key
+    )
+    .execute(conn)
+    .await;
+}
+
+// Redeems the invitation behind `token` for the given identity, burning it so it can't be used twice
+pub async fn claim_invitation(
+    conn: &mut PoolConnection<Postgres>,
+    token: &str,
+    email: &str,
+    matrix_user_id: &str,
+) -> Result<Option<Invitation>, AppError> {
+    let invitation = match get_invitation_by_token(conn, token).await? {
+        Some(i) if i.is_open() && i.matches(email, matrix_user_id) => i,
+        _ => return Ok(None),
+    };
+    match mark_accepted(conn, invitation.key).await?.rows_affected() {
+        1 => Ok(Some(invitation)),
+        _ => Ok(None),
+    }
+}
+
+// Claims the invitation for an existing user and adds them to its organization, which becomes the
+// one they land in. Someone already in the organization keeps the role they have.
+pub(crate) async fn join_invitation(
+    conn: &mut PoolConnection<Postgres>,
+    token: &str,
+    u: &mut user::User,
+) -> Result<Option<Invitation>, AppError> {
+    let claimed = match claim_invitation(conn, token, u.email.as_str(), u.matrix_user_id.as_str()).await? {
+        Some(i) => i,
+        None => return Ok(None),
+    };
+    if member::get_role(conn, claimed.organization_key, u.key).await.is_none() {
+        member::insert_member(conn, &Member::new(claimed.organization_key, u.key, claimed.role)).await?;
+    }
+    user::set_active_organization(conn, u.key, claimed.organization_key).await?;
+    u.organization_key = claimed.organization_key;
+    Ok(Some(claimed))
+}
+
+// Route Stuff
+
+pub async fn get(req: Request<State>) -> tide::Result {
+    let u = match crate::user::user_or_error(&req) {
+        Ok(value) => value,
+        Err(_) => return Ok(tide::Redirect::new("/login").into()),
+    };
+    let mut conn = req.state().db_pool.acquire().await?;
+    let invitations = get_organization_invitations(&mut conn, u.organization_key).await?;
+    Ok(tide::Response::builder(tide::StatusCode::Ok)
+        .content_type(mime::HTML)
+        .body(InvitationTemplate::new(u, invitations).render_string())
+        .build())
+}
+
+pub async fn insert(mut req: Request<State>) -> tide::Result {
+    let claims: user::UserJwtState = match user::request_claims(&req) {
+        Some(c) => c,
+        None => {
+            return Ok(tide::Redirect::new("/login").into());
+        }
+    };
+    let umd: Result<InvitationRequest, tide::Error> = req.body_json().await;
+    match umd {
+        Ok(request) => {
+            let email = request.email.trim().to_string();
+            let matrix_user_id = request.matrix_user_id.trim().to_string();
+            if email.is_empty() && matrix_user_id.is_empty() {
+                return Err(AppError::Validation("an email or matrix id is required".into()).into());
+            }
+            // same rule as member::update_role, only an owner can hand out ownership
+            if request.role == Role::Owner && claims.role != Role::Owner {
+                return Ok(tide::Response::builder(tide::StatusCode::Forbidden)
+                    .content_type(mime::PLAIN)
+                    .body("FORBIDDEN")
+                    .build());
+            }
+            let mut conn = req.state().db_pool.acquire().await?;
+            let s = Invitation::new(
+                crate::error::parse_key(claims.organization_key.as_str(), "organization")?,
+                crate::error::parse_key(claims.key.as_str(), "user")?,
+                email,
+                matrix_user_id,
+                request.role,
+            );
+            insert_invitation(&mut conn, &s).await?;
+            let j = serde_json::to_string(&s).expect("To JSON");
+            Ok(tide::Response::builder(tide::StatusCode::Ok)
+                .content_type(mime::JSON)
+                .body(j)
+                .build())
+        }
+        Err(e) => Err(AppError::Validation(format!("invalid json body: {}", e)).into()),
+    }
+}
+
+pub async fn delete(req: Request<State>) -> tide::Result {
+    let u = match crate::user::user_or_error(&req) {
+        Ok(value) => value,
+        Err(_) => return Ok(tide::Redirect::new("/login").into()),
+    };
+    match req.param("invitation_id") {
+        Ok(key) => {
+            let mut conn = req.state().db_pool.acquire().await?;
+            let s_uuid = crate::error::parse_key(key, "invitation")?;
+            match delete_invitation(&mut conn, s_uuid, u.organization_key).await {
+                Ok(_) => Ok(tide::Response::builder(tide::StatusCode::Ok)
+                    .content_type(mime::HTML)
+                    .build()),
+                Err(_) => Ok(
+                    tide::Response::builder(tide::StatusCode::InternalServerError)
+                        .content_type(mime::HTML)
+                        .body(NotFoundTemplate::new().render_string())
+                        .build(),
+                ),
+            }
+        }
+        Err(e) => {
+            println!("{:?}", e);
+            Ok(tide::Response::builder(tide::StatusCode::NotFound)
+                .content_type(mime::HTML)
+                .body(NotFoundTemplate::new().render_string())
+                .build())
+        }
+    }
+}
+
+// Landing point for an invitation link. Logged in users are attached straight away, everyone
+// else carries the token in the `invite` cookie through login, or registration when there is no
+// account for the invited identity yet.
+pub async fn accept(req: Request<State>) -> tide::Result {
+    let token = match req.param("token") {
+        Ok(t) => t.to_string(),
+        Err(_) => {
+            return Ok(tide::Response::builder(tide::StatusCode::NotFound)
+                .content_type(mime::HTML)
+                .body(NotFoundTemplate::new().render_string())
+                .build())
+        }
+    };
+    let mut conn = req.state().db_pool.acquire().await?;
+    let invitation = match get_invitation_by_token(&mut conn, token.as_str()).await? {
+        Some(i) if i.is_open() => i,
+        _ => {
+            return Ok(tide::Response::builder(tide::StatusCode::NotFound)
+                .content_type(mime::HTML)
+                .body(NotFoundTemplate::new().render_string())
+                .build())
+        }
+    };
+    if let Ok(mut u) = crate::user::user_or_error(&req) {
+        if join_invitation(&mut conn, token.as_str(), &mut u).await?.is_none() {
+            return Ok(tide::Response::builder(tide::StatusCode::Forbidden)
+                .content_type(mime::PLAIN)
+                .body("FORBIDDEN")
+                .build());
+        }
+        return Ok(tide::Redirect::new("/dashboard").into());
+    }
+    let next = match user::account_exists(&mut conn, invitation.email.as_str(), invitation.matrix_user_id.as_str()).await? {
+        true => "/login",
+        false => "/register",
+    };
+    let mut res: tide::Response = tide::Redirect::new(next).into();
+    let c = Cookie::parse(format!("invite={}; Path=/", invitation.token)).unwrap();
+    res.insert_cookie(c);
+    Ok(res)
+}
+
+// data types
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct InvitationRequest {
+    pub email: String,
+    pub matrix_user_id: String,
+    pub role: Role,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Invitation {
+    pub key: uuid::Uuid,
+    pub organization_key: uuid::Uuid,
+    pub invited_by: uuid::Uuid,
+    pub email: String,
+    pub matrix_user_id: String,
+    pub role: Role,
+    pub token: String,
+    pub expires: i64,
+    pub accepted: i64,
+    pub created: i64,
+    pub updated: i64,
+}
+
+impl Invitation {
+    pub fn new(
+        organization_key: uuid::Uuid,
+        invited_by: uuid::Uuid,
+        email: String,
+        matrix_user_id: String,
+        role: Role,
+    ) -> Self {
+        let key = Uuid::new_v4();
+        let token = format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple());
+        let created = chrono::Utc::now().timestamp();
+        let expires = created + INVITATION_TTL_SECONDS;
+        let updated = 0;
+        Self {
+            key,
+            organization_key,
+            invited_by,
+            email,
+            matrix_user_id,
+            role,
+            token,
+            expires,
+            accepted: 0,
+            created,
+            updated,
+        }
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.accepted == 0 && self.expires > chrono::Utc::now().timestamp()
+    }
+
+    // An invitation is addressed to a matrix id, an email or both and only that person can redeem it
+    pub fn matches(&self, email: &str, matrix_user_id: &str) -> bool {
+        if !self.matrix_user_id.is_empty() && self.matrix_user_id != matrix_user_id {
+            return false;
+        }
+        if !self.email.is_empty() && !self.email.eq_ignore_ascii_case(email) {
+            return false;
+        }
+        true
+    }
+}
+
+#[derive(Template)]
+#[template(path = "invitation.html")]
+pub struct InvitationTemplate {
+    user: crate::user::User,
+    invitations: Vec<Invitation>,
+}
+
+impl<'a> InvitationTemplate {
+    pub fn new(user: crate::user::User, invitations: Vec<Invitation>) -> Self {
+        return Self { user, invitations };
+    }
+
+    pub fn expires_on<'aa>(&'aa self, expires: &i64) -> String {
+        match chrono::NaiveDateTime::from_timestamp_opt(*expires, 0) {
+            Some(d) => d.format("%Y-%m-%d").to_string(),
+            None => "".to_string(),
+        }
+    }
+
+    pub fn render_string(&self) -> String {
+        return self.render().unwrap();
+    }
+}
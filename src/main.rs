@@ -1,23 +1,60 @@
+// The house style clippy would otherwise flag everywhere: explicit returns, named lifetimes on
+// the template helpers, constructors that take every field, and Into/ToString on the enums.
+#![allow(
+    clippy::needless_return,
+    clippy::needless_lifetimes,
+    clippy::extra_unused_lifetimes,
+    clippy::too_many_arguments,
+    clippy::from_over_into,
+    clippy::to_string_trait_impl
+)]
+
+mod activity;
 mod akaunting;
+mod api;
+mod api_token;
+mod auth;
+mod blueprint;
 mod board;
+mod budget;
+mod calendar;
+mod capacity;
+mod checklist;
+mod comment;
 mod common;
+mod dependency;
 mod entity;
+mod error;
 mod file;
+mod filter;
+mod gantt;
 mod home;
+mod invitation;
 mod matrix;
+mod member;
 mod milestone;
 mod note;
+mod openapi;
 mod organization;
 mod project;
+mod recurrence;
+mod search;
 mod service_item;
 mod task;
+mod time_entry;
 mod user;
+mod workflow;
+#[cfg(test)]
+mod tests;
 use dotenv::dotenv;
-use file::get_file_fs;
+use auth::{Authorize, Permission};
+use error::AppError;
+use std::str::FromStr;
+use file::{get_file_fs, AssociationType};
 use sqlx::{PgPool, Pool};
 use tide::{Body, Request, Response, StatusCode, http::mime};
 use tokio::io;
-use user::{read_jwt_cookie, UserJwtState};
+use user::UserJwtState;
 #[derive(Clone, Debug)]
 pub struct State {
     db_pool: PgPool,
@@ -39,7 +76,7 @@ pub async fn serve_dir(req: Request<State>) -> tide::Result {
 }
 
 pub async fn serve_s3(req: Request<State>) -> tide::Result {
-    let claims: UserJwtState = match read_jwt_cookie(req.cookie("token")) {
+    let claims: UserJwtState = match user::request_claims(&req) {
         Some(c) => c,
         None => {
             return Ok(Response::new(StatusCode::Unauthorized));
@@ -47,30 +84,41 @@ pub async fn serve_s3(req: Request<State>) -> tide::Result {
     };
     let format = match req.param("format") {
         Ok(i) => i,
-        Err(e) => return Ok(Response::new(StatusCode::BadRequest)),
+        Err(_) => return Ok(Response::new(StatusCode::BadRequest)),
     };
     let association_type = match req.param("association_type") {
         Ok(i) => i,
-        Err(e) => return Ok(Response::new(StatusCode::BadRequest)),
+        Err(_) => return Ok(Response::new(StatusCode::BadRequest)),
     };
     let association_id = match req.param("association_id") {
         Ok(i) => i,
-        Err(e) => return Ok(Response::new(StatusCode::BadRequest)),
+        Err(_) => return Ok(Response::new(StatusCode::BadRequest)),
     };
     let name = match req.param("name") {
         Ok(i) => i,
-        Err(e) => return Ok(Response::new(StatusCode::BadRequest)),
+        Err(_) => return Ok(Response::new(StatusCode::BadRequest)),
     };
     let organization_id = match req.param("organization_id") {
         Ok(i) => i,
-        Err(e) => return Ok(Response::new(StatusCode::BadRequest)),
+        Err(_) => return Ok(Response::new(StatusCode::BadRequest)),
     };
     let org_claim = claims.organization_key.clone();
     println!("{organization_id} {org_claim}");
     if organization_id != claims.organization_key {
         return Ok(Response::new(StatusCode::Unauthorized));
     }
-    let resp = get_file_fs(association_type.to_owned(), association_id.to_owned(), name.to_owned()).await.expect("S3 worked");
+    // the bucket is keyed by association only, so make sure the file is one of ours
+    let organization_key = crate::error::parse_key(organization_id, "organization")?;
+    let association_key = match uuid::Uuid::from_str(association_id) {
+        Ok(k) => k,
+        Err(_) => return Ok(Response::new(StatusCode::BadRequest)),
+    };
+    let mut conn = req.state().db_pool.acquire().await?;
+    let files = file::get_associated_files(&mut conn, organization_key, AssociationType::from_str(association_type).map_err(|_| AppError::Validation("unknown association type".to_string()))?, association_key).await?;
+    if !files.iter().any(|f| f.name == name) {
+        return Ok(Response::new(StatusCode::NotFound));
+    }
+    let resp = get_file_fs(association_type.to_owned(), association_id.to_owned(), name.to_owned()).await.map_err(AppError::from)?;
     let m_type = match format {
         "jpg" => mime::JPEG,
         "jpeg" => mime::JPEG,
@@ -79,7 +127,7 @@ pub async fn serve_s3(req: Request<State>) -> tide::Result {
         "ico" => mime::ICO,
         _ => mime::BYTE_STREAM,
     };
-    let body = Body::from_bytes(resp.bytes().await.expect("Body").to_vec());
+    let body = Body::from_bytes(resp.bytes().await.map_err(|e| AppError::Storage(e.to_string()))?.to_vec());
     Ok(tide::Response::builder(tide::StatusCode::Ok)
         .content_type(m_type)
         .body(body)
@@ -95,23 +143,21 @@ macro_rules! log {
     };
 }
 
-#[tokio::main]
-async fn main() -> tide::Result<()> {
-    dotenv().ok();
-    let db_url = std::env::var("DATABASE_URL")
-        .expect("Missing `DATABASE_URL` env variable, needed for running the server");
-
-    let db_pool: PgPool = Pool::connect(&db_url).await.unwrap();
-
-    let state = State { db_pool };
+// Every route with its middleware. main serves it, the tests send requests straight to it.
+pub fn server(state: State) -> tide::Server<State> {
     let mut app = tide::with_state(state);
     app.with(tide_compress::CompressMiddleware::new());
+    app.with(error::Responder);
 
     app.at("/").get(home::home);
 
-    app.at("/dashboard").get(home::dashboard);
+    app.at("/dashboard")
+        .with(Authorize::new(Permission::View))
+        .get(home::dashboard);
     app.at("/documentation").get(home::documentation);
-    app.at("/account").get(home::account);
+    app.at("/account")
+        .with(Authorize::new(Permission::View))
+        .get(home::account);
 
     app.at("/login").get(user::login);
     app.at("/logout").get(user::logout);
@@ -122,83 +168,348 @@ async fn main() -> tide::Result<()> {
     app.at("/register").get(user::register);
     app.at("/register").post(user::register_post);
     app.at("/register_matrix").get(user::register_post);
+    app.at("/invite/accept/:token").get(invitation::accept);
 
-    app.at("/users/:user_id").post(user::update);
-    app.at("/users/:user_id").get(user::get);
-    app.at("/users/:user_id").delete(user::delete);
+    app.at("/users/:user_id")
+        .with(Authorize::new(Permission::View))
+        .post(user::update);
+    app.at("/calendar")
+        .with(Authorize::new(Permission::View))
+        .get(calendar::get);
+    app.at("/calendar/feed")
+        .with(Authorize::new(Permission::View))
+        .post(calendar::insert_feed);
+    app.at("/calendar/feed")
+        .with(Authorize::new(Permission::View))
+        .delete(calendar::delete_feed);
+    app.at("/calendar/feed/:feed_id").get(calendar::feed);
+    app.at("/search")
+        .with(Authorize::new(Permission::View))
+        .get(search::get);
+    app.at("/capacity")
+        .with(Authorize::new(Permission::View))
+        .get(capacity::get);
+    app.at("/capacity/:user_id")
+        .with(Authorize::new(Permission::Edit))
+        .post(capacity::update);
+    app.at("/tokens")
+        .with(Authorize::new(Permission::View))
+        .get(api_token::get);
+    app.at("/tokens")
+        .with(Authorize::new(Permission::View))
+        .post(api_token::insert);
+    app.at("/tokens/:token_id")
+        .with(Authorize::new(Permission::View))
+        .delete(api_token::delete);
+    app.at("/users/:user_id")
+        .with(Authorize::new(Permission::View))
+        .get(user::get);
+    app.at("/users/:user_id")
+        .with(Authorize::new(Permission::Administer))
+        .delete(user::delete);
 
-    app.at("/project").post(project::insert);
-    app.at("/project/add").get(project::add);
-    app.at("/project/:project_id").get(project::get);
-    app.at("/project/:project_id").delete(project::delete);
+    app.at("/project")
+        .with(Authorize::new(Permission::Edit))
+        .post(project::insert);
+    app.at("/project/add")
+        .with(Authorize::new(Permission::Edit))
+        .get(project::add);
+    app.at("/project/:project_id")
+        .with(Authorize::new(Permission::View))
+        .get(project::get);
+    app.at("/project/:project_id")
+        .with(Authorize::new(Permission::Delete))
+        .delete(project::delete);
+    app.at("/project/:project_id/critical_path")
+        .with(Authorize::new(Permission::View))
+        .get(dependency::get_critical_path);
+    app.at("/project/:project_id/blueprint")
+        .with(Authorize::new(Permission::Edit))
+        .post(blueprint::insert);
+    app.at("/blueprint")
+        .with(Authorize::new(Permission::View))
+        .get(blueprint::get);
+    app.at("/blueprint/:blueprint_id/project")
+        .with(Authorize::new(Permission::Edit))
+        .post(blueprint::instantiate);
+    app.at("/blueprint/:blueprint_id")
+        .with(Authorize::new(Permission::Delete))
+        .delete(blueprint::delete);
+    app.at("/project/:project_id/gantt")
+        .with(Authorize::new(Permission::View))
+        .get(gantt::get);
+    app.at("/project/:project_id/budget")
+        .with(Authorize::new(Permission::Edit))
+        .get(budget::get);
+    app.at("/project/:project_id/budget")
+        .with(Authorize::new(Permission::Edit))
+        .post(budget::update);
 
-    app.at("/task").post(task::insert);
-    app.at("/task/add/:project_id").get(task::add);
-    app.at("/task/:task_id").get(task::get);
-    app.at("/task/:task_id").delete(task::delete);
+    app.at("/task")
+        .with(Authorize::new(Permission::Edit))
+        .post(task::insert);
+    app.at("/task/add/:project_id")
+        .with(Authorize::new(Permission::Edit))
+        .get(task::add);
+    app.at("/task/:task_id")
+        .with(Authorize::new(Permission::View))
+        .get(task::get);
+    app.at("/task/:task_id")
+        .with(Authorize::new(Permission::Delete))
+        .delete(task::delete);
+    app.at("/task/:task_id/move")
+        .with(Authorize::new(Permission::Edit))
+        .post(task::move_card);
+    app.at("/task/:task_id/comments")
+        .with(Authorize::new(Permission::View))
+        .get(comment::get_by_task);
+    app.at("/task/:task_id/comments")
+        .with(Authorize::new(Permission::Edit))
+        .post(comment::insert);
+    app.at("/task/:task_id/activity")
+        .with(Authorize::new(Permission::View))
+        .get(activity::get_by_task);
+    app.at("/task/:task_id/dependencies")
+        .with(Authorize::new(Permission::View))
+        .get(dependency::get_by_task);
+    app.at("/task/:task_id/dependencies")
+        .with(Authorize::new(Permission::Edit))
+        .post(dependency::insert);
+    app.at("/dependency/:dependency_id")
+        .with(Authorize::new(Permission::Edit))
+        .delete(dependency::delete);
+    app.at("/task/:task_id/checklist")
+        .with(Authorize::new(Permission::View))
+        .get(checklist::get_by_task);
+    app.at("/task/:task_id/checklist")
+        .with(Authorize::new(Permission::Edit))
+        .post(checklist::insert);
+    app.at("/task/:task_id/recurrence")
+        .with(Authorize::new(Permission::View))
+        .get(recurrence::get_by_task);
+    app.at("/task/:task_id/recurrence")
+        .with(Authorize::new(Permission::Edit))
+        .post(recurrence::insert);
+    app.at("/task/:task_id/recurrence")
+        .with(Authorize::new(Permission::Edit))
+        .delete(recurrence::delete);
+    app.at("/task/:task_id/time")
+        .with(Authorize::new(Permission::View))
+        .get(time_entry::get_by_task);
+    app.at("/task/:task_id/time")
+        .with(Authorize::new(Permission::Edit))
+        .post(time_entry::insert);
+    app.at("/time/stop")
+        .with(Authorize::new(Permission::Edit))
+        .post(time_entry::stop);
+    app.at("/time/:entry_id")
+        .with(Authorize::new(Permission::Edit))
+        .delete(time_entry::delete);
+    app.at("/checklist/:item_id")
+        .with(Authorize::new(Permission::Edit))
+        .delete(checklist::delete);
+    app.at("/comment/:comment_id")
+        .with(Authorize::new(Permission::Edit))
+        .delete(comment::delete);
 
-    app.at("/entity").post(entity::insert);
-    app.at("/entity/add").get(entity::add);
-    app.at("/entity/:entity_id").get(entity::get);
-    app.at("/entity/:entity_id").delete(entity::delete);
+    app.at("/entity")
+        .with(Authorize::new(Permission::Edit))
+        .post(entity::insert);
+    app.at("/entity/add")
+        .with(Authorize::new(Permission::Edit))
+        .get(entity::add);
+    app.at("/entity/:entity_id")
+        .with(Authorize::new(Permission::View))
+        .get(entity::get);
+    app.at("/entity/:entity_id")
+        .with(Authorize::new(Permission::Delete))
+        .delete(entity::delete);
+    app.at("/entity/:entity_id/time")
+        .with(Authorize::new(Permission::View))
+        .get(time_entry::report);
     app.at("/entity/invoices/:entity_id/:external_id")
+        .with(Authorize::new(Permission::View))
         .get(entity::get_invoices);
 
-    app.at("/contact").post(entity::insert_contact_route);
-    app.at("/contact/add/:entity_id").get(entity::add_contact);
+    app.at("/contact")
+        .with(Authorize::new(Permission::Edit))
+        .post(entity::insert_contact_route);
+    app.at("/contact/add/:entity_id")
+        .with(Authorize::new(Permission::Edit))
+        .get(entity::add_contact);
     app.at("/contact/:contact_id")
+        .with(Authorize::new(Permission::View))
         .get(entity::get_contact_route);
     app.at("/contact/:contact_id")
+        .with(Authorize::new(Permission::Delete))
         .delete(entity::delete_contact_route);
 
-    app.at("/board").post(board::insert);
-    app.at("/board/add").get(board::add);
-    app.at("/board/:board_id").get(board::get);
-    app.at("/board/:board_id").delete(board::delete);
+    app.at("/board")
+        .with(Authorize::new(Permission::Edit))
+        .post(board::insert);
+    app.at("/board/add")
+        .with(Authorize::new(Permission::Edit))
+        .get(board::add);
+    app.at("/board/:board_id")
+        .with(Authorize::new(Permission::View))
+        .get(board::get);
+    app.at("/board/:board_id")
+        .with(Authorize::new(Permission::Delete))
+        .delete(board::delete);
 
-    app.at("/service_item").post(service_item::insert);
-    app.at("/service_item/add").get(service_item::add);
+    app.at("/service_item")
+        .with(Authorize::new(Permission::Edit))
+        .post(service_item::insert);
+    app.at("/service_item/add")
+        .with(Authorize::new(Permission::Edit))
+        .get(service_item::add);
     app.at("/service_item/:service_item_id")
+        .with(Authorize::new(Permission::View))
         .get(service_item::get);
     app.at("/service_item/:service_item_id")
+        .with(Authorize::new(Permission::Delete))
         .delete(service_item::delete);
 
-    app.at("/organization").post(organization::insert);
+    app.at("/organization")
+        .with(Authorize::new(Permission::Administer))
+        .post(organization::insert);
+    app.at("/organization/switch")
+        .with(Authorize::new(Permission::View))
+        .post(organization::switch);
     app.at("/organization/:organization_id")
+        .with(Authorize::new(Permission::View))
         .get(organization::get);
     app.at("/organization/:organization_id")
+        .with(Authorize::new(Permission::Own))
         .delete(organization::delete);
+    app.at("/organization/:organization_id/members/:user_id")
+        .with(Authorize::new(Permission::Administer))
+        .post(member::update_role);
+    app.at("/workflow")
+        .with(Authorize::new(Permission::View))
+        .get(workflow::get);
+    app.at("/workflow/status")
+        .with(Authorize::new(Permission::Administer))
+        .post(workflow::insert_status);
+    app.at("/workflow/status/:status_id")
+        .with(Authorize::new(Permission::Administer))
+        .delete(workflow::delete_status);
+    app.at("/workflow/transition")
+        .with(Authorize::new(Permission::Administer))
+        .post(workflow::insert_transition);
+    app.at("/workflow/transition/:transition_id")
+        .with(Authorize::new(Permission::Administer))
+        .delete(workflow::delete_transition);
+    app.at("/invite")
+        .with(Authorize::new(Permission::Administer))
+        .get(invitation::get);
+    app.at("/invite")
+        .with(Authorize::new(Permission::Administer))
+        .post(invitation::insert);
+    app.at("/invite/:invitation_id")
+        .with(Authorize::new(Permission::Administer))
+        .delete(invitation::delete);
 
-    app.at("/milestone").post(milestone::insert);
-    app.at("/milestone/add/:project_id").get(milestone::add);
-    app.at("/milestone/:milestone_id").get(milestone::get);
-    app.at("/milestone/:milestone_id").delete(milestone::delete);
+    app.at("/milestone")
+        .with(Authorize::new(Permission::Edit))
+        .post(milestone::insert);
+    app.at("/milestone/add/:project_id")
+        .with(Authorize::new(Permission::Edit))
+        .get(milestone::add);
+    app.at("/milestone/:milestone_id")
+        .with(Authorize::new(Permission::View))
+        .get(milestone::get);
+    app.at("/milestone/:milestone_id")
+        .with(Authorize::new(Permission::Delete))
+        .delete(milestone::delete);
+    app.at("/milestone/:milestone_id/outcome")
+        .with(Authorize::new(Permission::Edit))
+        .post(milestone::check_outcome);
 
-    app.at("/akaunting").post(akaunting::save_akaunting_options);
     app.at("/akaunting")
+        .with(Authorize::new(Permission::Administer))
+        .post(akaunting::save_akaunting_options);
+    app.at("/akaunting")
+        .with(Authorize::new(Permission::Administer))
         .get(akaunting::get_akaunting_options_page);
     app.at("/akaunting/import_item")
+        .with(Authorize::new(Permission::Administer))
         .post(akaunting::import_item);
     app.at("/akaunting/import_customer")
+        .with(Authorize::new(Permission::Administer))
         .post(akaunting::import_customer);
 
-    app.at("/file").post(file::insert);
+    app.at("/file")
+        .with(Authorize::new(Permission::Edit))
+        .post(file::insert);
     app.at("/file/add/:association_type/:association_id")
+        .with(Authorize::new(Permission::Edit))
         .get(file::add);
-    app.at("/file/:file_id").get(file::get);
-    app.at("/file/:file_id").delete(file::delete);
+    app.at("/file/:file_id")
+        .with(Authorize::new(Permission::View))
+        .get(file::get);
+    app.at("/file/:file_id")
+        .with(Authorize::new(Permission::Delete))
+        .delete(file::delete);
 
-    app.at("/note").post(note::insert);
+    app.at("/note")
+        .with(Authorize::new(Permission::Edit))
+        .post(note::insert);
     app.at("/note/add/:association_type/:association_id")
+        .with(Authorize::new(Permission::Edit))
         .get(note::add);
-    app.at("/note/:note_id").get(note::get);
-    app.at("/note/:note_id").delete(note::delete);
+    app.at("/note/:note_id")
+        .with(Authorize::new(Permission::View))
+        .get(note::get);
+    app.at("/note/:note_id")
+        .with(Authorize::new(Permission::Delete))
+        .delete(note::delete);
 
     app.at("/fs/*").get(serve_dir);
     app.at("/files/:format/:organization_id/:association_type/:association_id/:name")
+        .with(Authorize::new(Permission::View))
         .get(serve_s3);
     // app.at("/fs").serve_dir("./assets")?;
 
+    app.at("/api/openapi.json").get(openapi::get);
+    app.at("/api/v1/search")
+        .with(Authorize::new(Permission::View))
+        .get(search::get_json);
+    api::resource::<organization::Organization>(&mut app, "/api/v1/organizations", Permission::Administer, Permission::Administer, Permission::Own);
+    api::resource::<project::Project>(&mut app, "/api/v1/projects", Permission::Edit, Permission::Edit, Permission::Delete);
+    api::resource::<milestone::Milestone>(&mut app, "/api/v1/milestones", Permission::Edit, Permission::Edit, Permission::Delete);
+    api::resource::<task::Task>(&mut app, "/api/v1/tasks", Permission::Edit, Permission::Edit, Permission::Delete);
+    api::resource::<board::Board>(&mut app, "/api/v1/boards", Permission::Edit, Permission::Edit, Permission::Delete);
+    api::resource::<entity::Entity>(&mut app, "/api/v1/entities", Permission::Edit, Permission::Edit, Permission::Delete);
+    api::resource::<entity::Contact>(&mut app, "/api/v1/contacts", Permission::Edit, Permission::Edit, Permission::Delete);
+    api::resource::<service_item::ServiceItem>(&mut app, "/api/v1/service_items", Permission::Edit, Permission::Edit, Permission::Delete);
+    api::resource::<note::Note>(&mut app, "/api/v1/notes", Permission::Edit, Permission::Edit, Permission::Delete);
+    api::resource::<file::File>(&mut app, "/api/v1/files", Permission::Edit, Permission::Edit, Permission::Delete);
+
+    app
+}
+
+#[tokio::main]
+async fn main() -> tide::Result<()> {
+    dotenv().ok();
+    let db_url = std::env::var("DATABASE_URL")
+        .expect("Missing `DATABASE_URL` env variable, needed for running the server");
+
+    let db_pool: PgPool = Pool::connect(&db_url).await.unwrap();
+
+    // schema lives in ./migrations and is embedded at compile time, applied in order on every start
+    sqlx::migrate!("./migrations")
+        .run(&db_pool)
+        .await
+        .expect("Applying database migrations");
+    if std::env::args().any(|a| a == "--migrate-only") {
+        log!("Migrations applied, exiting (--migrate-only)");
+        return Ok(());
+    }
+
+    tokio::spawn(recurrence::schedule(db_pool.clone()));
+
+    let app = server(State { db_pool });
     app.listen("0.0.0.0:8080").await?;
     Ok(())
 }
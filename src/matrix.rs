@@ -18,14 +18,21 @@ use serde::{Deserialize, Serialize};
 use sqlx::{pool::PoolConnection, postgres::PgQueryResult, Postgres};
 use url::Url;
 
+use crate::error::AppError;
 use crate::{
-    board::Board, entity::{Entity, Contact}, file::File, milestone::Milestone, project::Project, task::Task, note::Note,
+    board::Board, entity::{Entity, Contact}, file::File, milestone::{Milestone, MilestoneOutcome}, project::Project, task::Task, note::Note,
 };
 
 // authentication, messaging and server management stuff for matrix.
 
+#[allow(dead_code)]
 const INITIAL_DEVICE_DISPLAY_NAME: &str = "Kinbrio-client";
 
+// what the homeserver said goes to the log, the caller only learns matrix is unavailable
+fn unavailable<E: fmt::Display>(e: E) -> AppError {
+    AppError::Matrix(e.to_string())
+}
+
 pub struct Choice {
     pub url: String,
     pub display: String,
@@ -34,16 +41,14 @@ pub struct Choice {
 pub async fn get_login_urls(
     homeserver_url: String,
     redirect_url: String,
-) -> Result<Vec<Choice>, matrix_sdk::Error> {
-    let homeserver_url = Url::parse(&homeserver_url).expect("Url Correct");
-    let client = Client::new(homeserver_url)
-        .await
-        .expect("Matrix Server Connecting");
+) -> Result<Vec<Choice>, AppError> {
+    let homeserver_url = Url::parse(&homeserver_url).map_err(unavailable)?;
+    let client = Client::new(homeserver_url).await.map_err(unavailable)?;
     let mut choices = Vec::new();
     let login_types = client
         .get_login_types()
         .await
-        .expect("Login types found")
+        .map_err(unavailable)?
         .flows;
     for login_type in login_types {
         match login_type {
@@ -54,7 +59,7 @@ pub async fn get_login_urls(
                     choices.extend(sso.identity_providers.into_iter().map(LoginChoice::SsoIdp))
                 }
             }
-            LoginType::Password(t) => {
+            LoginType::Password(_) => {
                 choices.push(LoginChoice::Password)
             }
             LoginType::Token(_) | _ => {}
@@ -62,10 +67,7 @@ pub async fn get_login_urls(
     }
     let mut urls = vec![];
     for c in &choices {
-        let u = c
-            .login(&client, redirect_url.clone())
-            .await
-            .expect("login URL fails");
+        let u = c.login(&client, redirect_url.clone()).await?;
         urls.push(Choice {
             url: u.clone(),
             display: c.to_string(),
@@ -86,7 +88,7 @@ pub enum LoginChoice {
 
 impl LoginChoice {
     /// Login with this login choice.
-    async fn login(&self, client: &Client, redirect: String) -> anyhow::Result<String> {
+    async fn login(&self, client: &Client, redirect: String) -> Result<String, AppError> {
         match self {
             LoginChoice::Password => login_with_password_url(client),
             LoginChoice::Sso => login_with_sso_url(client, redirect, None).await,
@@ -94,11 +96,12 @@ impl LoginChoice {
         }
     }
 
+    #[allow(dead_code)]
     fn get_icon_mxc(&self) -> String {
         match self {
             LoginChoice::Password =>  "/fs/images/sso/user_password.svg".to_string(),
             LoginChoice::Sso => "hmm".to_string(),
-            LoginChoice::SsoIdp(idp) => idp.icon.as_ref().expect("get icon URL").to_string(),
+            LoginChoice::SsoIdp(idp) => idp.icon.as_ref().map(|i| i.to_string()).unwrap_or_default(),
         }
     }
 
@@ -107,7 +110,7 @@ impl LoginChoice {
             LoginChoice::Password =>  "/fs/images/sso/user_password.svg".to_string(),
             LoginChoice::Sso => "hmm".to_string(),
             LoginChoice::SsoIdp(idp) => {
-                let mxc_uri = idp.icon.as_ref().expect("get icon URL").to_string();
+                let mxc_uri = idp.icon.as_ref().map(|i| i.to_string()).unwrap_or_default();
                 mxc_uri.replace("mxc://", "https://matrix.org/_matrix/media/r0/download/")
             },
         }
@@ -125,7 +128,7 @@ impl fmt::Display for LoginChoice {
 }
 
 /// Login with a username and password.
-fn login_with_password_url(client: &Client) -> anyhow::Result<String> {
+fn login_with_password_url(_client: &Client) -> Result<String, AppError> {
     return Ok("/login_by_username".to_string())
 }
 
@@ -133,12 +136,10 @@ fn login_with_password_url(client: &Client) -> anyhow::Result<String> {
 pub(crate) async fn restore_from_session(
     homeserver_url: String,
     session: Session,
-) -> anyhow::Result<()> {
-    let homeserver_url = Url::parse(&homeserver_url).expect("URL parsing");
-    let client = Client::new(homeserver_url)
-        .await
-        .expect("Matrix server connection");
-    client.restore_login(session).await.expect("Restore login");
+) -> Result<(), AppError> {
+    let homeserver_url = Url::parse(&homeserver_url).map_err(unavailable)?;
+    let client = Client::new(homeserver_url).await.map_err(unavailable)?;
+    client.restore_login(session).await.map_err(unavailable)?;
     Ok(())
 }
 
@@ -146,13 +147,11 @@ pub(crate) async fn login_with_password(
     homeserver_url: String,
     uid: String,
     password: String,
-) -> anyhow::Result<login::v3::Response> {
-    let homeserver_url = Url::parse(&homeserver_url).expect("URL parsing");
-    let client = Client::new(homeserver_url)
-        .await
-        .expect("Matrix server connection");
-    let login_builder = client.login_username(uid.as_str(), password.as_str()).send().await.expect("logged in");
-           
+) -> Result<login::v3::Response, AppError> {
+    let homeserver_url = Url::parse(&homeserver_url).map_err(unavailable)?;
+    let client = Client::new(homeserver_url).await.map_err(unavailable)?;
+    let login_builder = client.login_username(uid.as_str(), password.as_str()).send().await.map_err(unavailable)?;
+
     Ok(login_builder)
 }
 
@@ -161,19 +160,18 @@ pub(crate) async fn account(
     homeserver_url: String,
     token: String,
     user_id:OwnedUserId,
-) -> (std::string::String, std::string::String, std::string::String) {
-    let homeserver_url = Url::parse(&homeserver_url).expect("URL parsing");
-    let client = Client::new(homeserver_url)
-        .await
-        .expect("Matrix server connection"); 
+) -> Result<(String, String, String), AppError> {
+    let homeserver_url = Url::parse(&homeserver_url).map_err(unavailable)?;
+    let client = Client::new(homeserver_url).await.map_err(unavailable)?;
     client.restore_login(Session {
         access_token: token,
         refresh_token: None,
-        user_id: OwnedUserId::from(user_id),
+        user_id,
         device_id: device_id!("kinbrio").to_owned(),
-    }).await.expect("Restore");
-    let avatar = client.account().get_avatar_url().await.expect("Get avatar URL").expect("Unroll").to_string();
-    let threepids = client.account().get_3pids().await.expect("Get 3pid").threepids;
+    }).await.map_err(unavailable)?;
+    // not every account has an avatar
+    let avatar = client.account().get_avatar_url().await.map_err(unavailable)?.map(|a| a.to_string()).unwrap_or_default();
+    let threepids = client.account().get_3pids().await.map_err(unavailable)?.threepids;
     let mut addy = String::default();
     for pid in threepids {
         addy = match pid.medium {
@@ -181,24 +179,22 @@ pub(crate) async fn account(
             matrix_sdk::ruma::thirdparty::Medium::Msisdn => pid.address,
             _ => String::default(),
         };
-        if addy.len() > 0 {
+        if !addy.is_empty() {
             break;
         }
     };
-    let display_name = client.account().get_profile().await.expect("get prorfile").displayname.unwrap_or_default();
-    (addy, avatar, display_name)
+    let display_name = client.account().get_profile().await.map_err(unavailable)?.displayname.unwrap_or_default();
+    Ok((addy, avatar, display_name))
 }
 
 pub(crate) async fn login_with_token(
     homeserver_url: String,
     token: String,
-) -> anyhow::Result<login::v3::Response> {
-    let homeserver_url = Url::parse(&homeserver_url).expect("URL parsing");
-    let client = Client::new(homeserver_url)
-        .await
-        .expect("Matrix server connection");
-    
-    let login_builder = client.login_token(&token).send().await?;
+) -> Result<login::v3::Response, AppError> {
+    let homeserver_url = Url::parse(&homeserver_url).map_err(unavailable)?;
+    let client = Client::new(homeserver_url).await.map_err(unavailable)?;
+
+    let login_builder = client.login_token(&token).send().await.map_err(unavailable)?;
     Ok(login_builder)
 }
 
@@ -206,16 +202,17 @@ async fn login_with_sso_url(
     client: &Client,
     redirect: String,
     idp: Option<&IdentityProvider>,
-) -> anyhow::Result<String> {
-    
+) -> Result<String, AppError> {
+    // plain SSO has no provider to pick, the homeserver asks
     let login_builder = client
-        .get_sso_login_url(&redirect, Some(&idp.expect("get provider").id))
+        .get_sso_login_url(&redirect, idp.map(|i| i.id.as_str()))
         .await
-        .expect("Get provider");
+        .map_err(unavailable)?;
 
     Ok(login_builder)
 }
 
+#[allow(dead_code)]
 pub async fn delete_room(
     conn: &mut PoolConnection<Postgres>,
     key: uuid::Uuid,
@@ -245,7 +242,7 @@ pub struct Room {
 pub async fn get_rooms(
     conn: &mut PoolConnection<Postgres>,
     organization_key: uuid::Uuid,
-) -> Vec<Room> {
+) -> Result<Vec<Room>, AppError> {
     let room_records = sqlx::query!(
         "select 
         key, 
@@ -263,29 +260,29 @@ pub async fn get_rooms(
         organization_key
     )
     .fetch_all(conn)
-    .await
-    .expect("Select room by key");
+    .await?;
     let mut rooms = vec![];
     for room in room_records {
-        let m_type: MessageDataType = room.message_types.expect("message_types exists").into();
+        let m_type: MessageDataType = room.message_types.into();
         rooms.push(Room {
-            key: room.key.expect("key exists"),
-            owner_key: room.owner_key.expect("owner_key exists"),
-            organization_key: room.organization_key.expect("organization_key exists"),
-            name: room.name.expect("name exists"),
-            description: room.description.expect("description exists"),
-            matrix_room_url: room.matrix_room_url.expect("matrix_room_url exists"),
-            matrix_room_id: room.matrix_room_id.expect("matrix_room_id exists"),
+            key: room.key,
+            owner_key: room.owner_key,
+            organization_key: room.organization_key,
+            name: room.name,
+            description: room.description,
+            matrix_room_url: room.matrix_room_url,
+            matrix_room_id: room.matrix_room_id,
             message_types: m_type,
-            alert_level: room.alert_level.expect("alert_level exists"),
-            created: room.created.expect("created exists"),
-            updated: room.updated.expect("updated exists"),
+            alert_level: room.alert_level,
+            created: room.created,
+            updated: room.updated,
         });
     }
-    rooms
+    Ok(rooms)
 }
 
-pub async fn get_room(conn: &mut PoolConnection<Postgres>, key: uuid::Uuid) -> Room {
+#[allow(dead_code)]
+pub async fn get_room(conn: &mut PoolConnection<Postgres>, key: uuid::Uuid) -> Result<Room, AppError> {
     let room = sqlx::query!(
         "select 
         key, 
@@ -303,24 +300,24 @@ pub async fn get_room(conn: &mut PoolConnection<Postgres>, key: uuid::Uuid) -> R
         key
     )
     .fetch_one(conn)
-    .await
-    .expect("Select room by key");
-    Room {
-        key: room.key.expect("key exists"),
-        owner_key: room.owner_key.expect("owner_key exists"),
-        organization_key: room.organization_key.expect("organization_key exists"),
-        name: room.name.expect("name exists"),
-        description: room.description.expect("description exists"),
-        matrix_room_url: room.matrix_room_url.expect("matrix_room_url exists"),
-        matrix_room_id: room.matrix_room_id.expect("matrix_room_id exists"),
+    .await?;
+    Ok(Room {
+        key: room.key,
+        owner_key: room.owner_key,
+        organization_key: room.organization_key,
+        name: room.name,
+        description: room.description,
+        matrix_room_url: room.matrix_room_url,
+        matrix_room_id: room.matrix_room_id,
         message_types: MessageDataType::All,
-        alert_level: room.alert_level.expect("alert_level exists"),
-        created: room.created.expect("created exists"),
-        updated: room.updated.expect("updated exists"),
-    }
+        alert_level: room.alert_level,
+        created: room.created,
+        updated: room.updated,
+    })
 }
 
-pub async fn insert_room(conn: &mut PoolConnection<Postgres>, new_room: &Room) {
+#[allow(dead_code)]
+pub async fn insert_room(conn: &mut PoolConnection<Postgres>, new_room: &Room) -> Result<(), AppError> {
     let t = new_room.message_types as i16;
     sqlx::query!(
         "INSERT INTO rooms (
@@ -348,8 +345,8 @@ pub async fn insert_room(conn: &mut PoolConnection<Postgres>, new_room: &Room) {
         new_room.updated,
     )
     .execute(conn)
-    .await
-    .expect("Insert Success");
+    .await?;
+    Ok(())
 }
 
 #[derive(PartialEq, Debug, Deserialize, Serialize, Clone, Copy, sqlx::Type)]
@@ -402,6 +399,7 @@ impl From<MessageDataType> for i16 {
     }
 }
 
+#[allow(dead_code)]
 enum MessageActionType {
     All,
     Create,
@@ -420,7 +418,11 @@ async fn message(
     _action_type: MessageActionType,
     msg: String,
 ) -> Result<(), anyhow::Error> {
-    let rooms = get_rooms(conn, organization_id).await;
+    // requests made with an API token have no matrix session to post as
+    if token.is_empty() {
+        return Ok(());
+    }
+    let rooms = get_rooms(conn, organization_id).await?;
     for room in rooms {
         if room.message_types == data_type {
             send_room_message(
@@ -430,7 +432,7 @@ async fn message(
                 token.clone(),
                 room.matrix_room_id.clone(),
                 msg.clone(),
-            ).await.expect("message sent");
+            ).await?;
         }
     }
     Ok(())
@@ -444,7 +446,7 @@ async fn send_room_message(
     msg: String,
 ) -> Result<(), anyhow::Error> {
     let user_id_str = matrix_user_id.to_string();
-    let user_id = <&UserId>::try_from(user_id_str.as_str()).expect("parse user id");
+    let user_id = <&UserId>::try_from(user_id_str.as_str())?;
     let homeserver_url =
         Url::parse(&homeserver_url).unwrap_or(Url::parse("https://matrix-client.matrix.org")?);
     let client = Client::new(homeserver_url).await?;
@@ -455,19 +457,22 @@ async fn send_room_message(
         user_id: OwnedUserId::from(user_id),
         device_id: device_id!("kinbrio").to_owned(),
     };
-    client.restore_login(session).await.expect("Send login");
+    client.restore_login(session).await?;
     client
         .sync_once(SyncSettings::default())
-        .await
-        .expect("sync");
-    let room_id = <&RoomId>::try_from(room_id.as_str()).expect("parse room id");
-    let room = client.get_joined_room(room_id).expect("Retrieve room");
+        .await?;
+    let room_id = <&RoomId>::try_from(room_id.as_str())?;
+    let room = match client.get_joined_room(room_id) {
+        Some(room) => room,
+        None => return Err(anyhow::anyhow!("not joined to room {}", room_id)),
+    };
 
     let content = RoomMessageEventContent::text_plain(msg);
-    room.send(content, None).await.expect("Send room");
+    room.send(content, None).await?;
     Ok(())
 }
 
+#[allow(dead_code)]
 pub(crate) async fn post_room_create(
     conn: &mut PoolConnection<Postgres>,
     homeserver_url: String,
@@ -548,6 +553,47 @@ pub(crate) async fn post_milestone_create(
     Ok(())
 }
 
+pub(crate) async fn post_milestone_outcome(
+    conn: &mut PoolConnection<Postgres>,
+    homeserver_url: String,
+    matrix_user_id: String,
+    organization_id: uuid::Uuid,
+    token: String,
+    milestone: &Milestone,
+    outcome: MilestoneOutcome,
+    done: usize,
+    total: usize,
+) -> Result<(), anyhow::Error> {
+    let (msg, action) = match outcome {
+        MilestoneOutcome::Missed => (
+            format!(
+                "Milestone Missed ⏰ \n {}\n {} of {} tasks done\n  https://kinbrio.com/milestone/{}",
+                milestone.name, done, total, milestone.key
+            ),
+            MessageActionType::Update,
+        ),
+        _ => (
+            format!(
+                "Milestone Hit 🎯 \n {}\n all {} tasks done\n  https://kinbrio.com/milestone/{}",
+                milestone.name, total, milestone.key
+            ),
+            MessageActionType::Complete,
+        ),
+    };
+    message(
+        conn,
+        matrix_user_id,
+        organization_id,
+        homeserver_url,
+        token,
+        MessageDataType::Milestone,
+        action,
+        msg,
+    )
+    .await?;
+    Ok(())
+}
+
 pub(crate) async fn post_project_create(
     conn: &mut PoolConnection<Postgres>,
     homeserver_url: String,
@@ -710,3 +756,31 @@ pub(crate) async fn post_task_create(
     .await?;
     Ok(())
 }
+
+pub(crate) async fn post_task_move(
+    conn: &mut PoolConnection<Postgres>,
+    homeserver_url: String,
+    matrix_user_id: String,
+    organization_id: uuid::Uuid,
+    token: String,
+    task: &Task,
+    from: String,
+    to: String,
+) -> Result<(), anyhow::Error> {
+    let msg = format!(
+        "Task Moved 🚚 \n {}\n {} → {}\n  https://kinbrio.com/task/{}",
+        task.name, from, to, task.key
+    );
+    message(
+        conn,
+        matrix_user_id,
+        organization_id,
+        homeserver_url,
+        token,
+        MessageDataType::Task,
+        MessageActionType::Update,
+        msg,
+    )
+    .await?;
+    Ok(())
+}
@@ -0,0 +1,292 @@
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+use sqlx::pool::PoolConnection;
+use sqlx::postgres::PgQueryResult;
+use sqlx::{PgConnection, Postgres};
+use strum_macros::EnumIter;
+use uuid::Uuid;
+
+use tide::{http::mime, Request};
+
+use crate::error::AppError;
+use crate::home::NotFoundTemplate;
+use crate::{user, State};
+
+// SQL STUFF
+
+pub async fn get_role(
+    conn: &mut PoolConnection<Postgres>,
+    organization_key: uuid::Uuid,
+    user_key: uuid::Uuid,
+) -> Option<Role> {
+    match sqlx::query!(
+        "select role from organization_members where organization_key = $1 AND user_key = $2",
+        organization_key,
+        user_key
+    )
+    .fetch_one(conn)
+    .await
+    {
+        Ok(member) => Some(member.role.into()),
+        Err(_) => None,
+    }
+}
+
+pub async fn get_organization_members(
+    conn: &mut PoolConnection<Postgres>,
+    organization_key: uuid::Uuid,
+) -> Result<Vec<Member>, AppError> {
+    let records = sqlx::query!(
+        "select key, organization_key, user_key, role, created, updated from organization_members where organization_key = $1",
+        organization_key
+    )
+    .fetch_all(conn)
+    .await?;
+    let mut members = vec![];
+    for member in records {
+        members.push(Member {
+            key: member.key,
+            organization_key: member.organization_key,
+            user_key: member.user_key,
+            role: member.role.into(),
+            created: member.created,
+            updated: member.updated,
+        })
+    }
+    Ok(members)
+}
+
+pub async fn get_user_memberships(
+    conn: &mut PoolConnection<Postgres>,
+    user_key: uuid::Uuid,
+) -> Result<Vec<Member>, AppError> {
+    let records = sqlx::query!(
+        "select key, organization_key, user_key, role, created, updated from organization_members where user_key = $1 order by created",
+        user_key
+    )
+    .fetch_all(conn)
+    .await?;
+    let mut members = vec![];
+    for member in records {
+        members.push(Member {
+            key: member.key,
+            organization_key: member.organization_key,
+            user_key: member.user_key,
+            role: member.role.into(),
+            created: member.created,
+            updated: member.updated,
+        })
+    }
+    Ok(members)
+}
+
+pub(crate) async fn insert_member(conn: &mut PgConnection, new_member: &Member) -> Result<(), AppError> {
+    sqlx::query!("INSERT INTO organization_members (key, organization_key, user_key, role, created, updated) values($1, $2, $3, $4, $5, $6)",
+        new_member.key,
+        new_member.organization_key,
+        new_member.user_key,
+        new_member.role as i16,
+        new_member.created,
+        new_member.updated,
+    )
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+pub(crate) async fn delete_member(
+    conn: &mut PoolConnection<Postgres>,
+    organization_key: uuid::Uuid,
+    user_key: uuid::Uuid,
+) -> Result<PgQueryResult, sqlx::Error> {
+    return sqlx::query!(
+        "DELETE FROM organization_members where organization_key=$1 AND user_key=$2",
+        organization_key,
+        user_key
+    )
+    .execute(conn)
+    .await;
+}
+
+async fn update_member_role(
+    conn: &mut PoolConnection<Postgres>,
+    organization_key: uuid::Uuid,
+    user_key: uuid::Uuid,
+    role: Role,
+) -> Result<PgQueryResult, sqlx::Error> {
+    return sqlx::query!(
+        "UPDATE organization_members SET role=$1, updated=extract(epoch from now()) where organization_key=$2 AND user_key=$3",
+        role as i16,
+        organization_key,
+        user_key
+    )
+    .execute(conn)
+    .await;
+}
+
+async fn count_owners(conn: &mut PoolConnection<Postgres>, organization_key: uuid::Uuid) -> Result<i64, AppError> {
+    let record = sqlx::query!(
+        r#"select count(*) as "count!" from organization_members where organization_key = $1 AND role = $2"#,
+        organization_key,
+        Role::Owner as i16
+    )
+    .fetch_one(conn)
+    .await?;
+    Ok(record.count)
+}
+
+// Route Stuff
+
+pub async fn update_role(mut req: Request<State>) -> tide::Result {
+    let claims: user::UserJwtState = match user::request_claims(&req) {
+        Some(c) => c,
+        None => {
+            return Ok(tide::Redirect::new("/login").into());
+        }
+    };
+    let organization_key = match req.param("organization_id").map(uuid::Uuid::from_str) {
+        Ok(Ok(k)) => k,
+        _ => {
+            return Ok(tide::Response::builder(tide::StatusCode::NotFound)
+                .content_type(mime::HTML)
+                .body(NotFoundTemplate::new().render_string())
+                .build())
+        }
+    };
+    let user_key = match req.param("user_id").map(uuid::Uuid::from_str) {
+        Ok(Ok(k)) => k,
+        _ => {
+            return Ok(tide::Response::builder(tide::StatusCode::NotFound)
+                .content_type(mime::HTML)
+                .body(NotFoundTemplate::new().render_string())
+                .build())
+        }
+    };
+    if organization_key.to_string() != claims.organization_key {
+        return Ok(tide::Response::builder(tide::StatusCode::Forbidden)
+            .content_type(mime::PLAIN)
+            .body("FORBIDDEN")
+            .build());
+    }
+    let umd: Result<RoleUpdate, tide::Error> = req.body_json().await;
+    match umd {
+        Ok(update) => {
+            let mut conn = req.state().db_pool.acquire().await?;
+            let current = get_role(&mut conn, organization_key, user_key).await;
+            // only an owner can hand out or take away ownership
+            let touches_owner = update.role == Role::Owner || current == Some(Role::Owner);
+            if current.is_none() || (touches_owner && claims.role != Role::Owner) {
+                return Ok(tide::Response::builder(tide::StatusCode::Forbidden)
+                    .content_type(mime::PLAIN)
+                    .body("FORBIDDEN")
+                    .build());
+            }
+            // an organization always keeps an owner, the last one can't step down
+            if current == Some(Role::Owner) && update.role != Role::Owner && count_owners(&mut conn, organization_key).await? <= 1 {
+                return Err(AppError::Validation("an organization needs an owner, make someone else owner first".to_string()).into());
+            }
+            update_member_role(&mut conn, organization_key, user_key, update.role).await?;
+            let j = serde_json::to_string(&update).expect("To JSON");
+            Ok(tide::Response::builder(tide::StatusCode::Ok)
+                .content_type(mime::JSON)
+                .body(j)
+                .build())
+        }
+        Err(e) => Err(AppError::Validation(format!("invalid json body: {}", e)).into()),
+    }
+}
+
+// data types
+
+#[derive(PartialEq, Debug, Deserialize, Serialize, Clone, Copy, sqlx::Type, EnumIter, Default)]
+pub enum Role {
+    Owner,
+    Admin,
+    Member,
+    #[default]
+    Guest,
+    Client,
+}
+
+impl Into<Role> for i16 {
+    fn into(self) -> Role {
+        match self {
+            0 => Role::Owner,
+            1 => Role::Admin,
+            2 => Role::Member,
+            3 => Role::Guest,
+            4 => Role::Client,
+            _ => Role::Guest,
+        }
+    }
+}
+
+impl From<Role> for i16 {
+    fn from(r: Role) -> Self {
+        match r {
+            Role::Owner => 0,
+            Role::Admin => 1,
+            Role::Member => 2,
+            Role::Guest => 3,
+            Role::Client => 4,
+        }
+    }
+}
+
+impl FromStr for Role {
+    type Err = ();
+    fn from_str(input: &str) -> Result<Role, Self::Err> {
+        match input {
+            "Owner" => Ok(Role::Owner),
+            "Admin" => Ok(Role::Admin),
+            "Member" => Ok(Role::Member),
+            "Guest" => Ok(Role::Guest),
+            "Client" => Ok(Role::Client),
+            _ => Err(()),
+        }
+    }
+}
+
+impl ToString for Role {
+    fn to_string(&self) -> String {
+        match self {
+            Role::Owner => "Owner".to_owned(),
+            Role::Admin => "Admin".to_owned(),
+            Role::Member => "Member".to_owned(),
+            Role::Guest => "Guest".to_owned(),
+            Role::Client => "Client".to_owned(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct RoleUpdate {
+    pub role: Role,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Member {
+    pub key: uuid::Uuid,
+    pub organization_key: uuid::Uuid,
+    pub user_key: uuid::Uuid,
+    pub role: Role,
+    pub created: i64,
+    pub updated: i64,
+}
+
+impl Member {
+    pub fn new(organization_key: uuid::Uuid, user_key: uuid::Uuid, role: Role) -> Self {
+        let key = Uuid::new_v4();
+        let created = chrono::Utc::now().timestamp();
+        let updated = 0;
+        Self {
+            key,
+            organization_key,
+            user_key,
+            role,
+            created,
+            updated,
+        }
+    }
+}
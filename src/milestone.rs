@@ -1,82 +1,152 @@
-use std::str::FromStr;
-
 use askama::Template;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use sqlx::pool::PoolConnection;
-use sqlx::Postgres;
+use sqlx::{PgConnection, Postgres};
 use sqlx::postgres::PgQueryResult;
 use uuid::Uuid;
 
 use tide::{http::mime, Request};
 
+use crate::api::{report_announcement, report_matrix};
+use crate::error::AppError;
 use crate::home::NotFoundTemplate;
-use crate::matrix::post_milestone_create;
+use crate::matrix::{post_milestone_create, post_milestone_outcome};
+use crate::task::Task;
+use crate::workflow::Workflow;
 use crate::{State, user};
  
 // SQL STUFF
 
-pub async fn get_milestones_by_project(conn: &mut PoolConnection<Postgres>, project_key: uuid::Uuid) -> Vec::<Milestone> {
+pub async fn get_milestones_by_project(conn: &mut PoolConnection<Postgres>, project_key: uuid::Uuid, organization_key: uuid::Uuid) -> Result<Vec::<Milestone>, AppError> {
     let milestone_records = sqlx::query!(
-        "select key, organization_key, owner_key, project_key, name, description, tags, estimated_quarter_days, start, due, created, updated from mile_stones where project_key = $1",
-        project_key
+        "select key, organization_key, owner_key, project_key, name, description, tags, estimated_quarter_days, start, due, outcome, created, updated from mile_stones where project_key = $1 AND organization_key = $2",
+        project_key, organization_key
     )
     .fetch_all(conn)
-    .await
-    .expect("Select milestone by key");
+    .await?;
     let mut milestones = vec![];
     for milestone in milestone_records { 
         milestones.push(Milestone {
-            key: milestone.key.expect("key exists"),
-            organization_key: milestone.organization_key.expect("organization_key"), 
-            owner_key: milestone.owner_key.expect("owner_key exists"),
-            project_key: milestone.project_key.expect("project_key exists"),
-            name: milestone.name.expect("name exists"),
-            description: milestone.description.expect("description exists"),
-            tags: milestone.tags.expect("tags exists"),
-            estimated_quarter_days: milestone.estimated_quarter_days.expect("estimated_quarter_days exists"),
-            start: milestone.start.expect("start"),
-            due:milestone.due.expect("due"),
-            created: milestone.created.expect("created exists"),
-            updated: milestone.updated.expect("updated exists"),
+            key: milestone.key,
+            organization_key: milestone.organization_key, 
+            owner_key: milestone.owner_key,
+            project_key: milestone.project_key,
+            name: milestone.name,
+            description: milestone.description,
+            tags: milestone.tags,
+            estimated_quarter_days: milestone.estimated_quarter_days,
+            start: milestone.start,
+            due:milestone.due,
+            outcome: milestone.outcome.into(),
+            created: milestone.created,
+            updated: milestone.updated,
         })
     }
-    milestones
+    Ok(milestones)
 }
 
-pub async fn get_milestone(conn: &mut PoolConnection<Postgres>, key: uuid::Uuid) -> Milestone {
-    let milestone = sqlx::query!(
-        "select key, organization_key, owner_key, project_key, name, description, tags, estimated_quarter_days, start, due, created, updated from mile_stones where key = $1",
-        key
+pub async fn get_milestones_by_organization(conn: &mut PoolConnection<Postgres>, organization_key: uuid::Uuid) -> Result<Vec::<Milestone>, AppError> {
+    let milestone_records = sqlx::query!(
+        "select key, organization_key, owner_key, project_key, name, description, tags, estimated_quarter_days, start, due, outcome, created, updated from mile_stones where organization_key = $1",
+        organization_key
     )
-    .fetch_one(conn)
-    .await
-    .expect("Select milestone by key");
-
-    Milestone {
-        key: milestone.key.expect("key exists"),
-        organization_key: milestone.organization_key.expect("organization_key"), 
-        owner_key: milestone.owner_key.expect("owner_key exists"),
-        project_key: milestone.project_key.expect("project_key exists"),
-        name: milestone.name.expect("name exists"),
-        description: milestone.description.expect("description exists"),
-        tags: milestone.tags.expect("tags exists"),
-        estimated_quarter_days: milestone.estimated_quarter_days.expect("estimated_quarter_days exists"),
-        start: milestone.start.expect("start"),
-        due:milestone.due.expect("due"),
-        created: milestone.created.expect("created exists"),
-        updated: milestone.updated.expect("updated exists"),
+    .fetch_all(conn)
+    .await?;
+    let mut milestones = vec![];
+    for milestone in milestone_records { 
+        milestones.push(Milestone {
+            key: milestone.key,
+            organization_key: milestone.organization_key, 
+            owner_key: milestone.owner_key,
+            project_key: milestone.project_key,
+            name: milestone.name,
+            description: milestone.description,
+            tags: milestone.tags,
+            estimated_quarter_days: milestone.estimated_quarter_days,
+            start: milestone.start,
+            due:milestone.due,
+            outcome: milestone.outcome.into(),
+            created: milestone.created,
+            updated: milestone.updated,
+        })
     }
+    Ok(milestones)
 }
 
-async fn delete_milestone(conn: &mut PoolConnection<Postgres>, key: uuid::Uuid, owner_key: uuid::Uuid) -> Result<PgQueryResult, sqlx::Error> {
-    return sqlx::query!("DELETE FROM mile_stones where owner_key=$1 AND key=$2", owner_key, key)
+pub async fn get_milestones_by_organization_page(conn: &mut PoolConnection<Postgres>, organization_key: uuid::Uuid, limit: i64, offset: i64) -> Result<Vec::<Milestone>, AppError> {
+    let milestone_records = sqlx::query!(
+        "select key, organization_key, owner_key, project_key, name, description, tags, estimated_quarter_days, start, due, outcome, created, updated from mile_stones where organization_key = $1 order by created, key limit $2 offset $3",
+        organization_key, limit, offset
+    )
+    .fetch_all(conn)
+    .await?;
+    let mut milestones = vec![];
+    for milestone in milestone_records { 
+        milestones.push(Milestone {
+            key: milestone.key,
+            organization_key: milestone.organization_key, 
+            owner_key: milestone.owner_key,
+            project_key: milestone.project_key,
+            name: milestone.name,
+            description: milestone.description,
+            tags: milestone.tags,
+            estimated_quarter_days: milestone.estimated_quarter_days,
+            start: milestone.start,
+            due:milestone.due,
+            outcome: milestone.outcome.into(),
+            created: milestone.created,
+            updated: milestone.updated,
+        })
+    }
+    Ok(milestones)
+}
+
+pub async fn count_milestones_by_organization(conn: &mut PoolConnection<Postgres>, organization_key: uuid::Uuid) -> Result<i64, AppError> {
+    let record = sqlx::query!(r#"select count(*) as "count!" from mile_stones where organization_key = $1"#, organization_key)
+        .fetch_one(conn)
+        .await?;
+    Ok(record.count)
+}
+
+pub async fn get_milestone(conn: &mut PoolConnection<Postgres>, key: uuid::Uuid, organization_key: uuid::Uuid) -> Result<Option<Milestone>, AppError> {
+    let milestone = match sqlx::query!(
+        "select key, organization_key, owner_key, project_key, name, description, tags, estimated_quarter_days, start, due, outcome, created, updated from mile_stones where key = $1 AND organization_key = $2",
+        key, organization_key
+    )
+    .fetch_optional(conn)
+    .await?
+    {
+        Some(r) => r,
+        None => return Ok(None),
+    };
+
+    Ok(Some(Milestone {
+        key: milestone.key,
+        organization_key: milestone.organization_key, 
+        owner_key: milestone.owner_key,
+        project_key: milestone.project_key,
+        name: milestone.name,
+        description: milestone.description,
+        tags: milestone.tags,
+        estimated_quarter_days: milestone.estimated_quarter_days,
+        start: milestone.start,
+        due:milestone.due,
+        outcome: milestone.outcome.into(),
+        created: milestone.created,
+        updated: milestone.updated,
+    }))
+}
+
+pub(crate) async fn delete_milestone(conn: &mut PoolConnection<Postgres>, key: uuid::Uuid, organization_key: uuid::Uuid) -> Result<PgQueryResult, sqlx::Error> {
+    return sqlx::query!("DELETE FROM mile_stones where organization_key=$1 AND key=$2", organization_key, key)
     .execute(conn)
     .await;
 }
 
-async fn update_milestone(conn: &mut PoolConnection<Postgres>, board: &Milestone) {
+pub(crate) async fn update_milestone(conn: &mut PoolConnection<Postgres>, board: &Milestone) -> Result<(), AppError> {
     sqlx::query!("UPDATE mile_stones SET name=$1, description=$2, tags=$3, 
-    estimated_quarter_days=$4, start=$5, due=$6 where key=$7",
+    estimated_quarter_days=$4, start=$5, due=$6 where key=$7 AND organization_key=$8",
     &board.name,
     &board.description,
     &board.tags, 
@@ -84,13 +154,14 @@ async fn update_milestone(conn: &mut PoolConnection<Postgres>, board: &Milestone
     &board.start, 
     &board.due, 
     board.key,
+    board.organization_key,
 )
 .execute(conn)
-.await
-.expect("Insert Success");
+.await?;
+    Ok(())
 }
 
-async fn insert_milestone(conn: &mut PoolConnection<Postgres>, new_milestone: &Milestone) {
+pub(crate) async fn insert_milestone(conn: &mut PgConnection, new_milestone: &Milestone) -> Result<(), AppError> {
     sqlx::query!("INSERT INTO mile_stones (key, organization_key, project_key, owner_key, name, description, tags, estimated_quarter_days, start, due, created, updated) values($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12)", 
         new_milestone.key,
         new_milestone.organization_key, 
@@ -106,8 +177,79 @@ async fn insert_milestone(conn: &mut PoolConnection<Postgres>, new_milestone: &M
         new_milestone.updated,
     )
     .execute(conn)
-    .await
-    .expect("Insert Success");
+    .await?;
+    Ok(())
+}
+
+// Only moves the outcome on if nobody else has announced it in the meantime
+async fn set_outcome(
+    conn: &mut PoolConnection<Postgres>,
+    key: uuid::Uuid,
+    organization_key: uuid::Uuid,
+    from: MilestoneOutcome,
+    to: MilestoneOutcome,
+) -> Result<PgQueryResult, sqlx::Error> {
+    return sqlx::query!(
+        "UPDATE mile_stones SET outcome=$4 where key=$1 AND organization_key=$2 AND outcome=$3",
+        key,
+        organization_key,
+        from as i16,
+        to as i16
+    )
+    .execute(conn)
+    .await;
+}
+
+// Tells the organization's milestone rooms when a milestone is hit or missed. There's no
+// background job with a matrix session to post from, so this runs on the requests that can change
+// the answer (task edits, moves, milestone edits) and on the POST the pages that show it make when
+// they load, as whoever made them.
+pub(crate) async fn announce_outcome(
+    conn: &mut PoolConnection<Postgres>,
+    claims: &user::UserJwtState,
+    organization_key: uuid::Uuid,
+    milestone_key: Option<uuid::Uuid>,
+) -> Result<(), AppError> {
+    let milestone = match milestone_key {
+        Some(key) => match get_milestone(conn, key, organization_key).await? {
+            Some(m) => m,
+            None => return Ok(()),
+        },
+        None => return Ok(()),
+    };
+    let tasks = crate::task::get_tasks_by_project(conn, milestone.project_key, organization_key).await?;
+    let workflow = crate::workflow::get_workflow(conn, organization_key).await?;
+    let p = progress(&milestone, &tasks, &workflow, &[]);
+    let outcome = p.outcome();
+    if outcome == milestone.outcome {
+        return Ok(());
+    }
+    if set_outcome(conn, milestone.key, organization_key, milestone.outcome, outcome).await?.rows_affected() == 0 {
+        return Ok(());
+    }
+    // a task reopened under a finished milestone quietly puts it back to open
+    if outcome == MilestoneOutcome::Open {
+        return Ok(());
+    }
+    post_milestone_outcome(conn, claims.matrix_home_server.clone(), claims.matrix_user_id.clone(), organization_key, claims.matrix_access_token.clone(), &milestone, outcome, p.done, p.total)
+        .await
+        .map_err(|e| AppError::Matrix(e.to_string()))
+}
+
+// How far along a milestone is from the tasks counted towards it, any tasks can be passed in
+pub fn progress(milestone: &Milestone, tasks: &[Task], workflow: &Workflow, time: &[(uuid::Uuid, i64)]) -> Progress {
+    let counted: Vec<&Task> = tasks.iter().filter(|t| t.milestone_key == Some(milestone.key)).collect();
+    let estimated_quarter_days = match milestone.estimated_quarter_days {
+        0 => counted.iter().map(|t| t.estimated_quarter_days).sum(),
+        q => q,
+    };
+    Progress {
+        total: counted.len(),
+        done: counted.iter().filter(|t| workflow.is_done(&t.status_key)).count(),
+        estimated_quarter_days,
+        tracked_seconds: time.iter().filter(|(k, _)| counted.iter().any(|t| t.key == *k)).map(|(_, s)| *s).sum(),
+        due: milestone.due,
+    }
 }
 
 // Route Stuff
@@ -120,8 +262,8 @@ pub async fn delete(req: Request<State>) -> tide::Result {
     match req.param("milestone_id") {
         Ok(key) => {
             let mut conn = req.state().db_pool.acquire().await?;
-            let s_uuid = uuid::Uuid::from_str(key).expect("Milestone uuid parse");
-            match delete_milestone(&mut conn, s_uuid, u.key).await {
+            let s_uuid = crate::error::parse_key(key, "milestone")?;
+            match delete_milestone(&mut conn, s_uuid, u.organization_key).await {
                 Ok(_) =>    Ok(tide::Response::builder(tide::StatusCode::Ok)
                 .content_type(mime::HTML)
                 .build()),
@@ -142,7 +284,7 @@ pub async fn delete(req: Request<State>) -> tide::Result {
 }
 
 pub async fn get(req: Request<State>) -> tide::Result {
-    let u = match crate::user::read_jwt_cookie_to_user(req.cookie("token")) {
+    let u = match crate::user::request_user(&req) {
         Some(c) => c,
         None => {
             return Ok(tide::Redirect::new("/login").into());
@@ -151,8 +293,24 @@ pub async fn get(req: Request<State>) -> tide::Result {
     match req.param("milestone_id") {
         Ok(key) => {
             let mut conn = req.state().db_pool.acquire().await?; // .await? needs to be a real connection pool error handler!!!!!!!!
-            let s_uuid = uuid::Uuid::from_str(key).expect("Milestone uuid parse");
-            let milestone = get_milestone(&mut conn, s_uuid).await;
+            let s_uuid = crate::error::parse_key(key, "milestone")?;
+            let milestone = match get_milestone(&mut conn, s_uuid, u.organization_key).await? {
+                Some(m) => m,
+                None => {
+                    return Ok(tide::Response::builder(tide::StatusCode::NotFound)
+                        .content_type(mime::HTML)
+                        .body(NotFoundTemplate::new().render_string())
+                        .build())
+                }
+            };
+            let tasks: Vec<Task> = crate::task::get_tasks_by_project(&mut conn, milestone.project_key, u.organization_key)
+                .await?
+                .into_iter()
+                .filter(|t| t.milestone_key == Some(milestone.key))
+                .collect();
+            let time = crate::time_entry::get_project_time(&mut conn, milestone.project_key, u.organization_key).await?;
+            let workflow = crate::workflow::get_workflow(&mut conn, u.organization_key).await?;
+            let progress = progress(&milestone, &tasks, &workflow, &time);
 
             Ok(tide::Response::builder(tide::StatusCode::Ok)
                 .content_type(mime::HTML)
@@ -160,6 +318,9 @@ pub async fn get(req: Request<State>) -> tide::Result {
                     MilestoneTemplate::new(
                         milestone,
                         u,
+                        tasks,
+                        progress,
+                        workflow,
                     )
                     .render_string(),
                 )
@@ -177,14 +338,16 @@ pub async fn get(req: Request<State>) -> tide::Result {
 
 pub async fn insert(mut req: Request<State>) -> tide::Result {
     let umd: Result<Milestone, tide::Error> = req.body_json().await;
-    let claims: user::UserJwtState = match user::read_jwt_cookie(req.cookie("token")) {
+    let claims: user::UserJwtState = match user::request_claims(&req) {
         Some(c) => c,
         None => {
             return Ok(tide::Redirect::new("/login").into());
         },
     }; 
     match umd {
-        Ok(milestone) => {
+        Ok(mut milestone) => {
+            let organization_key = crate::error::parse_key(claims.organization_key.as_str(), "organization")?;
+            milestone.organization_key = organization_key;
             let mut conn = match req.state().db_pool.acquire().await {
                 Ok(c) => c,
                 Err(e) => {
@@ -196,19 +359,26 @@ pub async fn insert(mut req: Request<State>) -> tide::Result {
                     )
                 }
             };
+            if crate::project::get_project(&mut conn, milestone.project_key, organization_key).await?.is_none() {
+                return Ok(tide::Response::builder(tide::StatusCode::NotFound)
+                    .content_type(mime::HTML)
+                    .body(NotFoundTemplate::new().render_string())
+                    .build());
+            }
             
             if milestone.key == uuid::Uuid::nil() {
                 let s = Milestone::new(milestone.organization_key, milestone.owner_key, milestone.project_key, milestone.name, milestone.description, milestone.tags, milestone.estimated_quarter_days, milestone.start, milestone.due);
-                insert_milestone(&mut conn, &s).await;
-                let organization_key = uuid::Uuid::from_str(claims.organization_key.as_str()).expect("organization key");
-                post_milestone_create(&mut conn, claims.matrix_home_server, claims.matrix_user_id, organization_key, claims.matrix_access_token, &s).await.expect("Posting to matrix");
+                insert_milestone(&mut conn, &s).await?;
+                report_matrix(post_milestone_create(&mut conn, claims.matrix_home_server, claims.matrix_user_id, organization_key, claims.matrix_access_token, &s).await);
                 let j = serde_json::to_string(&s).expect("To JSON");
                 Ok(tide::Response::builder(tide::StatusCode::Ok)
                     .content_type(mime::JSON)
                     .body(j)
                     .build())
             } else {
-                update_milestone(&mut conn, &milestone).await;
+                update_milestone(&mut conn, &milestone).await?;
+                // moving the due date can turn a miss back into open, or the other way around
+                report_announcement(announce_outcome(&mut conn, &claims, organization_key, Some(milestone.key)).await);
                 let j = serde_json::to_string(&milestone).expect("To JSON");
                 return Ok(tide::Response::builder(tide::StatusCode::Ok)
                     .content_type(mime::JSON)
@@ -217,13 +387,7 @@ pub async fn insert(mut req: Request<State>) -> tide::Result {
             }
             
         }
-        Err(e) => {
-            println!("{:?}", e);
-            Ok(tide::Response::builder(tide::StatusCode::BadRequest)
-                .content_type(mime::JSON)
-                .body("{'error': 'invalid json body'}")
-                .build())
-        }
+        Err(e) => Err(AppError::Validation(format!("invalid json body: {}", e)).into()),
     }
 }
 
@@ -235,7 +399,7 @@ pub async fn add(req: Request<State>) -> tide::Result {
     
     match req.param("project_id") {
         Ok(project_id) => { 
-            let project_id = uuid::Uuid::from_str(project_id).expect("Project uuid parse");
+            let project_id = crate::error::parse_key(project_id, "project")?;
             let mut milestone= Milestone::new( u.organization_key, u.key, project_id,  "".to_owned(), "".to_owned(), "".to_owned(),  0, 0, 0);
             milestone.key = uuid::Uuid::nil();
             Ok(tide::Response::builder(tide::StatusCode::Ok)
@@ -244,6 +408,9 @@ pub async fn add(req: Request<State>) -> tide::Result {
                 MilestoneTemplate::new(
                     milestone,
                     u,
+                    vec![],
+                    Progress::default(),
+                    Workflow::default(),
                 )
                 .render_string(),
             )
@@ -258,9 +425,30 @@ pub async fn add(req: Request<State>) -> tide::Result {
         }
     }
 }
+// A milestone that ran out of time while nobody touched it is noticed here. The milestone and
+// project pages post to this when they load, so viewing them doesn't write anything by itself.
+pub async fn check_outcome(req: Request<State>) -> tide::Result {
+    let claims = match user::request_claims(&req) {
+        Some(c) => c,
+        None => return Err(AppError::Unauthorized.into()),
+    };
+    let key = crate::error::param_key(&req, "milestone_id")?;
+    let organization_key = crate::error::parse_key(claims.organization_key.as_str(), "organization")?;
+    let mut conn = req.state().db_pool.acquire().await?;
+    if get_milestone(&mut conn, key, organization_key).await?.is_none() {
+        return Err(AppError::NotFound("milestone".to_string()).into());
+    }
+    report_announcement(announce_outcome(&mut conn, &claims, organization_key, Some(key)).await);
+    let milestone = get_milestone(&mut conn, key, organization_key).await?;
+    Ok(tide::Response::builder(tide::StatusCode::Ok)
+        .content_type(mime::JSON)
+        .body(serde_json::to_string(&milestone).expect("To JSON"))
+        .build())
+}
+
 // data types
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
 pub struct Milestone {
     pub key: uuid::Uuid,
     pub organization_key: uuid::Uuid,
@@ -273,6 +461,8 @@ pub struct Milestone {
     pub estimated_quarter_days: i32,
     pub start: i64,
     pub due: i64,
+    #[serde(default)]
+    pub outcome: MilestoneOutcome,
     pub created: i64,
     pub updated: i64,
 }
@@ -303,30 +493,128 @@ impl Milestone {
             estimated_quarter_days,
             start,
             due,
+            outcome: MilestoneOutcome::Open,
             created, 
             updated,
         }
     }
 }
 
+#[derive(PartialEq, Debug, Default, Deserialize, Serialize, JsonSchema, Clone, Copy, sqlx::Type)]
+pub enum MilestoneOutcome {
+    #[default]
+    Open,
+    Hit,
+    Missed,
+}
+
+impl Into<MilestoneOutcome> for i16 {
+    fn into(self) -> MilestoneOutcome {
+        match self {
+            0 => MilestoneOutcome::Open,
+            1 => MilestoneOutcome::Hit,
+            2 => MilestoneOutcome::Missed,
+            _ => MilestoneOutcome::Open,
+        }
+    }
+}
+
+impl From<MilestoneOutcome> for i16 {
+    fn from(o: MilestoneOutcome) -> Self {
+        match o {
+            MilestoneOutcome::Open => 0,
+            MilestoneOutcome::Hit => 1,
+            MilestoneOutcome::Missed => 2,
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct Progress {
+    pub total: usize,
+    pub done: usize,
+    pub estimated_quarter_days: i32,
+    pub tracked_seconds: i64,
+    pub due: i64,
+}
+
+impl Progress {
+    pub fn percent(&self) -> usize {
+        if self.total == 0 {
+            return 0;
+        }
+        self.done * 100 / self.total
+    }
+
+    pub fn complete(&self) -> bool {
+        self.total > 0 && self.done == self.total
+    }
+
+    pub fn overdue(&self) -> bool {
+        !self.complete() && self.due > 0 && chrono::Utc::now().timestamp() > self.due
+    }
+
+    pub fn outcome(&self) -> MilestoneOutcome {
+        if self.complete() {
+            return MilestoneOutcome::Hit;
+        }
+        if self.overdue() {
+            return MilestoneOutcome::Missed;
+        }
+        MilestoneOutcome::Open
+    }
+
+    pub fn estimated_hours(&self) -> String {
+        crate::time_entry::hours(self.estimated_quarter_days as i64 * crate::time_entry::QUARTER_DAY_SECONDS)
+    }
+
+    pub fn tracked_hours(&self) -> String {
+        crate::time_entry::hours(self.tracked_seconds)
+    }
+
+    pub fn over_estimate(&self) -> bool {
+        self.estimated_quarter_days > 0 && self.tracked_seconds > self.estimated_quarter_days as i64 * crate::time_entry::QUARTER_DAY_SECONDS
+    }
+}
+
 #[derive(Template)]
 #[template(path = "milestone.html")]
 pub struct MilestoneTemplate {
     milestone: Milestone,
     user: crate::user::User,
+    tasks: Vec<Task>,
+    progress: Progress,
+    workflow: Workflow,
 }
 
 impl<'a> MilestoneTemplate {
     pub fn new(
         milestone: Milestone,
         user: crate::user::User,
+        tasks: Vec<Task>,
+        progress: Progress,
+        workflow: Workflow,
     ) -> Self {
         return Self {
             milestone,
             user,
+            tasks,
+            progress,
+            workflow,
         };
     }
 
+    pub fn status_name<'aa>(&'aa self, key: &uuid::Uuid) -> String {
+        self.workflow.name(key)
+    }
+
+    pub fn when<'aa>(&'aa self, timestamp: &i64) -> String {
+        match chrono::DateTime::from_timestamp(*timestamp, 0) {
+            Some(d) => d.format("%Y-%m-%d").to_string(),
+            None => "".to_string(),
+        }
+    }
+
     pub fn render_string(&self) -> String {
         return self.render().unwrap();
     }
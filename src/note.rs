@@ -1,150 +1,188 @@
 use std::str::FromStr;
 
 use askama::Template;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use sqlx::pool::PoolConnection;
-use sqlx::Postgres;
+use sqlx::{PgConnection, Postgres};
 use sqlx::postgres::PgQueryResult;
 use uuid::Uuid;
 
 use tide::{http::mime, Request};
 
+use crate::error::AppError;
 use crate::file::AssociationType;
 use crate::home::NotFoundTemplate;
 use crate::matrix::post_note_create;
 use crate::{State, user};
  
 // SQL STUFF
-pub async fn get_associated_notes(conn: &mut PoolConnection<Postgres>, association_type: AssociationType, associated_key: uuid::Uuid) -> Vec<Note> {
+pub async fn get_associated_notes(conn: &mut PoolConnection<Postgres>, organization_key: uuid::Uuid, association_type: AssociationType, associated_key: uuid::Uuid) -> Result<Vec<Note>, AppError> {
     let records = sqlx::query!(
         "select key, organization_key, owner_key, association_type, association_key, title, content, url, created, updated from notes 
-        where association_type= $1 AND association_key = $2",
-        association_type as i16, associated_key
+        where organization_key = $1 AND association_type= $2 AND association_key = $3",
+        organization_key, association_type as i16, associated_key
     )
     .fetch_all(conn)
-    .await
-    .expect("Select project by key");
+    .await?;
     let mut notes = Vec::<Note>::new();
     for note in records {
-        let association_type: AssociationType = note.association_type.expect("association_type exists").into();
+        let association_type: AssociationType = note.association_type.into();
         let n= Note {
-            key: note.key.expect("key exists"),
-            organization_key: note.organization_key.expect("organization_key"), 
-            owner_key: note.owner_key.expect("owner_key exists"),
+            key: note.key,
+            organization_key: note.organization_key, 
+            owner_key: note.owner_key,
             association_type, 
-            association_key: note.association_key.expect("association_key exists"), 
-            title: note.title.expect("title exists"),
-            content: note.content.expect("content exists"),
-            url: note.url.expect("url exists"),
-            created: note.created.expect("created exists"),
-            updated: note.updated.expect("updated exists"),
+            association_key: note.association_key, 
+            title: note.title,
+            content: note.content,
+            url: note.url,
+            created: note.created,
+            updated: note.updated,
         };
         notes.push(n);
     }
-    return notes
+    Ok(notes)
 }
 
-pub async fn get_organization_notes(conn: &mut PoolConnection<Postgres>, organization_key: uuid::Uuid) -> Vec<Note> {
+pub async fn get_organization_notes(conn: &mut PoolConnection<Postgres>, organization_key: uuid::Uuid) -> Result<Vec<Note>, AppError> {
     let records = sqlx::query!(
         "select key, organization_key, owner_key, association_type, association_key, title, content, url, created, updated from notes where organization_key = $1",
         organization_key
     )
     .fetch_all(conn)
-    .await
-    .expect("Select project by key");
+    .await?;
     let mut notes = Vec::<Note>::new();
     for note in records {
-        let association_type: AssociationType = note.association_type.expect("association_type exists").into();
+        let association_type: AssociationType = note.association_type.into();
         let n = Note {
-            key: note.key.expect("key exists"),
-            organization_key: note.organization_key.expect("organization_key"), 
-            owner_key: note.owner_key.expect("owner_key exists"),
+            key: note.key,
+            organization_key: note.organization_key, 
+            owner_key: note.owner_key,
             association_type, 
-            association_key: note.association_key.expect("association_key exists"), 
-            title: note.title.expect("title exists"),
-            content: note.content.expect("content exists"),
-            url: note.url.expect("url exists"),
-            created: note.created.expect("created exists"),
-            updated: note.updated.expect("updated exists"),
+            association_key: note.association_key, 
+            title: note.title,
+            content: note.content,
+            url: note.url,
+            created: note.created,
+            updated: note.updated,
         };
         notes.push(n);
     }
-    return notes
+    Ok(notes)
 }
 
-pub async fn get_user_notes(conn: &mut PoolConnection<Postgres>, user_key: uuid::Uuid) -> Vec<Note> {
+pub async fn get_organization_notes_page(conn: &mut PoolConnection<Postgres>, organization_key: uuid::Uuid, limit: i64, offset: i64) -> Result<Vec<Note>, AppError> {
+    let records = sqlx::query!(
+        "select key, organization_key, owner_key, association_type, association_key, title, content, url, created, updated from notes where organization_key = $1 order by created, key limit $2 offset $3",
+        organization_key, limit, offset
+    )
+    .fetch_all(conn)
+    .await?;
+    let mut notes = Vec::<Note>::new();
+    for note in records {
+        let association_type: AssociationType = note.association_type.into();
+        let n = Note {
+            key: note.key,
+            organization_key: note.organization_key, 
+            owner_key: note.owner_key,
+            association_type, 
+            association_key: note.association_key, 
+            title: note.title,
+            content: note.content,
+            url: note.url,
+            created: note.created,
+            updated: note.updated,
+        };
+        notes.push(n);
+    }
+    Ok(notes)
+}
+
+pub async fn count_organization_notes(conn: &mut PoolConnection<Postgres>, organization_key: uuid::Uuid) -> Result<i64, AppError> {
+    let record = sqlx::query!(r#"select count(*) as "count!" from notes where organization_key = $1"#, organization_key)
+        .fetch_one(conn)
+        .await?;
+    Ok(record.count)
+}
+
+#[allow(dead_code)]
+pub async fn get_user_notes(conn: &mut PoolConnection<Postgres>, user_key: uuid::Uuid) -> Result<Vec<Note>, AppError> {
     let records = sqlx::query!(
         "select key, organization_key, owner_key, association_type, association_key, title, content, url, created, updated from notes where owner_key = $1",
         user_key
     )
     .fetch_all(conn)
-    .await
-    .expect("Select project by key");
+    .await?;
     let mut notes = Vec::<Note>::new();
     for note in records {
-        let association_type: AssociationType = note.association_type.expect("association_type exists").into();
+        let association_type: AssociationType = note.association_type.into();
         let n: Note = Note {
-            key: note.key.expect("key exists"),
-            organization_key: note.organization_key.expect("organization_key"), 
-            owner_key: note.owner_key.expect("owner_key exists"),
+            key: note.key,
+            organization_key: note.organization_key, 
+            owner_key: note.owner_key,
             association_type, 
-            association_key: note.association_key.expect("association_key exists"), 
-            title: note.title.expect("title exists"),
-            content: note.content.expect("content exists"),
-            url: note.url.expect("url exists"),
-            created: note.created.expect("created exists"),
-            updated: note.updated.expect("updated exists"),
+            association_key: note.association_key, 
+            title: note.title,
+            content: note.content,
+            url: note.url,
+            created: note.created,
+            updated: note.updated,
         };
         notes.push(n);
     }
-    return notes
+    Ok(notes)
 }
 
-pub async fn get_note(conn: &mut PoolConnection<Postgres>, key: uuid::Uuid) -> Note {
-    let note = sqlx::query!(
-        "select key, organization_key, owner_key, association_type, association_key, title, content, url, created, updated from notes where key = $1",
-        key
+pub async fn get_note(conn: &mut PoolConnection<Postgres>, key: uuid::Uuid, organization_key: uuid::Uuid) -> Result<Option<Note>, AppError> {
+    let note = match sqlx::query!(
+        "select key, organization_key, owner_key, association_type, association_key, title, content, url, created, updated from notes where key = $1 AND organization_key = $2",
+        key, organization_key
     )
-    .fetch_one(conn)
-    .await
-    .expect("Select note by key");
-    let association_type: AssociationType = note.association_type.expect("association_type exists").into();
-    Note {
-        key: note.key.expect("key exists"),
-        organization_key: note.organization_key.expect("organization_key"), 
-        owner_key: note.owner_key.expect("owner_key exists"),
+    .fetch_optional(conn)
+    .await?
+    {
+        Some(r) => r,
+        None => return Ok(None),
+    };
+    let association_type: AssociationType = note.association_type.into();
+    Ok(Some(Note {
+        key: note.key,
+        organization_key: note.organization_key, 
+        owner_key: note.owner_key,
         association_type, 
-        association_key: note.association_key.expect("association_key exists"), 
-        title: note.title.expect("title exists"),
-        content: note.content.expect("content exists"),
-        url: note.url.expect("url exists"),
-        created: note.created.expect("created exists"),
-        updated: note.updated.expect("updated exists"),
-    }
+        association_key: note.association_key, 
+        title: note.title,
+        content: note.content,
+        url: note.url,
+        created: note.created,
+        updated: note.updated,
+    }))
 }
 
-async fn delete_note(conn: &mut PoolConnection<Postgres>, key: uuid::Uuid, owner_key: uuid::Uuid) -> Result<PgQueryResult, sqlx::Error> {
-    return sqlx::query!("DELETE FROM notes where owner_key=$1 AND key=$2", owner_key, key)
+pub(crate) async fn delete_note(conn: &mut PoolConnection<Postgres>, key: uuid::Uuid, organization_key: uuid::Uuid) -> Result<PgQueryResult, sqlx::Error> {
+    return sqlx::query!("DELETE FROM notes where organization_key=$1 AND key=$2", organization_key, key)
     .execute(conn)
     .await;
 }
-async fn update_note(conn: &mut PoolConnection<Postgres>, note: &Note) {
+pub(crate) async fn update_note(conn: &mut PoolConnection<Postgres>, note: &Note) -> Result<(), AppError> {
     sqlx::query!("UPDATE notes SET title=$1, content=$2, association_type=$3, 
-    association_key=$4, url=$5 where key=$6",  
+    association_key=$4, url=$5 where key=$6 AND organization_key=$7",  
     &note.title,
     &note.content,
     note.association_type as i16, 
     &note.association_key, 
     &note.url, 
     note.key,
+    note.organization_key,
 )
 .execute(conn)
-.await
-.expect("Insert Success");
+.await?;
+    Ok(())
 }
 
-async fn insert_note(conn: &mut PoolConnection<Postgres>, new_note: &Note) {
-    sqlx::query!("INSERT INTO notes (key, organization_key, owner_key, association_type, association_key, title, content, url, created, updated) values($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)", 
+pub(crate) async fn insert_note(conn: &mut PgConnection, new_note: &Note) -> Result<(), AppError> {
+    sqlx::query!("INSERT INTO notes (key, organization_key, owner_key, association_type, association_key, title, content, url, hash, created, updated) values($1, $2, $3, $4, $5, $6, $7, $8, '', $9, $10)", 
         new_note.key,
         new_note.organization_key, 
         new_note.owner_key,
@@ -157,8 +195,8 @@ async fn insert_note(conn: &mut PoolConnection<Postgres>, new_note: &Note) {
         new_note.updated,
     )
     .execute(conn)
-    .await
-    .expect("Insert Success");
+    .await?;
+    Ok(())
 }
 
 pub async fn add(req: Request<State>) -> tide::Result {
@@ -186,8 +224,9 @@ pub async fn add(req: Request<State>) -> tide::Result {
             .build())
         },
     };
-    let associated_uuid = uuid::Uuid::parse_str(ass_id).expect("associated uuid valid");
-    let association_type: AssociationType = AssociationType::from_str(ass_type).expect("Parse type");
+    let associated_uuid = crate::error::parse_key(ass_id, "association")?;
+    let association_type: AssociationType = AssociationType::from_str(ass_type)
+        .map_err(|_| AppError::Validation("unknown association type".to_string()))?;
     let mut note= Note::new( u.organization_key, u.key, association_type, associated_uuid, "".to_owned(), "".to_owned(), "".to_owned());
     note.key = uuid::Uuid::nil();
     Ok(tide::Response::builder(tide::StatusCode::Ok)
@@ -211,8 +250,8 @@ pub async fn delete(req: Request<State>) -> tide::Result {
     match req.param("note_id") {
         Ok(key) => {
             let mut conn = req.state().db_pool.acquire().await?;
-            let s_uuid = uuid::Uuid::from_str(key).expect("Note uuid parse");
-            match delete_note(&mut conn, s_uuid, u.key).await {
+            let s_uuid = crate::error::parse_key(key, "note")?;
+            match delete_note(&mut conn, s_uuid, u.organization_key).await {
                 Ok(_) =>    Ok(tide::Response::builder(tide::StatusCode::Ok)
                 .content_type(mime::HTML)
                 .build()),
@@ -233,7 +272,7 @@ pub async fn delete(req: Request<State>) -> tide::Result {
 }
 
 pub async fn get(req: Request<State>) -> tide::Result {
-    let u = match crate::user::read_jwt_cookie_to_user(req.cookie("token")) {
+    let u = match crate::user::request_user(&req) {
         Some(c) => c,
         None => {
             return Ok(tide::Redirect::new("/login").into());
@@ -243,8 +282,16 @@ pub async fn get(req: Request<State>) -> tide::Result {
     match req.param("note_id") {
         Ok(key) => {
             let mut conn = req.state().db_pool.acquire().await?; // .await? needs to be a real connection pool error handler!!!!!!!!
-            let s_uuid = uuid::Uuid::from_str(key).expect("Note uuid parse");
-            let note = get_note(&mut conn, s_uuid).await;
+            let s_uuid = crate::error::parse_key(key, "note")?;
+            let note = match get_note(&mut conn, s_uuid, u.organization_key).await? {
+                Some(n) => n,
+                None => {
+                    return Ok(tide::Response::builder(tide::StatusCode::NotFound)
+                        .content_type(mime::HTML)
+                        .body(NotFoundTemplate::new().render_string())
+                        .build())
+                }
+            };
 
             Ok(tide::Response::builder(tide::StatusCode::Ok)
                 .content_type(mime::HTML)
@@ -269,14 +316,16 @@ pub async fn get(req: Request<State>) -> tide::Result {
 
 pub async fn insert(mut req: Request<State>) -> tide::Result {
     let umd: Result<Note, tide::Error> = req.body_json().await;
-    let claims: user::UserJwtState = match user::read_jwt_cookie(req.cookie("token")) {
+    let claims: user::UserJwtState = match user::request_claims(&req) {
         Some(c) => c,
         None => {
             return Ok(tide::Redirect::new("/login").into());
         },
     };
     match umd {
-        Ok(note) => {
+        Ok(mut note) => {
+            let organization_key = crate::error::parse_key(claims.organization_key.as_str(), "organization")?;
+            note.organization_key = organization_key;
             let mut conn = match req.state().db_pool.acquire().await {
                 Ok(c) => c,
                 Err(e) => {
@@ -290,16 +339,15 @@ pub async fn insert(mut req: Request<State>) -> tide::Result {
             };
             if note.key == uuid::Uuid::nil() {
                 let s = Note::new(note.organization_key, note.owner_key, note.association_type, note.association_key, note.url, note.title, note.content);
-                insert_note(&mut conn, &s).await;
-                let organization_key = uuid::Uuid::from_str(claims.organization_key.as_str()).expect("organization key");
-                post_note_create(&mut conn, claims.matrix_home_server, claims.matrix_user_id, organization_key, claims.matrix_access_token, &s).await.expect("Posting to matrix");
+                insert_note(&mut conn, &s).await?;
+                crate::api::report_matrix(post_note_create(&mut conn, claims.matrix_home_server, claims.matrix_user_id, organization_key, claims.matrix_access_token, &s).await);
                 let j = serde_json::to_string(&s).expect("To JSON");
                 Ok(tide::Response::builder(tide::StatusCode::Ok)
                     .content_type(mime::JSON)
                     .body(j)
                     .build())
             } else {
-                update_note(&mut conn, &note).await;
+                update_note(&mut conn, &note).await?;
                 let j = serde_json::to_string(&note).expect("To JSON");
                 return Ok(tide::Response::builder(tide::StatusCode::Ok)
                     .content_type(mime::JSON)
@@ -307,19 +355,13 @@ pub async fn insert(mut req: Request<State>) -> tide::Result {
                     .build())
             }
         }
-        Err(e) => {
-            println!("{:?}", e);
-            Ok(tide::Response::builder(tide::StatusCode::BadRequest)
-                .content_type(mime::JSON)
-                .body("{'error': 'invalid json body'}")
-                .build())
-        }
+        Err(e) => Err(AppError::Validation(format!("invalid json body: {}", e)).into()),
     }
 }
 
 // data types
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
 pub struct Note {
     pub key: uuid::Uuid,
     pub organization_key: uuid::Uuid, 
@@ -0,0 +1,213 @@
+use schemars::gen::{SchemaGenerator, SchemaSettings};
+use serde_json::{json, Map, Value};
+
+use tide::Request;
+
+use crate::api::{self, ApiError, ApiResource};
+use crate::board::Board;
+use crate::entity::{Contact, Entity};
+use crate::file::File;
+use crate::milestone::Milestone;
+use crate::note::Note;
+use crate::organization::Organization;
+use crate::project::Project;
+use crate::search::{self, SearchResults};
+use crate::service_item::ServiceItem;
+use crate::task::Task;
+use crate::State;
+
+// The OpenAPI 3 document for /api/v1. Schemas come from the JsonSchema derives on the data types
+// so the spec changes whenever the structs do, keep the list below in step with main.rs.
+
+pub fn document() -> Value {
+    let mut gen = SchemaSettings::openapi3().into_generator();
+    let error = gen.subschema_for::<ApiError>();
+    let mut paths = Map::new();
+    resource_paths::<Organization>(&mut gen, &mut paths, &error, "/api/v1/organizations");
+    resource_paths::<Project>(&mut gen, &mut paths, &error, "/api/v1/projects");
+    resource_paths::<Milestone>(&mut gen, &mut paths, &error, "/api/v1/milestones");
+    resource_paths::<Task>(&mut gen, &mut paths, &error, "/api/v1/tasks");
+    resource_paths::<Board>(&mut gen, &mut paths, &error, "/api/v1/boards");
+    resource_paths::<Entity>(&mut gen, &mut paths, &error, "/api/v1/entities");
+    resource_paths::<Contact>(&mut gen, &mut paths, &error, "/api/v1/contacts");
+    resource_paths::<ServiceItem>(&mut gen, &mut paths, &error, "/api/v1/service_items");
+    resource_paths::<Note>(&mut gen, &mut paths, &error, "/api/v1/notes");
+    resource_paths::<File>(&mut gen, &mut paths, &error, "/api/v1/files");
+    search_paths(&mut gen, &mut paths, &error);
+
+    json!({
+        "openapi": "3.0.3",
+        "info": {
+            "title": "Kinbrio API",
+            "version": "1",
+        },
+        "servers": [{ "url": "/" }],
+        "security": [{ "bearer": [] }, { "session": [] }],
+        "paths": paths,
+        "components": {
+            "schemas": gen.take_definitions(),
+            "securitySchemes": {
+                "bearer": { "type": "http", "scheme": "bearer", "description": "A personal API token, kb_..." },
+                "session": { "type": "apiKey", "in": "cookie", "name": "token" },
+            },
+        },
+    })
+}
+
+fn resource_paths<R: ApiResource>(gen: &mut SchemaGenerator, paths: &mut Map<String, Value>, error: &impl serde::Serialize, path: &str) {
+    let schema = gen.subschema_for::<R>();
+    let name = R::NAME;
+    let tag = path.trim_start_matches("/api/v1/");
+    let error_response = |description: &str| {
+        json!({
+            "description": description,
+            "content": { "application/json": { "schema": error } },
+        })
+    };
+    let body = json!({
+        "required": true,
+        "content": { "application/json": { "schema": schema } },
+    });
+    let key = json!({
+        "name": "key",
+        "in": "path",
+        "required": true,
+        "schema": { "type": "string", "format": "uuid" },
+    });
+
+    paths.insert(
+        path.to_string(),
+        json!({
+            "get": {
+                "tags": [tag],
+                "summary": format!("List every {} in the active organization", name),
+                "operationId": format!("list_{}", tag),
+                "parameters": [
+                    { "name": "page", "in": "query", "schema": { "type": "integer", "minimum": 1, "default": 1 } },
+                    { "name": "per_page", "in": "query", "schema": { "type": "integer", "minimum": 1, "maximum": api::MAX_PER_PAGE, "default": api::DEFAULT_PER_PAGE } },
+                ],
+                "responses": {
+                    "200": {
+                        "description": format!("A page of {}", tag),
+                        "content": { "application/json": { "schema": {
+                            "type": "object",
+                            "required": ["data", "page", "per_page", "total"],
+                            "properties": {
+                                "data": { "type": "array", "items": schema },
+                                "page": { "type": "integer" },
+                                "per_page": { "type": "integer" },
+                                "total": { "type": "integer" },
+                            },
+                        } } },
+                    },
+                    "400": error_response("Bad paging parameters"),
+                    "401": error_response("Missing or expired credentials"),
+                },
+            },
+            "post": {
+                "tags": [tag],
+                "summary": format!("Create a {}", name),
+                "operationId": format!("create_{}", tag),
+                "requestBody": body,
+                "responses": {
+                    "201": {
+                        "description": format!("The created {}", name),
+                        "content": { "application/json": { "schema": schema } },
+                    },
+                    "400": error_response("Invalid body"),
+                    "401": error_response("Missing or expired credentials"),
+                    "403": error_response("The caller's role does not allow this"),
+                    "404": error_response("A referenced record is not in the organization"),
+                },
+            },
+        }),
+    );
+    paths.insert(
+        format!("{}/{{key}}", path),
+        json!({
+            "parameters": [key],
+            "get": {
+                "tags": [tag],
+                "summary": format!("Get a {}", name),
+                "operationId": format!("get_{}", tag),
+                "responses": {
+                    "200": {
+                        "description": format!("The {}", name),
+                        "content": { "application/json": { "schema": schema } },
+                    },
+                    "400": error_response("The key is not a uuid"),
+                    "401": error_response("Missing or expired credentials"),
+                    "404": error_response("Not found in the organization"),
+                },
+            },
+            "put": {
+                "tags": [tag],
+                "summary": format!("Update a {}", name),
+                "operationId": format!("update_{}", tag),
+                "requestBody": body,
+                "responses": {
+                    "200": {
+                        "description": format!("The updated {}", name),
+                        "content": { "application/json": { "schema": schema } },
+                    },
+                    "400": error_response("Invalid body or key"),
+                    "401": error_response("Missing or expired credentials"),
+                    "403": error_response("The caller's role does not allow this"),
+                    "404": error_response("Not found in the organization"),
+                },
+            },
+            "delete": {
+                "tags": [tag],
+                "summary": format!("Delete a {}", name),
+                "operationId": format!("delete_{}", tag),
+                "responses": {
+                    "204": { "description": "Deleted" },
+                    "400": error_response("The key is not a uuid"),
+                    "401": error_response("Missing or expired credentials"),
+                    "403": error_response("The caller's role does not allow this"),
+                    "404": error_response("Not found in the organization"),
+                },
+            },
+        }),
+    );
+}
+
+fn search_paths(gen: &mut SchemaGenerator, paths: &mut Map<String, Value>, error: &impl serde::Serialize) {
+    let schema = gen.subschema_for::<SearchResults>();
+    let error_response = |description: &str| {
+        json!({
+            "description": description,
+            "content": { "application/json": { "schema": error } },
+        })
+    };
+    paths.insert(
+        "/api/v1/search".to_string(),
+        json!({
+            "get": {
+                "tags": ["search"],
+                "summary": "Full-text search across the active organization",
+                "operationId": "search",
+                "parameters": [
+                    { "name": "q", "in": "query", "required": true, "description": "Words and \"quoted phrases\" to match, `or` between alternatives and -word to leave a word out", "schema": { "type": "string" } },
+                    { "name": "kind", "in": "query", "description": "Only one kind of result", "schema": { "type": "string", "enum": search::KINDS } },
+                    { "name": "page", "in": "query", "schema": { "type": "integer", "minimum": 1, "default": 1 } },
+                    { "name": "per_page", "in": "query", "schema": { "type": "integer", "minimum": 1, "maximum": api::MAX_PER_PAGE, "default": api::DEFAULT_PER_PAGE } },
+                ],
+                "responses": {
+                    "200": {
+                        "description": "A page of matches, best first, with the number of matches of each kind",
+                        "content": { "application/json": { "schema": schema } },
+                    },
+                    "400": error_response("No query, an unknown kind or bad paging parameters"),
+                    "401": error_response("Missing or expired credentials"),
+                },
+            },
+        }),
+    );
+}
+
+// Route Stuff
+
+pub async fn get(_req: Request<State>) -> tide::Result {
+    Ok(api::json(tide::StatusCode::Ok, &document()))
+}
@@ -1,15 +1,18 @@
-use std::str::FromStr;
-
 use askama::Template;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use sqlx::pool::PoolConnection;
 use sqlx::postgres::PgQueryResult;
-use sqlx::Postgres;
+use sqlx::{Connection, PgConnection, Postgres};
+use strum::IntoEnumIterator;
 use uuid::Uuid;
 
+use tide::http::cookies::Cookie;
 use tide::{http::mime, Request};
 
+use crate::error::AppError;
 use crate::home::NotFoundTemplate;
+use crate::member::{self, Member, Role};
 use crate::{user, State};
 
 // SQL STUFF
@@ -17,50 +20,134 @@ use crate::{user, State};
 pub async fn get_organization(
     conn: &mut PoolConnection<Postgres>,
     key: uuid::Uuid,
-) -> Organization {
+) -> Result<Organization, AppError> {
     let organization = sqlx::query!(  
         "select key, external_accounting_id, external_accounting_url, owner_key, name, description, matrix_home_server, matrix_live_support_room_url, matrix_general_room_url, domain, contact_email, created, updated from organization where key = $1",
         key
     )
     .fetch_one(conn)
-    .await
-    .expect("Select organization by key");
+    .await?;
+
+    Ok(Organization {
+        key: organization.key,
+        external_accounting_id: organization.external_accounting_id,
+        external_accounting_url: organization.external_accounting_url,
+        owner_key: organization.owner_key,
+        name: organization.name,
+        description: organization.description,
+        matrix_home_server: organization.matrix_home_server,
+        matrix_live_support_room_url: organization.matrix_live_support_room_url,
+        matrix_general_room_url: organization.matrix_general_room_url,
+        domain: organization.domain,
+        contact_email: organization.contact_email,
+        created: organization.created,
+        updated: organization.updated,
+    })
+}
+
+pub async fn get_user_organizations(
+    conn: &mut PoolConnection<Postgres>,
+    user_key: uuid::Uuid,
+) -> Result<Vec<Organization>, AppError> {
+    let records = sqlx::query!(
+        "select o.key, o.external_accounting_id, o.external_accounting_url, o.owner_key, o.name, o.description, o.matrix_home_server, o.matrix_live_support_room_url, o.matrix_general_room_url, o.domain, o.contact_email, o.created, o.updated from organization o
+        inner join organization_members m on m.organization_key = o.key
+        where m.user_key = $1 order by o.name",
+        user_key
+    )
+    .fetch_all(conn)
+    .await?;
+    let mut organizations = vec![];
+    for organization in records {
+        organizations.push(Organization {
+            key: organization.key,
+            external_accounting_id: organization.external_accounting_id,
+            external_accounting_url: organization.external_accounting_url,
+            owner_key: organization.owner_key,
+            name: organization.name,
+            description: organization.description,
+            matrix_home_server: organization.matrix_home_server,
+            matrix_live_support_room_url: organization.matrix_live_support_room_url,
+            matrix_general_room_url: organization.matrix_general_room_url,
+            domain: organization.domain,
+            contact_email: organization.contact_email,
+            created: organization.created,
+            updated: organization.updated,
+        })
+    }
+    Ok(organizations)
+}
 
-    Organization {
-        key: organization.key.expect("key exists"),
-        external_accounting_id: organization.external_accounting_id.expect("external_accounting_id exists"),
-        external_accounting_url: organization.external_accounting_url.expect("external_accounting_url exists"),
-        owner_key: organization.owner_key.expect("owner_key exists"),
-        name: organization.name.expect("name exists"),
-        description: organization.description.expect("description exists"),
-        matrix_home_server: organization.matrix_home_server.expect("matrix_home_server exists"),
-        matrix_live_support_room_url: organization.matrix_live_support_room_url.expect("matrix_live_support_room_url exists"),
-        matrix_general_room_url: organization.matrix_general_room_url.expect("matrix_general_room_url exists"),
-        domain: organization.domain.expect("domain exists"),
-        contact_email: organization.contact_email.expect("contact_email exists"),
-        created: organization.created.expect("created exists"),
-        updated: organization.updated.expect("updated exists"),
+pub async fn get_user_organizations_page(
+    conn: &mut PoolConnection<Postgres>,
+    user_key: uuid::Uuid,
+    limit: i64,
+    offset: i64,
+) -> Result<Vec<Organization>, AppError> {
+    let records = sqlx::query!(
+        "select o.key, o.external_accounting_id, o.external_accounting_url, o.owner_key, o.name, o.description, o.matrix_home_server, o.matrix_live_support_room_url, o.matrix_general_room_url, o.domain, o.contact_email, o.created, o.updated from organization o
+        inner join organization_members m on m.organization_key = o.key
+        where m.user_key = $1 order by o.name, o.key limit $2 offset $3",
+        user_key, limit, offset
+    )
+    .fetch_all(conn)
+    .await?;
+    let mut organizations = vec![];
+    for organization in records {
+        organizations.push(Organization {
+            key: organization.key,
+            external_accounting_id: organization.external_accounting_id,
+            external_accounting_url: organization.external_accounting_url,
+            owner_key: organization.owner_key,
+            name: organization.name,
+            description: organization.description,
+            matrix_home_server: organization.matrix_home_server,
+            matrix_live_support_room_url: organization.matrix_live_support_room_url,
+            matrix_general_room_url: organization.matrix_general_room_url,
+            domain: organization.domain,
+            contact_email: organization.contact_email,
+            created: organization.created,
+            updated: organization.updated,
+        })
     }
+    Ok(organizations)
+}
+
+pub async fn count_user_organizations(conn: &mut PoolConnection<Postgres>, user_key: uuid::Uuid) -> Result<i64, AppError> {
+    let record = sqlx::query!(r#"select count(*) as "count!" from organization_members where user_key = $1"#, user_key)
+        .fetch_one(conn)
+        .await?;
+    Ok(record.count)
 }
 
-async fn delete_organization(
+// Users who had it as their active organization land in their oldest other membership, or in
+// none until they are invited somewhere
+pub(crate) async fn delete_organization(
     conn: &mut PoolConnection<Postgres>,
     key: uuid::Uuid,
-    owner_key: uuid::Uuid,
 ) -> Result<PgQueryResult, sqlx::Error> {
-    return sqlx::query!(
-        "DELETE FROM organization where owner_key=$1 AND key=$2",
-        owner_key,
+    let mut tx = conn.begin().await?;
+    sqlx::query!(
+        "UPDATE users u SET organization_key = coalesce((select m.organization_key from organization_members m where m.user_key = u.key AND m.organization_key <> $1 order by m.created limit 1), $2), updated=extract(epoch from now()) where u.organization_key = $1",
+        key,
+        uuid::Uuid::nil()
+    )
+    .execute(&mut *tx)
+    .await?;
+    let deleted = sqlx::query!(
+        "DELETE FROM organization where key=$1",
         key
     )
-    .execute(conn)
-    .await;
+    .execute(&mut *tx)
+    .await?;
+    tx.commit().await?;
+    Ok(deleted)
 }
 
 pub(crate) async fn insert_organization(
-    conn: &mut PoolConnection<Postgres>,
+    conn: &mut PgConnection,
     new_organization: &Organization,
-) {
+) -> Result<(), AppError> {
     sqlx::query!("INSERT INTO organization (key, external_accounting_id, external_accounting_url, owner_key, name, description, matrix_home_server, matrix_live_support_room_url, matrix_general_room_url, domain, contact_email, created, updated) values($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13)", 
         new_organization.key,
         new_organization.external_accounting_id,
@@ -76,9 +163,24 @@ pub(crate) async fn insert_organization(
         new_organization.created,
         new_organization.updated,
     )
-    .execute(conn)
-    .await
-    .expect("Insert Success");
+    .execute(&mut *conn)
+    .await?;
+    // every organization needs statuses before it can hold a task
+    crate::workflow::seed_default_workflow(conn, new_organization.key).await?;
+    Ok(())
+}
+
+// A new organization and its creator as the Owner, the one can't be there without the other
+pub(crate) async fn create_organization(
+    conn: &mut PoolConnection<Postgres>,
+    new_organization: &Organization,
+    owner_key: uuid::Uuid,
+) -> Result<(), AppError> {
+    let mut tx = conn.begin().await?;
+    insert_organization(&mut tx, new_organization).await?;
+    member::insert_member(&mut tx, &Member::new(new_organization.key, owner_key, Role::Owner)).await?;
+    tx.commit().await?;
+    Ok(())
 }
 
 // Route Stuff
@@ -91,8 +193,15 @@ pub async fn delete(req: Request<State>) -> tide::Result {
     match req.param("organization_id") {
         Ok(key) => {
             let mut conn = req.state().db_pool.acquire().await?;
-            let s_uuid = uuid::Uuid::from_str(key).expect("Organization uuid parse");
-            match delete_organization(&mut conn, s_uuid, u.key).await {
+            let s_uuid = crate::error::parse_key(key, "organization")?;
+            // the only organization a caller can reach is the one they are signed into
+            if s_uuid != u.organization_key {
+                return Ok(tide::Response::builder(tide::StatusCode::NotFound)
+                    .content_type(mime::HTML)
+                    .body(NotFoundTemplate::new().render_string())
+                    .build());
+            }
+            match delete_organization(&mut conn, s_uuid).await {
                 Ok(_) => Ok(tide::Response::builder(tide::StatusCode::Ok)
                     .content_type(mime::HTML)
                     .build()),
@@ -116,7 +225,7 @@ pub async fn delete(req: Request<State>) -> tide::Result {
 
 
 pub async fn get(req: Request<State>) -> tide::Result {
-    let u = match crate::user::read_jwt_cookie_to_user(req.cookie("token")) {
+    let u = match crate::user::request_user(&req) {
         Some(c) => c,
         None => {
             return Ok(tide::Redirect::new("/login").into());
@@ -125,12 +234,20 @@ pub async fn get(req: Request<State>) -> tide::Result {
     match req.param("organization_id") {
         Ok(key) => {
             let mut conn = req.state().db_pool.acquire().await?; // .await? needs to be a real connection pool error handler!!!!!!!!
-            let s_uuid = uuid::Uuid::from_str(key).expect("Organization uuid parse");
-            let organization = get_organization(&mut conn, s_uuid).await;
-            
+            let s_uuid = crate::error::parse_key(key, "organization")?;
+            if s_uuid != u.organization_key {
+                return Ok(tide::Response::builder(tide::StatusCode::NotFound)
+                    .content_type(mime::HTML)
+                    .body(NotFoundTemplate::new().render_string())
+                    .build());
+            }
+            let organization = get_organization(&mut conn, s_uuid).await?;
+            let members = member::get_organization_members(&mut conn, s_uuid).await?;
+            let users = user::get_users_by_organization(&mut conn, s_uuid).await?;
+
             Ok(tide::Response::builder(tide::StatusCode::Ok)
                 .content_type(mime::HTML)
-                .body(OrganizationTemplate::new(organization, u).render_string())
+                .body(OrganizationTemplate::new(organization, u, members, users).render_string())
                 .build())
         }
         Err(e) => {
@@ -143,7 +260,57 @@ pub async fn get(req: Request<State>) -> tide::Result {
     }
 }
 
-pub async fn update_organization(conn: &mut PoolConnection<Postgres>, organization: &Organization) {
+// Makes another organization the active one by reissuing the JWT with that organization and the role held there.
+// Only a session can switch, an API token stays in the organization it was made for.
+pub async fn switch(mut req: Request<State>) -> tide::Result {
+    let claims: user::UserJwtState = match user::read_jwt_cookie(req.cookie("token")) {
+        Some(c) => c,
+        None => {
+            return Ok(tide::Redirect::new("/login").into());
+        }
+    };
+    let umd: Result<OrganizationSwitch, tide::Error> = req.body_json().await;
+    match umd {
+        Ok(switch) => {
+            let mut conn = req.state().db_pool.acquire().await?;
+            let user_key = crate::error::parse_key(claims.key.as_str(), "user")?;
+            let role = match member::get_role(&mut conn, switch.organization_key, user_key).await {
+                Some(r) => r,
+                None => {
+                    return Ok(tide::Response::builder(tide::StatusCode::NotFound)
+                        .content_type(mime::HTML)
+                        .body(NotFoundTemplate::new().render_string())
+                        .build())
+                }
+            };
+            let mut u = match user::get_user(&mut conn, user_key).await {
+                Some(u) => u,
+                None => return Ok(tide::Redirect::new("/login").into()),
+            };
+            user::set_active_organization(&mut conn, user_key, switch.organization_key).await?;
+            u.organization_key = switch.organization_key;
+            let jwt = user::create_jwt(
+                &u,
+                claims.matrix_access_token,
+                claims.matrix_device_id,
+                claims.matrix_refresh_token,
+                role,
+            )
+            .expect("JWT Created");
+            let j = serde_json::to_string(&switch).expect("To JSON");
+            let mut res = tide::Response::builder(tide::StatusCode::Ok)
+                .content_type(mime::JSON)
+                .body(j)
+                .build();
+            let c = Cookie::parse(format!("token={}; Path=/", jwt)).unwrap();
+            res.insert_cookie(c);
+            Ok(res)
+        }
+        Err(e) => Err(AppError::Validation(format!("invalid json body: {}", e)).into()),
+    }
+}
+
+pub async fn update_organization(conn: &mut PoolConnection<Postgres>, organization: &Organization) -> Result<(), AppError> {
     sqlx::query!(
         "UPDATE organization SET name=$1, description=$2, external_accounting_id=$3, external_accounting_url=$4, matrix_home_server=$5, matrix_live_support_room_url=$6, matrix_general_room_url=$7, domain=$8, contact_email=$9 where key=$10",
         &organization.name,
@@ -158,13 +325,13 @@ pub async fn update_organization(conn: &mut PoolConnection<Postgres>, organizati
         organization.key,
     )
     .execute(conn)
-    .await
-    .expect("Insert Success");
+    .await?;
+    Ok(())
 }
 pub async fn insert(mut req: Request<State>) -> tide::Result {
     let umd: Result<Organization, tide::Error> = req.body_json().await;
 
-    let _claims: user::UserJwtState = match user::read_jwt_cookie(req.cookie("token")) {
+    let claims: user::UserJwtState = match user::request_claims(&req) {
         Some(c) => c,
         None => {
             return Ok(tide::Redirect::new("/login").into());
@@ -186,11 +353,13 @@ pub async fn insert(mut req: Request<State>) -> tide::Result {
             };
 
             if organization.key == uuid::Uuid::nil() {
+                // whoever creates it owns it, not whoever the body names
+                let owner_key = crate::error::parse_key(claims.key.as_str(), "user")?;
                 let s = Organization::new(
                     Uuid::new_v4(),
                     organization.external_accounting_id,
                     organization.external_accounting_url,
-                    organization.owner_key,
+                    owner_key,
                     organization.name,
                     organization.description,
                     organization.matrix_home_server,
@@ -199,7 +368,7 @@ pub async fn insert(mut req: Request<State>) -> tide::Result {
                     organization.domain,
                     organization.contact_email,
                 );
-                insert_organization(&mut conn, &s).await;
+                create_organization(&mut conn, &s, owner_key).await?;
                 let j = serde_json::to_string(&s).expect("To JSON");
                 return Ok(tide::Response::builder(tide::StatusCode::Ok)
                     .content_type(mime::JSON)
@@ -207,26 +376,31 @@ pub async fn insert(mut req: Request<State>) -> tide::Result {
                     .build());
             }
 
-            update_organization(&mut conn, &organization).await;
+            if organization.key.to_string() != claims.organization_key {
+                return Ok(tide::Response::builder(tide::StatusCode::NotFound)
+                    .content_type(mime::HTML)
+                    .body(NotFoundTemplate::new().render_string())
+                    .build());
+            }
+            update_organization(&mut conn, &organization).await?;
             let j = serde_json::to_string(&organization).expect("To JSON");
             Ok(tide::Response::builder(tide::StatusCode::Ok)
                 .content_type(mime::JSON)
                 .body(j)
                 .build())
         }
-        Err(e) => {
-            println!("{:?}", e);
-            Ok(tide::Response::builder(tide::StatusCode::BadRequest)
-                .content_type(mime::JSON)
-                .body("{'error': 'invalid json body'}")
-                .build())
-        }
+        Err(e) => Err(AppError::Validation(format!("invalid json body: {}", e)).into()),
     }
 }
 
 // data types
 
 #[derive(Debug, Deserialize, Serialize)]
+pub struct OrganizationSwitch {
+    pub organization_key: uuid::Uuid,
+}
+
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
 pub struct Organization {
     pub key: uuid::Uuid,
     pub external_accounting_id: String,
@@ -276,8 +450,9 @@ impl Organization {
         }
     }
 
+    #[allow(dead_code)]
     pub fn has_external_accounting(&self) -> bool {
-        return self.external_accounting_id.len() > 0;
+        return !self.external_accounting_id.is_empty();
     }
 }
 
@@ -286,11 +461,30 @@ impl Organization {
 pub struct OrganizationTemplate {
     organization: Organization,
     user: crate::user::User,
+    members: Vec<Member>,
+    users: Vec<crate::user::User>,
 }
 
 impl<'a> OrganizationTemplate {
-    pub fn new(organization: Organization, user: crate::user::User) -> Self {
-        return Self { organization, user };
+    pub fn new(
+        organization: Organization,
+        user: crate::user::User,
+        members: Vec<Member>,
+        users: Vec<crate::user::User>,
+    ) -> Self {
+        return Self {
+            organization,
+            user,
+            members,
+            users,
+        };
+    }
+
+    pub fn member_name<'aa>(&'aa self, user_key: &uuid::Uuid) -> String {
+        match self.users.iter().find(|u| u.key == *user_key) {
+            Some(u) => u.to_string(),
+            None => user_key.to_string(),
+        }
     }
 
     pub fn render_string(&self) -> String {
@@ -1,83 +1,119 @@
-use std::str::FromStr;
-
 use askama::Template;
 use chrono::Datelike;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use sqlx::pool::PoolConnection;
-use sqlx::Postgres;
+use sqlx::{PgConnection, Postgres};
 use sqlx::postgres::PgQueryResult;
 use uuid::Uuid;
-use strum::IntoEnumIterator;
 
 use tide::{http::mime, Request};
 
+use crate::error::AppError;
 use crate::home::NotFoundTemplate;
 use crate::milestone::{Milestone, get_milestones_by_project};
 use crate::{State, user, entity, note, file};
 use crate::matrix::post_project_create;
-use crate::task::{Task, get_tasks_by_project, TaskStatus};
+use crate::task::{Task, get_tasks_by_project};
+use crate::workflow::Workflow;
  
 // SQL STUFF
-pub async fn get_projects_by_organization(conn: &mut PoolConnection<Postgres>, organization_key: uuid::Uuid) -> Vec<Project> {
+pub async fn get_projects_by_organization(conn: &mut PoolConnection<Postgres>, organization_key: uuid::Uuid) -> Result<Vec<Project>, AppError> {
     let records = sqlx::query!(
         "select key, owner_key, organization_key, name, description, tags, estimated_quarter_days, start, due, created, updated from projects where organization_key = $1",
         organization_key
     )
     .fetch_all(conn)
-    .await
-    .expect("Select project by key");
+    .await?;
     let mut projects = Vec::<Project>::new();
     for project in records {
         let prj = Project {
-            key: project.key.expect("key exists"),
-            organization_key: project.organization_key.expect("organization_key"), 
-            owner_key: project.owner_key.expect("owner_key exists"),
-            name: project.name.expect("name exists"),
-            description: project.description.expect("description exists"),
-            tags: project.tags.expect("tags exists"),
-            estimated_quarter_days: project.estimated_quarter_days.expect("estimated_quarter_days exists"),
-            start: project.start.expect("start"),
-            due:project.due.expect("due"),
-            created: project.created.expect("created exists"),
-            updated: project.updated.expect("updated exists"),
+            key: project.key,
+            organization_key: project.organization_key, 
+            owner_key: project.owner_key,
+            name: project.name,
+            description: project.description,
+            tags: project.tags,
+            estimated_quarter_days: project.estimated_quarter_days,
+            start: project.start,
+            due:project.due,
+            created: project.created,
+            updated: project.updated,
         };
         projects.push(prj);
     }
-    return projects
+    Ok(projects)
 }
 
-pub async fn get_project(conn: &mut PoolConnection<Postgres>, key: uuid::Uuid) -> Project {
-    let project = sqlx::query!(
-        "select key, owner_key, organization_key, name, description, tags, estimated_quarter_days, start, due, created, updated from projects where key = $1",
-        key
+pub async fn get_projects_by_organization_page(conn: &mut PoolConnection<Postgres>, organization_key: uuid::Uuid, limit: i64, offset: i64) -> Result<Vec<Project>, AppError> {
+    let records = sqlx::query!(
+        "select key, owner_key, organization_key, name, description, tags, estimated_quarter_days, start, due, created, updated from projects where organization_key = $1 order by created, key limit $2 offset $3",
+        organization_key, limit, offset
     )
-    .fetch_one(conn)
-    .await
-    .expect("Select project by key");
-
-    Project {
-        key: project.key.expect("key exists"),
-        organization_key: project.organization_key.expect("organization_key"), 
-        owner_key: project.owner_key.expect("owner_key exists"),
-        name: project.name.expect("name exists"),
-        description: project.description.expect("description exists"),
-        tags: project.tags.expect("tags exists"),
-        estimated_quarter_days: project.estimated_quarter_days.expect("estimated_quarter_days exists"),
-        start: project.start.expect("start"),
-        due:project.due.expect("due"),
-        created: project.created.expect("created exists"),
-        updated: project.updated.expect("updated exists"),
+    .fetch_all(conn)
+    .await?;
+    let mut projects = Vec::<Project>::new();
+    for project in records {
+        let prj = Project {
+            key: project.key,
+            organization_key: project.organization_key, 
+            owner_key: project.owner_key,
+            name: project.name,
+            description: project.description,
+            tags: project.tags,
+            estimated_quarter_days: project.estimated_quarter_days,
+            start: project.start,
+            due:project.due,
+            created: project.created,
+            updated: project.updated,
+        };
+        projects.push(prj);
     }
+    Ok(projects)
 }
 
-async fn delete_project(conn: &mut PoolConnection<Postgres>, key: uuid::Uuid, owner_key: uuid::Uuid) -> Result<PgQueryResult, sqlx::Error> {
-    return sqlx::query!("DELETE FROM projects where owner_key=$1 AND key=$2", owner_key, key)
+pub async fn count_projects_by_organization(conn: &mut PoolConnection<Postgres>, organization_key: uuid::Uuid) -> Result<i64, AppError> {
+    let record = sqlx::query!(r#"select count(*) as "count!" from projects where organization_key = $1"#, organization_key)
+        .fetch_one(conn)
+        .await?;
+    Ok(record.count)
+}
+
+pub async fn get_project(conn: &mut PoolConnection<Postgres>, key: uuid::Uuid, organization_key: uuid::Uuid) -> Result<Option<Project>, AppError> {
+    let project = match sqlx::query!(
+        "select key, owner_key, organization_key, name, description, tags, estimated_quarter_days, start, due, created, updated from projects where key = $1 AND organization_key = $2",
+        key, organization_key
+    )
+    .fetch_optional(conn)
+    .await?
+    {
+        Some(r) => r,
+        None => return Ok(None),
+    };
+
+    Ok(Some(Project {
+        key: project.key,
+        organization_key: project.organization_key, 
+        owner_key: project.owner_key,
+        name: project.name,
+        description: project.description,
+        tags: project.tags,
+        estimated_quarter_days: project.estimated_quarter_days,
+        start: project.start,
+        due:project.due,
+        created: project.created,
+        updated: project.updated,
+    }))
+}
+
+pub(crate) async fn delete_project(conn: &mut PoolConnection<Postgres>, key: uuid::Uuid, organization_key: uuid::Uuid) -> Result<PgQueryResult, sqlx::Error> {
+    return sqlx::query!("DELETE FROM projects where organization_key=$1 AND key=$2", organization_key, key)
     .execute(conn)
     .await;
 }
  
-async fn update_project(conn: &mut PoolConnection<Postgres>, project: &Project) {
-    sqlx::query!("UPDATE projects SET name=$1, description=$2, tags=$3, estimated_quarter_days=$4, start=$5, due=$6 where key=$7", 
+pub(crate) async fn update_project(conn: &mut PoolConnection<Postgres>, project: &Project) -> Result<(), AppError> {
+    sqlx::query!("UPDATE projects SET name=$1, description=$2, tags=$3, estimated_quarter_days=$4, start=$5, due=$6 where key=$7 AND organization_key=$8", 
         &project.name,
         &project.description,
         &project.tags,
@@ -85,13 +121,14 @@ async fn update_project(conn: &mut PoolConnection<Postgres>, project: &Project)
         project.start,
         project.due,
         project.key,
+        project.organization_key,
     )
     .execute(conn)
-    .await
-    .expect("Insert Success");
+    .await?;
+    Ok(())
 }
 
-async fn insert_project(conn: &mut PoolConnection<Postgres>, new_project: &Project) {
+pub(crate) async fn insert_project(conn: &mut PgConnection, new_project: &Project) -> Result<(), AppError> {
     sqlx::query!("INSERT INTO projects (key, organization_key, owner_key, name, description, tags, estimated_quarter_days, start, due, created, updated) values($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)", 
         new_project.key,
         new_project.organization_key, 
@@ -106,8 +143,8 @@ async fn insert_project(conn: &mut PoolConnection<Postgres>, new_project: &Proje
         new_project.updated,
     )
     .execute(conn)
-    .await
-    .expect("Insert Success");
+    .await?;
+    Ok(())
 }
 
 // Route Stuff
@@ -120,8 +157,8 @@ pub async fn delete(req: Request<State>) -> tide::Result {
     match req.param("project_id") {
         Ok(key) => {
             let mut conn = req.state().db_pool.acquire().await?;
-            let s_uuid = uuid::Uuid::from_str(key).expect("Project uuid parse");
-            match delete_project(&mut conn, s_uuid, u.key).await {
+            let s_uuid = crate::error::parse_key(key, "project")?;
+            match delete_project(&mut conn, s_uuid, u.organization_key).await {
                 Ok(_) =>    Ok(tide::Response::builder(tide::StatusCode::Ok)
                 .content_type(mime::HTML)
                 .build()),
@@ -160,6 +197,9 @@ pub async fn add(req: Request<State>) -> tide::Result {
             vec![],
             vec![],
             vec![],
+            vec![],
+            Workflow::default(),
+            vec![],
         )
         .render_string(),
     )
@@ -167,7 +207,7 @@ pub async fn add(req: Request<State>) -> tide::Result {
 }
 
 pub async fn get(req: Request<State>) -> tide::Result {
-    let u = match crate::user::read_jwt_cookie_to_user(req.cookie("token")) {
+    let u = match crate::user::request_user(&req) {
         Some(c) => c,
         None => {
             return Ok(tide::Redirect::new("/login").into());
@@ -175,16 +215,28 @@ pub async fn get(req: Request<State>) -> tide::Result {
     };
     match req.param("project_id") {
         Ok(key) => {
-            let mut conn = req.state().db_pool.acquire().await.expect("Acquiring connection");
-            let p_uuid = uuid::Uuid::from_str(key).expect("Project uuid parse");
-            let project = get_project(&mut conn, p_uuid).await;
-            let tasks = get_tasks_by_project(&mut conn, project.key).await;
-            let milestones = get_milestones_by_project(&mut conn, project.key).await;
+            let mut conn = req.state().db_pool.acquire().await?;
+            let p_uuid = crate::error::parse_key(key, "project")?;
+            let project = match get_project(&mut conn, p_uuid, u.organization_key).await? {
+                Some(p) => p,
+                None => {
+                    return Ok(tide::Response::builder(tide::StatusCode::NotFound)
+                        .content_type(mime::HTML)
+                        .body(NotFoundTemplate::new().render_string())
+                        .build())
+                }
+            };
+            let tasks = get_tasks_by_project(&mut conn, project.key, u.organization_key).await?;
+            let milestones = get_milestones_by_project(&mut conn, project.key, u.organization_key).await?;
 
-            let entitys = entity::get_organization_entitys(&mut conn, u.organization_key).await;
-            let contacts = entity::get_contacts(&mut conn, u.organization_key).await;
-            let notes = note::get_associated_notes(&mut conn, crate::file::AssociationType::Project, p_uuid).await;
-            let files = file::get_associated_files(&mut conn, crate::file::AssociationType::Project, p_uuid).await;
+            let entitys = entity::get_organization_entitys(&mut conn, u.organization_key).await?;
+            let contacts = entity::get_contacts(&mut conn, u.organization_key).await?;
+            let notes = note::get_associated_notes(&mut conn, u.organization_key, crate::file::AssociationType::Project, p_uuid).await?;
+            let files = file::get_associated_files(&mut conn, u.organization_key, crate::file::AssociationType::Project, p_uuid).await?;
+            let dependencies = crate::dependency::get_organization_dependencies(&mut conn, u.organization_key).await?;
+            let critical = crate::dependency::critical_path(&tasks, &dependencies);
+            let workflow = crate::workflow::get_workflow(&mut conn, u.organization_key).await?;
+            let time = crate::time_entry::get_project_time(&mut conn, project.key, u.organization_key).await?;
 
             Ok(tide::Response::builder(tide::StatusCode::Ok)
                 .content_type(mime::HTML)
@@ -198,6 +250,9 @@ pub async fn get(req: Request<State>) -> tide::Result {
                         contacts,
                         notes, 
                         files,
+                        critical,
+                        workflow,
+                        time,
                     )
                     .render_string(),
                 )
@@ -216,7 +271,7 @@ pub async fn get(req: Request<State>) -> tide::Result {
 pub async fn insert(mut req: Request<State>) -> tide::Result {
     let umd: Result<Project, tide::Error> = req.body_json().await;
 
-    let claims: user::UserJwtState = match user::read_jwt_cookie(req.cookie("token")) {
+    let claims: user::UserJwtState = match user::request_claims(&req) {
         Some(c) => c,
         None => {
             return Ok(tide::Redirect::new("/login").into());
@@ -224,7 +279,9 @@ pub async fn insert(mut req: Request<State>) -> tide::Result {
     };
     
     match umd {
-        Ok(project) => {
+        Ok(mut project) => {
+            let organization_key = crate::error::parse_key(claims.organization_key.as_str(), "organization")?;
+            project.organization_key = organization_key;
             let mut conn = match req.state().db_pool.acquire().await {
                 Ok(c) => c,
                 Err(e) => {
@@ -238,9 +295,8 @@ pub async fn insert(mut req: Request<State>) -> tide::Result {
             };
             if project.key == uuid::Uuid::nil() {
                 let s = Project::new(project.organization_key, project.owner_key, project.name, project.description, project.tags, project.estimated_quarter_days, project.start, project.due);
-                insert_project(&mut conn, &s).await;
-                let organization_key = uuid::Uuid::from_str(claims.organization_key.as_str()).expect("organization key");
-                post_project_create(&mut conn, claims.matrix_home_server, claims.matrix_user_id, organization_key, claims.matrix_access_token, &s).await.expect("Posting to matrix");
+                insert_project(&mut conn, &s).await?;
+                crate::api::report_matrix(post_project_create(&mut conn, claims.matrix_home_server, claims.matrix_user_id, organization_key, claims.matrix_access_token, &s).await);
             
                 let j = serde_json::to_string(&s).expect("To JSON");
                 return Ok(tide::Response::builder(tide::StatusCode::Ok)
@@ -249,26 +305,20 @@ pub async fn insert(mut req: Request<State>) -> tide::Result {
                     .build())
             }
 
-            update_project(&mut conn, &project).await;
+            update_project(&mut conn, &project).await?;
             let j = serde_json::to_string(&project).expect("To JSON");
             Ok(tide::Response::builder(tide::StatusCode::Ok)
                 .content_type(mime::JSON)
                 .body(j)
                 .build())
         }
-        Err(e) => {
-            println!("{:?}", e);
-            Ok(tide::Response::builder(tide::StatusCode::BadRequest)
-                .content_type(mime::JSON)
-                .body("{'error': 'invalid json body'}")
-                .build())
-        }
+        Err(e) => Err(AppError::Validation(format!("invalid json body: {}", e)).into()),
     }
 }
 
 // data types
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
 pub struct Project {
     pub key: uuid::Uuid,
     pub organization_key: uuid::Uuid,
@@ -322,10 +372,15 @@ pub struct ProjectTemplate {
     milestones: Vec<Milestone>,
     user: crate::user::User,
     
+    #[allow(dead_code)]
     entitys: Vec<entity::Entity>,
+    #[allow(dead_code)]
     contacts: Vec<entity::Contact>,
     notes: Vec<note::Note>,
     files: Vec<file::File>,
+    critical: Vec<uuid::Uuid>,
+    workflow: Workflow,
+    time: Vec<(uuid::Uuid, i64)>,
 }
 
 impl<'a> ProjectTemplate {
@@ -338,6 +393,9 @@ impl<'a> ProjectTemplate {
         contacts: Vec<entity::Contact>,
         notes: Vec<note::Note>,
         files: Vec<file::File>,
+        critical: Vec<uuid::Uuid>,
+        workflow: Workflow,
+        time: Vec<(uuid::Uuid, i64)>,
     ) -> Self {
         return Self {
             project,
@@ -348,20 +406,39 @@ impl<'a> ProjectTemplate {
             contacts,
             notes, 
             files,
+            critical,
+            workflow,
+            time,
         };
     }
+
+    pub fn is_critical<'aa>(&'aa self, key: &uuid::Uuid) -> bool {
+        self.critical.contains(key)
+    }
     
-    pub fn get_task_background_color<'aa>(&'aa self, status: &TaskStatus) -> String { 
-        match *status {
-            TaskStatus::Wishlist => "#cc66ff85".to_string(),
-            TaskStatus::Todo => "#ff6a4a85".to_string(),
-            TaskStatus::PlanningAndEstimating => "#ffb26085".to_string(),
-            TaskStatus::InQueue => "#f9ff5b85".to_string(),
-            TaskStatus::InProgress => "#61d1d085".to_string(),
-            TaskStatus::ToReview => "#658cff85".to_string(),
-            TaskStatus::InReviewal => "#D2CAFF85".to_string(),
-            TaskStatus::Complete => "#64ff6385".to_string(),
-        }
+    pub fn milestone_progress<'aa>(&'aa self, milestone: &Milestone) -> crate::milestone::Progress {
+        crate::milestone::progress(milestone, &self.tasks, &self.workflow, &self.time)
+    }
+
+    pub fn task_hours<'aa>(&'aa self, key: &uuid::Uuid) -> String {
+        crate::time_entry::hours(self.time.iter().filter(|(k, _)| k == key).map(|(_, s)| *s).sum())
+    }
+
+    pub fn tracked_hours<'aa>(&'aa self) -> String {
+        crate::time_entry::hours(self.time.iter().map(|(_, s)| *s).sum())
+    }
+
+    // the project's own estimate, or what its tasks add up to when it doesn't have one
+    pub fn estimated_hours<'aa>(&'aa self) -> String {
+        let quarter_days = match self.project.estimated_quarter_days {
+            0 => self.tasks.iter().map(|t| t.estimated_quarter_days as i64).sum(),
+            q => q as i64,
+        };
+        crate::time_entry::hours(quarter_days * crate::time_entry::QUARTER_DAY_SECONDS)
+    }
+
+    pub fn get_task_background_color<'aa>(&'aa self, status_key: &uuid::Uuid) -> String { 
+        self.workflow.color(status_key)
     }
 
 
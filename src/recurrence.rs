@@ -0,0 +1,438 @@
+use serde::{Deserialize, Serialize};
+use sqlx::pool::PoolConnection;
+use sqlx::postgres::PgQueryResult;
+use sqlx::{Connection, PgPool, Postgres};
+use strum_macros::EnumIter;
+use uuid::Uuid;
+
+use tide::{http::mime, Request};
+
+use crate::error::AppError;
+use crate::task::Task;
+use crate::{user, State};
+
+// how often the scheduler looks for occurrences to create
+const SCHEDULE_SECONDS: u64 = 60;
+
+// SQL STUFF
+
+pub async fn get_task_recurrence(
+    conn: &mut PoolConnection<Postgres>,
+    task_key: uuid::Uuid,
+    organization_key: uuid::Uuid,
+) -> Result<Option<Recurrence>, AppError> {
+    let recurrence = match sqlx::query!(
+        "select key, organization_key, task_key, frequency, interval, until, count, dtstart, occurrences, next_start, created, updated from task_recurrences where task_key = $1 AND organization_key = $2",
+        task_key,
+        organization_key
+    )
+    .fetch_optional(conn)
+    .await?
+    {
+        Some(r) => r,
+        None => return Ok(None),
+    };
+    Ok(Some(Recurrence {
+        key: recurrence.key,
+        organization_key: recurrence.organization_key,
+        task_key: recurrence.task_key,
+        frequency: recurrence.frequency.into(),
+        interval: recurrence.interval,
+        until: recurrence.until,
+        count: recurrence.count,
+        dtstart: recurrence.dtstart,
+        occurrences: recurrence.occurrences,
+        next_start: recurrence.next_start,
+        created: recurrence.created,
+        updated: recurrence.updated,
+    }))
+}
+
+// A task has at most one rule, saving another one replaces it and starts the series over
+async fn upsert_recurrence(conn: &mut PoolConnection<Postgres>, r: &Recurrence) -> Result<(), AppError> {
+    sqlx::query!("INSERT INTO task_recurrences (key, organization_key, task_key, frequency, interval, until, count, dtstart, occurrences, next_start, created, updated) values($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12)
+        ON CONFLICT (task_key) DO UPDATE SET frequency=$4, interval=$5, until=$6, count=$7, dtstart=$8, occurrences=$9, next_start=$10, updated=extract(epoch from now())",
+        r.key,
+        r.organization_key,
+        r.task_key,
+        r.frequency as i16,
+        r.interval,
+        r.until,
+        r.count,
+        r.dtstart,
+        r.occurrences,
+        r.next_start,
+        r.created,
+        r.updated,
+    )
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+async fn delete_recurrence(
+    conn: &mut PoolConnection<Postgres>,
+    task_key: uuid::Uuid,
+    organization_key: uuid::Uuid,
+) -> Result<PgQueryResult, sqlx::Error> {
+    return sqlx::query!(
+        "DELETE FROM task_recurrences where organization_key=$1 AND task_key=$2",
+        organization_key,
+        task_key
+    )
+    .execute(conn)
+    .await;
+}
+
+// Rules whose current task is finished, or whose next date has come
+async fn get_ready_recurrences(conn: &mut PoolConnection<Postgres>, now: i64) -> Result<Vec<Recurrence>, AppError> {
+    let records = sqlx::query!(
+        "select r.key, r.organization_key, r.task_key, r.frequency, r.interval, r.until, r.count, r.dtstart, r.occurrences, r.next_start, r.created, r.updated
+        from task_recurrences r
+        join tasks t on t.key = r.task_key
+        join workflow_statuses ws on ws.key = t.status_key
+        where r.next_start > 0 AND (ws.category = 2 OR r.next_start <= $1)",
+        now
+    )
+    .fetch_all(conn)
+    .await?;
+    let mut recurrences = vec![];
+    for r in records {
+        recurrences.push(Recurrence {
+            key: r.key,
+            organization_key: r.organization_key,
+            task_key: r.task_key,
+            frequency: r.frequency.into(),
+            interval: r.interval,
+            until: r.until,
+            count: r.count,
+            dtstart: r.dtstart,
+            occurrences: r.occurrences,
+            next_start: r.next_start,
+            created: r.created,
+            updated: r.updated,
+        })
+    }
+    Ok(recurrences)
+}
+
+// Runs for the life of the server
+pub async fn schedule(db_pool: PgPool) {
+    loop {
+        if let Err(e) = materialize(&db_pool).await {
+            println!("recurring tasks: {:?}", e);
+        }
+        tokio::time::sleep(std::time::Duration::from_secs(SCHEDULE_SECONDS)).await;
+    }
+}
+
+async fn materialize(db_pool: &PgPool) -> Result<(), AppError> {
+    let mut conn = db_pool.acquire().await?;
+    let now = chrono::Utc::now().timestamp();
+    for rule in get_ready_recurrences(&mut conn, now).await? {
+        let task = match crate::task::get_task(&mut conn, rule.task_key, rule.organization_key).await? {
+            Some(t) => t,
+            None => continue,
+        };
+        let workflow = crate::workflow::get_workflow(&mut conn, rule.organization_key).await?;
+        let status_key = match workflow.initial() {
+            Some(k) => k,
+            None => continue,
+        };
+        let next = rule.next_task(&task, status_key);
+        let occurrences = rule.occurrences + 1;
+        let next_start = rule.occurrence(occurrences).unwrap_or(0);
+
+        // the rule only moves if nobody else moved it first, otherwise the new task is rolled back
+        let mut tx = conn.begin().await?;
+        crate::task::insert_task(&mut tx, &next).await?;
+        let moved = sqlx::query!(
+            "UPDATE task_recurrences SET task_key=$3, occurrences=$4, next_start=$5, updated=extract(epoch from now()) where key = $1 AND occurrences = $2",
+            rule.key,
+            rule.occurrences,
+            next.key,
+            occurrences,
+            next_start
+        )
+        .execute(&mut *tx)
+        .await?;
+        if moved.rows_affected() == 0 {
+            tx.rollback().await?;
+            continue;
+        }
+        tx.commit().await?;
+    }
+    Ok(())
+}
+
+// Route Stuff
+
+pub async fn get_by_task(req: Request<State>) -> tide::Result {
+    let u = match crate::user::user_or_error(&req) {
+        Ok(value) => value,
+        Err(_) => return Ok(tide::Redirect::new("/login").into()),
+    };
+    let task_key = crate::error::param_key(&req, "task_id")?;
+    let mut conn = req.state().db_pool.acquire().await?;
+    let recurrence = match get_task_recurrence(&mut conn, task_key, u.organization_key).await? {
+        Some(r) => r,
+        None => return Err(AppError::NotFound("recurrence".to_string()).into()),
+    };
+    let j = serde_json::to_string(&recurrence).expect("To JSON");
+    Ok(tide::Response::builder(tide::StatusCode::Ok)
+        .content_type(mime::JSON)
+        .body(j)
+        .build())
+}
+
+pub async fn insert(mut req: Request<State>) -> tide::Result {
+    let umd: Result<Recurrence, tide::Error> = req.body_json().await;
+    let claims: user::UserJwtState = match user::request_claims(&req) {
+        Some(c) => c,
+        None => {
+            return Ok(tide::Redirect::new("/login").into());
+        }
+    };
+    let rule = match umd {
+        Ok(r) => r,
+        Err(e) => return Err(AppError::Validation(format!("invalid json body: {}", e)).into()),
+    };
+    let task_key = crate::error::param_key(&req, "task_id")?;
+    let organization_key = crate::error::parse_key(claims.organization_key.as_str(), "organization")?;
+    let mut conn = req.state().db_pool.acquire().await?;
+    let task = match crate::task::get_task(&mut conn, task_key, organization_key).await? {
+        Some(t) => t,
+        None => return Err(AppError::NotFound("task".to_string()).into()),
+    };
+    let r = Recurrence::new(&task, rule.frequency, rule.interval, rule.until, rule.count)?;
+    upsert_recurrence(&mut conn, &r).await?;
+    let j = serde_json::to_string(&r).expect("To JSON");
+    Ok(tide::Response::builder(tide::StatusCode::Ok)
+        .content_type(mime::JSON)
+        .body(j)
+        .build())
+}
+
+pub async fn delete(req: Request<State>) -> tide::Result {
+    let u = match crate::user::user_or_error(&req) {
+        Ok(value) => value,
+        Err(_) => return Ok(tide::Redirect::new("/login").into()),
+    };
+    let task_key = crate::error::param_key(&req, "task_id")?;
+    let mut conn = req.state().db_pool.acquire().await?;
+    if delete_recurrence(&mut conn, task_key, u.organization_key).await?.rows_affected() == 0 {
+        return Err(AppError::NotFound("recurrence".to_string()).into());
+    }
+    Ok(tide::Response::builder(tide::StatusCode::Ok)
+        .content_type(mime::HTML)
+        .build())
+}
+
+// data types
+
+#[derive(PartialEq, Debug, Default, Deserialize, Serialize, Clone, Copy, sqlx::Type, EnumIter)]
+pub enum Frequency {
+    #[default]
+    Daily,
+    Weekly,
+    Monthly,
+}
+
+impl Into<Frequency> for i16 {
+    fn into(self) -> Frequency {
+        match self {
+            0 => Frequency::Daily,
+            1 => Frequency::Weekly,
+            2 => Frequency::Monthly,
+            _ => Frequency::Daily,
+        }
+    }
+}
+
+impl From<Frequency> for i16 {
+    fn from(f: Frequency) -> Self {
+        match f {
+            Frequency::Daily => 0,
+            Frequency::Weekly => 1,
+            Frequency::Monthly => 2,
+        }
+    }
+}
+
+impl ToString for Frequency {
+    fn to_string(&self) -> String {
+        match self {
+            Frequency::Daily => "Daily".to_owned(),
+            Frequency::Weekly => "Weekly".to_owned(),
+            Frequency::Monthly => "Monthly".to_owned(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct Recurrence {
+    #[serde(default)]
+    pub key: uuid::Uuid,
+    #[serde(default)]
+    pub organization_key: uuid::Uuid,
+    #[serde(default)]
+    pub task_key: uuid::Uuid,
+    #[serde(default)]
+    pub frequency: Frequency,
+    pub interval: i32,
+    #[serde(default)]
+    pub until: i64,
+    #[serde(default)]
+    pub count: i32,
+    #[serde(default)]
+    pub dtstart: i64,
+    #[serde(default)]
+    pub occurrences: i32,
+    #[serde(default)]
+    pub next_start: i64,
+    #[serde(default)]
+    pub created: i64,
+    #[serde(default)]
+    pub updated: i64,
+}
+
+impl Recurrence {
+    // The series starts at the task's start date, or its due date when it only has one of those
+    pub fn new(task: &Task, frequency: Frequency, interval: i32, until: i64, count: i32) -> Result<Self, AppError> {
+        let dtstart = if task.start != 0 { task.start } else { task.due };
+        if dtstart == 0 {
+            return Err(AppError::Validation("a recurring task needs a start or due date".to_string()));
+        }
+        if interval < 1 {
+            return Err(AppError::Validation("a recurring task repeats at least every 1 period".to_string()));
+        }
+        if until < 0 || count < 0 {
+            return Err(AppError::Validation("until and count can't be negative".to_string()));
+        }
+        if until > 0 && until < dtstart {
+            return Err(AppError::Validation("the series would end before it starts".to_string()));
+        }
+        let mut r = Self {
+            key: Uuid::new_v4(),
+            organization_key: task.organization_key,
+            task_key: task.key,
+            frequency,
+            interval,
+            until,
+            count,
+            dtstart,
+            occurrences: 1,
+            next_start: 0,
+            created: chrono::Utc::now().timestamp(),
+            updated: 0,
+        };
+        r.next_start = r.occurrence(1).unwrap_or(0);
+        Ok(r)
+    }
+
+    // The start of occurrence `n`, counting the first one as 0, or None once the series is over.
+    // Months that are too short for the first date's day land on their last day instead.
+    pub fn occurrence(&self, n: i32) -> Option<i64> {
+        if n < 0 || (self.count > 0 && n >= self.count) {
+            return None;
+        }
+        let first = chrono::DateTime::from_timestamp(self.dtstart, 0)?;
+        let steps = n.checked_mul(self.interval)?;
+        let date = match self.frequency {
+            Frequency::Daily => first.checked_add_signed(chrono::Duration::days(steps as i64)),
+            Frequency::Weekly => first.checked_add_signed(chrono::Duration::weeks(steps as i64)),
+            Frequency::Monthly => first.checked_add_months(chrono::Months::new(steps as u32)),
+        }?
+        .timestamp();
+        if self.until > 0 && date > self.until {
+            return None;
+        }
+        Some(date)
+    }
+
+    // A fresh copy of `task` for the next date, due the same length of time after its start
+    pub fn next_task(&self, task: &Task, status_key: uuid::Uuid) -> Task {
+        let (start, due) = match (task.start, task.due) {
+            (0, _) => (0, self.next_start),
+            (_, 0) => (self.next_start, 0),
+            (start, due) => (self.next_start, self.next_start + (due - start)),
+        };
+        Task::new(
+            task.organization_key,
+            task.project_key,
+            task.parent_key,
+            task.milestone_key,
+            task.owner_key,
+            task.assignee_key,
+            task.name.clone(),
+            task.description.clone(),
+            task.tags.clone(),
+            status_key,
+            task.estimated_quarter_days,
+            start,
+            due,
+        )
+    }
+
+    pub fn describe(&self) -> String {
+        let unit = match self.frequency {
+            Frequency::Daily => "day",
+            Frequency::Weekly => "week",
+            Frequency::Monthly => "month",
+        };
+        let every = match self.interval {
+            1 => format!("Repeats every {}", unit),
+            n => format!("Repeats every {} {}s", n, unit),
+        };
+        match chrono::DateTime::from_timestamp(self.next_start, 0) {
+            Some(next) if self.next_start > 0 => format!("{}, next on {}", every, next.format("%Y-%m-%d")),
+            _ => format!("{}, this is the last one", every),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(date: &str) -> i64 {
+        crate::common::day(date).unwrap()
+    }
+
+    fn monthly(start: &str, interval: i32, until: i64, count: i32) -> Recurrence {
+        let nil = Uuid::nil();
+        let task = Task::new(nil, nil, None, None, nil, nil, String::new(), String::new(), String::new(), nil, 0, at(start), 0);
+        Recurrence::new(&task, Frequency::Monthly, interval, until, count).unwrap()
+    }
+
+    #[test]
+    fn month_ends_clamp_to_short_months_and_come_back() {
+        let r = monthly("2024-01-31", 1, 0, 0);
+        assert_eq!(r.next_start, at("2024-02-29"));
+        assert_eq!(r.occurrence(2), Some(at("2024-03-31")));
+        assert_eq!(r.occurrence(3), Some(at("2024-04-30")));
+
+        let r = monthly("2023-01-31", 1, 0, 0);
+        assert_eq!(r.next_start, at("2023-02-28"));
+        assert_eq!(r.occurrence(2), Some(at("2023-03-31")));
+    }
+
+    #[test]
+    fn intervals_count_from_the_first_date() {
+        let r = monthly("2024-08-31", 3, 0, 0);
+        assert_eq!(r.occurrence(1), Some(at("2024-11-30")));
+        assert_eq!(r.occurrence(2), Some(at("2025-02-28")));
+        assert_eq!(r.occurrence(3), Some(at("2025-05-31")));
+    }
+
+    #[test]
+    fn series_end_on_count_and_until() {
+        let r = monthly("2024-01-31", 1, 0, 2);
+        assert_eq!(r.occurrence(1), Some(at("2024-02-29")));
+        assert_eq!(r.occurrence(2), None);
+
+        let r = monthly("2024-01-31", 1, at("2024-03-30"), 0);
+        assert_eq!(r.occurrence(1), Some(at("2024-02-29")));
+        assert_eq!(r.occurrence(2), None);
+    }
+}
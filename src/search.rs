@@ -0,0 +1,287 @@
+use askama::Template;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use sqlx::pool::PoolConnection;
+use sqlx::Postgres;
+
+use tide::{http::mime, Request};
+
+use crate::api::{self, Caller};
+use crate::error::AppError;
+use crate::{user, State};
+
+// what search_documents holds, in the order the facets are listed. Each kind is also the path
+// its pages live under.
+pub const KINDS: [&str; 8] = ["project", "task", "milestone", "note", "entity", "contact", "service_item", "file"];
+
+// SQL STUFF
+
+pub async fn get_facets(conn: &mut PoolConnection<Postgres>, organization_key: uuid::Uuid, terms: &str) -> Result<Vec<Facet>, AppError> {
+    let records = sqlx::query!(
+        r#"select kind as "kind!", count(*) as "count!" from search_documents
+        where organization_key = $1 AND search @@ websearch_to_tsquery('english', $2)
+        group by kind"#,
+        organization_key,
+        terms
+    )
+    .fetch_all(conn)
+    .await?;
+    Ok(KINDS
+        .iter()
+        .filter_map(|k| {
+            records.iter().find(|r| r.kind == *k).map(|r| Facet {
+                kind: k.to_string(),
+                count: r.count,
+            })
+        })
+        .collect())
+}
+
+// The best matches first. ts_headline only runs on the rows of the page, and marks the matched
+// words with control characters so they survive `highlight` escaping the rest.
+pub async fn get_hits(
+    conn: &mut PoolConnection<Postgres>,
+    organization_key: uuid::Uuid,
+    terms: &str,
+    kind: &str,
+    limit: i64,
+    offset: i64,
+) -> Result<Vec<SearchHit>, AppError> {
+    let records = sqlx::query!(
+        r#"select d.kind as "kind!", d.key as "key!", d.title as "title!", ts_rank(d.search, q) as "rank!",
+            ts_headline('english', coalesce(nullif(trim(d.body), ''), d.title), q,
+                'StartSel=' || chr(2) || ', StopSel=' || chr(3) || ', MaxFragments=2, MinWords=5, MaxWords=20') as "headline!"
+        from search_documents d, websearch_to_tsquery('english', $2) q
+        where d.organization_key = $1 AND d.search @@ q AND ($3 = '' OR d.kind = $3)
+        order by 4 desc, 3
+        limit $4 offset $5"#,
+        organization_key,
+        terms,
+        kind,
+        limit,
+        offset
+    )
+    .fetch_all(conn)
+    .await?;
+    Ok(records
+        .into_iter()
+        .map(|r| SearchHit {
+            url: format!("/{}/{}", r.kind, r.key),
+            kind: r.kind,
+            key: r.key,
+            title: r.title,
+            highlight: highlight(&r.headline),
+            rank: r.rank,
+        })
+        .collect())
+}
+
+// Route Stuff
+
+pub async fn get(req: Request<State>) -> tide::Result {
+    let u = match user::user_or_error(&req) {
+        Ok(value) => value,
+        Err(_) => return Ok(tide::Redirect::new("/login").into()),
+    };
+    let query: SearchQuery = req.query()?;
+    let mut conn = req.state().db_pool.acquire().await?;
+    let results = if query.q.trim().is_empty() {
+        SearchResults::empty(&query)
+    } else {
+        search(&mut conn, u.organization_key, &query).await?
+    };
+    Ok(tide::Response::builder(tide::StatusCode::Ok)
+        .content_type(mime::HTML)
+        .body(SearchTemplate::new(u, results).render_string())
+        .build())
+}
+
+pub async fn get_json(req: Request<State>) -> tide::Result {
+    let caller = Caller::from_request(&req)?;
+    let query: SearchQuery = req
+        .query()
+        .map_err(|e| AppError::Validation(format!("bad search parameters: {}", e)))?;
+    if query.q.trim().is_empty() {
+        return Err(AppError::Validation("q is what to search for and can't be empty".to_string()).into());
+    }
+    let mut conn = req.state().db_pool.acquire().await?;
+    let results = search(&mut conn, caller.organization_key, &query).await?;
+    Ok(api::json(tide::StatusCode::Ok, &results))
+}
+
+async fn search(conn: &mut PoolConnection<Postgres>, organization_key: uuid::Uuid, query: &SearchQuery) -> Result<SearchResults, AppError> {
+    if !query.kind.is_empty() && !KINDS.contains(&query.kind.as_str()) {
+        return Err(AppError::Validation(format!("kind has to be one of {}", KINDS.join(", "))));
+    }
+    if query.page == 0 || query.per_page == 0 || query.per_page > api::MAX_PER_PAGE {
+        return Err(AppError::Validation(format!("page starts at 1 and per_page is 1 to {}", api::MAX_PER_PAGE)));
+    }
+    let terms = query.q.trim();
+    let facets = get_facets(conn, organization_key, terms).await?;
+    let total = facets
+        .iter()
+        .filter(|f| query.kind.is_empty() || f.kind == query.kind)
+        .map(|f| f.count)
+        .sum();
+    let data = get_hits(
+        conn,
+        organization_key,
+        terms,
+        &query.kind,
+        query.per_page as i64,
+        api::offset(query.page, query.per_page)?,
+    )
+    .await?;
+    Ok(SearchResults {
+        q: terms.to_string(),
+        kind: query.kind.clone(),
+        facets,
+        data,
+        page: query.page,
+        per_page: query.per_page,
+        total,
+    })
+}
+
+// the headline with everything but the matched words escaped, so it can go into the page as is
+pub fn highlight(headline: &str) -> String {
+    let mut html = String::with_capacity(headline.len());
+    for c in headline.chars() {
+        match c {
+            '\u{2}' => html.push_str("<mark>"),
+            '\u{3}' => html.push_str("</mark>"),
+            '&' => html.push_str("&amp;"),
+            '<' => html.push_str("&lt;"),
+            '>' => html.push_str("&gt;"),
+            '"' => html.push_str("&quot;"),
+            '\'' => html.push_str("&#x27;"),
+            c => html.push(c),
+        }
+    }
+    html
+}
+
+fn link(q: &str, kind: &str, page: usize) -> String {
+    let mut url = tide::http::Url::parse("http://localhost/search").expect("static url");
+    url.query_pairs_mut()
+        .append_pair("q", q)
+        .append_pair("kind", kind)
+        .append_pair("page", &page.to_string());
+    format!("/search?{}", url.query().unwrap_or_default())
+}
+
+pub fn kind_name(kind: &str) -> String {
+    match kind {
+        "service_item" => "Service Item".to_string(),
+        k => {
+            let mut name = k.to_string();
+            if let Some(first) = name.get_mut(0..1) {
+                first.make_ascii_uppercase();
+            }
+            name
+        }
+    }
+}
+
+// data types
+
+#[derive(Debug, Deserialize)]
+pub struct SearchQuery {
+    #[serde(default)]
+    pub q: String,
+    #[serde(default)]
+    pub kind: String,
+    #[serde(default = "first_page")]
+    pub page: usize,
+    #[serde(default = "default_per_page")]
+    pub per_page: usize,
+}
+
+fn first_page() -> usize {
+    1
+}
+
+fn default_per_page() -> usize {
+    api::DEFAULT_PER_PAGE
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct Facet {
+    pub kind: String,
+    pub count: i64,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct SearchHit {
+    pub kind: String,
+    pub key: uuid::Uuid,
+    pub title: String,
+    // html, the matched words are wrapped in <mark> and the rest is escaped
+    pub highlight: String,
+    pub url: String,
+    pub rank: f32,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct SearchResults {
+    pub q: String,
+    pub kind: String,
+    pub facets: Vec<Facet>,
+    pub data: Vec<SearchHit>,
+    pub page: usize,
+    pub per_page: usize,
+    pub total: i64,
+}
+
+impl SearchResults {
+    fn empty(query: &SearchQuery) -> Self {
+        Self {
+            q: String::new(),
+            kind: query.kind.clone(),
+            facets: vec![],
+            data: vec![],
+            page: 1,
+            per_page: query.per_page,
+            total: 0,
+        }
+    }
+
+    pub fn pages(&self) -> usize {
+        let per_page = self.per_page.max(1);
+        (self.total as usize).div_ceil(per_page)
+    }
+}
+
+#[derive(Template)]
+#[template(path = "search.html")]
+pub struct SearchTemplate {
+    user: crate::user::User,
+    results: SearchResults,
+}
+
+impl<'a> SearchTemplate {
+    pub fn new(user: crate::user::User, results: SearchResults) -> Self {
+        return Self { user, results };
+    }
+
+    pub fn kind_name<'aa>(&'aa self, kind: &str) -> String {
+        kind_name(kind)
+    }
+
+    // the same search narrowed to one kind, or "" for every kind
+    pub fn kind_link<'aa>(&'aa self, kind: &str) -> String {
+        link(&self.results.q, kind, 1)
+    }
+
+    pub fn previous_link<'aa>(&'aa self) -> String {
+        link(&self.results.q, &self.results.kind, self.results.page - 1)
+    }
+
+    pub fn next_link<'aa>(&'aa self) -> String {
+        link(&self.results.q, &self.results.kind, self.results.page + 1)
+    }
+
+    pub fn render_string(&self) -> String {
+        return self.render().unwrap();
+    }
+}
@@ -1,6 +1,7 @@
 use std::str::FromStr;
 
 use askama::Template;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use sqlx::pool::PoolConnection;
 use sqlx::Postgres;
@@ -11,80 +12,121 @@ use uuid::Uuid;
 
 use tide::{http::mime, Request};
 
+use crate::error::AppError;
 use crate::home::NotFoundTemplate;
 use crate::organization::{get_organization, Organization};
 use crate::{State, user};
  
 // SQL STUFF
 
-pub async fn get_organization_service_items(conn: &mut PoolConnection<Postgres>, organization_key: uuid::Uuid) -> Vec<ServiceItem> {
+pub async fn get_organization_service_items(conn: &mut PoolConnection<Postgres>, organization_key: uuid::Uuid) -> Result<Vec<ServiceItem>, AppError> {
     let records = sqlx::query!(
         "select  key, owner_key, organization_key, external_accounting_id, name, description, value, currency, service_item_type, service_value_type, expenses, created, updated from service_items where organization_key = $1",
         organization_key
     )
     .fetch_all(conn)
-    .await
-    .expect("Select project by key");
+    .await?;
     let mut service_items = Vec::<ServiceItem>::new();
     for service_item in records { 
-        let service_item_type: ServiceItemType = service_item.service_item_type.expect("service_item_type exists").into();
-        let service_value_type: ServiceValueType = service_item.service_value_type.expect("service_value_type exists").into();
+        let service_item_type: ServiceItemType = service_item.service_item_type.into();
+        let service_value_type: ServiceValueType = service_item.service_value_type.into();
         let svc = ServiceItem {
-            key: service_item.key.expect("key exists"),
-            organization_key: service_item.organization_key.expect("organization_key"), 
-            external_accounting_id: service_item.external_accounting_id.expect("external_accounting_id"),
-            owner_key: service_item.owner_key.expect("owner_key exists"),
-            name: service_item.name.expect("name exists"),
-            description: service_item.description.expect("description exists"), 
-            value: service_item.value.expect("value exists"),
-            currency: service_item.currency.expect("currency exists"), 
+            key: service_item.key,
+            organization_key: service_item.organization_key, 
+            external_accounting_id: service_item.external_accounting_id,
+            owner_key: service_item.owner_key,
+            name: service_item.name,
+            description: service_item.description, 
+            value: service_item.value,
+            currency: service_item.currency, 
             service_item_type,
             service_value_type,
-            expenses: service_item.expenses.expect("expenses exists"),
-            created: service_item.created.expect("created exists"),
-            updated: service_item.updated.expect("updated exists"),
+            expenses: service_item.expenses,
+            created: service_item.created,
+            updated: service_item.updated,
         };
         service_items.push(svc);
     }
-    return service_items
+    Ok(service_items)
 }
 
-pub async fn get_service_item(conn: &mut PoolConnection<Postgres>, key: uuid::Uuid) -> ServiceItem {
-    let service_item = sqlx::query!(
-        "select key, owner_key, organization_key, external_accounting_id, name, description, value, currency, service_item_type, service_value_type, expenses, created, updated from service_items where key = $1",
-        key
+pub async fn get_organization_service_items_page(conn: &mut PoolConnection<Postgres>, organization_key: uuid::Uuid, limit: i64, offset: i64) -> Result<Vec<ServiceItem>, AppError> {
+    let records = sqlx::query!(
+        "select  key, owner_key, organization_key, external_accounting_id, name, description, value, currency, service_item_type, service_value_type, expenses, created, updated from service_items where organization_key = $1 order by created, key limit $2 offset $3",
+        organization_key, limit, offset
     )
-    .fetch_one(conn)
-    .await
-    .expect("Select service_item by key"); 
+    .fetch_all(conn)
+    .await?;
+    let mut service_items = Vec::<ServiceItem>::new();
+    for service_item in records { 
+        let service_item_type: ServiceItemType = service_item.service_item_type.into();
+        let service_value_type: ServiceValueType = service_item.service_value_type.into();
+        let svc = ServiceItem {
+            key: service_item.key,
+            organization_key: service_item.organization_key, 
+            external_accounting_id: service_item.external_accounting_id,
+            owner_key: service_item.owner_key,
+            name: service_item.name,
+            description: service_item.description, 
+            value: service_item.value,
+            currency: service_item.currency, 
+            service_item_type,
+            service_value_type,
+            expenses: service_item.expenses,
+            created: service_item.created,
+            updated: service_item.updated,
+        };
+        service_items.push(svc);
+    }
+    Ok(service_items)
+}
+
+pub async fn count_organization_service_items(conn: &mut PoolConnection<Postgres>, organization_key: uuid::Uuid) -> Result<i64, AppError> {
+    let record = sqlx::query!(r#"select count(*) as "count!" from service_items where organization_key = $1"#, organization_key)
+        .fetch_one(conn)
+        .await?;
+    Ok(record.count)
+}
+
+pub async fn get_service_item(conn: &mut PoolConnection<Postgres>, key: uuid::Uuid, organization_key: uuid::Uuid) -> Result<Option<ServiceItem>, AppError> {
+    let service_item = match sqlx::query!(
+        "select key, owner_key, organization_key, external_accounting_id, name, description, value, currency, service_item_type, service_value_type, expenses, created, updated from service_items where key = $1 AND organization_key = $2",
+        key, organization_key
+    )
+    .fetch_optional(conn)
+    .await?
+    {
+        Some(r) => r,
+        None => return Ok(None),
+    }; 
     
-    let service_item_type: ServiceItemType = service_item.service_item_type.expect("service_item_type exists").into();
-    let service_value_type: ServiceValueType = service_item.service_value_type.expect("service_value_type exists").into();
-    ServiceItem {
-        key: service_item.key.expect("key exists"),
-        organization_key: service_item.organization_key.expect("organization_key"), 
-        external_accounting_id: service_item.external_accounting_id.expect("external_accounting_id"), 
-        owner_key: service_item.owner_key.expect("owner_key exists"),
-        name: service_item.name.expect("name exists"),
-        description: service_item.description.expect("description exists"),
-        value: service_item.value.expect("value exists"),
-        currency: service_item.currency.expect("currency exists"), 
+    let service_item_type: ServiceItemType = service_item.service_item_type.into();
+    let service_value_type: ServiceValueType = service_item.service_value_type.into();
+    Ok(Some(ServiceItem {
+        key: service_item.key,
+        organization_key: service_item.organization_key, 
+        external_accounting_id: service_item.external_accounting_id, 
+        owner_key: service_item.owner_key,
+        name: service_item.name,
+        description: service_item.description,
+        value: service_item.value,
+        currency: service_item.currency, 
         service_item_type,
         service_value_type,
-        expenses: service_item.expenses.expect("expenses exists"),
-        created: service_item.created.expect("created exists"),
-        updated: service_item.updated.expect("updated exists"),
-    }
+        expenses: service_item.expenses,
+        created: service_item.created,
+        updated: service_item.updated,
+    }))
 }
 
-async fn delete_service_item(conn: &mut PoolConnection<Postgres>, key: uuid::Uuid, owner_key: uuid::Uuid) -> Result<PgQueryResult, sqlx::Error> {
-    return sqlx::query!("DELETE FROM service_items where owner_key=$1 AND key=$2", owner_key, key)
+pub(crate) async fn delete_service_item(conn: &mut PoolConnection<Postgres>, key: uuid::Uuid, organization_key: uuid::Uuid) -> Result<PgQueryResult, sqlx::Error> {
+    return sqlx::query!("DELETE FROM service_items where organization_key=$1 AND key=$2", organization_key, key)
     .execute(conn)
     .await;
 }
 
-async fn update_service_item(conn: &mut PoolConnection<Postgres>, service_item: &ServiceItem) {
-    sqlx::query!("UPDATE service_items SET name=$1, description=$2, value=$3, currency=$4, service_item_type=$5, service_value_type=$6, expenses=$7, external_accounting_id=$8 where key=$9", 
+pub(crate) async fn update_service_item(conn: &mut PoolConnection<Postgres>, service_item: &ServiceItem) -> Result<(), AppError> {
+    sqlx::query!("UPDATE service_items SET name=$1, description=$2, value=$3, currency=$4, service_item_type=$5, service_value_type=$6, expenses=$7, external_accounting_id=$8 where key=$9 AND organization_key=$10", 
         &service_item.name,
         &service_item.description,
         &service_item.value,
@@ -94,12 +136,13 @@ async fn update_service_item(conn: &mut PoolConnection<Postgres>, service_item:
         &service_item.expenses,
         &service_item.external_accounting_id,
         service_item.key,
+        service_item.organization_key,
     )
     .execute(conn)
-    .await
-    .expect("Insert Success");
+    .await?;
+    Ok(())
 }
-pub async fn insert_service_item(conn: &mut PoolConnection<Postgres>, new_service_item: &ServiceItem) {
+pub async fn insert_service_item(conn: &mut PoolConnection<Postgres>, new_service_item: &ServiceItem) -> Result<(), AppError> {
     sqlx::query!("INSERT INTO service_items (key, organization_key, owner_key, name, description, value, currency, service_item_type, service_value_type, expenses, external_accounting_id, created, updated) values($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13)", 
         new_service_item.key,
         new_service_item.organization_key, 
@@ -116,8 +159,8 @@ pub async fn insert_service_item(conn: &mut PoolConnection<Postgres>, new_servic
         new_service_item.updated,
     )
     .execute(conn)
-    .await
-    .expect("Insert Success");
+    .await?;
+    Ok(())
 }
  
 
@@ -129,7 +172,7 @@ pub async fn add(req: Request<State>) -> tide::Result {
     let mut service_item= ServiceItem::new( u.organization_key, String::default(), u.key,  "".to_owned(), "".to_owned(), 0, "USD".to_string(),  ServiceItemType::Service, ServiceValueType::Full, vec![]);
     service_item.key = uuid::Uuid::nil();
     let mut conn = req.state().db_pool.acquire().await?;
-    let organization = get_organization(&mut conn, u.organization_key).await;
+    let organization = get_organization(&mut conn, u.organization_key).await?;
     Ok(tide::Response::builder(tide::StatusCode::Ok)
     .content_type(mime::HTML)
     .body(
@@ -152,8 +195,8 @@ pub async fn delete(req: Request<State>) -> tide::Result {
     match req.param("service_item_id") {
         Ok(key) => {
             let mut conn = req.state().db_pool.acquire().await?;
-            let s_uuid = uuid::Uuid::from_str(key).expect("ServiceItem uuid parse");
-            match delete_service_item(&mut conn, s_uuid, u.key).await {
+            let s_uuid = crate::error::parse_key(key, "service item")?;
+            match delete_service_item(&mut conn, s_uuid, u.organization_key).await {
                 Ok(_) =>    Ok(tide::Response::builder(tide::StatusCode::Ok)
                 .content_type(mime::HTML)
                 .build()),
@@ -174,7 +217,7 @@ pub async fn delete(req: Request<State>) -> tide::Result {
 }
 
 pub async fn get(req: Request<State>) -> tide::Result {
-    let u = match crate::user::read_jwt_cookie_to_user(req.cookie("token")) {
+    let u = match crate::user::request_user(&req) {
         Some(c) => c,
         None => {
             return Ok(tide::Redirect::new("/login").into());
@@ -184,9 +227,17 @@ pub async fn get(req: Request<State>) -> tide::Result {
     match req.param("service_item_id") {
         Ok(key) => {
             let mut conn = req.state().db_pool.acquire().await?; // .await? needs to be a real connection pool error handler!!!!!!!!
-            let s_uuid = uuid::Uuid::from_str(key).expect("ServiceItem uuid parse");
-            let service_item = get_service_item(&mut conn, s_uuid).await;
-            let organization = get_organization(&mut conn, u.organization_key).await;
+            let s_uuid = crate::error::parse_key(key, "service item")?;
+            let service_item = match get_service_item(&mut conn, s_uuid, u.organization_key).await? {
+                Some(s) => s,
+                None => {
+                    return Ok(tide::Response::builder(tide::StatusCode::NotFound)
+                        .content_type(mime::HTML)
+                        .body(NotFoundTemplate::new().render_string())
+                        .build())
+                }
+            };
+            let organization = get_organization(&mut conn, u.organization_key).await?;
             Ok(tide::Response::builder(tide::StatusCode::Ok)
                 .content_type(mime::HTML)
                 .body(
@@ -211,14 +262,15 @@ pub async fn get(req: Request<State>) -> tide::Result {
 
 pub async fn insert(mut req: Request<State>) -> tide::Result {
     let umd: Result<ServiceItem, tide::Error> = req.body_json().await;
-    let _claims: user::UserJwtState = match user::read_jwt_cookie(req.cookie("token")) {
+    let claims: user::UserJwtState = match user::request_claims(&req) {
         Some(c) => c,
         None => {
             return Ok(tide::Redirect::new("/login").into());
         },
     };
     match umd {
-        Ok(service_item) => {
+        Ok(mut service_item) => {
+            service_item.organization_key = crate::error::parse_key(claims.organization_key.as_str(), "organization")?;
             let mut conn = match req.state().db_pool.acquire().await {
                 Ok(c) => c,
                 Err(e) => {
@@ -233,7 +285,7 @@ pub async fn insert(mut req: Request<State>) -> tide::Result {
             if service_item.key == uuid::Uuid::nil() { 
                 let s = ServiceItem::new(service_item.organization_key,service_item.external_accounting_id, service_item.owner_key, service_item.name, service_item.description,
                      service_item.value, service_item.currency, service_item.service_item_type, service_item.service_value_type, service_item.expenses);
-                insert_service_item(&mut conn, &s).await;
+                insert_service_item(&mut conn, &s).await?;
                 //let organization_key = uuid::Uuid::from_str(claims.organization_key.as_str()).expect("organization key");
                 // post_service_item_create(&mut conn, claims.matrix_home_server, claims.matrix_user_id, organization_key, claims.matrix_access_token, &s).await.expect("Posting to matrix");
                 let j = serde_json::to_string(&s).expect("To JSON");
@@ -243,24 +295,18 @@ pub async fn insert(mut req: Request<State>) -> tide::Result {
                     .build());
             }
             
-            update_service_item(&mut conn, &service_item).await;
+            update_service_item(&mut conn, &service_item).await?;
             let j = serde_json::to_string(&service_item).expect("To JSON");
             Ok(tide::Response::builder(tide::StatusCode::Ok)
                 .content_type(mime::JSON)
                 .body(j)
                 .build())
         }
-        Err(e) => {
-            println!("{:?}", e);
-            Ok(tide::Response::builder(tide::StatusCode::BadRequest)
-                .content_type(mime::JSON)
-                .body("{'error': 'invalid json body'}")
-                .build())
-        }
+        Err(e) => Err(AppError::Validation(format!("invalid json body: {}", e)).into()),
     }
 }
 
-#[derive(PartialEq, Debug, Deserialize, Serialize, Clone, Copy, sqlx::Type, EnumIter)]
+#[derive(PartialEq, Debug, Deserialize, Serialize, JsonSchema, Clone, Copy, sqlx::Type, EnumIter)]
 pub enum ServiceValueType {
     Hourly,
     Milestone,
@@ -310,7 +356,7 @@ impl From<ServiceValueType> for i16 {
         }
     }
 }
-#[derive(PartialEq, Debug, Deserialize, Serialize, Clone, Copy, sqlx::Type, EnumIter)]
+#[derive(PartialEq, Debug, Deserialize, Serialize, JsonSchema, Clone, Copy, sqlx::Type, EnumIter)]
 pub enum ServiceItemType {
     Service,
     Item,
@@ -344,7 +390,7 @@ impl From<ServiceItemType> for i16 {
     }
 } 
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
 pub struct ServiceItem {
     pub key: uuid::Uuid,
     pub organization_key: uuid::Uuid,
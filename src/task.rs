@@ -1,153 +1,383 @@
-use std::str::FromStr;
-
 use askama::Template;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use sqlx::pool::PoolConnection;
-use sqlx::Postgres;
+use sqlx::{Connection, PgConnection, Postgres};
 use sqlx::postgres::PgQueryResult;
 use strum::IntoEnumIterator;
-use strum_macros::EnumIter;
 use uuid::Uuid;
 
 use tide::{http::mime, Request};
 
+use crate::activity::Activity;
+use crate::api::{report_announcement, report_matrix};
+use crate::checklist::ChecklistItem;
+use crate::comment::Comment;
+use crate::dependency::{Dependency, DependencyKind};
+use crate::error::AppError;
 use crate::home::NotFoundTemplate;
 use crate::{State, user};
-use crate::matrix::post_task_create;
+use crate::matrix::{post_task_create, post_task_move};
+use crate::recurrence::{Frequency, Recurrence};
+use crate::time_entry::TimeEntry;
+use crate::workflow::Workflow;
  
 // SQL STUFF
 
-pub async fn get_task( conn: &mut PoolConnection<Postgres>, key: uuid::Uuid) -> Task {
-    let task = sqlx::query!(
-        "select key, owner_key, organization_key, project_key, assignee_key, name, description, tags, status, estimated_quarter_days, start, due, created, updated from tasks where key = $1",
-        key
+pub async fn get_task( conn: &mut PoolConnection<Postgres>, key: uuid::Uuid, organization_key: uuid::Uuid) -> Result<Option<Task>, AppError> {
+    let task = match sqlx::query!(
+        "select key, owner_key, organization_key, project_key, parent_key, milestone_key, assignee_key, name, description, tags, status_key, rank, estimated_quarter_days, start, due, created, updated from tasks where key = $1 AND organization_key = $2",
+        key, organization_key
     )
-    .fetch_one(conn)
-    .await
-    .expect("Select task by key");
-
-    let t_status: TaskStatus = task.status.expect("status exists").into();
-    Task {
-        key: task.key.expect("key exists"),
-        organization_key: task.organization_key.expect("organization_key"), 
-        project_key: task.project_key.expect("project_key"), 
-        owner_key: task.owner_key.expect("owner_key exists"),
-        assignee_key: task.assignee_key.expect("assignee_key exists"),
-        name: task.name.expect("name exists"),
-        description: task.description.expect("description exists"),
-        tags: task.tags.expect("tags exists"),
-        status: t_status,
-        estimated_quarter_days: task.estimated_quarter_days.expect("estimated_quarter_days exists"),
-        start: task.start.expect("start"),
-        due:task.due.expect("due"),
-        created: task.created.expect("created exists"),
-        updated: task.updated.expect("updated exists"),
-    }
+    .fetch_optional(conn)
+    .await?
+    {
+        Some(r) => r,
+        None => return Ok(None),
+    };
+
+    Ok(Some(Task {
+        key: task.key,
+        organization_key: task.organization_key, 
+        project_key: task.project_key, 
+        parent_key: task.parent_key,
+        milestone_key: task.milestone_key,
+        owner_key: task.owner_key,
+        assignee_key: task.assignee_key,
+        name: task.name,
+        description: task.description,
+        tags: task.tags,
+        status_key: task.status_key,
+        rank: task.rank,
+        estimated_quarter_days: task.estimated_quarter_days,
+        start: task.start,
+        due:task.due,
+        created: task.created,
+        updated: task.updated,
+    }))
 }
-pub async fn get_tasks_by_organization(conn: &mut PoolConnection<Postgres>,organization_key: uuid::Uuid) -> Vec<Task> {
+pub async fn get_tasks_by_organization(conn: &mut PoolConnection<Postgres>,organization_key: uuid::Uuid) -> Result<Vec<Task>, AppError> {
     let records = sqlx::query!(
-        "select key, owner_key, organization_key, project_key, assignee_key, name, description, tags, status, estimated_quarter_days, start, due, created, updated from tasks where organization_key = $1",
+        "select key, owner_key, organization_key, project_key, parent_key, milestone_key, assignee_key, name, description, tags, status_key, rank, estimated_quarter_days, start, due, created, updated from tasks where organization_key = $1",
         organization_key
     )
     .fetch_all(conn)
-    .await
-    .unwrap_or_default();
+    .await?;
 
     let mut tasks = Vec::<Task>::new();
     for task in records {
-        let t_status: TaskStatus = task.status.expect("status exists").into();
         let tsk = Task {
-            key: task.key.expect("key exists"),
-            organization_key: task.organization_key.expect("organization_key"), 
-            project_key: task.project_key.expect("project_key"), 
-            owner_key: task.owner_key.expect("owner_key exists"),
-            assignee_key: task.assignee_key.expect("assignee_key exists"),
-            name: task.name.expect("name exists"),
-            description: task.description.expect("description exists"),
-            tags: task.tags.expect("tags exists"),
-            status: t_status,
-            estimated_quarter_days: task.estimated_quarter_days.expect("estimated_quarter_days exists"),
-            start: task.start.expect("start"),
-            due:task.due.expect("due"),
-            created: task.created.expect("created exists"),
-            updated: task.updated.expect("updated exists"),
+            key: task.key,
+            organization_key: task.organization_key, 
+            project_key: task.project_key, 
+            parent_key: task.parent_key,
+            milestone_key: task.milestone_key,
+            owner_key: task.owner_key,
+            assignee_key: task.assignee_key,
+            name: task.name,
+            description: task.description,
+            tags: task.tags,
+            status_key: task.status_key,
+            rank: task.rank,
+            estimated_quarter_days: task.estimated_quarter_days,
+            start: task.start,
+            due:task.due,
+            created: task.created,
+            updated: task.updated,
         };
         tasks.push(tsk);
     }
-    return tasks
+    Ok(tasks)
 }
-pub async fn get_tasks_by_project(conn: &mut PoolConnection<Postgres>,project_key: uuid::Uuid) -> Vec<Task> {
+
+pub async fn get_tasks_by_organization_page(conn: &mut PoolConnection<Postgres>,organization_key: uuid::Uuid, limit: i64, offset: i64) -> Result<Vec<Task>, AppError> {
     let records = sqlx::query!(
-        "select key, owner_key, organization_key, project_key, assignee_key, name, description, tags, status, estimated_quarter_days, start, due, created, updated from tasks where project_key = $1",
-        project_key
+        "select key, owner_key, organization_key, project_key, parent_key, milestone_key, assignee_key, name, description, tags, status_key, rank, estimated_quarter_days, start, due, created, updated from tasks where organization_key = $1 order by created, key limit $2 offset $3",
+        organization_key, limit, offset
     )
     .fetch_all(conn)
-    .await
-    .unwrap_or_default();
+    .await?;
 
     let mut tasks = Vec::<Task>::new();
     for task in records {
-        let t_status: TaskStatus = task.status.expect("status exists").into();
         let tsk = Task {
-            key: task.key.expect("key exists"),
-            organization_key: task.organization_key.expect("organization_key"), 
-            project_key: task.project_key.expect("project_key"), 
-            owner_key: task.owner_key.expect("owner_key exists"),
-            assignee_key: task.assignee_key.expect("assignee_key exists"),
-            name: task.name.expect("name exists"),
-            description: task.description.expect("description exists"),
-            tags: task.tags.expect("tags exists"),
-            status: t_status,
-            estimated_quarter_days: task.estimated_quarter_days.expect("estimated_quarter_days exists"),
-            start: task.start.expect("start"),
-            due:task.due.expect("due"),
-            created: task.created.expect("created exists"),
-            updated: task.updated.expect("updated exists"),
+            key: task.key,
+            organization_key: task.organization_key, 
+            project_key: task.project_key, 
+            parent_key: task.parent_key,
+            milestone_key: task.milestone_key,
+            owner_key: task.owner_key,
+            assignee_key: task.assignee_key,
+            name: task.name,
+            description: task.description,
+            tags: task.tags,
+            status_key: task.status_key,
+            rank: task.rank,
+            estimated_quarter_days: task.estimated_quarter_days,
+            start: task.start,
+            due:task.due,
+            created: task.created,
+            updated: task.updated,
         };
         tasks.push(tsk);
     }
-    return tasks
+    Ok(tasks)
 }
 
+// Every task below the given ones at any depth, enough for their children and rollups without
+// loading the whole organization
+pub async fn get_descendant_tasks(conn: &mut PoolConnection<Postgres>, organization_key: uuid::Uuid, keys: &[uuid::Uuid]) -> Result<Vec<Task>, AppError> {
+    let records = sqlx::query!(
+        "select key, owner_key, organization_key, project_key, parent_key, milestone_key, assignee_key, name, description, tags, status_key, rank, estimated_quarter_days, start, due, created, updated from tasks
+        where organization_key = $1 AND key in (
+            with recursive below(key) as (
+                select key from tasks where organization_key = $1 AND parent_key = ANY($2)
+                union
+                select t.key from tasks t inner join below b on t.parent_key = b.key where t.organization_key = $1
+            ) select key from below
+        )",
+        organization_key,
+        keys
+    )
+    .fetch_all(conn)
+    .await?;
+
+    let mut tasks = Vec::<Task>::new();
+    for task in records {
+        let tsk = Task {
+            key: task.key,
+            organization_key: task.organization_key, 
+            project_key: task.project_key, 
+            parent_key: task.parent_key,
+            milestone_key: task.milestone_key,
+            owner_key: task.owner_key,
+            assignee_key: task.assignee_key,
+            name: task.name,
+            description: task.description,
+            tags: task.tags,
+            status_key: task.status_key,
+            rank: task.rank,
+            estimated_quarter_days: task.estimated_quarter_days,
+            start: task.start,
+            due:task.due,
+            created: task.created,
+            updated: task.updated,
+        };
+        tasks.push(tsk);
+    }
+    Ok(tasks)
+}
 
-async fn delete_task( conn: &mut PoolConnection<Postgres>, key: uuid::Uuid, owner_key: uuid::Uuid) -> Result<PgQueryResult, sqlx::Error> {
-    return sqlx::query!("DELETE FROM tasks where owner_key=$1 AND key=$2", owner_key, key)
+pub async fn count_tasks_by_organization(conn: &mut PoolConnection<Postgres>, organization_key: uuid::Uuid) -> Result<i64, AppError> {
+    let record = sqlx::query!(r#"select count(*) as "count!" from tasks where organization_key = $1"#, organization_key)
+        .fetch_one(conn)
+        .await?;
+    Ok(record.count)
+}
+pub async fn get_tasks_by_project(conn: &mut PoolConnection<Postgres>,project_key: uuid::Uuid, organization_key: uuid::Uuid) -> Result<Vec<Task>, AppError> {
+    let records = sqlx::query!(
+        "select key, owner_key, organization_key, project_key, parent_key, milestone_key, assignee_key, name, description, tags, status_key, rank, estimated_quarter_days, start, due, created, updated from tasks where project_key = $1 AND organization_key = $2",
+        project_key, organization_key
+    )
+    .fetch_all(conn)
+    .await?;
+
+    let mut tasks = Vec::<Task>::new();
+    for task in records {
+        let tsk = Task {
+            key: task.key,
+            organization_key: task.organization_key, 
+            project_key: task.project_key, 
+            parent_key: task.parent_key,
+            milestone_key: task.milestone_key,
+            owner_key: task.owner_key,
+            assignee_key: task.assignee_key,
+            name: task.name,
+            description: task.description,
+            tags: task.tags,
+            status_key: task.status_key,
+            rank: task.rank,
+            estimated_quarter_days: task.estimated_quarter_days,
+            start: task.start,
+            due:task.due,
+            created: task.created,
+            updated: task.updated,
+        };
+        tasks.push(tsk);
+    }
+    Ok(tasks)
+}
+
+
+pub(crate) async fn delete_task( conn: &mut PoolConnection<Postgres>, key: uuid::Uuid, organization_key: uuid::Uuid) -> Result<PgQueryResult, sqlx::Error> {
+    return sqlx::query!("DELETE FROM tasks where organization_key=$1 AND key=$2", organization_key, key)
     .execute( conn)
     .await;
 }
 
-async fn insert_task( conn: &mut PoolConnection<Postgres>, new_task: &Task) {
-    sqlx::query!("INSERT INTO tasks (key, owner_key, organization_key, project_key, assignee_key, name, description, tags, status, estimated_quarter_days, start, due, created, updated) values($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14)", 
+pub(crate) async fn insert_task( conn: &mut PgConnection, new_task: &Task) -> Result<(), AppError> {
+    // new tasks go to the bottom of their column
+    sqlx::query!("INSERT INTO tasks (key, owner_key, organization_key, project_key, parent_key, assignee_key, name, description, tags, status_key, estimated_quarter_days, start, due, created, updated, milestone_key, rank) values($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16,
+        (select coalesce(max(rank) + 1, 0) from tasks where organization_key = $3 AND status_key = $10))", 
         new_task.key,
         new_task.owner_key,
         new_task.organization_key,
         new_task.project_key, 
+        new_task.parent_key,
         new_task.assignee_key,
         &new_task.name,
         &new_task.description,
         &new_task.tags,
-        new_task.status as i16,
+        new_task.status_key,
         new_task.estimated_quarter_days,
         new_task.start,
         new_task.due,
         new_task.created,
         new_task.updated,
+        new_task.milestone_key,
     )
     .execute( conn)
-    .await
-    .expect("Insert Success");
+    .await?;
+    Ok(())
 }
 
-async fn update_task(conn: &mut PoolConnection<Postgres>, task: &Task) {
+pub(crate) async fn update_task(conn: &mut PoolConnection<Postgres>, task: &Task) -> Result<(), AppError> {
     sqlx::query!("UPDATE tasks SET 
-    organization_key=$2, project_key=$3, owner_key=$4, assignee_key=$5, name=$6, 
-    description=$7, tags=$8, status=$9, estimated_quarter_days=$10, start=$11, due=$12, 
-    updated=extract(epoch from now()) where key = $1",
+    project_key=$3, owner_key=$4, assignee_key=$5, name=$6, 
+    description=$7, tags=$8, status_key=$9, estimated_quarter_days=$10, start=$11, due=$12, parent_key=$13, milestone_key=$14,
+    rank=CASE WHEN status_key = $9 THEN rank ELSE (select coalesce(max(t.rank) + 1, 0) from tasks t where t.organization_key = $2 AND t.status_key = $9) END,
+    updated=extract(epoch from now()) where key = $1 AND organization_key = $2",
      task.key, task.organization_key, task.project_key, task.owner_key, task.assignee_key, task.name,task.description, 
-     task.tags, task.status as i16, task.estimated_quarter_days, task.start, task.due)
+     task.tags, task.status_key, task.estimated_quarter_days, task.start, task.due, task.parent_key, task.milestone_key)
     .execute(conn)
-    .await
-    .expect("Insert Success");
+    .await?;
+    Ok(())
+}
+
+// Card moves and new dependencies in one organization take turns on this lock, it is held until
+// the transaction around it ends
+pub(crate) async fn lock_organization(conn: &mut PgConnection, organization_key: uuid::Uuid) -> Result<(), AppError> {
+    sqlx::query("select pg_advisory_xact_lock(hashtext($1))")
+        .bind(organization_key.to_string())
+        .execute(conn)
+        .await?;
+    Ok(())
+}
+
+// Puts `task` into the `status_key` column just below `after_key`, or at the top without one, and
+// renumbers the column. Moves in one organization are serialized on an advisory lock so two people
+// dragging cards at once can't hand out the same rank. The workflow is checked against the status
+// the task has once the lock is held, which is returned, not the one it had when it was loaded.
+pub(crate) async fn move_task(
+    conn: &mut PoolConnection<Postgres>,
+    task: &Task,
+    workflow: &crate::workflow::Workflow,
+    status_key: uuid::Uuid,
+    after_key: Option<uuid::Uuid>,
+) -> Result<uuid::Uuid, AppError> {
+    let mut tx = conn.begin().await?;
+    lock_organization(&mut tx, task.organization_key).await?;
+    let from_key = match sqlx::query!(
+        "select status_key from tasks where key = $1 AND organization_key = $2",
+        task.key,
+        task.organization_key
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    {
+        Some(r) => r.status_key,
+        None => return Err(AppError::NotFound("task".to_string())),
+    };
+    workflow.check_move(Some(from_key), status_key)?;
+    let column = sqlx::query!(
+        "select key from tasks where organization_key = $1 AND status_key = $2 AND key <> $3 order by rank, created",
+        task.organization_key,
+        status_key,
+        task.key
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+    let mut keys: Vec<uuid::Uuid> = column.into_iter().map(|r| r.key).collect();
+    let index = match after_key {
+        Some(after_key) => match keys.iter().position(|k| *k == after_key) {
+            Some(i) => i + 1,
+            None => return Err(AppError::Validation("the card to drop below isn't in that column anymore".to_string())),
+        },
+        None => 0,
+    };
+    keys.insert(index, task.key);
+    let ranks: Vec<i32> = (0..keys.len() as i32).collect();
+    sqlx::query!(
+        "UPDATE tasks SET status_key=$3, updated=extract(epoch from now()) where key = $1 AND organization_key = $2",
+        task.key,
+        task.organization_key,
+        status_key
+    )
+    .execute(&mut *tx)
+    .await?;
+    sqlx::query!(
+        "UPDATE tasks SET rank = v.rank from unnest($2::uuid[], $3::int[]) as v(key, rank) where tasks.key = v.key AND tasks.organization_key = $1",
+        task.organization_key,
+        &keys,
+        &ranks
+    )
+    .execute(&mut *tx)
+    .await?;
+    tx.commit().await?;
+    Ok(from_key)
+}
+
+// A parent has to be another task in the same project, and can't be one of the task's own children
+pub(crate) async fn check_parent(conn: &mut PoolConnection<Postgres>, task: &Task) -> Result<(), AppError> {
+    let mut next = task.parent_key;
+    let mut seen = vec![task.key];
+    while let Some(parent_key) = next {
+        if seen.contains(&parent_key) {
+            return Err(AppError::Validation("a task can't sit under itself or its own subtasks".to_string()));
+        }
+        let parent = match get_task(conn, parent_key, task.organization_key).await? {
+            Some(p) if p.project_key == task.project_key => p,
+            _ => return Err(AppError::NotFound("parent task".to_string())),
+        };
+        seen.push(parent_key);
+        next = parent.parent_key;
+    }
+    Ok(())
+}
+
+// Only a milestone of the task's own project can count it
+pub(crate) async fn check_milestone(conn: &mut PoolConnection<Postgres>, task: &Task) -> Result<(), AppError> {
+    if let Some(milestone_key) = task.milestone_key {
+        match crate::milestone::get_milestone(conn, milestone_key, task.organization_key).await? {
+            Some(m) if m.project_key == task.project_key => {}
+            _ => return Err(AppError::NotFound("milestone".to_string())),
+        }
+    }
+    Ok(())
+}
+
+// Everything below `key` in the hierarchy, children first then theirs
+pub fn descendants<'a>(key: uuid::Uuid, tasks: &'a [Task]) -> Vec<&'a Task> {
+    let mut found: Vec<&Task> = tasks.iter().filter(|t| t.parent_key == Some(key)).collect();
+    let mut i = 0;
+    while i < found.len() {
+        let k = found[i].key;
+        found.extend(tasks.iter().filter(|t| t.parent_key == Some(k) && t.key != key));
+        i += 1;
+    }
+    found
+}
+
+// Progress counts finished subtasks (at any depth) and ticked checklist items, the estimate is
+// the sum of what the subtasks were estimated at.
+pub fn rollup(key: uuid::Uuid, tasks: &[Task], checklist: &[ChecklistItem], workflow: &Workflow) -> Rollup {
+    let children = descendants(key, tasks);
+    let items: Vec<&ChecklistItem> = checklist.iter().filter(|i| i.task_key == key).collect();
+    Rollup {
+        done: children.iter().filter(|t| workflow.is_done(&t.status_key)).count() + items.iter().filter(|i| i.done).count(),
+        total: children.len() + items.len(),
+        estimated_quarter_days: children.iter().map(|t| t.estimated_quarter_days).sum(),
+    }
 }
 
 // Route Stuff
@@ -160,8 +390,8 @@ pub async fn delete(req: Request<State>) -> tide::Result {
     match req.param("task_id") {
         Ok(key) => {
             let mut conn = req.state().db_pool.acquire().await?;
-            let s_uuid = uuid::Uuid::from_str(key).expect("Task uuid parse");
-            match delete_task(&mut conn, s_uuid, u.key).await {
+            let s_uuid = crate::error::parse_key(key, "task")?;
+            match delete_task(&mut conn, s_uuid, u.organization_key).await {
                 Ok(_) =>    Ok(tide::Response::builder(tide::StatusCode::Ok)
                 .content_type(mime::HTML)
                 .build()),
@@ -188,10 +418,31 @@ pub async fn get(req: Request<State>) -> tide::Result {
     };
     match req.param("task_id") {
         Ok(key) => {
-            let s_uuid = uuid::Uuid::from_str(key).expect("Task uuid parse");
+            let s_uuid = crate::error::parse_key(key, "task")?;
             let mut conn = req.state().db_pool.acquire().await?; // .await? needs to be a real connection pool error handler!!!!!!!!
-            let task = get_task(&mut conn, s_uuid).await;
-            let users = crate::user::get_users_by_organization(&mut conn, u.organization_key).await;
+            let task = match get_task(&mut conn, s_uuid, u.organization_key).await? {
+                Some(t) => t,
+                None => {
+                    return Ok(tide::Response::builder(tide::StatusCode::NotFound)
+                        .content_type(mime::HTML)
+                        .body(NotFoundTemplate::new().render_string())
+                        .build())
+                }
+            };
+            let users = crate::user::get_users_by_organization(&mut conn, u.organization_key).await?;
+            let comments = crate::comment::get_task_comments(&mut conn, s_uuid, u.organization_key).await?;
+            let activity = crate::activity::get_task_activity(&mut conn, s_uuid, u.organization_key).await?;
+            let dependencies = crate::dependency::get_task_dependencies(&mut conn, s_uuid, u.organization_key).await?;
+            let blockers = crate::dependency::get_blockers(&mut conn, s_uuid, u.organization_key).await?;
+            let project_tasks = get_tasks_by_project(&mut conn, task.project_key, u.organization_key).await?;
+            let checklist = crate::checklist::get_task_checklist(&mut conn, s_uuid, u.organization_key).await?;
+            let workflow = crate::workflow::get_workflow(&mut conn, u.organization_key).await?;
+            let rollup = rollup(s_uuid, &project_tasks, &checklist, &workflow);
+            let recurrence = crate::recurrence::get_task_recurrence(&mut conn, s_uuid, u.organization_key).await?;
+            let time_entries = crate::time_entry::get_task_time_entries(&mut conn, s_uuid, u.organization_key).await?;
+            let entitys = crate::entity::get_organization_entitys(&mut conn, u.organization_key).await?;
+            let milestones = crate::milestone::get_milestones_by_project(&mut conn, task.project_key, u.organization_key).await?;
+            let service_items = crate::service_item::get_organization_service_items(&mut conn, u.organization_key).await?;
             
             Ok(tide::Response::builder(tide::StatusCode::Ok)
                 .content_type(mime::HTML)
@@ -200,6 +451,19 @@ pub async fn get(req: Request<State>) -> tide::Result {
                         task,
                         u,
                         users,
+                        crate::comment::thread(comments),
+                        activity,
+                        dependencies,
+                        blockers,
+                        project_tasks,
+                        checklist,
+                        rollup,
+                        workflow,
+                        recurrence,
+                        time_entries,
+                        entitys,
+                        service_items,
+                        milestones,
                     )
                     .render_string(),
                 )
@@ -218,7 +482,7 @@ pub async fn get(req: Request<State>) -> tide::Result {
 pub async fn insert(mut req: Request<State>) -> tide::Result {
     let umd: Result<Task, tide::Error> = req.body_json().await;
 
-    let claims: user::UserJwtState = match user::read_jwt_cookie(req.cookie("token")) {
+    let claims: user::UserJwtState = match user::request_claims(&req) {
         Some(c) => c,
         None => {
             return Ok(tide::Redirect::new("/login").into());
@@ -226,7 +490,10 @@ pub async fn insert(mut req: Request<State>) -> tide::Result {
     };
      
     match umd {
-        Ok(task) => {
+        Ok(mut task) => {
+            // the organization always comes from the caller, never from the body
+            let organization_key = crate::error::parse_key(claims.organization_key.as_str(), "organization")?;
+            task.organization_key = organization_key;
             let mut conn = match req.state().db_pool.acquire().await {
                 Ok(c) => c,
                 Err(e) => {
@@ -238,12 +505,25 @@ pub async fn insert(mut req: Request<State>) -> tide::Result {
                     )
                 }
             };
+            if crate::project::get_project(&mut conn, task.project_key, organization_key).await?.is_none() {
+                return Ok(tide::Response::builder(tide::StatusCode::NotFound)
+                    .content_type(mime::HTML)
+                    .body(NotFoundTemplate::new().render_string())
+                    .build());
+            }
+            check_parent(&mut conn, &task).await?;
+            check_milestone(&mut conn, &task).await?;
+            let workflow = crate::workflow::get_workflow(&mut conn, organization_key).await?;
 
             if task.key == uuid::Uuid::nil() {
-                let s = Task::new(task.organization_key, task.project_key, task.owner_key, task.assignee_key, task.name, task.description, task.tags,TaskStatus::Todo, task.estimated_quarter_days, task.start, task.due);
-                insert_task(&mut conn, &s).await;
-                let organization_key = uuid::Uuid::from_str(claims.organization_key.as_str()).expect("organization key");
-                post_task_create(&mut conn, claims.matrix_home_server, claims.matrix_user_id, organization_key, claims.matrix_access_token, &s).await.expect("Posting to matrix");
+                let status_key = match workflow.status(&task.status_key) {
+                    Some(s) => s.key,
+                    None => workflow.initial().ok_or(AppError::Validation("the workflow has no statuses".to_string()))?,
+                };
+                let s = Task::new(task.organization_key, task.project_key, task.parent_key, task.milestone_key, task.owner_key, task.assignee_key, task.name, task.description, task.tags, status_key, task.estimated_quarter_days, task.start, task.due);
+                insert_task(&mut conn, &s).await?;
+                report_announcement(crate::milestone::announce_outcome(&mut conn, &claims, organization_key, s.milestone_key).await);
+                report_matrix(post_task_create(&mut conn, claims.matrix_home_server, claims.matrix_user_id, organization_key, claims.matrix_access_token, &s).await);
                 let j = serde_json::to_string(&s).expect("To JSON");
                 return Ok(tide::Response::builder(tide::StatusCode::Ok)
                     .content_type(mime::JSON)
@@ -251,110 +531,104 @@ pub async fn insert(mut req: Request<State>) -> tide::Result {
                     .build());
             }
 
-            update_task(&mut conn, &task).await;
-            let j = serde_json::to_string(&task).expect("To JSON");
+            let before = match get_task(&mut conn, task.key, organization_key).await? {
+                Some(t) => t,
+                None => return Err(AppError::NotFound("task".to_string()).into()),
+            };
+            workflow.check_move(Some(before.status_key), task.status_key)?;
+            let warnings = crate::dependency::blocker_warnings(&mut conn, &workflow, task.key, organization_key, before.status_key, task.status_key).await?;
+            update_task(&mut conn, &task).await?;
+            let actor_key = crate::error::parse_key(claims.key.as_str(), "user")?;
+            crate::activity::record_task_changes(&mut conn, actor_key, &before, &task).await?;
+            report_announcement(crate::milestone::announce_outcome(&mut conn, &claims, organization_key, task.milestone_key).await);
+            if before.milestone_key != task.milestone_key {
+                report_announcement(crate::milestone::announce_outcome(&mut conn, &claims, organization_key, before.milestone_key).await);
+            }
+            let mut body = serde_json::to_value(&task).expect("To JSON");
+            body["warnings"] = serde_json::json!(warnings);
+            let j = body.to_string();
             Ok(tide::Response::builder(tide::StatusCode::Ok)
                 .content_type(mime::JSON)
                 .body(j)
                 .build())
         }
-        Err(e) => {
-            println!("{:?}", e);
-            Ok(tide::Response::builder(tide::StatusCode::BadRequest)
-                .content_type(mime::JSON)
-                .body("{'error': 'invalid json body'}")
-                .build())
-        }
-    }
-}
-
-// data types
-#[derive(PartialEq, Debug, Deserialize, Serialize, Clone, Copy, sqlx::Type, EnumIter)]
-pub enum TaskStatus {
-    Wishlist,
-    Todo,
-    PlanningAndEstimating,
-    InQueue,
-    InProgress,
-    ToReview,
-    InReviewal,
-    Complete,
-}
-impl Into<TaskStatus> for i16 {
-    fn into(self) -> TaskStatus {
-        match self {
-            0 => TaskStatus::Wishlist,
-            1 => TaskStatus::Todo, 
-            2 => TaskStatus::PlanningAndEstimating, 
-            3 => TaskStatus::InQueue, 
-            4 => TaskStatus::InProgress, 
-            5 => TaskStatus::ToReview, 
-            6 => TaskStatus::InReviewal, 
-            7 => TaskStatus::Complete, 
-            _ => TaskStatus::Wishlist
-        }
+        Err(e) => Err(AppError::Validation(format!("invalid json body: {}", e)).into()),
     }
 }
 
- 
-impl FromStr for TaskStatus {
-    type Err = ();
-    fn from_str(input: &str) -> Result<TaskStatus, Self::Err> {
-        match input {
-            "Wishlist" => Ok(TaskStatus::Wishlist),
-            "Todo" => Ok(TaskStatus::Todo),
-            "PlanningAndEstimating" => Ok(TaskStatus::PlanningAndEstimating),
-            "InQueue" => Ok(TaskStatus::InQueue),
-            "InProgress" => Ok(TaskStatus::InProgress),
-            "ToReview" => Ok(TaskStatus::ToReview),
-            "InReviewal" => Ok(TaskStatus::InReviewal),
-            "Complete" => Ok(TaskStatus::Complete),
-            _ => Ok(TaskStatus::Wishlist),
-        }
+// Dropping a card on the board. The card lands below `after_key`, or at the top of the column
+// when there isn't one, and everyone watching the task's room hears about status changes.
+pub async fn move_card(mut req: Request<State>) -> tide::Result {
+    let claims: user::UserJwtState = match user::request_claims(&req) {
+        Some(c) => c,
+        None => {
+            return Ok(tide::Redirect::new("/login").into());
+        },
+    };
+    let mv: MoveRequest = match req.body_json().await {
+        Ok(m) => m,
+        Err(e) => return Err(AppError::Validation(format!("invalid json body: {}", e)).into()),
+    };
+    let key = crate::error::param_key(&req, "task_id")?;
+    let organization_key = crate::error::parse_key(claims.organization_key.as_str(), "organization")?;
+    let actor_key = crate::error::parse_key(claims.key.as_str(), "user")?;
+    let mut conn = req.state().db_pool.acquire().await?;
+    let mut before = match get_task(&mut conn, key, organization_key).await? {
+        Some(t) => t,
+        None => return Err(AppError::NotFound("task".to_string()).into()),
+    };
+    let workflow = crate::workflow::get_workflow(&mut conn, organization_key).await?;
+    if workflow.status(&mv.status_key).is_none() {
+        return Err(AppError::NotFound("status".to_string()).into());
     }
-}
-
-impl ToString for TaskStatus {
-    fn to_string(&self) -> String {
-        match self {
-            TaskStatus::Wishlist => "Wishlist".to_owned(),
-            TaskStatus::Todo => "Todo".to_owned(),
-            TaskStatus::PlanningAndEstimating => "Planning And Estimating".to_owned(),
-            TaskStatus::InQueue => "In Queue".to_owned(),
-            TaskStatus::InProgress => "In Progress".to_owned(),
-            TaskStatus::ToReview =>"To Review".to_owned(),
-            TaskStatus::InReviewal => "In Reviewal".to_owned(),
-            TaskStatus::Complete => "Complete".to_owned(),
-        }
+    // someone else may have moved it in the meantime
+    before.status_key = move_task(&mut conn, &before, &workflow, mv.status_key, mv.after_key).await?;
+    let task = match get_task(&mut conn, key, organization_key).await? {
+        Some(t) => t,
+        None => return Err(AppError::NotFound("task".to_string()).into()),
+    };
+    crate::activity::record_task_changes(&mut conn, actor_key, &before, &task).await?;
+    report_announcement(crate::milestone::announce_outcome(&mut conn, &claims, organization_key, task.milestone_key).await);
+    if before.status_key != task.status_key {
+        report_matrix(post_task_move(&mut conn, claims.matrix_home_server, claims.matrix_user_id, organization_key, claims.matrix_access_token, &task,
+            workflow.name(&before.status_key), workflow.name(&task.status_key)).await);
     }
+    let warnings = crate::dependency::blocker_warnings(&mut conn, &workflow, task.key, organization_key, before.status_key, task.status_key).await?;
+    let mut body = serde_json::to_value(&task).expect("To JSON");
+    body["warnings"] = serde_json::json!(warnings);
+    let j = body.to_string();
+    Ok(tide::Response::builder(tide::StatusCode::Ok)
+        .content_type(mime::JSON)
+        .body(j)
+        .build())
 }
 
-impl From<TaskStatus> for i16 {
-    fn from(t: TaskStatus) -> Self {
-        match t {
-            TaskStatus::Wishlist => 0,
-            TaskStatus::Todo => 1, 
-            TaskStatus::PlanningAndEstimating => 2,
-            TaskStatus::InQueue => 3,
-            TaskStatus::InProgress => 4,
-            TaskStatus::ToReview => 5,
-            TaskStatus::InReviewal => 6,
-            TaskStatus::Complete => 7,
-        }
-    }
+// data types
+#[derive(Debug, Deserialize)]
+pub struct MoveRequest {
+    pub status_key: uuid::Uuid,
+    #[serde(default)]
+    pub after_key: Option<uuid::Uuid>,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
 pub struct Task {
     pub key: uuid::Uuid,
     pub organization_key: uuid::Uuid,
     pub project_key: uuid::Uuid,
+    #[serde(default)]
+    pub parent_key: Option<uuid::Uuid>,
+    #[serde(default)]
+    pub milestone_key: Option<uuid::Uuid>,
     pub owner_key: uuid::Uuid,
     pub assignee_key: uuid::Uuid,
     pub name: String,
     pub description: String,
     pub tags: String,
-    pub status: TaskStatus,
+    #[serde(default)]
+    pub status_key: uuid::Uuid,
+    #[serde(default)]
+    pub rank: i32,
     pub estimated_quarter_days: i32,
     pub start: i64,
     pub due: i64,
@@ -366,12 +640,14 @@ impl Task {
     pub fn new(
         organization_key: uuid::Uuid,
         project_key: uuid::Uuid,
+        parent_key: Option<uuid::Uuid>,
+        milestone_key: Option<uuid::Uuid>,
         owner_key: uuid::Uuid,
         assignee_key: uuid::Uuid,
         name: String,
         description: String,
         tags: String,
-        status: TaskStatus,
+        status_key: uuid::Uuid,
         estimated_quarter_days:i32,
         start: i64,
         due: i64,
@@ -383,12 +659,15 @@ impl Task {
             key,
             organization_key,
             project_key,
+            parent_key,
+            milestone_key,
             owner_key,
             assignee_key,
             name,
             description,
             tags,
-            status,
+            status_key,
+            rank: 0,
             estimated_quarter_days,
             start,
             due,
@@ -398,6 +677,30 @@ impl Task {
     }
 }
 
+#[derive(Debug, Default, Serialize, Clone, Copy)]
+pub struct Rollup {
+    pub done: usize,
+    pub total: usize,
+    pub estimated_quarter_days: i32,
+}
+
+impl Rollup {
+    pub fn percent(&self) -> usize {
+        if self.total == 0 {
+            return 0;
+        }
+        self.done * 100 / self.total
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct AddTaskQuery {
+    #[serde(default)]
+    parent_key: Option<Uuid>,
+    #[serde(default)]
+    milestone_key: Option<Uuid>,
+}
+
 pub async fn add(req: Request<State>) -> tide::Result {
     
     let u = match crate::user::user_or_error(&req) {
@@ -405,9 +708,10 @@ pub async fn add(req: Request<State>) -> tide::Result {
         Err(_) => return Ok(tide::Redirect::new("/login").into()),
     };
     
+    let query: AddTaskQuery = req.query()?;
     match req.param("project_id") {
         Ok(project_id) => {  
-            let project_id = uuid::Uuid::from_str(project_id).expect("Project uuid parse");
+            let project_id = crate::error::parse_key(project_id, "project")?;
             let mut conn = match req.state().db_pool.acquire().await {
                 Ok(c) => c,
                 Err(e) => {
@@ -419,8 +723,12 @@ pub async fn add(req: Request<State>) -> tide::Result {
                     )
                 }
             };
-            let users = crate::user::get_users_by_organization(&mut conn, u.organization_key).await;
-            let mut task= Task::new( u.organization_key, project_id,  u.key,  uuid::Uuid::nil(),  "".to_owned(), "".to_owned(), "".to_owned(), TaskStatus::Todo,  0, 0, 0);
+            let users = crate::user::get_users_by_organization(&mut conn, u.organization_key).await?;
+            let project_tasks = get_tasks_by_project(&mut conn, project_id, u.organization_key).await?;
+            let milestones = crate::milestone::get_milestones_by_project(&mut conn, project_id, u.organization_key).await?;
+            let workflow = crate::workflow::get_workflow(&mut conn, u.organization_key).await?;
+            let status_key = workflow.initial().unwrap_or_default();
+            let mut task= Task::new( u.organization_key, project_id, query.parent_key, query.milestone_key, u.key,  uuid::Uuid::nil(),  "".to_owned(), "".to_owned(), "".to_owned(), status_key,  0, 0, 0);
             task.key = uuid::Uuid::nil();
             Ok(tide::Response::builder(tide::StatusCode::Ok)
             .content_type(mime::HTML)
@@ -429,6 +737,19 @@ pub async fn add(req: Request<State>) -> tide::Result {
                     task,
                     u,
                     users,
+                    vec![],
+                    vec![],
+                    vec![],
+                    vec![],
+                    project_tasks,
+                    vec![],
+                    Rollup::default(),
+                    workflow,
+                    None,
+                    vec![],
+                    vec![],
+                    vec![],
+                    milestones,
                 )
                 .render_string(),
             )
@@ -450,6 +771,19 @@ pub struct TaskTemplate {
     task: Task,
     user: crate::user::User,
     users: Vec::<crate::user::User>,
+    comments: Vec::<(usize, Comment)>,
+    activity: Vec::<Activity>,
+    dependencies: Vec::<Dependency>,
+    blockers: Vec::<Task>,
+    project_tasks: Vec::<Task>,
+    checklist: Vec::<ChecklistItem>,
+    rollup: Rollup,
+    workflow: Workflow,
+    recurrence: Option<Recurrence>,
+    time_entries: Vec::<TimeEntry>,
+    entitys: Vec::<crate::entity::Entity>,
+    service_items: Vec::<crate::service_item::ServiceItem>,
+    milestones: Vec::<crate::milestone::Milestone>,
 }
 
 impl<'a> TaskTemplate {
@@ -457,14 +791,98 @@ impl<'a> TaskTemplate {
         task: Task,
         user: crate::user::User,
         users: Vec::<crate::user::User>,
+        comments: Vec::<(usize, Comment)>,
+        activity: Vec::<Activity>,
+        dependencies: Vec::<Dependency>,
+        blockers: Vec::<Task>,
+        project_tasks: Vec::<Task>,
+        checklist: Vec::<ChecklistItem>,
+        rollup: Rollup,
+        workflow: Workflow,
+        recurrence: Option<Recurrence>,
+        time_entries: Vec::<TimeEntry>,
+        entitys: Vec::<crate::entity::Entity>,
+        service_items: Vec::<crate::service_item::ServiceItem>,
+        milestones: Vec::<crate::milestone::Milestone>,
     ) -> Self {
         return Self {
             task,
             user,
             users,
+            comments,
+            activity,
+            dependencies,
+            blockers,
+            project_tasks,
+            checklist,
+            rollup,
+            workflow,
+            recurrence,
+            time_entries,
+            entitys,
+            service_items,
+            milestones,
         };
     }
 
+    pub fn children<'aa>(&'aa self) -> Vec<&'aa Task> {
+        self.project_tasks.iter().filter(|t| t.parent_key == Some(self.task.key)).collect()
+    }
+
+    pub fn parent_name<'aa>(&'aa self) -> String {
+        match self.task.parent_key {
+            Some(key) => Self::task_name(self, &key),
+            None => "".to_string(),
+        }
+    }
+
+    pub fn status_name<'aa>(&'aa self, key: &uuid::Uuid) -> String {
+        self.workflow.name(key)
+    }
+
+    pub fn task_name<'aa>(&'aa self, key: &uuid::Uuid) -> String {
+        match self.project_tasks.iter().find(|t| t.key == *key) {
+            Some(t) => t.name.clone(),
+            None => "a task in another project".to_string(),
+        }
+    }
+
+    pub fn in_milestone<'aa>(&'aa self, key: &uuid::Uuid) -> bool {
+        self.task.milestone_key == Some(*key)
+    }
+
+    pub fn tracked_hours<'aa>(&'aa self) -> String {
+        crate::time_entry::hours(self.time_entries.iter().map(|e| e.seconds()).sum())
+    }
+
+    pub fn estimated_hours<'aa>(&'aa self) -> String {
+        crate::time_entry::hours(self.task.estimated_quarter_days as i64 * crate::time_entry::QUARTER_DAY_SECONDS)
+    }
+
+    pub fn over_estimate<'aa>(&'aa self) -> bool {
+        let estimate = self.task.estimated_quarter_days as i64 * crate::time_entry::QUARTER_DAY_SECONDS;
+        estimate > 0 && self.time_entries.iter().map(|e| e.seconds()).sum::<i64>() > estimate
+    }
+
+    // whether the viewer has a timer going on this task
+    pub fn running_timer<'aa>(&'aa self) -> bool {
+        self.time_entries.iter().any(|e| e.running() && e.user_key == self.user.key)
+    }
+
+    pub fn author<'aa>(&'aa self, key: &uuid::Uuid) -> String {
+        match self.users.iter().find(|u| u.key == *key) {
+            Some(u) => u.to_string(),
+            None => "someone".to_string(),
+        }
+    }
+
+    pub fn when<'aa>(&'aa self, timestamp: &i64) -> String {
+        match chrono::NaiveDateTime::from_timestamp_opt(*timestamp, 0) {
+            Some(d) => d.format("%Y-%m-%d %H:%M").to_string(),
+            None => "".to_string(),
+        }
+    }
+
     pub fn render_string(&self) -> String {
         return self.render().unwrap();
     }
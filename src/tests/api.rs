@@ -0,0 +1,66 @@
+use sqlx::PgPool;
+use tide::http::Method;
+use tide::StatusCode;
+
+use super::{app, send, tenant};
+
+#[sqlx::test]
+async fn lists_page_in_the_database(pool: PgPool) {
+    let app = app(&pool);
+    let a = tenant(&pool, "alpha").await;
+    let body = serde_json::json!({"name": "second", "description": "", "tags": "", "estimated_quarter_days": 0, "start": 0, "due": 0,
+        "key": uuid::Uuid::nil(), "organization_key": uuid::Uuid::nil(), "owner_key": uuid::Uuid::nil(), "created": 0, "updated": 0});
+    let (status, _) = send(&app, &a.owner, Method::Post, "/api/v1/projects", Some(body)).await;
+    assert_eq!(status, StatusCode::Created);
+
+    let mut names = vec![];
+    for n in 1..=2 {
+        let (status, body) = send(&app, &a.owner, Method::Get, &format!("/api/v1/projects?page={}&per_page=1", n), None).await;
+        assert_eq!(status, StatusCode::Ok);
+        let page: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(page["total"], 2);
+        assert_eq!(page["data"].as_array().unwrap().len(), 1);
+        names.push(page["data"][0]["name"].as_str().unwrap().to_string());
+    }
+    names.sort();
+    assert_eq!(names, vec!["alpha project", "second"]);
+
+    let (status, body) = send(&app, &a.owner, Method::Get, "/api/v1/projects?page=3&per_page=1", None).await;
+    assert_eq!(status, StatusCode::Ok);
+    let page: serde_json::Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(page["data"].as_array().unwrap().len(), 0);
+    assert_eq!(page["total"], 2);
+}
+
+#[sqlx::test]
+async fn a_page_past_any_list_is_a_bad_request(pool: PgPool) {
+    let app = app(&pool);
+    let a = tenant(&pool, "alpha").await;
+    let path = format!("/api/v1/tasks?page={}&per_page=200", usize::MAX);
+    let (status, body) = send(&app, &a.owner, Method::Get, &path, None).await;
+    assert_eq!(status, StatusCode::BadRequest);
+    assert!(body.contains("\"code\":\"bad_request\""), "{}", body);
+}
+
+#[sqlx::test]
+async fn search_only_finds_the_callers_contacts(pool: PgPool) {
+    let app = app(&pool);
+    let a = tenant(&pool, "alpha").await;
+    let b = tenant(&pool, "bravo").await;
+    let (status, body) = send(&app, &a.owner, Method::Get, "/api/v1/search?q=contact&kind=contact", None).await;
+    assert_eq!(status, StatusCode::Ok, "{}", body);
+    let results: serde_json::Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(results["total"], 1);
+    assert_eq!(results["data"][0]["key"], a.contact.to_string());
+    assert!(!body.contains(&b.contact.to_string()), "{}", body);
+}
+
+#[sqlx::test]
+async fn a_search_page_past_any_list_is_a_bad_request(pool: PgPool) {
+    let app = app(&pool);
+    let a = tenant(&pool, "alpha").await;
+    let path = format!("/api/v1/search?q=alpha&page={}&per_page=100", usize::MAX);
+    let (status, body) = send(&app, &a.owner, Method::Get, &path, None).await;
+    assert_eq!(status, StatusCode::BadRequest);
+    assert!(body.contains("\"code\":\"bad_request\""), "{}", body);
+}
@@ -0,0 +1,26 @@
+use sqlx::PgPool;
+use tide::http::Method;
+use tide::StatusCode;
+
+use super::{app, send, tenant};
+use crate::board::{self, Board, Swimlane};
+use crate::task::{self, Task};
+
+#[sqlx::test]
+async fn a_card_rolls_up_every_level_below_it(pool: PgPool) {
+    let app = app(&pool);
+    let a = tenant(&pool, "alpha").await;
+    let mut conn = pool.acquire().await.unwrap();
+    let parent = task::get_task(&mut conn, a.task, a.organization).await.unwrap().unwrap();
+    let child = Task::new(a.organization, a.project, Some(parent.key), None, parent.owner_key, parent.owner_key, "child".to_owned(), "".to_owned(), "".to_owned(), parent.status_key, 4, 0, 0);
+    task::insert_task(&mut conn, &child).await.unwrap();
+    let grandchild = Task::new(a.organization, a.project, Some(child.key), None, parent.owner_key, parent.owner_key, "grandchild".to_owned(), "".to_owned(), "".to_owned(), parent.status_key, 4, 0, 0);
+    task::insert_task(&mut conn, &grandchild).await.unwrap();
+    let b = Board::new(a.organization, parent.owner_key, "lanes".to_owned(), "".to_owned(), vec![], vec![parent.status_key.to_string()], "".to_owned(), Swimlane::None, vec![]);
+    board::insert_board(&mut conn, &b).await.unwrap();
+
+    let (status, body) = send(&app, &a.owner, Method::Get, &format!("/board/{}", b.key), None).await;
+    assert_eq!(status, StatusCode::Ok);
+    assert!(body.contains("0 of 2 done"), "{}", body);
+    assert!(body.contains("0 of 1 done"), "{}", body);
+}
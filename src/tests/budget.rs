@@ -0,0 +1,37 @@
+use serde_json::json;
+use sqlx::PgPool;
+use tide::http::Method;
+use tide::StatusCode;
+
+use super::{app, send, session, tenant};
+use crate::member::Role;
+
+#[sqlx::test]
+async fn only_editors_see_what_a_project_is_worth(pool: PgPool) {
+    let app = app(&pool);
+    let a = tenant(&pool, "alpha").await;
+    let path = format!("/project/{}/budget", a.project);
+    let guest = session(&pool, a.organization, Role::Guest).await;
+    let (status, _) = send(&app, &guest, Method::Get, &path, None).await;
+    assert_eq!(status, StatusCode::Forbidden);
+    let member = session(&pool, a.organization, Role::Member).await;
+    let (status, body) = send(&app, &member, Method::Get, &path, None).await;
+    assert_eq!(status, StatusCode::Ok, "{}", body);
+}
+
+#[sqlx::test]
+async fn invoices_that_cant_be_loaded_say_so(pool: PgPool) {
+    let app = app(&pool);
+    let a = tenant(&pool, "alpha").await;
+    let mut conn = pool.acquire().await.unwrap();
+    // nothing listens on port 1
+    let options = crate::akaunting::AkauntingSyncOption::new(a.organization, a.owner.user.key, "user".to_string(), "secret".to_string(), "http://127.0.0.1:1".to_string(), "1".to_string(), true, true, true, true, true, true, true, 0);
+    crate::akaunting::insert_akaunting_options(&mut conn, &options).await.unwrap();
+    let path = format!("/project/{}/budget", a.project);
+    let (status, body) = send(&app, &a.owner, Method::Post, &path, Some(json!({"amount": 1000, "entity_key": a.entity}))).await;
+    assert_eq!(status, StatusCode::Ok, "{}", body);
+
+    let (status, body) = send(&app, &a.owner, Method::Get, &format!("{}?invoices=true", path), None).await;
+    assert_eq!(status, StatusCode::Ok, "{}", body);
+    assert!(body.contains("be loaded from Akaunting"), "{}", body);
+}
@@ -0,0 +1,36 @@
+use serde_json::json;
+use sqlx::PgPool;
+use tide::http::Method;
+use tide::StatusCode;
+
+use super::{app, send, tenant};
+use crate::task::{self, Task};
+use crate::workflow;
+
+#[sqlx::test]
+async fn starting_blocked_work_warns_on_every_path(pool: PgPool) {
+    let app = app(&pool);
+    let a = tenant(&pool, "alpha").await;
+    let mut conn = pool.acquire().await.unwrap();
+    let workflow = workflow::get_workflow(&mut conn, a.organization).await.unwrap();
+    let in_progress = workflow.statuses.iter().find(|s| s.name == "In Progress").unwrap().key;
+    let first = task::get_task(&mut conn, a.task, a.organization).await.unwrap().unwrap();
+    let blocker = Task::new(a.organization, a.project, None, None, first.owner_key, first.owner_key, "the blocker".to_owned(), "".to_owned(), "".to_owned(), first.status_key, 0, 0, 0);
+    task::insert_task(&mut conn, &blocker).await.unwrap();
+    let second = Task::new(a.organization, a.project, None, None, first.owner_key, first.owner_key, "second".to_owned(), "".to_owned(), "".to_owned(), first.status_key, 0, 0, 0);
+    task::insert_task(&mut conn, &second).await.unwrap();
+    for key in [first.key, second.key] {
+        let (status, body) = send(&app, &a.owner, Method::Post, &format!("/task/{}/dependencies", key), Some(json!({"depends_on_key": blocker.key, "kind": "FinishToStart"}))).await;
+        assert_eq!(status, StatusCode::Ok, "{}", body);
+    }
+
+    let (status, body) = send(&app, &a.owner, Method::Post, &format!("/task/{}/move", first.key), Some(json!({"status_key": in_progress}))).await;
+    assert_eq!(status, StatusCode::Ok, "{}", body);
+    assert!(body.contains("the blocker is still"), "{}", body);
+
+    let mut record = serde_json::to_value(&second).unwrap();
+    record["status_key"] = json!(in_progress);
+    let (status, body) = send(&app, &a.owner, Method::Put, &format!("/api/v1/tasks/{}", second.key), Some(record)).await;
+    assert_eq!(status, StatusCode::Ok, "{}", body);
+    assert!(body.contains("the blocker is still"), "{}", body);
+}
@@ -0,0 +1,73 @@
+use sqlx::PgPool;
+use tide::http::Method;
+use tide::StatusCode;
+
+use super::{app, send, send_text, tenant};
+
+fn code(body: &str) -> String {
+    let envelope: serde_json::Value = serde_json::from_str(body).unwrap_or_else(|_| panic!("not an error envelope: {}", body));
+    envelope["error"]["code"].as_str().unwrap_or_default().to_string()
+}
+
+#[sqlx::test]
+async fn bodies_that_are_not_json_are_bad_requests(pool: PgPool) {
+    let app = app(&pool);
+    let a = tenant(&pool, "alpha").await;
+    let paths = [
+        "/project".to_string(),
+        "/task".to_string(),
+        format!("/task/{}/comments", a.task),
+        format!("/task/{}/time", a.task),
+        "/milestone".to_string(),
+        "/note".to_string(),
+        "/entity".to_string(),
+        "/board".to_string(),
+        "/service_item".to_string(),
+        "/organization".to_string(),
+        format!("/users/{}", a.owner.user.key),
+        "/api/v1/tasks".to_string(),
+    ];
+    for path in paths {
+        let (status, body) = send_text(&app, &a.owner, Method::Post, &path, "{\"name\": ").await;
+        assert_eq!(status, StatusCode::BadRequest, "{}: {}", path, body);
+        assert_eq!(code(&body), "bad_request", "{}", path);
+    }
+}
+
+#[sqlx::test]
+async fn keys_that_are_not_uuids_are_bad_requests(pool: PgPool) {
+    let app = app(&pool);
+    let a = tenant(&pool, "alpha").await;
+    for path in ["/api/v1/tasks/not-a-key", "/api/v1/projects/42"] {
+        let (status, body) = send(&app, &a.owner, Method::Get, path, None).await;
+        assert_eq!(status, StatusCode::BadRequest, "{}: {}", path, body);
+        assert_eq!(code(&body), "bad_request", "{}", path);
+    }
+    let (status, body) = send_text(&app, &a.owner, Method::Post, "/task/not-a-key/comments", "{}").await;
+    assert_eq!(status, StatusCode::BadRequest, "{}", body);
+    assert_eq!(code(&body), "bad_request");
+}
+
+#[sqlx::test]
+async fn keys_that_match_nothing_are_not_found(pool: PgPool) {
+    let app = app(&pool);
+    let a = tenant(&pool, "alpha").await;
+    let missing = uuid::Uuid::new_v4();
+    for path in [format!("/api/v1/tasks/{}", missing), format!("/api/v1/notes/{}", missing)] {
+        let (status, body) = send(&app, &a.owner, Method::Get, &path, None).await;
+        assert_eq!(status, StatusCode::NotFound, "{}: {}", path, body);
+        assert_eq!(code(&body), "not_found", "{}", path);
+    }
+    let (status, body) = send_text(&app, &a.owner, Method::Post, &format!("/task/{}/comments", missing), "{\"body\": \"hi\"}").await;
+    assert_eq!(status, StatusCode::NotFound, "{}", body);
+    assert_eq!(code(&body), "not_found");
+}
+
+#[sqlx::test]
+async fn tokens_without_a_name_are_bad_requests(pool: PgPool) {
+    let app = app(&pool);
+    let a = tenant(&pool, "alpha").await;
+    let (status, body) = send_text(&app, &a.owner, Method::Post, "/tokens", "{\"name\": \"  \", \"scope\": \"Read\"}").await;
+    assert_eq!(status, StatusCode::BadRequest, "{}", body);
+    assert_eq!(code(&body), "bad_request");
+}
@@ -0,0 +1,64 @@
+use serde_json::json;
+use sqlx::PgPool;
+use tide::http::Method;
+use tide::StatusCode;
+use uuid::Uuid;
+
+use super::{app, redirect, send, tenant, Session};
+use crate::member::Role;
+use crate::user::User;
+
+// the token of a new invitation for `email` into the organization `by` is signed into
+async fn invite(app: &tide::Server<crate::State>, by: &Session, email: &str) -> String {
+    let (status, body) = send(app, by, Method::Post, "/invite", Some(json!({"email": email, "matrix_user_id": "", "role": "Member"}))).await;
+    assert_eq!(status, StatusCode::Ok, "{}", body);
+    let invitation: serde_json::Value = serde_json::from_str(&body).unwrap();
+    invitation["token"].as_str().unwrap().to_string()
+}
+
+// someone who isn't logged in
+fn anonymous() -> Session {
+    Session {
+        user: User::new(Uuid::new_v4(), Uuid::nil(), "".to_owned(), "".to_owned(), "".to_owned()),
+        cookie: String::new(),
+    }
+}
+
+#[sqlx::test]
+async fn an_invitation_needs_someone_to_invite(pool: PgPool) {
+    let app = app(&pool);
+    let a = tenant(&pool, "alpha").await;
+    let (status, body) = send(&app, &a.owner, Method::Post, "/invite", Some(json!({"email": " ", "matrix_user_id": "", "role": "Member"}))).await;
+    assert_eq!(status, StatusCode::BadRequest);
+    let envelope: serde_json::Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(envelope["error"]["code"], "bad_request");
+}
+
+#[sqlx::test]
+async fn invitees_with_an_account_are_sent_to_login(pool: PgPool) {
+    let app = app(&pool);
+    let a = tenant(&pool, "alpha").await;
+    let b = tenant(&pool, "bravo").await;
+    let known = invite(&app, &a.owner, b.owner.user.email.as_str()).await;
+    let unknown = invite(&app, &a.owner, "nobody@example.com").await;
+
+    let visitor = anonymous();
+    assert_eq!(redirect(&app, &visitor, &format!("/invite/accept/{}", known)).await.as_deref(), Some("/login"));
+    assert_eq!(redirect(&app, &visitor, &format!("/invite/accept/{}", unknown)).await.as_deref(), Some("/register"));
+}
+
+#[sqlx::test]
+async fn accepting_while_logged_in_joins_the_organization(pool: PgPool) {
+    let app = app(&pool);
+    let a = tenant(&pool, "alpha").await;
+    let b = tenant(&pool, "bravo").await;
+    let token = invite(&app, &a.owner, b.owner.user.email.as_str()).await;
+
+    assert_eq!(redirect(&app, &b.owner, &format!("/invite/accept/{}", token)).await.as_deref(), Some("/dashboard"));
+    let mut conn = pool.acquire().await.unwrap();
+    assert_eq!(crate::member::get_role(&mut conn, a.organization, b.owner.user.key).await, Some(Role::Member));
+    let user = crate::user::get_user(&mut conn, b.owner.user.key).await.unwrap();
+    assert_eq!(user.organization_key, a.organization);
+    // and the link is spent
+    assert_eq!(redirect(&app, &b.owner, &format!("/invite/accept/{}", token)).await, None);
+}
@@ -0,0 +1,154 @@
+use sqlx::PgPool;
+use tide::http::Method;
+use tide::StatusCode;
+
+use super::{app, send, session, tenant, Tenant};
+use crate::member::Role;
+
+// every record of `b` reachable by key, paired with the page and the API path it lives under
+fn records(b: &Tenant) -> Vec<(String, String)> {
+    vec![
+        (format!("/project/{}", b.project), format!("/api/v1/projects/{}", b.project)),
+        (format!("/milestone/{}", b.milestone), format!("/api/v1/milestones/{}", b.milestone)),
+        (format!("/task/{}", b.task), format!("/api/v1/tasks/{}", b.task)),
+        (format!("/board/{}", b.board), format!("/api/v1/boards/{}", b.board)),
+        (format!("/entity/{}", b.entity), format!("/api/v1/entities/{}", b.entity)),
+        (format!("/contact/{}", b.contact), format!("/api/v1/contacts/{}", b.contact)),
+        (format!("/service_item/{}", b.service_item), format!("/api/v1/service_items/{}", b.service_item)),
+        (format!("/note/{}", b.note), format!("/api/v1/notes/{}", b.note)),
+        (format!("/file/{}", b.file), format!("/api/v1/files/{}", b.file)),
+    ]
+}
+
+fn leaks(body: &str, b: &Tenant) -> bool {
+    [b.organization, b.owner.user.key, b.project, b.milestone, b.task, b.board, b.entity, b.contact, b.service_item, b.note, b.file]
+        .iter()
+        .any(|k| body.contains(&k.to_string()))
+}
+
+#[sqlx::test]
+async fn records_of_another_organization_are_not_found(pool: PgPool) {
+    let app = app(&pool);
+    let a = tenant(&pool, "alpha").await;
+    let b = tenant(&pool, "bravo").await;
+    let mut paths = vec![
+        format!("/project/{}/critical_path", b.project),
+        format!("/project/{}/gantt", b.project),
+        format!("/project/{}/budget", b.project),
+        format!("/task/{}/recurrence", b.task),
+        format!("/entity/{}/time", b.entity),
+        format!("/users/{}", b.owner.user.key),
+        format!("/organization/{}", b.organization),
+        format!("/api/v1/organizations/{}", b.organization),
+    ];
+    for (page, api) in records(&b) {
+        paths.push(page);
+        paths.push(api);
+    }
+    for path in paths {
+        let (status, body) = send(&app, &a.owner, Method::Get, &path, None).await;
+        assert_eq!(status, StatusCode::NotFound, "GET {}", path);
+        assert!(!leaks(&body, &b), "GET {} shows bravo's records", path);
+    }
+}
+
+#[sqlx::test]
+async fn lists_only_hold_the_callers_organization(pool: PgPool) {
+    let app = app(&pool);
+    let a = tenant(&pool, "alpha").await;
+    let b = tenant(&pool, "bravo").await;
+    let paths = vec![
+        "/dashboard".to_string(),
+        "/calendar".to_string(),
+        "/capacity".to_string(),
+        "/blueprint".to_string(),
+        "/workflow".to_string(),
+        "/search?q=bravo".to_string(),
+        "/api/v1/search?q=bravo".to_string(),
+        "/api/v1/organizations".to_string(),
+        "/api/v1/projects".to_string(),
+        "/api/v1/milestones".to_string(),
+        "/api/v1/tasks".to_string(),
+        "/api/v1/boards".to_string(),
+        "/api/v1/entities".to_string(),
+        "/api/v1/contacts".to_string(),
+        "/api/v1/service_items".to_string(),
+        "/api/v1/notes".to_string(),
+        "/api/v1/files".to_string(),
+        format!("/task/{}/comments", b.task),
+        format!("/task/{}/activity", b.task),
+        format!("/task/{}/dependencies", b.task),
+        format!("/task/{}/checklist", b.task),
+        format!("/task/{}/time", b.task),
+    ];
+    for path in paths {
+        let (status, body) = send(&app, &a.owner, Method::Get, &path, None).await;
+        assert!(status != StatusCode::InternalServerError, "GET {} failed", path);
+        assert!(!leaks(&body, &b), "GET {} shows bravo's records", path);
+    }
+    // and the same lists do show the caller's own records
+    let (_, body) = send(&app, &a.owner, Method::Get, "/api/v1/projects", None).await;
+    assert!(body.contains(&a.project.to_string()));
+}
+
+#[sqlx::test]
+async fn records_of_another_organization_can_not_be_changed(pool: PgPool) {
+    let app = app(&pool);
+    let a = tenant(&pool, "alpha").await;
+    let b = tenant(&pool, "bravo").await;
+    for (page, api) in records(&b) {
+        let (_, body) = send(&app, &b.owner, Method::Get, &api, None).await;
+        let mut record: serde_json::Value = serde_json::from_str(&body).unwrap();
+        record["name"] = serde_json::json!("alpha was here");
+        record["title"] = serde_json::json!("alpha was here");
+        let (status, _) = send(&app, &a.owner, Method::Put, &api, Some(record)).await;
+        assert_eq!(status, StatusCode::NotFound, "PUT {}", api);
+        let (status, _) = send(&app, &a.owner, Method::Delete, &api, None).await;
+        assert_eq!(status, StatusCode::NotFound, "DELETE {}", api);
+        send(&app, &a.owner, Method::Delete, &page, None).await;
+    }
+    let posts = vec![
+        (format!("/task/{}/move", b.task), serde_json::json!({"status_key": uuid::Uuid::nil()})),
+        (format!("/project/{}/budget", b.project), serde_json::json!({})),
+        (format!("/project/{}/blueprint", b.project), serde_json::json!({"name": "alpha was here"})),
+        (format!("/task/{}/dependencies", b.task), serde_json::json!({"depends_on_key": a.task, "kind": "FinishToStart"})),
+    ];
+    for (path, body) in posts {
+        let (status, _) = send(&app, &a.owner, Method::Post, &path, Some(body)).await;
+        assert_eq!(status, StatusCode::NotFound, "POST {}", path);
+    }
+    send(&app, &a.owner, Method::Delete, &format!("/users/{}", b.owner.user.key), None).await;
+    send(&app, &a.owner, Method::Delete, &format!("/organization/{}", b.organization), None).await;
+
+    // bravo still has all of it, untouched
+    for (_, api) in records(&b) {
+        let (status, body) = send(&app, &b.owner, Method::Get, &api, None).await;
+        assert_eq!(status, StatusCode::Ok, "GET {} as bravo", api);
+        assert!(body.contains("bravo") && !body.contains("alpha"), "GET {} as bravo: {}", api, body);
+    }
+    let (status, _) = send(&app, &b.owner, Method::Get, &format!("/users/{}", b.owner.user.key), None).await;
+    assert_eq!(status, StatusCode::Ok);
+}
+
+#[sqlx::test]
+async fn only_users_themselves_change_how_they_log_in(pool: PgPool) {
+    let app = app(&pool);
+    let a = tenant(&pool, "alpha").await;
+    let admin = session(&pool, a.organization, Role::Admin).await;
+    let path = format!("/users/{}", a.owner.user.key);
+    let mut owner = serde_json::to_value(&a.owner.user).unwrap();
+    owner["matrix_user_id"] = serde_json::json!("@admin:example.com");
+    owner["email"] = serde_json::json!("admin@example.com");
+
+    let (status, _) = send(&app, &admin, Method::Post, &path, Some(owner.clone())).await;
+    assert_eq!(status, StatusCode::Forbidden);
+    let mut conn = pool.acquire().await.unwrap();
+    let stored = crate::user::get_user(&mut conn, a.owner.user.key).await.unwrap();
+    assert_eq!(stored.matrix_user_id, a.owner.user.matrix_user_id);
+    assert_eq!(stored.email, a.owner.user.email);
+
+    let (status, _) = send(&app, &a.owner, Method::Post, &path, Some(owner)).await;
+    assert_eq!(status, StatusCode::Ok);
+    let stored = crate::user::get_user(&mut conn, a.owner.user.key).await.unwrap();
+    assert_eq!(stored.email, "admin@example.com");
+}
@@ -0,0 +1,151 @@
+// Requests go straight to `server()`, each test gets its own database from #[sqlx::test] with
+// ./migrations applied. Sessions are JWT cookies signed with the JWT_SECRET from .env.
+
+mod api;
+mod boards;
+mod budget;
+mod dependencies;
+mod errors;
+mod invitations;
+mod isolation;
+mod organizations;
+mod time;
+
+use sqlx::PgPool;
+use tide::http::{Method, Request, Url};
+use tide::StatusCode;
+use uuid::Uuid;
+
+use crate::board::{self, Board, Swimlane};
+use crate::entity::{self, Contact, Entity, EntityType};
+use crate::file::{self, AssociationType, File};
+use crate::member::{self, Member, Role};
+use crate::milestone::{self, Milestone};
+use crate::note::{self, Note};
+use crate::organization::{self, Organization};
+use crate::project::{self, Project};
+use crate::service_item::{self, ServiceItem, ServiceItemType, ServiceValueType};
+use crate::task::{self, Task};
+use crate::user::{self, User};
+use crate::{server, workflow, State};
+
+// An organization with one of everything in it, every name and description starts with `marker`
+// so a test can tell whose records a response holds.
+pub struct Tenant {
+    pub organization: Uuid,
+    pub owner: Session,
+    pub project: Uuid,
+    pub milestone: Uuid,
+    pub task: Uuid,
+    pub board: Uuid,
+    pub entity: Uuid,
+    pub contact: Uuid,
+    pub service_item: Uuid,
+    pub note: Uuid,
+    pub file: Uuid,
+}
+
+pub struct Session {
+    pub user: User,
+    pub cookie: String,
+}
+
+pub fn app(pool: &PgPool) -> tide::Server<State> {
+    dotenv::dotenv().ok();
+    server(State { db_pool: pool.clone() })
+}
+
+pub async fn tenant(pool: &PgPool, marker: &str) -> Tenant {
+    let mut conn = pool.acquire().await.unwrap();
+    let organization_key = Uuid::new_v4();
+    let owner_key = Uuid::new_v4();
+    let o = Organization::new(organization_key, "".to_owned(), "".to_owned(), owner_key, format!("{} organization", marker), "".to_owned(), "".to_owned(), "".to_owned(), "".to_owned(), "".to_owned(), "".to_owned());
+    organization::insert_organization(&mut conn, &o).await.unwrap();
+    let owner = user_session(pool, owner_key, organization_key, Role::Owner).await;
+    let text = |what: &str| format!("{} {}", marker, what);
+
+    let p = Project::new(organization_key, owner_key, text("project"), text("project description"), "".to_owned(), 0, 0, 0);
+    project::insert_project(&mut conn, &p).await.unwrap();
+    let m = Milestone::new(organization_key, owner_key, p.key, text("milestone"), text("milestone description"), "".to_owned(), 0, 0, 0);
+    milestone::insert_milestone(&mut conn, &m).await.unwrap();
+    let status = workflow::get_workflow(&mut conn, organization_key).await.unwrap().statuses[0].key;
+    let t = Task::new(organization_key, p.key, None, None, owner_key, owner_key, text("task"), text("task description"), "".to_owned(), status, 0, 0, 0);
+    task::insert_task(&mut conn, &t).await.unwrap();
+    let b = Board::new(organization_key, owner_key, text("board"), text("board description"), vec![], vec![], "".to_owned(), Swimlane::None, vec![]);
+    board::insert_board(&mut conn, &b).await.unwrap();
+    let e = Entity::new(organization_key, "".to_owned(), owner_key, text("entity"), text("entity description"), "".to_owned(), "".to_owned(), "".to_owned(), EntityType::Client, "".to_owned(), "".to_owned(), "".to_owned(), "".to_owned(), "".to_owned(), "".to_owned());
+    entity::insert_entity(&mut conn, &e).await.unwrap();
+    let c = Contact::new(e.key, "".to_owned(), text("contact"), "".to_owned(), "".to_owned(), text("contact description"), "".to_owned(), "".to_owned(), "".to_owned(), "".to_owned(), "".to_owned(), "".to_owned(), "".to_owned(), "".to_owned(), vec![], "".to_owned(), "".to_owned(), "".to_owned(), "".to_owned(), "".to_owned(), "".to_owned());
+    entity::insert_contact(&mut conn, &c, organization_key).await.unwrap();
+    let s = ServiceItem::new(organization_key, "".to_owned(), owner_key, text("service item"), text("service item description"), 100, "USD".to_owned(), ServiceItemType::Service, ServiceValueType::Hourly, vec![]);
+    service_item::insert_service_item(&mut conn, &s).await.unwrap();
+    let n = Note::new(organization_key, owner_key, AssociationType::Project, p.key, "".to_owned(), text("note"), text("note content"));
+    note::insert_note(&mut conn, &n).await.unwrap();
+    let f = File::new(owner_key, organization_key, AssociationType::Project, p.key, "".to_owned(), "".to_owned(), text("file"), text("file description"), "".to_owned(), "txt".to_owned(), 0);
+    file::insert_file(&mut conn, &f).await.unwrap();
+
+    Tenant {
+        organization: organization_key,
+        owner,
+        project: p.key,
+        milestone: m.key,
+        task: t.key,
+        board: b.key,
+        entity: e.key,
+        contact: c.key,
+        service_item: s.key,
+        note: n.key,
+        file: f.key,
+    }
+}
+
+// A new member of `organization_key` with `role`, signed into it
+pub async fn session(pool: &PgPool, organization_key: Uuid, role: Role) -> Session {
+    user_session(pool, Uuid::new_v4(), organization_key, role).await
+}
+
+async fn user_session(pool: &PgPool, key: Uuid, organization_key: Uuid, role: Role) -> Session {
+    dotenv::dotenv().ok();
+    let mut conn = pool.acquire().await.unwrap();
+    let u = User::new(key, organization_key, format!("{}@example.com", key), format!("@{}:example.com", key), "https://example.com".to_owned());
+    user::insert_user(&mut conn, &u).await.unwrap();
+    member::insert_member(&mut conn, &Member::new(organization_key, key, role)).await.unwrap();
+    let jwt = user::create_jwt(&u, "".to_owned(), "".to_owned(), "".to_owned(), role).unwrap();
+    Session {
+        user: u,
+        cookie: format!("token={}", jwt),
+    }
+}
+
+// Status and body of `method path` sent as `session`, with `body` as JSON when there is one
+pub async fn send(app: &tide::Server<State>, session: &Session, method: Method, path: &str, body: Option<serde_json::Value>) -> (StatusCode, String) {
+    let body = body.map(|b| tide::Body::from_json(&b).unwrap());
+    send_body(app, session, method, path, body).await
+}
+
+// for bodies that aren't valid json, they still go out as application/json
+pub async fn send_text(app: &tide::Server<State>, session: &Session, method: Method, path: &str, text: &str) -> (StatusCode, String) {
+    let mut body = tide::Body::from_string(text.to_string());
+    body.set_mime(tide::http::mime::JSON);
+    send_body(app, session, method, path, Some(body)).await
+}
+
+// Where `GET path` sends `session` on to, None when it answers itself
+pub async fn redirect(app: &tide::Server<State>, session: &Session, path: &str) -> Option<String> {
+    let res = respond(app, session, Method::Get, path, None).await;
+    res.header("Location").map(|l| l.as_str().to_string())
+}
+
+async fn send_body(app: &tide::Server<State>, session: &Session, method: Method, path: &str, body: Option<tide::Body>) -> (StatusCode, String) {
+    let mut res = respond(app, session, method, path, body).await;
+    (res.status(), res.body_string().await.unwrap())
+}
+
+async fn respond(app: &tide::Server<State>, session: &Session, method: Method, path: &str, body: Option<tide::Body>) -> tide::http::Response {
+    let mut req = Request::new(method, Url::parse(&format!("http://localhost{}", path)).unwrap());
+    req.insert_header("Cookie", session.cookie.as_str());
+    if let Some(b) = body {
+        req.set_body(b);
+    }
+    app.respond(req).await.unwrap()
+}
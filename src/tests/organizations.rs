@@ -0,0 +1,53 @@
+use sqlx::PgPool;
+use tide::http::Method;
+use tide::StatusCode;
+use uuid::Uuid;
+
+use super::{app, send, tenant};
+use crate::member::{self, Member, Role};
+use crate::organization::Organization;
+
+#[sqlx::test]
+async fn deleting_an_organization_keeps_its_members_and_their_other_memberships(pool: PgPool) {
+    let app = app(&pool);
+    let a = tenant(&pool, "alpha").await;
+    let b = tenant(&pool, "bravo").await;
+    let mut conn = pool.acquire().await.unwrap();
+    member::insert_member(&mut conn, &Member::new(b.organization, a.owner.user.key, Role::Member)).await.unwrap();
+
+    let (status, _) = send(&app, &a.owner, Method::Delete, &format!("/organization/{}", a.organization), None).await;
+    assert_eq!(status, StatusCode::Ok);
+
+    let user = sqlx::query!("select organization_key from users where key = $1", a.owner.user.key)
+        .fetch_optional(&mut conn)
+        .await
+        .unwrap()
+        .expect("the user survives");
+    assert_eq!(user.organization_key, b.organization);
+    let memberships = sqlx::query!("select organization_key from organization_members where user_key = $1", a.owner.user.key)
+        .fetch_all(&mut conn)
+        .await
+        .unwrap();
+    assert_eq!(memberships.len(), 1);
+    assert_eq!(memberships[0].organization_key, b.organization);
+}
+
+#[sqlx::test]
+async fn the_creator_of_an_organization_is_its_owner(pool: PgPool) {
+    let app = app(&pool);
+    let a = tenant(&pool, "alpha").await;
+    let o = Organization::new(Uuid::nil(), "".to_owned(), "".to_owned(), Uuid::new_v4(), "charlie organization".to_owned(), "".to_owned(), "".to_owned(), "".to_owned(), "".to_owned(), "".to_owned(), "".to_owned());
+
+    let (status, body) = send(&app, &a.owner, Method::Post, "/organization", Some(serde_json::to_value(&o).unwrap())).await;
+    assert_eq!(status, StatusCode::Ok, "{}", body);
+    let created: Organization = serde_json::from_str(&body).unwrap();
+    assert_eq!(created.owner_key, a.owner.user.key);
+
+    let mut conn = pool.acquire().await.unwrap();
+    let role = sqlx::query!("select role from organization_members where organization_key = $1 and user_key = $2", created.key, a.owner.user.key)
+        .fetch_one(&mut conn)
+        .await
+        .unwrap()
+        .role;
+    assert_eq!(role, Role::Owner as i16);
+}
@@ -0,0 +1,27 @@
+use serde_json::json;
+use sqlx::PgPool;
+use tide::http::Method;
+use tide::StatusCode;
+
+use super::{app, send, tenant};
+
+#[sqlx::test]
+async fn restarting_an_entry_while_a_timer_runs_is_a_bad_request(pool: PgPool) {
+    let app = app(&pool);
+    let a = tenant(&pool, "alpha").await;
+    let path = format!("/task/{}/time", a.task);
+    let (status, body) = send(&app, &a.owner, Method::Post, &path, Some(json!({"key": uuid::Uuid::nil(), "started": 1000, "stopped": 2000}))).await;
+    assert_eq!(status, StatusCode::Ok, "{}", body);
+    let logged: serde_json::Value = serde_json::from_str(&body).unwrap();
+    let (status, body) = send(&app, &a.owner, Method::Post, &path, Some(json!({"key": uuid::Uuid::nil()}))).await;
+    assert_eq!(status, StatusCode::Ok, "{}", body);
+
+    let (status, body) = send(&app, &a.owner, Method::Post, &path, Some(json!({"key": logged["key"], "started": 1000, "stopped": 0}))).await;
+    assert_eq!(status, StatusCode::BadRequest, "{}", body);
+    assert!(body.contains("another timer is already running"), "{}", body);
+
+    let (status, _) = send(&app, &a.owner, Method::Post, "/time/stop", None).await;
+    assert_eq!(status, StatusCode::Ok);
+    let (status, body) = send(&app, &a.owner, Method::Post, &path, Some(json!({"key": logged["key"], "started": 1000, "stopped": 0}))).await;
+    assert_eq!(status, StatusCode::Ok, "{}", body);
+}
@@ -0,0 +1,572 @@
+use askama::Template;
+use serde::{Deserialize, Serialize};
+use sqlx::pool::PoolConnection;
+use sqlx::postgres::PgQueryResult;
+use sqlx::Postgres;
+use uuid::Uuid;
+
+use tide::{http::mime, Request};
+
+use crate::entity::Entity;
+use crate::common::day;
+use crate::error::AppError;
+use crate::home::NotFoundTemplate;
+use crate::service_item::{ServiceItem, ServiceValueType};
+use crate::{user, State};
+
+// estimates are in quarters of an eight hour day
+pub const QUARTER_DAY_SECONDS: i64 = 2 * 60 * 60;
+
+// SQL STUFF
+
+pub async fn get_task_time_entries(
+    conn: &mut PoolConnection<Postgres>,
+    task_key: uuid::Uuid,
+    organization_key: uuid::Uuid,
+) -> Result<Vec<TimeEntry>, AppError> {
+    let records = sqlx::query!(
+        "select key, organization_key, user_key, task_key, entity_key, service_item_key, started, stopped, billable, note, created, updated from time_entries where task_key = $1 AND organization_key = $2 order by started desc",
+        task_key,
+        organization_key
+    )
+    .fetch_all(conn)
+    .await?;
+    let mut entries = vec![];
+    for entry in records {
+        entries.push(TimeEntry {
+            key: entry.key,
+            organization_key: entry.organization_key,
+            user_key: entry.user_key,
+            task_key: entry.task_key,
+            entity_key: entry.entity_key,
+            service_item_key: entry.service_item_key,
+            started: entry.started,
+            stopped: entry.stopped,
+            billable: entry.billable,
+            note: entry.note,
+            created: entry.created,
+            updated: entry.updated,
+        })
+    }
+    Ok(entries)
+}
+
+pub async fn get_entity_time_entries(
+    conn: &mut PoolConnection<Postgres>,
+    entity_key: uuid::Uuid,
+    organization_key: uuid::Uuid,
+    from: i64,
+    to: i64,
+) -> Result<Vec<TimeEntry>, AppError> {
+    let records = sqlx::query!(
+        "select key, organization_key, user_key, task_key, entity_key, service_item_key, started, stopped, billable, note, created, updated from time_entries where entity_key = $1 AND organization_key = $2 AND started >= $3 AND ($4::bigint = 0 OR started < $4) order by started",
+        entity_key,
+        organization_key,
+        from,
+        to
+    )
+    .fetch_all(conn)
+    .await?;
+    let mut entries = vec![];
+    for entry in records {
+        entries.push(TimeEntry {
+            key: entry.key,
+            organization_key: entry.organization_key,
+            user_key: entry.user_key,
+            task_key: entry.task_key,
+            entity_key: entry.entity_key,
+            service_item_key: entry.service_item_key,
+            started: entry.started,
+            stopped: entry.stopped,
+            billable: entry.billable,
+            note: entry.note,
+            created: entry.created,
+            updated: entry.updated,
+        })
+    }
+    Ok(entries)
+}
+
+pub async fn get_project_time_entries(
+    conn: &mut PoolConnection<Postgres>,
+    project_key: uuid::Uuid,
+    organization_key: uuid::Uuid,
+) -> Result<Vec<TimeEntry>, AppError> {
+    let records = sqlx::query!(
+        "select e.key, e.organization_key, e.user_key, e.task_key, e.entity_key, e.service_item_key, e.started, e.stopped, e.billable, e.note, e.created, e.updated
+        from time_entries e join tasks t on t.key = e.task_key
+        where t.project_key = $1 AND e.organization_key = $2 order by e.started",
+        project_key,
+        organization_key
+    )
+    .fetch_all(conn)
+    .await?;
+    let mut entries = vec![];
+    for entry in records {
+        entries.push(TimeEntry {
+            key: entry.key,
+            organization_key: entry.organization_key,
+            user_key: entry.user_key,
+            task_key: entry.task_key,
+            entity_key: entry.entity_key,
+            service_item_key: entry.service_item_key,
+            started: entry.started,
+            stopped: entry.stopped,
+            billable: entry.billable,
+            note: entry.note,
+            created: entry.created,
+            updated: entry.updated,
+        })
+    }
+    Ok(entries)
+}
+
+// Seconds logged against every task in a project that has any, running timers count up to now
+pub async fn get_project_time(
+    conn: &mut PoolConnection<Postgres>,
+    project_key: uuid::Uuid,
+    organization_key: uuid::Uuid,
+) -> Result<Vec<(uuid::Uuid, i64)>, AppError> {
+    let records = sqlx::query!(
+        r#"select e.task_key, sum(case when e.stopped = 0 then extract(epoch from now())::bigint - e.started else e.stopped - e.started end)::bigint as "seconds!"
+        from time_entries e join tasks t on t.key = e.task_key
+        where t.project_key = $1 AND e.organization_key = $2
+        group by e.task_key"#,
+        project_key,
+        organization_key
+    )
+    .fetch_all(conn)
+    .await?;
+    Ok(records.into_iter().map(|r| (r.task_key, r.seconds)).collect())
+}
+
+async fn insert_time_entry(conn: &mut PoolConnection<Postgres>, new_entry: &TimeEntry) -> Result<(), AppError> {
+    sqlx::query!("INSERT INTO time_entries (key, organization_key, user_key, task_key, entity_key, service_item_key, started, stopped, billable, note, created, updated) values($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12)",
+        new_entry.key,
+        new_entry.organization_key,
+        new_entry.user_key,
+        new_entry.task_key,
+        new_entry.entity_key,
+        new_entry.service_item_key,
+        new_entry.started,
+        new_entry.stopped,
+        new_entry.billable,
+        &new_entry.note,
+        new_entry.created,
+        new_entry.updated,
+    )
+    .execute(conn)
+    .await
+    .map_err(running_conflict)?;
+    Ok(())
+}
+
+// only the person who logged the time can change it
+async fn update_time_entry(conn: &mut PoolConnection<Postgres>, entry: &TimeEntry) -> Result<PgQueryResult, AppError> {
+    sqlx::query!("UPDATE time_entries SET entity_key=$4, service_item_key=$5, started=$6, stopped=$7, billable=$8, note=$9, updated=extract(epoch from now()) where key = $1 AND organization_key = $2 AND user_key = $3",
+        entry.key,
+        entry.organization_key,
+        entry.user_key,
+        entry.entity_key,
+        entry.service_item_key,
+        entry.started,
+        entry.stopped,
+        entry.billable,
+        &entry.note,
+    )
+    .execute(conn)
+    .await
+    .map_err(running_conflict)
+}
+
+// the second running timer of a user trips time_entries_running_idx
+fn running_conflict(e: sqlx::Error) -> AppError {
+    match &e {
+        sqlx::Error::Database(d) if d.constraint() == Some("time_entries_running_idx") => {
+            AppError::Validation("another timer is already running, stop it first".to_string())
+        }
+        _ => e.into(),
+    }
+}
+
+async fn stop_running_timers(
+    conn: &mut PoolConnection<Postgres>,
+    user_key: uuid::Uuid,
+    organization_key: uuid::Uuid,
+) -> Result<PgQueryResult, sqlx::Error> {
+    return sqlx::query!(
+        "UPDATE time_entries SET stopped=extract(epoch from now()), updated=extract(epoch from now()) where user_key = $1 AND organization_key = $2 AND stopped = 0",
+        user_key,
+        organization_key
+    )
+    .execute(conn)
+    .await;
+}
+
+async fn delete_time_entry(
+    conn: &mut PoolConnection<Postgres>,
+    key: uuid::Uuid,
+    user_key: uuid::Uuid,
+    organization_key: uuid::Uuid,
+) -> Result<PgQueryResult, sqlx::Error> {
+    return sqlx::query!(
+        "DELETE FROM time_entries where organization_key=$1 AND key=$2 AND user_key=$3",
+        organization_key,
+        key,
+        user_key
+    )
+    .execute(conn)
+    .await;
+}
+
+// Route Stuff
+
+pub async fn get_by_task(req: Request<State>) -> tide::Result {
+    let u = match crate::user::user_or_error(&req) {
+        Ok(value) => value,
+        Err(_) => return Ok(tide::Redirect::new("/login").into()),
+    };
+    let task_key = crate::error::param_key(&req, "task_id")?;
+    let mut conn = req.state().db_pool.acquire().await?;
+    let entries = get_task_time_entries(&mut conn, task_key, u.organization_key).await?;
+    let j = serde_json::to_string(&entries).expect("To JSON");
+    Ok(tide::Response::builder(tide::StatusCode::Ok)
+        .content_type(mime::JSON)
+        .body(j)
+        .build())
+}
+
+// A nil key with no start or stop starts a timer, stopping whatever else the user had running.
+// A nil key with both logs time after the fact, any other key edits that entry.
+pub async fn insert(mut req: Request<State>) -> tide::Result {
+    let umd: Result<TimeEntry, tide::Error> = req.body_json().await;
+    let claims: user::UserJwtState = match user::request_claims(&req) {
+        Some(c) => c,
+        None => {
+            return Ok(tide::Redirect::new("/login").into());
+        }
+    };
+    let mut entry = match umd {
+        Ok(e) => e,
+        Err(e) => return Err(AppError::Validation(format!("invalid json body: {}", e)).into()),
+    };
+    let task_key = crate::error::param_key(&req, "task_id")?;
+    let organization_key = crate::error::parse_key(claims.organization_key.as_str(), "organization")?;
+    let user_key = crate::error::parse_key(claims.key.as_str(), "user")?;
+    let mut conn = req.state().db_pool.acquire().await?;
+    if crate::task::get_task(&mut conn, task_key, organization_key).await?.is_none() {
+        return Err(AppError::NotFound("task".to_string()).into());
+    }
+    if let Some(entity_key) = entry.entity_key {
+        if crate::entity::get_entity(&mut conn, entity_key, organization_key).await?.is_none() {
+            return Err(AppError::NotFound("entity".to_string()).into());
+        }
+    }
+    if let Some(service_item_key) = entry.service_item_key {
+        if crate::service_item::get_service_item(&mut conn, service_item_key, organization_key).await?.is_none() {
+            return Err(AppError::NotFound("service item".to_string()).into());
+        }
+    }
+    entry.organization_key = organization_key;
+    entry.task_key = task_key;
+    entry.user_key = user_key;
+
+    if entry.key == uuid::Uuid::nil() {
+        let timer = entry.started == 0 && entry.stopped == 0;
+        if timer {
+            stop_running_timers(&mut conn, user_key, organization_key).await?;
+            entry.started = chrono::Utc::now().timestamp();
+        } else if entry.stopped <= entry.started {
+            return Err(AppError::Validation("time has to stop after it starts".to_string()).into());
+        }
+        let s = TimeEntry::new(organization_key, user_key, task_key, entry.entity_key, entry.service_item_key, entry.started, entry.stopped, entry.billable, entry.note);
+        insert_time_entry(&mut conn, &s).await?;
+        let j = serde_json::to_string(&s).expect("To JSON");
+        return Ok(tide::Response::builder(tide::StatusCode::Ok)
+            .content_type(mime::JSON)
+            .body(j)
+            .build());
+    }
+
+    if entry.stopped != 0 && entry.stopped <= entry.started {
+        return Err(AppError::Validation("time has to stop after it starts".to_string()).into());
+    }
+    if update_time_entry(&mut conn, &entry).await?.rows_affected() == 0 {
+        return Err(AppError::NotFound("time entry".to_string()).into());
+    }
+    let j = serde_json::to_string(&entry).expect("To JSON");
+    Ok(tide::Response::builder(tide::StatusCode::Ok)
+        .content_type(mime::JSON)
+        .body(j)
+        .build())
+}
+
+pub async fn stop(req: Request<State>) -> tide::Result {
+    let u = match crate::user::user_or_error(&req) {
+        Ok(value) => value,
+        Err(_) => return Ok(tide::Redirect::new("/login").into()),
+    };
+    let mut conn = req.state().db_pool.acquire().await?;
+    if stop_running_timers(&mut conn, u.key, u.organization_key).await?.rows_affected() == 0 {
+        return Err(AppError::NotFound("running timer".to_string()).into());
+    }
+    Ok(tide::Response::builder(tide::StatusCode::Ok)
+        .content_type(mime::HTML)
+        .build())
+}
+
+pub async fn delete(req: Request<State>) -> tide::Result {
+    let u = match crate::user::user_or_error(&req) {
+        Ok(value) => value,
+        Err(_) => return Ok(tide::Redirect::new("/login").into()),
+    };
+    let key = crate::error::param_key(&req, "entry_id")?;
+    let mut conn = req.state().db_pool.acquire().await?;
+    if delete_time_entry(&mut conn, key, u.key, u.organization_key).await?.rows_affected() == 0 {
+        return Err(AppError::NotFound("time entry".to_string()).into());
+    }
+    Ok(tide::Response::builder(tide::StatusCode::Ok)
+        .content_type(mime::HTML)
+        .build())
+}
+
+pub async fn report(req: Request<State>) -> tide::Result {
+    let u = match crate::user::user_or_error(&req) {
+        Ok(value) => value,
+        Err(_) => return Ok(tide::Redirect::new("/login").into()),
+    };
+    let entity_key = crate::error::param_key(&req, "entity_id")?;
+    let query: ReportQuery = req.query()?;
+    let from = query.from.as_deref().map(day).transpose()?.unwrap_or(0);
+    // the whole last day is part of the report
+    let to = query.to.as_deref().map(day).transpose()?.map(|d| d + 60 * 60 * 24).unwrap_or(0);
+    let mut conn = req.state().db_pool.acquire().await?;
+    let entity = match crate::entity::get_entity(&mut conn, entity_key, u.organization_key).await? {
+        Some(e) => e,
+        None => {
+            return Ok(tide::Response::builder(tide::StatusCode::NotFound)
+                .content_type(mime::HTML)
+                .body(NotFoundTemplate::new().render_string())
+                .build())
+        }
+    };
+    let entries = get_entity_time_entries(&mut conn, entity_key, u.organization_key, from, to).await?;
+    let service_items = crate::service_item::get_organization_service_items(&mut conn, u.organization_key).await?;
+    let lines = report_lines(&entries, &service_items);
+    let tasks = crate::task::get_tasks_by_organization(&mut conn, u.organization_key).await?;
+    let users = crate::user::get_users_by_organization(&mut conn, u.organization_key).await?;
+    Ok(tide::Response::builder(tide::StatusCode::Ok)
+        .content_type(mime::HTML)
+        .body(
+            TimeReportTemplate::new(
+                u,
+                entity,
+                entries,
+                lines,
+                tasks,
+                users,
+                query.from.unwrap_or_default(),
+                query.to.unwrap_or_default(),
+            )
+            .render_string(),
+        )
+        .build())
+}
+
+// One line per service item the time was billed under. Only hourly items put a price on it,
+// milestone and completion items are invoiced some other way.
+pub fn report_lines(entries: &[TimeEntry], service_items: &[ServiceItem]) -> Vec<ReportLine> {
+    let mut lines: Vec<ReportLine> = vec![];
+    for entry in entries {
+        let index = match lines.iter().position(|l| l.service_item_key == entry.service_item_key) {
+            Some(i) => i,
+            None => {
+                let item = service_items.iter().find(|s| Some(s.key) == entry.service_item_key);
+                lines.push(ReportLine {
+                    service_item_key: entry.service_item_key,
+                    name: item.map(|s| s.name.clone()).unwrap_or("No service item".to_string()),
+                    rate: item.filter(|s| s.service_value_type == ServiceValueType::Hourly).map(|s| s.value),
+                    currency: item.map(|s| s.currency.clone()).unwrap_or_default(),
+                    seconds: 0,
+                    billable_seconds: 0,
+                });
+                lines.len() - 1
+            }
+        };
+        let line = &mut lines[index];
+        line.seconds += entry.seconds();
+        if entry.billable {
+            line.billable_seconds += entry.seconds();
+        }
+    }
+    lines
+}
+
+pub fn hours(seconds: i64) -> String {
+    format!("{:.2}", seconds as f64 / 3600.0)
+}
+
+// data types
+
+#[derive(Debug, Deserialize)]
+pub struct ReportQuery {
+    pub from: Option<String>,
+    pub to: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct TimeEntry {
+    #[serde(default)]
+    pub key: uuid::Uuid,
+    #[serde(default)]
+    pub organization_key: uuid::Uuid,
+    #[serde(default)]
+    pub user_key: uuid::Uuid,
+    #[serde(default)]
+    pub task_key: uuid::Uuid,
+    #[serde(default)]
+    pub entity_key: Option<uuid::Uuid>,
+    #[serde(default)]
+    pub service_item_key: Option<uuid::Uuid>,
+    #[serde(default)]
+    pub started: i64,
+    #[serde(default)]
+    pub stopped: i64,
+    #[serde(default)]
+    pub billable: bool,
+    #[serde(default)]
+    pub note: String,
+    #[serde(default)]
+    pub created: i64,
+    #[serde(default)]
+    pub updated: i64,
+}
+
+impl TimeEntry {
+    pub fn new(
+        organization_key: uuid::Uuid,
+        user_key: uuid::Uuid,
+        task_key: uuid::Uuid,
+        entity_key: Option<uuid::Uuid>,
+        service_item_key: Option<uuid::Uuid>,
+        started: i64,
+        stopped: i64,
+        billable: bool,
+        note: String,
+    ) -> Self {
+        let key = Uuid::new_v4();
+        let created = chrono::Utc::now().timestamp();
+        let updated = 0;
+        Self {
+            key,
+            organization_key,
+            user_key,
+            task_key,
+            entity_key,
+            service_item_key,
+            started,
+            stopped,
+            billable,
+            note,
+            created,
+            updated,
+        }
+    }
+
+    pub fn running(&self) -> bool {
+        self.stopped == 0
+    }
+
+    pub fn seconds(&self) -> i64 {
+        if self.running() {
+            return chrono::Utc::now().timestamp() - self.started;
+        }
+        self.stopped - self.started
+    }
+
+    pub fn hours(&self) -> String {
+        hours(self.seconds())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ReportLine {
+    pub service_item_key: Option<uuid::Uuid>,
+    pub name: String,
+    pub rate: Option<i64>,
+    pub currency: String,
+    pub seconds: i64,
+    pub billable_seconds: i64,
+}
+
+impl ReportLine {
+    pub fn amount(&self) -> Option<String> {
+        self.rate.map(|rate| format!("{:.2}", rate as f64 * self.billable_seconds as f64 / 3600.0))
+    }
+}
+
+#[derive(Template)]
+#[template(path = "time_report.html")]
+pub struct TimeReportTemplate {
+    user: crate::user::User,
+    entity: Entity,
+    entries: Vec<TimeEntry>,
+    lines: Vec<ReportLine>,
+    tasks: Vec<crate::task::Task>,
+    users: Vec<crate::user::User>,
+    from: String,
+    to: String,
+}
+
+impl<'a> TimeReportTemplate {
+    pub fn new(
+        user: crate::user::User,
+        entity: Entity,
+        entries: Vec<TimeEntry>,
+        lines: Vec<ReportLine>,
+        tasks: Vec<crate::task::Task>,
+        users: Vec<crate::user::User>,
+        from: String,
+        to: String,
+    ) -> Self {
+        return Self {
+            user,
+            entity,
+            entries,
+            lines,
+            tasks,
+            users,
+            from,
+            to,
+        };
+    }
+
+    pub fn hours<'aa>(&'aa self, seconds: &i64) -> String {
+        hours(*seconds)
+    }
+
+    pub fn task_name<'aa>(&'aa self, key: &uuid::Uuid) -> String {
+        match self.tasks.iter().find(|t| t.key == *key) {
+            Some(t) => t.name.clone(),
+            None => "a deleted task".to_string(),
+        }
+    }
+
+    pub fn author<'aa>(&'aa self, key: &uuid::Uuid) -> String {
+        match self.users.iter().find(|u| u.key == *key) {
+            Some(u) => u.to_string(),
+            None => "someone".to_string(),
+        }
+    }
+
+    pub fn when<'aa>(&'aa self, timestamp: &i64) -> String {
+        match chrono::DateTime::from_timestamp(*timestamp, 0) {
+            Some(d) => d.format("%Y-%m-%d %H:%M").to_string(),
+            None => "".to_string(),
+        }
+    }
+
+    pub fn render_string(&self) -> String {
+        return self.render().unwrap();
+    }
+}
@@ -18,9 +18,13 @@ use tide::Response;
 use tide::StatusCode;
 use tide::{http::mime, Request};
 
+use crate::error::AppError;
 use crate::home::NotFoundTemplate;
+use crate::invitation;
 use crate::matrix;
 use crate::matrix::Choice;
+use crate::member;
+use crate::member::{Member, Role};
 use crate::organization;
 use crate::organization::Organization;
 use crate::State;
@@ -39,6 +43,9 @@ pub struct UserJwtState {
     pub matrix_refresh_token: String,
     pub matrix_home_server: String,
 
+    #[serde(default)]
+    pub role: Role,
+
     pub updated: i64,
     exp: i64,
 }
@@ -46,27 +53,28 @@ pub struct UserJwtState {
 pub async fn get_users_by_organization(
     conn: &mut PoolConnection<Postgres>,
     organization_key: uuid::Uuid,
-) -> Vec<User> {
+) -> Result<Vec<User>, AppError> {
     let user_records = sqlx::query!(
-        "select key, organization_key, email,  matrix_user_id, matrix_home_server, created, updated from users where organization_key = $1",
+        "select u.key, u.organization_key, u.email, u.matrix_user_id, u.matrix_home_server, u.created, u.updated from users u
+        inner join organization_members m on m.user_key = u.key
+        where m.organization_key = $1",
         organization_key
     )
     .fetch_all(conn)
-    .await
-    .expect("Select user by email");
+    .await?;
     let mut users = vec![];
     for user in user_records {
         users.push(User {
-            key: user.key.expect("key exists"),
-            organization_key: user.organization_key.expect("key exists"),
-            email: user.email.expect("email exists"),
-            matrix_user_id: user.matrix_user_id.expect("matrix_user_id exists"),
-            matrix_home_server: user.matrix_home_server.expect("matrix_home_server exists"),
-            created: user.created.expect("created exists"),
-            updated: user.updated.expect("updated exists"),
+            key: user.key,
+            organization_key: user.organization_key,
+            email: user.email,
+            matrix_user_id: user.matrix_user_id,
+            matrix_home_server: user.matrix_home_server,
+            created: user.created,
+            updated: user.updated,
         })
     }
-    users
+    Ok(users)
 }
 
 pub async fn get_user(conn: &mut PoolConnection<Postgres>, key: uuid::Uuid) -> Option<User> {
@@ -77,18 +85,47 @@ pub async fn get_user(conn: &mut PoolConnection<Postgres>, key: uuid::Uuid) -> O
     .fetch_one(conn)
     .await {
         Ok(user) => Some(User {
-            key: user.key.expect("key exists"),
-            organization_key: user.organization_key.expect("key exists"),
-            email: user.email.expect("email exists"),
-            matrix_user_id: user.matrix_user_id.expect("matrix_user_id exists"),
-            matrix_home_server: user.matrix_home_server.expect("matrix_home_server exists"),
-            created: user.created.expect("created exists"),
-            updated: user.updated.expect("updated exists"),
+            key: user.key,
+            organization_key: user.organization_key,
+            email: user.email,
+            matrix_user_id: user.matrix_user_id,
+            matrix_home_server: user.matrix_home_server,
+            created: user.created,
+            updated: user.updated,
         }),
         Err(_) => { None }
     }
 }
 
+pub async fn get_organization_user(
+    conn: &mut PoolConnection<Postgres>,
+    key: uuid::Uuid,
+    organization_key: uuid::Uuid,
+) -> Result<Option<User>, AppError> {
+    let user = match sqlx::query!(
+        "select u.key, u.organization_key, u.email, u.matrix_user_id, u.matrix_home_server, u.created, u.updated from users u
+        inner join organization_members m on m.user_key = u.key
+        where u.key = $1 AND m.organization_key = $2",
+        key,
+        organization_key
+    )
+    .fetch_optional(conn)
+    .await?
+    {
+        Some(r) => r,
+        None => return Ok(None),
+    };
+    Ok(Some(User {
+        key: user.key,
+        organization_key: user.organization_key,
+        email: user.email,
+        matrix_user_id: user.matrix_user_id,
+        matrix_home_server: user.matrix_home_server,
+        created: user.created,
+        updated: user.updated,
+    }))
+}
+
 pub async fn get_user_by_matrix_user_id(
     conn: &mut PoolConnection<Postgres>,
     matrix_user_id: String,
@@ -101,13 +138,13 @@ pub async fn get_user_by_matrix_user_id(
     .await {
         Ok(user) => {
             Some(User {
-                key: user.key.expect("key exists"),
-                organization_key: user.organization_key.expect("key exists"),
-                email: user.email.expect("email exists"),
-                matrix_user_id: user.matrix_user_id.expect("matrix_user_id exists"),
-                matrix_home_server: user.matrix_home_server.expect("matrix_home_server exists"),
-                created: user.created.expect("created exists"),
-                updated: user.updated.expect("updated exists"),
+                key: user.key,
+                organization_key: user.organization_key,
+                email: user.email,
+                matrix_user_id: user.matrix_user_id,
+                matrix_home_server: user.matrix_home_server,
+                created: user.created,
+                updated: user.updated,
             })
         }, Err(_) => {
             None
@@ -115,24 +152,52 @@ pub async fn get_user_by_matrix_user_id(
     }
 }
 
-async fn delete_user(conn: &mut PoolConnection<Postgres>, key: uuid::Uuid) {
-    sqlx::query!("DELETE FROM users where key=$1", key)
+// Whether someone can already log in as this identity, an empty email or matrix id matches nobody
+pub(crate) async fn account_exists(conn: &mut PoolConnection<Postgres>, email: &str, matrix_user_id: &str) -> Result<bool, AppError> {
+    let found = sqlx::query!(
+        "select exists(select 1 from users where ($1 <> '' AND lower(email) = lower($1)) OR ($2 <> '' AND matrix_user_id = $2)) as found",
+        email,
+        matrix_user_id
+    )
+    .fetch_one(conn)
+    .await?;
+    Ok(found.found.unwrap_or(false))
+}
+
+// users.organization_key is the organization the user last worked in, it's where the next login lands
+pub(crate) async fn set_active_organization(conn: &mut PoolConnection<Postgres>, key: uuid::Uuid, organization_key: uuid::Uuid) -> Result<(), AppError> {
+    sqlx::query!("UPDATE users SET organization_key=$2, updated=extract(epoch from now()) where key = $1", key, organization_key)
         .execute(conn)
-        .await
-        .expect("Insert Success");
+        .await?;
+    Ok(())
+}
+
+// Role for the organization the user lands in, falling back to their oldest membership when they
+// have been removed from the one they last used
+async fn landing_role(conn: &mut PoolConnection<Postgres>, user: &mut User) -> Result<Role, AppError> {
+    if let Some(role) = member::get_role(conn, user.organization_key, user.key).await {
+        return Ok(role);
+    }
+    match member::get_user_memberships(conn, user.key).await?.first() {
+        Some(m) => {
+            user.organization_key = m.organization_key;
+            Ok(m.role)
+        }
+        None => Ok(Role::default()),
+    }
 }
 
-async fn insert_user(conn: &mut PoolConnection<Postgres>, new_user: &User) {
+pub(crate) async fn insert_user(conn: &mut PoolConnection<Postgres>, new_user: &User) -> Result<(), AppError> {
     sqlx::query!("INSERT INTO users (key, organization_key, email, matrix_user_id, matrix_home_server, created, updated) values($1, $2, $3, $4, $5, $6, $7)", new_user.key, new_user.organization_key, &new_user.email, &new_user.matrix_user_id, &new_user.matrix_home_server, new_user.created, new_user.updated)
     .execute(conn)
-    .await
-    .expect("Insert Success");
+    .await?;
+    Ok(())
 }
-async fn update_user(conn: &mut PoolConnection<Postgres>, user: &User) {
-    sqlx::query!("UPDATE users SET organization_key=$2, email=$3, matrix_user_id=$4, matrix_home_server=$5, updated=extract(epoch from now()) where key = $1", user.key, user.organization_key, &user.email, &user.matrix_user_id, &user.matrix_home_server)
+async fn update_user(conn: &mut PoolConnection<Postgres>, user: &User) -> Result<(), AppError> {
+    sqlx::query!("UPDATE users SET email=$2, matrix_user_id=$3, matrix_home_server=$4, updated=extract(epoch from now()) where key = $1", user.key, &user.email, &user.matrix_user_id, &user.matrix_home_server)
     .execute(conn)
-    .await
-    .expect("Insert Success");
+    .await?;
+    Ok(())
 }
 
 // JWT AUTHENTICATION STUFF
@@ -141,7 +206,7 @@ pub fn jwt_valid(jwt: String) -> Result<TokenData<UserJwtState>, jsonwebtoken::e
     let jwt_secret = std::env::var("JWT_SECRET")
         .expect("Missing `JWT_SECRET` env variable, needed for running the server");
     return decode::<UserJwtState>(
-        &token_part,
+        token_part,
         &DecodingKey::from_secret(jwt_secret.as_bytes()),
         &Validation::new(Algorithm::HS512),
     )
@@ -156,6 +221,7 @@ pub fn create_jwt(
     matrix_access_token: String,
     matrix_device_id: String,
     matrix_refresh_token: String,
+    role: Role,
 ) -> Result<String, jsonwebtoken::errors::Error> {
     let expiration = chrono::Utc::now()
         .checked_add_signed(chrono::Duration::hours(2))
@@ -167,9 +233,10 @@ pub fn create_jwt(
         email: u.email.to_string(),
         matrix_user_id: u.matrix_user_id.clone(),
         matrix_home_server: u.matrix_home_server.clone(),
-        matrix_access_token: matrix_access_token,
-        matrix_device_id: matrix_device_id,
-        matrix_refresh_token: matrix_refresh_token,
+        matrix_access_token,
+        matrix_device_id,
+        matrix_refresh_token,
+        role,
         created: u.created,
         updated: u.updated,
         exp: expiration,
@@ -182,54 +249,74 @@ pub fn create_jwt(
         &jwt_state,
         &EncodingKey::from_secret(jwt_secret.as_bytes()),
     )
-    .map_err(|e| e)
 }
 
-pub fn read_jwt_cookie(cookie: Option<Cookie<'static>>) -> Option<UserJwtState> {
-    let cookie_val = match cookie {
-        Some(c) => c,
-        None => return None,
-    };
+// Claims for a request authenticated by an API token rather than a session. They only live for the
+// request, there is no matrix session behind them so the matrix fields are left empty.
+pub fn api_claims(u: &User, role: Role) -> UserJwtState {
+    let expiration = chrono::Utc::now()
+        .checked_add_signed(chrono::Duration::hours(2))
+        .expect("valid timestamp")
+        .timestamp();
+    UserJwtState {
+        key: u.key.to_string(),
+        organization_key: u.organization_key.to_string(),
+        email: u.email.to_string(),
+        matrix_user_id: u.matrix_user_id.clone(),
+        matrix_home_server: u.matrix_home_server.clone(),
+        matrix_access_token: "".to_string(),
+        matrix_device_id: "".to_string(),
+        matrix_refresh_token: "".to_string(),
+        role,
+        created: u.created,
+        updated: u.updated,
+        exp: expiration,
+    }
+}
 
-    let valid = match jwt_valid(cookie_val.to_string()) {
-        Ok(t) => t,
-        Err(_e) => return None,
-    };
-    return Some(valid.claims);
+// The claims Authorize settled on for this request, whether they came from the session cookie or
+// an API token. Falls back to the cookie for routes that aren't behind Authorize.
+pub fn request_claims(req: &Request<State>) -> Option<UserJwtState> {
+    match req.ext::<UserJwtState>() {
+        Some(c) => Some(c.clone()),
+        None => read_jwt_cookie(req.cookie("token")),
+    }
 }
 
-pub fn read_jwt_cookie_to_user(cookie: Option<Cookie<'static>>) -> Option<User> {
-    let cookie_val = match cookie {
-        Some(c) => c,
-        None => return None,
-    };
+pub fn request_user(req: &Request<State>) -> Option<User> {
+    let claims = request_claims(req)?;
+    Some(User::new(
+        uuid::Uuid::from_str(claims.key.as_str()).ok()?,
+        uuid::Uuid::from_str(&claims.organization_key).ok()?,
+        claims.email,
+        claims.matrix_user_id,
+        claims.matrix_home_server,
+    ))
+}
+
+pub fn read_jwt_cookie(cookie: Option<Cookie<'static>>) -> Option<UserJwtState> {
+    let cookie_val = cookie?;
 
     let valid = match jwt_valid(cookie_val.to_string()) {
         Ok(t) => t,
         Err(_e) => return None,
     };
-    let u = User::new(
-        uuid::Uuid::from_str(valid.claims.key.as_str()).expect("parse cookie"),
-        uuid::Uuid::from_str(&valid.claims.organization_key)
-            .expect("parse organization key from jwt"),
-        valid.claims.email,
-        valid.claims.matrix_user_id,
-        valid.claims.matrix_home_server,
-    );
-
-    return Some(u);
+    return Some(valid.claims);
 }
+
 // Route Stuff
 pub async fn delete(req: Request<State>) -> tide::Result {
-    if let Some(value) = jwt_invalid(&req) {
-        return value;
-    }
+    let u = match user_or_error(&req) {
+        Ok(value) => value,
+        Err(e) => return e,
+    };
 
     match req.param("user_id") {
         Ok(key) => {
             let mut conn = req.state().db_pool.acquire().await?;
-            let u_uuid = uuid::Uuid::from_str(key).expect("User uuid parse");
-            delete_user(&mut conn, u_uuid).await;
+            let u_uuid = crate::error::parse_key(key, "user")?;
+            // a user can belong to other organizations, so removing them only drops the membership
+            member::delete_member(&mut conn, u.organization_key, u_uuid).await?;
             Ok(tide::Response::builder(tide::StatusCode::Ok)
                 .content_type(mime::HTML)
                 .build())
@@ -255,28 +342,9 @@ pub fn user_jwt_state_invalid(claims: UserJwtState) -> Option<Result<Response, t
     None
 }
 
-pub fn jwt_invalid(req: &Request<State>) -> Option<Result<Response, tide::Error>> {
-    let claims = match read_jwt_cookie(req.cookie("token")) {
-        Some(c) => c,
-        None => {
-            return Some(Ok(tide::Response::builder(tide::StatusCode::Unauthorized)
-                .content_type(mime::PLAIN)
-                .body("UNAUTHORIZED")
-                .build()))
-        }
-    };
-    let now = chrono::Utc::now().timestamp();
-    if claims.exp < now {
-        return Some(Ok(tide::Response::builder(tide::StatusCode::Unauthorized)
-            .content_type(mime::PLAIN)
-            .body("EXPIRED")
-            .build()));
-    }
-    None
-}
-
+#[allow(clippy::result_large_err)]
 pub fn user_or_error(req: &Request<State>) -> Result<User, Result<tide::Response, tide::Error>> {
-    let claims = match read_jwt_cookie(req.cookie("token")) {
+    let claims = match request_claims(req) {
         Some(c) => c,
         None => {
             return Err(Ok(tide::Response::builder(tide::StatusCode::Unauthorized)
@@ -302,15 +370,24 @@ pub fn user_or_error(req: &Request<State>) -> Result<User, Result<tide::Response
 }
 
 pub async fn get(req: Request<State>) -> tide::Result {
-    if let Some(value) = jwt_invalid(&req) {
-        return value;
-    }
+    let u = match user_or_error(&req) {
+        Ok(value) => value,
+        Err(e) => return e,
+    };
 
     match req.param("user_id") {
         Ok(key) => {
             let mut conn = req.state().db_pool.acquire().await?; // .await? needs to be a real connection pool error handler!!!!!!!!
-            let u_uuid = uuid::Uuid::from_str(key).expect("User uuid parse");
-            let user = get_user(&mut conn, u_uuid).await.expect("user exists");
+            let u_uuid = crate::error::parse_key(key, "user")?;
+            let user = match get_organization_user(&mut conn, u_uuid, u.organization_key).await? {
+                Some(user) => user,
+                None => {
+                    return Ok(tide::Response::builder(tide::StatusCode::NotFound)
+                        .content_type(mime::HTML)
+                        .body(NotFoundTemplate::new().render_string())
+                        .build())
+                }
+            };
 
             Ok(tide::Response::builder(tide::StatusCode::Ok)
                 .content_type(mime::HTML)
@@ -335,8 +412,7 @@ pub async fn register(_req: Request<State>) -> tide::Result {
             homeserver_url.to_string(),
             "http://localhost:8080/register_matrix".to_string(),
         )
-        .await
-        .expect("Get login choices");
+        .await?;
         let register = RegisterTemplate::new(choices);
         return Ok(tide::Response::builder(tide::StatusCode::Ok)
             .content_type(mime::HTML)
@@ -351,7 +427,7 @@ pub async fn register(_req: Request<State>) -> tide::Result {
 }
 
 pub async fn update(mut req: Request<State>) -> tide::Result {
-    let _claims: UserJwtState = match read_jwt_cookie(req.cookie("token")) {
+    let claims: UserJwtState = match request_claims(&req) {
         Some(c) => c,
         None => {
             return Ok(tide::Redirect::new("/login").into());
@@ -360,6 +436,7 @@ pub async fn update(mut req: Request<State>) -> tide::Result {
     let umd: Result<User, tide::Error> = req.body_json().await;
     match umd {
         Ok(u) => {
+            let organization_key = crate::error::parse_key(claims.organization_key.as_str(), "organization")?;
             let mut conn = match req.state().db_pool.acquire().await {
                 Ok(c) => c,
                 Err(e) => {
@@ -371,27 +448,35 @@ pub async fn update(mut req: Request<State>) -> tide::Result {
                     )
                 }
             };
-
-            update_user(&mut conn, &u).await;
+            if get_organization_user(&mut conn, u.key, organization_key).await?.is_none() {
+                return Ok(tide::Response::builder(tide::StatusCode::NotFound)
+                    .content_type(mime::HTML)
+                    .body(NotFoundTemplate::new().render_string())
+                    .build());
+            }
+            // email and the matrix id are what logins find an account by, so nobody but the user
+            // themselves gets to change them, administrators included
+            if u.key.to_string() != claims.key {
+                return Err(AppError::Forbidden("you can only edit your own account".to_string()).into());
+            }
+
+            update_user(&mut conn, &u).await?;
             let j = serde_json::to_string(&u).expect("To JSON");
             Ok(tide::Response::builder(tide::StatusCode::Ok)
                 .content_type(mime::JSON)
                 .body(j)
                 .build())
         }
-        Err(e) => {
-            println!("{:?}", e);
-            Ok(tide::Response::builder(tide::StatusCode::BadRequest)
-                .content_type(mime::JSON)
-                .body("{'error': 'invalid json body'}")
-                .build())
-        }
+        Err(e) => Err(AppError::Validation(format!("invalid json body: {}", e)).into()),
     }
 }
 
 pub async fn register_post(req: Request<State>) -> tide::Result {
-    let vals = req.url().query().expect("Get query values");
-    let login_token = vals.split("loginToken=").last().expect("Token found");
+    let vals = req.url().query().unwrap_or_default();
+    let login_token = match vals.split("loginToken=").nth(1) {
+        Some(t) => t,
+        None => return Err(AppError::Validation("loginToken is required".to_string()).into()),
+    };
     let homeserver_url = "https://matrix-client.matrix.org".to_string();
     let matrix_login_response =
         crate::matrix::login_with_token(homeserver_url.clone(), login_token.to_string()).await;
@@ -409,34 +494,49 @@ pub async fn register_post(req: Request<State>) -> tide::Result {
                     )
                 }
             };
-            let (email, avatar, display_name)  = crate::matrix::account(homeserver_url.clone(), login_token.to_string(), u.user_id.clone()).await;
-            let organization_key = Uuid::new_v4();
+            // a matrix account registers once, after that it logs in, where a pending invitation is
+            // still claimed
+            if get_user_by_matrix_user_id(&mut conn, u.user_id.to_string()).await.is_some() {
+                return Ok(tide::Redirect::new("/login").into());
+            }
+            let (email, _avatar, _display_name)  = crate::matrix::account(homeserver_url.clone(), u.access_token.clone(), u.user_id.clone()).await?;
+            // an invited user joins the inviting organization instead of getting their own
+            let invite = match req.cookie("invite") {
+                Some(c) => invitation::claim_invitation(&mut conn, c.value(), email.as_str(), u.user_id.as_str()).await?,
+                None => None,
+            };
+            let organization_key = match &invite {
+                Some(i) => i.organization_key,
+                None => Uuid::new_v4(),
+            };
             let key = Uuid::new_v4();
             let new_user = User::new(
                 key,
                 organization_key,
                 email,
                 u.user_id.to_string(),
-                u.home_server.expect("Get home server").to_string(),
+                u.home_server.as_ref().map(|h| h.to_string()).ok_or_else(|| AppError::Matrix("login response has no home server".to_string()))?,
             );
 
-            organization::insert_organization(
-                &mut conn,
-                &Organization::new(
-                    organization_key,
-                    "".to_string(),
-                    "".to_string(),
-                    new_user.key,
-                    "Welcome Inc.".to_string(),
-                    "".to_string(),
-                    homeserver_url,
-                    "".to_string(),
-                    "".to_string(),
-                    "".to_string(),
-                    "".to_string(),
-                ),
-            )
-            .await;
+            if invite.is_none() {
+                organization::insert_organization(
+                    &mut conn,
+                    &Organization::new(
+                        organization_key,
+                        "".to_string(),
+                        "".to_string(),
+                        new_user.key,
+                        "Welcome Inc.".to_string(),
+                        "".to_string(),
+                        homeserver_url,
+                        "".to_string(),
+                        "".to_string(),
+                        "".to_string(),
+                        "".to_string(),
+                    ),
+                )
+                .await?;
+            }
 
             let mut conn = match req.state().db_pool.acquire().await {
                 Ok(c) => c,
@@ -450,7 +550,12 @@ pub async fn register_post(req: Request<State>) -> tide::Result {
                 }
             };
 
-            insert_user(&mut conn, &new_user).await;
+            insert_user(&mut conn, &new_user).await?;
+            let role = match &invite {
+                Some(i) => i.role,
+                None => Role::Owner,
+            };
+            member::insert_member(&mut conn, &Member::new(organization_key, new_user.key, role)).await?;
             let mut res = Response::new(StatusCode::TemporaryRedirect);
             let now = SystemTime::now();
             let cadd = now
@@ -458,23 +563,18 @@ pub async fn register_post(req: Request<State>) -> tide::Result {
                 .expect("added");
             let datetime: DateTime<Utc> = cadd.into();
 
-            let jwt = create_jwt(&new_user, "".to_string(), "".to_string(), "".to_string())
+            let jwt = create_jwt(&new_user, "".to_string(), "".to_string(), "".to_string(), role)
                 .expect("JWT Created");
 
-            let cookie_str = format!("token={}; Expires={}", jwt, datetime.to_string());
+            let cookie_str = format!("token={}; Expires={}", jwt, datetime);
             // let expire_time = "Wed, 21 Oct 2017 07:28:00 GMT";
             let c = Cookie::parse(cookie_str).unwrap();
             res.insert_cookie(c);
+            res.remove_cookie(Cookie::named("invite"));
             res.insert_header("Location", "/dashboard");
             Ok(res)
         }
-        Err(e) => {
-            println!("{:?}", e);
-            Ok(tide::Response::builder(tide::StatusCode::BadRequest)
-                .content_type(mime::JSON)
-                .body("{'error': 'invalid json body'}")
-                .build())
-        }
+        Err(e) => Err(AppError::Validation(format!("invalid json body: {}", e)).into()),
     }
 }
 
@@ -489,8 +589,8 @@ pub async fn logout(_req: Request<State>) -> tide::Result {
 }
 
 pub async fn login_by_username(req: Request<State>) -> tide::Result {
-    let homeserver_url = "https://matrix-client.matrix.org";
-    let claims: crate::user::UserJwtState = match crate::user::read_jwt_cookie(req.cookie("token"))
+    let _homeserver_url = "https://matrix-client.matrix.org";
+    let _claims: crate::user::UserJwtState = match crate::user::read_jwt_cookie(req.cookie("token"))
     {
         Some(c) => c,
         None => {
@@ -522,8 +622,7 @@ pub async fn login(req: Request<State>) -> tide::Result {
                 homeserver_url.to_string(),
                 "http://localhost:8080/login_matrix".to_string(),
             )
-            .await
-            .expect("Get login choices");
+            .await?;
             let login = LoginTemplate::new(choices);
             return Ok(tide::Response::builder(tide::StatusCode::Ok)
                 .content_type(mime::HTML)
@@ -532,9 +631,9 @@ pub async fn login(req: Request<State>) -> tide::Result {
         }
     };
     // lets refresh and go
-    if claims.matrix_refresh_token.len() > 0 {
+    if !claims.matrix_refresh_token.is_empty() {
         let user_id =
-            <OwnedUserId>::try_from(claims.matrix_user_id.as_str()).expect("parse user id");
+            <OwnedUserId>::try_from(claims.matrix_user_id.as_str()).map_err(|e| AppError::Matrix(e.to_string()))?;
         let session = Session {
             access_token: claims.matrix_access_token,
             refresh_token: Some(claims.matrix_refresh_token),
@@ -542,8 +641,7 @@ pub async fn login(req: Request<State>) -> tide::Result {
             device_id: device_id!("kinbrio").to_owned(),
         };
         crate::matrix::restore_from_session(claims.matrix_home_server, session)
-            .await
-            .expect("Restoring from session");
+            .await?;
         return Ok(tide::Redirect::new("/").into());
     } else {
         // fresh login
@@ -551,8 +649,7 @@ pub async fn login(req: Request<State>) -> tide::Result {
             homeserver_url.to_string(),
             "http://localhost:8080/login_matrix".to_string(),
         )
-        .await
-        .expect("Get login choices");
+        .await?;
         let login = LoginTemplate::new(choices);
         return Ok(tide::Response::builder(tide::StatusCode::Ok)
             .content_type(mime::HTML)
@@ -562,8 +659,11 @@ pub async fn login(req: Request<State>) -> tide::Result {
 }
 
 pub async fn login_matrix(req: Request<State>) -> tide::Result {
-    let vals = req.url().query().expect("Get query values");
-    let login_token = vals.split("loginToken=").last().expect("Token found");
+    let vals = req.url().query().unwrap_or_default();
+    let login_token = match vals.split("loginToken=").nth(1) {
+        Some(t) => t,
+        None => return Err(AppError::Validation("loginToken is required".to_string()).into()),
+    };
     let matrix_login_response = match crate::matrix::login_with_token(
         "https://matrix-client.matrix.org".to_string(),
         login_token.to_string(),
@@ -591,21 +691,25 @@ pub async fn login_matrix(req: Request<State>) -> tide::Result {
     let matrix_device_id = matrix_login_response.device_id.to_string();
     let matrix_refresh_token = matrix_login_response.refresh_token.unwrap_or_default();
     let matrix_expires_in = matrix_login_response.expires_in.unwrap_or_default();
-    let matrix_home_server = matrix_login_response
-        .home_server
-        .as_ref()
-        .expect("Get home serve");
+    let matrix_home_server = match matrix_login_response.home_server.as_ref() {
+        Some(h) => h,
+        None => return Err(AppError::Matrix("login response has no home server".to_string()).into()),
+    };
 
     // TODO: add access token/refresh/etc to redis with the matrix_expires_in
-    let user = match get_user_by_matrix_user_id(&mut conn, matrix_user_id.clone()).await {
+    let mut user = match get_user_by_matrix_user_id(&mut conn, matrix_user_id.clone()).await {
         Some(u) => u,
         None => {
             return Ok(tide::Redirect::new("/register").into());
         }
     };
+    if let Some(c) = req.cookie("invite") {
+        invitation::join_invitation(&mut conn, c.value(), &mut user).await?;
+    }
 
     let mut res = Response::new(StatusCode::TemporaryRedirect);
 
+    let role = landing_role(&mut conn, &mut user).await?;
     let user_t = User {
         key: user.key,
         organization_key: user.organization_key,
@@ -622,15 +726,17 @@ pub async fn login_matrix(req: Request<State>) -> tide::Result {
         matrix_access_token,
         matrix_device_id,
         matrix_refresh_token.to_string(),
+        role,
     )
     .expect("JWT Created");
     let now = SystemTime::now();
     let cadd = now.checked_add(matrix_expires_in).expect("added");
     let datetime: DateTime<Utc> = cadd.into();
 
-    let cookie_str = format!("token={}; Expires={}", jwt, datetime.to_string());
+    let cookie_str = format!("token={}; Expires={}", jwt, datetime);
     let c = Cookie::parse(cookie_str).unwrap();
     res.insert_cookie(c);
+    res.remove_cookie(Cookie::named("invite"));
     res.insert_header("Location", "/dashboard");
     Ok(res)
 }
@@ -670,7 +776,7 @@ pub async fn login_post(mut req: Request<State>) -> tide::Result {
             return Ok(tide::Redirect::new("/login_by_username").into());
         }
     };
-    if matrix_login_response.access_token.len() == 0 {
+    if matrix_login_response.access_token.is_empty() {
         return Ok(
             tide::Response::builder(tide::StatusCode::InternalServerError)
                 .content_type(mime::PLAIN)
@@ -678,14 +784,25 @@ pub async fn login_post(mut req: Request<State>) -> tide::Result {
                 .build(),
         );
     }
-    let (email, avatar, display_name)  = crate::matrix::account(homeserver.clone(), matrix_login_response.access_token.clone(), matrix_login_response.user_id.clone()).await;
+    let (email, _avatar, _display_name)  = crate::matrix::account(homeserver.clone(), matrix_login_response.access_token.clone(), matrix_login_response.user_id.clone()).await?;
     
-    let user = match get_user_by_matrix_user_id(&mut conn, matrix_login_response.user_id.to_string())
+    let mut user = match get_user_by_matrix_user_id(&mut conn, matrix_login_response.user_id.to_string())
     .await {
-        Some(u) => u,
+        Some(mut u) => {
+            if let Some(c) = req.cookie("invite") {
+                invitation::join_invitation(&mut conn, c.value(), &mut u).await?;
+            }
+            u
+        }
         None => {
-           
-            let organization_key = Uuid::new_v4();
+            let invite = match req.cookie("invite") {
+                Some(c) => invitation::claim_invitation(&mut conn, c.value(), email.as_str(), matrix_login_response.user_id.as_str()).await?,
+                None => None,
+            };
+            let organization_key = match &invite {
+                Some(i) => i.organization_key,
+                None => Uuid::new_v4(),
+            };
             let key = Uuid::new_v4();
             let new_user: User = User::new(
                 key,
@@ -695,23 +812,25 @@ pub async fn login_post(mut req: Request<State>) -> tide::Result {
                 homeserver.clone(),
             );
             
-            organization::insert_organization(
-                &mut conn,
-                &Organization::new(
-                    organization_key,
-                    "".to_string(),
-                    "".to_string(),
-                    new_user.key,
-                    "Welcome Inc.".to_string(),
-                    "".to_string(),
-                    homeserver.clone(),
-                    "".to_string(),
-                    "".to_string(),
-                    "".to_string(),
-                    "".to_string(),
-                ),
-            )
-            .await;
+            if invite.is_none() {
+                organization::insert_organization(
+                    &mut conn,
+                    &Organization::new(
+                        organization_key,
+                        "".to_string(),
+                        "".to_string(),
+                        new_user.key,
+                        "Welcome Inc.".to_string(),
+                        "".to_string(),
+                        homeserver.clone(),
+                        "".to_string(),
+                        "".to_string(),
+                        "".to_string(),
+                        "".to_string(),
+                    ),
+                )
+                .await?;
+            }
 
             let mut conn = match req.state().db_pool.acquire().await {
                 Ok(c) => c,
@@ -725,21 +844,28 @@ pub async fn login_post(mut req: Request<State>) -> tide::Result {
                 }
             };
 
-            insert_user(&mut conn, &new_user).await;
+            insert_user(&mut conn, &new_user).await?;
+            let role = match &invite {
+                Some(i) => i.role,
+                None => Role::Owner,
+            };
+            member::insert_member(&mut conn, &Member::new(organization_key, new_user.key, role)).await?;
             new_user
         }
     };
+    let role = landing_role(&mut conn, &mut user).await?;
     let mut res = Response::new(StatusCode::Ok);
      
     let jwt =
-        create_jwt(&user, "".to_string(), "".to_string(), "".to_string()).expect("JWT Created");
+        create_jwt(&user, "".to_string(), "".to_string(), "".to_string(), role).expect("JWT Created");
 
     let now = SystemTime::now();
     let cadd = now;
     let datetime: DateTime<Utc> = cadd.into();
-    let cookie_str = format!("token={}; Expires={}", jwt, datetime.to_string());
+    let cookie_str = format!("token={}; Expires={}", jwt, datetime);
     let c = Cookie::parse(cookie_str).unwrap();
     res.insert_cookie(c);
+    res.remove_cookie(Cookie::named("invite"));
     Ok(res)
 }
 
@@ -769,7 +895,6 @@ pub struct LoginPostRequest {
 
 #[derive(Template)]
 #[template(path = "login_username.html")]
-
 pub struct LoginUsernameTemplate {
     user: User,
 }
@@ -804,7 +929,7 @@ pub struct LoginTemplate {
 impl<'a> LoginTemplate {
     pub fn new(choices: Vec<Choice>) -> Self {
         return Self {
-            choices: choices,
+            choices,
             user: User {
                 key: uuid::Uuid::nil(),
                 organization_key: uuid::Uuid::nil(),
@@ -832,7 +957,7 @@ pub struct RegisterTemplate {
 impl<'a> RegisterTemplate {
     pub fn new(choices: Vec<Choice>) -> Self {
         return Self {
-            choices: choices,
+            choices,
             user: User {
                 key: uuid::Uuid::nil(),
                 organization_key: uuid::Uuid::nil(),
@@ -851,17 +976,20 @@ impl<'a> RegisterTemplate {
 }
 
 #[derive(Debug, Deserialize, Serialize)]
+#[allow(dead_code)]
 pub struct RegisterUser {
     pub email: String,
     pub organization: String,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
+#[allow(dead_code)]
 pub struct LoginData {
     email: String,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
+#[allow(dead_code)]
 pub struct UserPreferences {
     pub key: uuid::Uuid,
     pub user_key: uuid::Uuid,
@@ -886,7 +1014,7 @@ pub struct User {
 
 impl ToString for User {
     fn to_string(&self) -> String {
-        format!("{}", self.email)
+        self.email.clone()
     }
 }
 
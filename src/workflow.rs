@@ -0,0 +1,495 @@
+use askama::Template;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use sqlx::pool::PoolConnection;
+use sqlx::postgres::PgQueryResult;
+use sqlx::{PgConnection, Postgres};
+use strum::IntoEnumIterator;
+use strum_macros::EnumIter;
+use uuid::Uuid;
+
+use tide::{http::mime, Request};
+
+use crate::error::AppError;
+use crate::{user, State};
+
+// What a new organization starts with, the statuses tasks had before workflows were configurable
+const DEFAULT_STATUSES: [(&str, &str, StatusCategory); 8] = [
+    ("Wishlist", "#cc66ff85", StatusCategory::NotStarted),
+    ("Todo", "#ff6a4a85", StatusCategory::NotStarted),
+    ("Planning And Estimating", "#ffb26085", StatusCategory::NotStarted),
+    ("In Queue", "#f9ff5b85", StatusCategory::NotStarted),
+    ("In Progress", "#61d1d085", StatusCategory::Active),
+    ("To Review", "#658cff85", StatusCategory::Active),
+    ("In Reviewal", "#D2CAFF85", StatusCategory::Active),
+    ("Complete", "#64ff6385", StatusCategory::Done),
+];
+
+// SQL STUFF
+
+pub async fn get_workflow(
+    conn: &mut PoolConnection<Postgres>,
+    organization_key: uuid::Uuid,
+) -> Result<Workflow, AppError> {
+    let records = sqlx::query!(
+        "select key, organization_key, name, color, category, position, created, updated from workflow_statuses where organization_key = $1 order by position, created",
+        organization_key
+    )
+    .fetch_all(&mut *conn)
+    .await?;
+    let mut statuses = vec![];
+    for status in records {
+        statuses.push(WorkflowStatus {
+            key: status.key,
+            organization_key: status.organization_key,
+            name: status.name,
+            color: status.color,
+            category: status.category.into(),
+            position: status.position,
+            created: status.created,
+            updated: status.updated,
+        })
+    }
+    let records = sqlx::query!(
+        "select key, organization_key, from_key, to_key, created from workflow_transitions where organization_key = $1",
+        organization_key
+    )
+    .fetch_all(conn)
+    .await?;
+    let mut transitions = vec![];
+    for transition in records {
+        transitions.push(Transition {
+            key: transition.key,
+            organization_key: transition.organization_key,
+            from_key: transition.from_key,
+            to_key: transition.to_key,
+            created: transition.created,
+        })
+    }
+    Ok(Workflow { statuses, transitions })
+}
+
+async fn insert_workflow_status(conn: &mut PgConnection, new_status: &WorkflowStatus) -> Result<(), AppError> {
+    sqlx::query!("INSERT INTO workflow_statuses (key, organization_key, name, color, category, position, created, updated) values($1, $2, $3, $4, $5, $6, $7, $8)",
+        new_status.key,
+        new_status.organization_key,
+        &new_status.name,
+        &new_status.color,
+        new_status.category as i16,
+        new_status.position,
+        new_status.created,
+        new_status.updated,
+    )
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+async fn update_workflow_status(conn: &mut PoolConnection<Postgres>, status: &WorkflowStatus) -> Result<PgQueryResult, AppError> {
+    Ok(sqlx::query!("UPDATE workflow_statuses SET name=$3, color=$4, category=$5, position=$6, updated=extract(epoch from now()) where key = $1 AND organization_key = $2",
+        status.key,
+        status.organization_key,
+        &status.name,
+        &status.color,
+        status.category as i16,
+        status.position,
+    )
+    .execute(conn)
+    .await?)
+}
+
+async fn delete_workflow_status(
+    conn: &mut PoolConnection<Postgres>,
+    key: uuid::Uuid,
+    organization_key: uuid::Uuid,
+) -> Result<PgQueryResult, sqlx::Error> {
+    return sqlx::query!(
+        "DELETE FROM workflow_statuses where organization_key=$1 AND key=$2",
+        organization_key,
+        key
+    )
+    .execute(conn)
+    .await;
+}
+
+async fn count_tasks_in_status(
+    conn: &mut PoolConnection<Postgres>,
+    key: uuid::Uuid,
+    organization_key: uuid::Uuid,
+) -> Result<i64, AppError> {
+    let row = sqlx::query!(
+        "select count(*) as count from tasks where status_key = $1 AND organization_key = $2",
+        key,
+        organization_key
+    )
+    .fetch_one(conn)
+    .await?;
+    Ok(row.count.unwrap_or_default())
+}
+
+async fn insert_workflow_transition(conn: &mut PoolConnection<Postgres>, new_transition: &Transition) -> Result<(), AppError> {
+    sqlx::query!("INSERT INTO workflow_transitions (key, organization_key, from_key, to_key, created) values($1, $2, $3, $4, $5) ON CONFLICT (from_key, to_key) DO NOTHING",
+        new_transition.key,
+        new_transition.organization_key,
+        new_transition.from_key,
+        new_transition.to_key,
+        new_transition.created,
+    )
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+async fn delete_workflow_transition(
+    conn: &mut PoolConnection<Postgres>,
+    key: uuid::Uuid,
+    organization_key: uuid::Uuid,
+) -> Result<PgQueryResult, sqlx::Error> {
+    return sqlx::query!(
+        "DELETE FROM workflow_transitions where organization_key=$1 AND key=$2",
+        organization_key,
+        key
+    )
+    .execute(conn)
+    .await;
+}
+
+pub(crate) async fn seed_default_workflow(
+    conn: &mut PgConnection,
+    organization_key: uuid::Uuid,
+) -> Result<(), AppError> {
+    for (position, (name, color, category)) in DEFAULT_STATUSES.iter().enumerate() {
+        let s = WorkflowStatus::new(organization_key, name.to_string(), color.to_string(), *category, position as i32);
+        insert_workflow_status(conn, &s).await?;
+    }
+    Ok(())
+}
+
+// Route Stuff
+
+pub async fn get(req: Request<State>) -> tide::Result {
+    let u = match crate::user::user_or_error(&req) {
+        Ok(value) => value,
+        Err(_) => return Ok(tide::Redirect::new("/login").into()),
+    };
+    let mut conn = req.state().db_pool.acquire().await?;
+    let workflow = get_workflow(&mut conn, u.organization_key).await?;
+    Ok(tide::Response::builder(tide::StatusCode::Ok)
+        .content_type(mime::HTML)
+        .body(WorkflowTemplate::new(u, workflow).render_string())
+        .build())
+}
+
+// A nil key adds a status at the end of the workflow, any other key updates it
+pub async fn insert_status(mut req: Request<State>) -> tide::Result {
+    let umd: Result<WorkflowStatus, tide::Error> = req.body_json().await;
+    let claims: user::UserJwtState = match user::request_claims(&req) {
+        Some(c) => c,
+        None => {
+            return Ok(tide::Redirect::new("/login").into());
+        }
+    };
+    let mut status = match umd {
+        Ok(s) => s,
+        Err(e) => return Err(AppError::Validation(format!("invalid json body: {}", e)).into()),
+    };
+    if status.name.trim().is_empty() {
+        return Err(AppError::Validation("a status needs a name".to_string()).into());
+    }
+    let organization_key = crate::error::parse_key(claims.organization_key.as_str(), "organization")?;
+    status.organization_key = organization_key;
+    let mut conn = req.state().db_pool.acquire().await?;
+
+    if status.key == uuid::Uuid::nil() {
+        let position = get_workflow(&mut conn, organization_key).await?.statuses.len() as i32;
+        let s = WorkflowStatus::new(organization_key, status.name, status.color, status.category, position);
+        insert_workflow_status(&mut conn, &s).await?;
+        let j = serde_json::to_string(&s).expect("To JSON");
+        return Ok(tide::Response::builder(tide::StatusCode::Ok)
+            .content_type(mime::JSON)
+            .body(j)
+            .build());
+    }
+
+    if update_workflow_status(&mut conn, &status).await?.rows_affected() == 0 {
+        return Err(AppError::NotFound("status".to_string()).into());
+    }
+    let j = serde_json::to_string(&status).expect("To JSON");
+    Ok(tide::Response::builder(tide::StatusCode::Ok)
+        .content_type(mime::JSON)
+        .body(j)
+        .build())
+}
+
+pub async fn delete_status(req: Request<State>) -> tide::Result {
+    let u = match crate::user::user_or_error(&req) {
+        Ok(value) => value,
+        Err(_) => return Ok(tide::Redirect::new("/login").into()),
+    };
+    let key = crate::error::param_key(&req, "status_id")?;
+    let mut conn = req.state().db_pool.acquire().await?;
+    // tasks point at their status, so one in use has to be emptied out first
+    if count_tasks_in_status(&mut conn, key, u.organization_key).await? > 0 {
+        return Err(AppError::Validation("move the tasks out of this status before removing it".to_string()).into());
+    }
+    if get_workflow(&mut conn, u.organization_key).await?.statuses.len() <= 1 {
+        return Err(AppError::Validation("a workflow needs at least one status".to_string()).into());
+    }
+    if delete_workflow_status(&mut conn, key, u.organization_key).await?.rows_affected() == 0 {
+        return Err(AppError::NotFound("status".to_string()).into());
+    }
+    Ok(tide::Response::builder(tide::StatusCode::Ok)
+        .content_type(mime::HTML)
+        .build())
+}
+
+pub async fn insert_transition(mut req: Request<State>) -> tide::Result {
+    let umd: Result<Transition, tide::Error> = req.body_json().await;
+    let claims: user::UserJwtState = match user::request_claims(&req) {
+        Some(c) => c,
+        None => {
+            return Ok(tide::Redirect::new("/login").into());
+        }
+    };
+    let transition = match umd {
+        Ok(t) => t,
+        Err(e) => return Err(AppError::Validation(format!("invalid json body: {}", e)).into()),
+    };
+    if transition.from_key == transition.to_key {
+        return Err(AppError::Validation("a transition has to go to a different status".to_string()).into());
+    }
+    let organization_key = crate::error::parse_key(claims.organization_key.as_str(), "organization")?;
+    let mut conn = req.state().db_pool.acquire().await?;
+    let workflow = get_workflow(&mut conn, organization_key).await?;
+    if workflow.status(&transition.from_key).is_none() || workflow.status(&transition.to_key).is_none() {
+        return Err(AppError::NotFound("status".to_string()).into());
+    }
+    let s = Transition::new(organization_key, transition.from_key, transition.to_key);
+    insert_workflow_transition(&mut conn, &s).await?;
+    let j = serde_json::to_string(&s).expect("To JSON");
+    Ok(tide::Response::builder(tide::StatusCode::Ok)
+        .content_type(mime::JSON)
+        .body(j)
+        .build())
+}
+
+pub async fn delete_transition(req: Request<State>) -> tide::Result {
+    let u = match crate::user::user_or_error(&req) {
+        Ok(value) => value,
+        Err(_) => return Ok(tide::Redirect::new("/login").into()),
+    };
+    let key = crate::error::param_key(&req, "transition_id")?;
+    let mut conn = req.state().db_pool.acquire().await?;
+    if delete_workflow_transition(&mut conn, key, u.organization_key).await?.rows_affected() == 0 {
+        return Err(AppError::NotFound("transition".to_string()).into());
+    }
+    Ok(tide::Response::builder(tide::StatusCode::Ok)
+        .content_type(mime::HTML)
+        .build())
+}
+
+// data types
+
+#[derive(PartialEq, Debug, Deserialize, Serialize, JsonSchema, Clone, Copy, sqlx::Type, EnumIter)]
+pub enum StatusCategory {
+    NotStarted,
+    Active,
+    Done,
+}
+
+impl Into<StatusCategory> for i16 {
+    fn into(self) -> StatusCategory {
+        match self {
+            0 => StatusCategory::NotStarted,
+            1 => StatusCategory::Active,
+            2 => StatusCategory::Done,
+            _ => StatusCategory::NotStarted,
+        }
+    }
+}
+
+impl From<StatusCategory> for i16 {
+    fn from(c: StatusCategory) -> Self {
+        match c {
+            StatusCategory::NotStarted => 0,
+            StatusCategory::Active => 1,
+            StatusCategory::Done => 2,
+        }
+    }
+}
+
+impl ToString for StatusCategory {
+    fn to_string(&self) -> String {
+        match self {
+            StatusCategory::NotStarted => "Not Started".to_owned(),
+            StatusCategory::Active => "Active".to_owned(),
+            StatusCategory::Done => "Done".to_owned(),
+        }
+    }
+}
+
+fn default_color() -> String {
+    "#cccccc85".to_string()
+}
+
+fn default_category() -> StatusCategory {
+    StatusCategory::NotStarted
+}
+
+#[derive(Debug, Deserialize, Serialize, JsonSchema, Clone)]
+pub struct WorkflowStatus {
+    #[serde(default)]
+    pub key: uuid::Uuid,
+    #[serde(default)]
+    pub organization_key: uuid::Uuid,
+    pub name: String,
+    #[serde(default = "default_color")]
+    pub color: String,
+    #[serde(default = "default_category")]
+    pub category: StatusCategory,
+    #[serde(default)]
+    pub position: i32,
+    #[serde(default)]
+    pub created: i64,
+    #[serde(default)]
+    pub updated: i64,
+}
+
+impl WorkflowStatus {
+    pub fn new(
+        organization_key: uuid::Uuid,
+        name: String,
+        color: String,
+        category: StatusCategory,
+        position: i32,
+    ) -> Self {
+        let key = Uuid::new_v4();
+        let created = chrono::Utc::now().timestamp();
+        let updated = 0;
+        Self {
+            key,
+            organization_key,
+            name,
+            color,
+            category,
+            position,
+            created,
+            updated,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, JsonSchema, Clone)]
+pub struct Transition {
+    #[serde(default)]
+    pub key: uuid::Uuid,
+    #[serde(default)]
+    pub organization_key: uuid::Uuid,
+    pub from_key: uuid::Uuid,
+    pub to_key: uuid::Uuid,
+    #[serde(default)]
+    pub created: i64,
+}
+
+impl Transition {
+    pub fn new(organization_key: uuid::Uuid, from_key: uuid::Uuid, to_key: uuid::Uuid) -> Self {
+        let key = Uuid::new_v4();
+        let created = chrono::Utc::now().timestamp();
+        Self {
+            key,
+            organization_key,
+            from_key,
+            to_key,
+            created,
+        }
+    }
+}
+
+// An organization's statuses in board order plus the moves allowed between them
+#[derive(Debug, Default, Serialize, Clone)]
+pub struct Workflow {
+    pub statuses: Vec<WorkflowStatus>,
+    pub transitions: Vec<Transition>,
+}
+
+impl Workflow {
+    pub fn status(&self, key: &uuid::Uuid) -> Option<&WorkflowStatus> {
+        self.statuses.iter().find(|s| s.key == *key)
+    }
+
+    pub fn name(&self, key: &uuid::Uuid) -> String {
+        match self.status(key) {
+            Some(s) => s.name.clone(),
+            None => "Unknown".to_string(),
+        }
+    }
+
+    pub fn color(&self, key: &uuid::Uuid) -> String {
+        match self.status(key) {
+            Some(s) => s.color.clone(),
+            None => default_color(),
+        }
+    }
+
+    pub fn category(&self, key: &uuid::Uuid) -> StatusCategory {
+        match self.status(key) {
+            Some(s) => s.category,
+            None => StatusCategory::NotStarted,
+        }
+    }
+
+    pub fn is_done(&self, key: &uuid::Uuid) -> bool {
+        self.category(key) == StatusCategory::Done
+    }
+
+    // where new tasks land, the first not started status or failing that the first one
+    pub fn initial(&self) -> Option<uuid::Uuid> {
+        self.statuses
+            .iter()
+            .find(|s| s.category == StatusCategory::NotStarted)
+            .or(self.statuses.first())
+            .map(|s| s.key)
+    }
+
+    pub fn allows(&self, from_key: &uuid::Uuid, to_key: &uuid::Uuid) -> bool {
+        from_key == to_key
+            || self.transitions.is_empty()
+            || self.transitions.iter().any(|t| t.from_key == *from_key && t.to_key == *to_key)
+    }
+
+    // `from_key` is None for a task that doesn't exist yet
+    pub fn check_move(&self, from_key: Option<uuid::Uuid>, to_key: uuid::Uuid) -> Result<(), AppError> {
+        if self.status(&to_key).is_none() {
+            return Err(AppError::Validation("that status isn't part of this organization's workflow".to_string()));
+        }
+        match from_key {
+            Some(from_key) if !self.allows(&from_key, &to_key) => Err(AppError::Validation(format!(
+                "tasks can't move from {} to {}",
+                self.name(&from_key),
+                self.name(&to_key)
+            ))),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[derive(Template)]
+#[template(path = "workflow.html")]
+pub struct WorkflowTemplate {
+    user: crate::user::User,
+    workflow: Workflow,
+}
+
+impl<'a> WorkflowTemplate {
+    pub fn new(user: crate::user::User, workflow: Workflow) -> Self {
+        return Self { user, workflow };
+    }
+
+    pub fn status_name<'aa>(&'aa self, key: &uuid::Uuid) -> String {
+        self.workflow.name(key)
+    }
+
+    pub fn render_string(&self) -> String {
+        return self.render().unwrap();
+    }
+}